@@ -1,19 +1,26 @@
+extern crate libc;
 extern crate serial;
 extern crate structopt;
+extern crate termios;
 extern crate xmodem;
 #[macro_use] extern crate structopt_derive;
 
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, UNIX_EPOCH};
 use std::io::Write;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
 
 use structopt::StructOpt;
 use serial::core::{CharSize, BaudRate, StopBits, FlowControl, SerialDevice, SerialPortSettings};
-use xmodem::{Xmodem, Progress};
+use xmodem::{FileInfo, Xmodem, XmodemConfig, Ymodem, Zmodem, Progress};
 
 mod parsers;
+mod terminal;
+#[cfg(test)] mod tests;
 
 use parsers::{parse_width, parse_stop_bits, parse_flow_control, parse_baud_rate};
+use parsers::{parse_protocol, Protocol};
+use terminal::{RawMode, Terminal};
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Write to TTY using the XMODEM protocol by default.")]
@@ -44,8 +51,50 @@ struct Opt {
                 help = "Set number of stop bits", default_value = "1")]
     stop_bits: StopBits,
 
+    #[structopt(short = "p", long = "protocol", parse(try_from_str = "parse_protocol"),
+                help = "Set the transfer protocol ('xmodem', 'ymodem' or 'zmodem')",
+                default_value = "xmodem")]
+    protocol: Protocol,
+
+    #[structopt(long = "retries", parse(try_from_str),
+                help = "Set how many times each XMODEM packet is tried", default_value = "10")]
+    retries: usize,
+
     #[structopt(short = "r", long = "raw", help = "Disable XMODEM")]
     raw: bool,
+
+    #[structopt(long = "interactive",
+                help = "Open a terminal to the TTY instead; press C-a for a menu")]
+    interactive: bool,
+}
+
+fn progress(progress: Progress) {
+    match progress {
+        Progress::Packet(_) => {
+            println!(".");
+            std::io::stdout().flush().unwrap();
+        }
+        Progress::Waiting => println!("Ready"),
+        Progress::Retry(packet) => println!("Retrying packet {}", packet),
+        Progress::Error(kind) => println!("Transfer failed: {:?}", kind),
+        _ => {}
+    }
+}
+
+/// Returns the YMODEM metadata for the file at `path`, or for standard input
+/// holding `len` bytes if `path` is `None`.
+fn file_info(path: Option<&PathBuf>, len: u64) -> FileInfo {
+    let path = match path {
+        Some(path) => path,
+        None => return FileInfo::new("stdin", len)
+    };
+
+    let name = path.file_name().expect("input path has no file name").to_string_lossy();
+    let mut info = FileInfo::new(&name, len);
+    info.mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs());
+    info
 }
 
 fn main() {
@@ -61,10 +110,24 @@ fn main() {
     settings.set_stop_bits(opt.stop_bits);
     serial.write_settings(&settings).expect("Failed to overwrite serial settings");
     serial.set_timeout(Duration::from_secs(opt.timeout)).expect("Invalid timeout");
+
+    if opt.interactive {
+        // Standard input is read directly: `io::Stdin` buffers what the
+        // terminal polls for.
+        let mut input = unsafe { File::from_raw_fd(libc::STDIN_FILENO) };
+        let config = XmodemConfig::new().retries(opt.retries);
+        let raw_mode = RawMode::enable(input.as_raw_fd()).expect("Failed to set raw mode");
+        let result = Terminal::new(serial, config).run(&mut input, &mut io::stdout());
+        drop(raw_mode);
+        let _ = input.into_raw_fd();
+        result.expect("Terminal failed");
+        return;
+    }
+
     //Why Box here?
     //Ans: When you want to own a value and you care only that it’s a type that implements a particular
     //trait rather than being of a specific type. io::Read is our trait here.
-    let mut reader:Box<io::Read> = match opt.input {
+    let mut reader:Box<io::Read> = match opt.input.clone() {
         Some(path) => {
             let file = File::open(path).expect("Failed to open input file");
             Box::new(BufReader::new(file))
@@ -76,16 +139,24 @@ fn main() {
 
     if opt.raw {
             io::copy(&mut reader, &mut serial).expect("Write failed");
+    } else if opt.protocol == Protocol::Ymodem {
+            // The header carries the exact length, so read it all first.
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).expect("Failed to read input");
+            let info = file_info(opt.input.as_ref(), data.len() as u64);
+            Ymodem::transmit_with_progress(vec![(info, &data[..])], serial, progress)
+                .expect("Write failed");
+    } else if opt.protocol == Protocol::Zmodem {
+            // The receiver may ask to resume from any offset, so the data
+            // must be seekable.
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).expect("Failed to read input");
+            let info = file_info(opt.input.as_ref(), data.len() as u64);
+            Zmodem::transmit_with_progress(&info.name, io::Cursor::new(data), serial, progress)
+                .expect("Write failed");
     } else {
-            Xmodem::transmit_with_progress(reader, serial, |progress| {
-            if let Progress::Packet(_) = progress {
-                println!(".");
-                io::stdout().flush().unwrap();
-            } else if let Progress::Waiting = progress {
-                println!("Ready");
-            } else {
-                assert!(false);
-            }
-        }).expect("Write failed");
+            let mut xmodem = Xmodem::new_with_progress(serial, progress);
+            xmodem.set_config(XmodemConfig::new().retries(opt.retries));
+            xmodem.transmit_from(reader).expect("Write failed");
     }
 }
@@ -30,3 +30,20 @@ pub fn parse_flow_control(s: &str) -> Result<FlowControl, &str> {
 pub fn parse_baud_rate(s: &str) -> Result<BaudRate, ::std::num::ParseIntError> {
     Ok(BaudRate::from_speed(s.parse()?))
 }
+
+/// The protocols `ttywrite` can send with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Xmodem,
+    Ymodem,
+    Zmodem,
+}
+
+pub fn parse_protocol(s: &str) -> Result<Protocol, &str> {
+    match s {
+        "xmodem" => Ok(Protocol::Xmodem),
+        "ymodem" => Ok(Protocol::Ymodem),
+        "zmodem" => Ok(Protocol::Zmodem),
+        _ => Err("value must be 'xmodem', 'ymodem' or 'zmodem'")
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use libc;
+use serial::SerialPort;
+use termios::{self, Termios};
+use xmodem::{Xmodem, XmodemConfig};
+
+/// The byte that opens the menu: Ctrl-A, as in `screen`.
+pub const ESCAPE: u8 = 0x01;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const CTRL_C: u8 = 0x03;
+const ESC: u8 = 0x1b;
+
+const MENU: &str = "\r\n[ttywrite] s: send a file with XMODEM, r: reset, q: quit, \
+                    C-a: send C-a\r\n";
+
+/// Puts the terminal at a file descriptor in raw mode until dropped, so that
+/// every key press is passed on as it is typed, without being echoed.
+pub struct RawMode {
+    fd: RawFd,
+    original: Termios,
+}
+
+impl RawMode {
+    /// Puts the terminal at `fd` in raw mode. Returns `None` if `fd` is not a
+    /// terminal, such as when input is piped in.
+    pub fn enable(fd: RawFd) -> io::Result<Option<RawMode>> {
+        if unsafe { libc::isatty(fd) } != 1 {
+            return Ok(None);
+        }
+
+        let original = Termios::from_fd(fd)?;
+        let mut raw = original;
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(fd, termios::TCSANOW, &raw)?;
+        Ok(Some(RawMode { fd, original }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(self.fd, termios::TCSANOW, &self.original);
+    }
+}
+
+/// Waits until either file descriptor is readable or hung up. Returns
+/// whether each is.
+fn poll(first: RawFd, second: RawFd) -> io::Result<(bool, bool)> {
+    let events = libc::POLLIN;
+    let mut fds = [
+        libc::pollfd { fd: first, events, revents: 0 },
+        libc::pollfd { fd: second, events, revents: 0 },
+    ];
+
+    loop {
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } >= 0 {
+            return Ok((fds[0].revents != 0, fds[1].revents != 0));
+        }
+
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+/// An interactive terminal to a serial port: whatever is typed is sent to
+/// the port as it is typed, and whatever the port sends is shown.
+///
+/// Typing `ESCAPE` opens a menu, from which the next key sends a file over
+/// XMODEM, resets the board by pulsing DTR, or quits.
+pub struct Terminal<P> {
+    port: P,
+    config: XmodemConfig,
+}
+
+impl<P: SerialPort + AsRawFd> Terminal<P> {
+    /// Returns a terminal to `port` that sends files with the XMODEM policy
+    /// `config`.
+    pub fn new(port: P, config: XmodemConfig) -> Terminal<P> {
+        Terminal { port, config }
+    }
+
+    /// Passes bytes between the local terminal and the port until the user
+    /// quits from the menu or `input` ends. `input` should be in raw mode,
+    /// see [`RawMode`], and unbuffered, as it is polled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or writing either side fails. A failed
+    /// file transfer or reset is reported on `output` instead.
+    pub fn run<I, O>(&mut self, input: &mut I, output: &mut O) -> io::Result<()>
+        where I: Read + AsRawFd, O: Write
+    {
+        let mut buf = [0u8; 1024];
+        let mut menu = false;
+        loop {
+            let (local, remote) = poll(input.as_raw_fd(), self.port.as_raw_fd())?;
+            if remote {
+                let n = self.port.read(&mut buf)?;
+                output.write_all(&buf[..n])?;
+                output.flush()?;
+            }
+
+            if !local {
+                continue;
+            }
+
+            // Keys are read one at a time so that whatever follows a menu
+            // command, such as a path, is left for the command to read.
+            let mut key = [0u8; 1];
+            if input.read(&mut key)? == 0 {
+                return Ok(());
+            }
+
+            if !menu {
+                if key[0] == ESCAPE {
+                    menu = true;
+                    output.write_all(MENU.as_bytes())?;
+                    output.flush()?;
+                } else {
+                    self.port.write_all(&key)?;
+                }
+
+                continue;
+            }
+
+            menu = false;
+            match key[0] {
+                b's' => self.send(input, output)?,
+                b'r' => self.reset(output)?,
+                b'q' => return Ok(()),
+                ESCAPE => self.port.write_all(&key)?,
+                _ => output.write_all(b"[ttywrite] cancelled\r\n")?
+            }
+
+            output.flush()?;
+        }
+    }
+
+    /// Asks for the path of a file and sends it over XMODEM.
+    fn send<I: Read, O: Write>(&mut self, input: &mut I, output: &mut O) -> io::Result<()> {
+        output.write_all(b"[ttywrite] file to send: ")?;
+        output.flush()?;
+        let path = match read_line(input, output)? {
+            Some(ref path) if !path.is_empty() => path.clone(),
+            _ => return output.write_all(b"[ttywrite] cancelled\r\n")
+        };
+
+        let result = File::open(Path::new(&path)).and_then(|file| {
+            let mut xmodem = Xmodem::new(&mut self.port);
+            xmodem.set_config(self.config);
+            xmodem.transmit_from(file)
+        });
+
+        match result {
+            Ok(bytes) => write!(output, "[ttywrite] sent {} bytes\r\n", bytes),
+            Err(e) => write!(output, "[ttywrite] send failed: {}\r\n", e)
+        }
+    }
+
+    /// Resets the board by holding DTR low for 100 ms, for boards wired to
+    /// reset on it.
+    fn reset<O: Write>(&mut self, output: &mut O) -> io::Result<()> {
+        let result = self.port.set_dtr(false).and_then(|_| {
+            thread::sleep(Duration::from_millis(100));
+            self.port.set_dtr(true)
+        });
+
+        match result {
+            Ok(()) => output.write_all(b"[ttywrite] reset\r\n"),
+            Err(e) => write!(output, "[ttywrite] reset failed: {}\r\n", e)
+        }
+    }
+}
+
+/// Reads a line from `input`, echoing it to `output` as raw mode doesn't.
+/// Backspace and delete erase the last character. Returns `None` if the
+/// line is abandoned with Ctrl-C or escape, or `input` ends.
+fn read_line<I: Read, O: Write>(input: &mut I, output: &mut O) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        if input.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+
+        match byte[0] {
+            b'\r' | b'\n' => break,
+            CTRL_C | ESC => {
+                output.write_all(b"\r\n")?;
+                return Ok(None);
+            }
+            BACKSPACE | DELETE => {
+                if line.pop().is_some() {
+                    output.write_all(b"\x08 \x08")?;
+                }
+            }
+            byte => {
+                line.push(byte);
+                output.write_all(&[byte])?;
+            }
+        }
+
+        output.flush()?;
+    }
+
+    output.write_all(b"\r\n")?;
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
@@ -0,0 +1,130 @@
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::PathBuf;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use libc;
+use serial::{self, SerialPort};
+use xmodem::{Xmodem, XmodemConfig};
+
+use terminal::{RawMode, Terminal, ESCAPE};
+
+/// Opens a pseudo-terminal. Returns its master end; its slave end, kept open
+/// so that the master end doesn't hang up; and the path of the slave end.
+fn pty() -> (File, File, PathBuf) {
+    let (mut master, mut slave) = (0, 0);
+    let mut name = [0 as libc::c_char; 128];
+    let result = unsafe {
+        libc::openpty(&mut master, &mut slave, name.as_mut_ptr(), ptr::null(), ptr::null())
+    };
+
+    assert_eq!(result, 0, "openpty failed");
+    let path = unsafe { CStr::from_ptr(name.as_ptr()) }.to_str().expect("UTF-8 path").into();
+    unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave), path) }
+}
+
+/// Returns the read and write ends of a pipe.
+fn pipe() -> (File, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0, "pipe failed");
+    unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
+
+/// Reads from `from` until `expected` has been read, and returns what was.
+fn read_until(from: &mut File, expected: &[u8]) -> Vec<u8> {
+    let mut read = Vec::new();
+    let mut buf = [0u8; 256];
+    while !read.windows(expected.len()).any(|window| window == expected) {
+        let n = from.read(&mut buf).expect("read okay");
+        assert!(n > 0, "EOF before {:?}", String::from_utf8_lossy(expected));
+        read.extend_from_slice(&buf[..n]);
+    }
+
+    read
+}
+
+/// A terminal running on its own thread, with a pseudo-terminal for the
+/// keyboard, another for the board at the other end of the serial port, and
+/// a pipe for the screen.
+struct Session {
+    keyboard: File,
+    board: File,
+    screen: File,
+    thread: thread::JoinHandle<()>,
+}
+
+fn session() -> Session {
+    let (keyboard, mut keys, _) = pty();
+    let (board, port_slave, port_path) = pty();
+    let (screen, mut output) = pipe();
+
+    let mut port = serial::open(&port_path).expect("open port");
+    port.set_timeout(Duration::from_secs(5)).expect("set timeout");
+    let raw_mode = RawMode::enable(keys.as_raw_fd()).expect("raw mode");
+    assert!(raw_mode.is_some());
+    let thread = thread::spawn(move || {
+        let _port_slave = port_slave;
+        let _raw_mode = raw_mode;
+        Terminal::new(port, XmodemConfig::new())
+            .run(&mut keys, &mut output)
+            .expect("terminal okay");
+    });
+
+    Session { keyboard, board, screen, thread }
+}
+
+impl Session {
+    fn quit(mut self) {
+        self.keyboard.write_all(&[ESCAPE, b'q']).expect("write okay");
+        self.thread.join().expect("terminal join okay");
+    }
+}
+
+#[test]
+fn test_interactive_passthrough() {
+    let mut session = session();
+
+    session.keyboard.write_all(b"ls\r").expect("write okay");
+    read_until(&mut session.board, b"ls\r");
+
+    session.board.write_all(b"kernel.bin\r\n> ").expect("write okay");
+    read_until(&mut session.screen, b"kernel.bin\r\n> ");
+
+    // Escaping the escape sends it.
+    session.keyboard.write_all(&[ESCAPE, ESCAPE, b'x']).expect("write okay");
+    read_until(&mut session.screen, b"C-a: send C-a");
+    read_until(&mut session.board, &[ESCAPE, b'x']);
+
+    session.quit();
+}
+
+#[test]
+fn test_interactive_send() {
+    let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+    let path = ::std::env::temp_dir().join(format!("ttywrite-send-{}", ::std::process::id()));
+    File::create(&path).and_then(|mut file| file.write_all(&data)).expect("create file");
+
+    let mut session = session();
+    let mut board = session.board.try_clone().expect("clone okay");
+    let receiver = thread::spawn(move || {
+        let mut received = Vec::new();
+        Xmodem::receive(&mut board, &mut received).expect("receive okay");
+        received
+    });
+
+    session.keyboard.write_all(&[ESCAPE, b's']).expect("write okay");
+    read_until(&mut session.screen, b"file to send: ");
+    write!(session.keyboard, "{}\r", path.display()).expect("write okay");
+    read_until(&mut session.screen, b"[ttywrite] sent 1000 bytes\r\n");
+
+    let received = receiver.join().expect("receiver join okay");
+    assert_eq!(&received[..1000], &data[..]);
+    assert_eq!(received.len(), 1024);
+
+    ::std::fs::remove_file(&path).expect("remove file");
+    session.quit();
+}
@@ -0,0 +1,95 @@
+use std::io;
+
+/// A stream whose reads can be made to time out with an error of `TimedOut`,
+/// such as a serial port. `Xmodem` uses it to apply the timeouts of its
+/// `XmodemConfig`; see `Xmodem::enable_timeouts()`.
+pub trait ReadTimeout {
+    /// Sets how long a read waits for its first byte, in milliseconds, or
+    /// lets reads wait forever if `milliseconds` is `None`.
+    fn set_read_timeout(&mut self, milliseconds: Option<u32>) -> io::Result<()>;
+}
+
+impl<'a, T: ReadTimeout + ?Sized + 'a> ReadTimeout for &'a mut T {
+    fn set_read_timeout(&mut self, milliseconds: Option<u32>) -> io::Result<()> {
+        (**self).set_read_timeout(milliseconds)
+    }
+}
+
+/// The retry and timeout policy of an `Xmodem` transfer, built by chaining
+/// setters on `XmodemConfig::new()`:
+///
+/// ```
+/// # use xmodem::XmodemConfig;
+/// let config = XmodemConfig::new().retries(20).packet_timeout(Some(3000));
+/// ```
+///
+/// Timeouts are in milliseconds, and `None` waits forever. They only take
+/// effect once enabled with `Xmodem::enable_timeouts()`; otherwise reads
+/// time out, if at all, as the inner stream was set up to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XmodemConfig {
+    pub(crate) retries: usize,
+    pub(crate) start_timeout: Option<u32>,
+    pub(crate) packet_timeout: Option<u32>,
+    pub(crate) byte_timeout: Option<u32>,
+    pub(crate) cancel_len: usize,
+}
+
+impl XmodemConfig {
+    /// Returns the default policy: 10 attempts at each packet, one `CAN` to
+    /// cancel, and no timeouts.
+    pub fn new() -> XmodemConfig {
+        XmodemConfig {
+            retries: 10,
+            start_timeout: None,
+            packet_timeout: None,
+            byte_timeout: None,
+            cancel_len: 1,
+        }
+    }
+
+    /// Sets how many times in all a packet is sent or read before the
+    /// transfer fails because its checksum or CRC keeps failing or it keeps
+    /// timing out. At least one.
+    pub fn retries(mut self, retries: usize) -> XmodemConfig {
+        self.retries = if retries == 0 { 1 } else { retries };
+        self
+    }
+
+    /// Sets how long a transmitter waits for the receiver's initial `NAK` or
+    /// `C`, and how long a receiver waits for a reply to each one.
+    pub fn start_timeout(mut self, milliseconds: Option<u32>) -> XmodemConfig {
+        self.start_timeout = milliseconds;
+        self
+    }
+
+    /// Sets how long a receiver waits for the first byte of each packet, and
+    /// a transmitter for the reply to each packet. When either times out, the
+    /// packet is tried again: the receiver sends `NAK`, and the transmitter
+    /// sends the packet again.
+    pub fn packet_timeout(mut self, milliseconds: Option<u32>) -> XmodemConfig {
+        self.packet_timeout = milliseconds;
+        self
+    }
+
+    /// Sets how long a receiver waits for each of the rest of a packet's
+    /// bytes once it has started. When it times out, the packet is asked for
+    /// again with `NAK`.
+    pub fn byte_timeout(mut self, milliseconds: Option<u32>) -> XmodemConfig {
+        self.byte_timeout = milliseconds;
+        self
+    }
+
+    /// Sets how many `CAN` bytes are sent to cancel a transfer. Some
+    /// implementations ignore a single `CAN` as line noise. At least one.
+    pub fn cancel_len(mut self, cancel_len: usize) -> XmodemConfig {
+        self.cancel_len = if cancel_len == 0 { 1 } else { cancel_len };
+        self
+    }
+}
+
+impl Default for XmodemConfig {
+    fn default() -> XmodemConfig {
+        XmodemConfig::new()
+    }
+}
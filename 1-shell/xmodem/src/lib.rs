@@ -3,23 +3,71 @@ use std::io;
 #[cfg(test)] mod tests;
 mod read_ext;
 mod progress;
+mod config;
+mod ymodem;
+mod zmodem;
 
 pub use progress::{Progress, ProgressFn};
+pub use config::{ReadTimeout, XmodemConfig};
+pub use ymodem::{FileInfo, Ymodem};
+pub use zmodem::Zmodem;
 
 use read_ext::ReadExt;
 
 const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
 const EOT: u8 = 0x04;
 const ACK: u8 = 0x06;
 const NAK: u8 = 0x15;
 const CAN: u8 = 0x18;
+const CRC: u8 = b'C';
 
-/// Implementation of the XMODEM protocol.
+/// The number of times a receiver asks for CRC-16 packets with `C` before
+/// falling back to checksums with `NAK`.
+const CRC_REQUESTS: usize = 3;
+
+/// Returns the 8-bit checksum of `data` used by the original protocol: the
+/// sum of its bytes.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, &b| acc.wrapping_add(b))
+}
+
+/// Returns the CRC-16 of `data` used by XMODEM-CRC: polynomial 0x1021 with an
+/// initial value of 0.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 }
+        })
+    })
+}
+
+/// Sets the read timeout of a stream, in milliseconds.
+type SetTimeoutFn<T> = fn(&mut T, Option<u32>) -> io::Result<()>;
+
+/// Implementation of the XMODEM protocol, with the XMODEM-CRC and XMODEM-1K
+/// extensions.
+///
+/// A receiver asks for CRC-16 packets by sending `C` and falls back to the
+/// original 8-bit checksum, asked for with `NAK`, if the transmitter doesn't
+/// respond; see `set_crc()` for when it can. A transmitter uses whichever the receiver asks for. With CRC-16,
+/// a transmitter sends 1024-byte `STX` packets while at least 1024 bytes
+/// remain, and 128-byte `SOH` packets for the rest.
 pub struct Xmodem<R> {
     packet: u8,
     inner: R,
     started: bool,
-    progress: ProgressFn
+    /// Whether CRC-16 and 1024-byte packets may be used, if set with
+    /// `set_crc()`.
+    crc_enabled: Option<bool>,
+    /// Whether the transfer uses CRC-16 rather than checksums.
+    crc: bool,
+    progress: ProgressFn,
+    config: XmodemConfig,
+    /// Sets the inner stream's read timeout, once timeouts are enabled.
+    set_timeout: Option<SetTimeoutFn<R>>,
+    /// The read timeout last set on the inner stream, if any has been.
+    timeout: Option<Option<u32>>,
 }
 
 impl Xmodem<()> {
@@ -43,34 +91,10 @@ impl Xmodem<()> {
     /// the transmission. See the [`Progress`] enum for more information.
     ///
     /// Returns the number of bytes written to `to`, excluding padding zeroes.
-    pub fn transmit_with_progress<R, W>(mut data: R, to: W, f: ProgressFn) -> io::Result<usize>
+    pub fn transmit_with_progress<R, W>(data: R, to: W, f: ProgressFn) -> io::Result<usize>
         where W: io::Read + io::Write, R: io::Read
     {
-        let mut transmitter = Xmodem::new_with_progress(to, f);
-        let mut packet = [0u8; 128];
-        let mut written = 0;
-        'next_packet: loop {
-            let n = data.read_max(&mut packet)?;
-            packet[n..].iter_mut().for_each(|b| *b = 0);
-
-            if n == 0 {
-                transmitter.write_packet(&[])?;
-                return Ok(written);
-            }
-
-            for _ in 0..10 {
-                match transmitter.write_packet(&packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(_) => {
-                        written += n;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad transmit"));
-        }
+        Xmodem::new_with_progress(to, f).transmit_from(data)
     }
 
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
@@ -87,30 +111,10 @@ impl Xmodem<()> {
     ///
     /// The function `f` is used as a callback to indicate progress throughout
     /// the reception. See the [`Progress`] enum for more information.
-    pub fn receive_with_progress<R, W>(from: R, mut into: W, f: ProgressFn) -> io::Result<usize>
+    pub fn receive_with_progress<R, W>(from: R, into: W, f: ProgressFn) -> io::Result<usize>
        where R: io::Read + io::Write, W: io::Write
     {
-        let mut receiver = Xmodem::new_with_progress(from, f);
-        let mut packet = [0u8; 128];
-        let mut received = 0;
-        'next_packet: loop {
-            for _ in 0..10 {
-                match receiver.read_packet(&mut packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(0) => break 'next_packet,
-                    Ok(n) => {
-                        received += n;
-                        into.write_all(&packet)?;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad receive"));
-        }
-
-        Ok(received)
+        Xmodem::new_with_progress(from, f).receive_into(into)
     }
 }
 
@@ -119,7 +123,7 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// `inner`. The returned instance can be used for both receiving
     /// (downloading) and sending (uploading).
     pub fn new(inner: T) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: progress::noop}
+        Xmodem::new_with_progress(inner, progress::noop)
     }
 
     /// Returns a new `Xmodem` instance with the internal reader/writer set to
@@ -128,7 +132,190 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// callback to indicate progress throughout the transfer. See the
     /// [`Progress`] enum for more information.
     pub fn new_with_progress(inner: T, f: ProgressFn) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: f }
+        Xmodem {
+            packet: 1,
+            started: false,
+            crc_enabled: None,
+            crc: false,
+            inner,
+            progress: f,
+            config: XmodemConfig::new(),
+            set_timeout: None,
+            timeout: None,
+        }
+    }
+
+    /// Sets the retry and timeout policy. See [`XmodemConfig`] for the
+    /// default.
+    pub fn set_config(&mut self, config: XmodemConfig) {
+        self.config = config;
+    }
+
+    /// Makes the transfer set the inner stream's read timeout as it goes,
+    /// to those of its [`XmodemConfig`]. Until this is called, the inner
+    /// stream's read timeout is left as it is.
+    pub fn enable_timeouts(&mut self) where T: ReadTimeout {
+        self.set_timeout = Some(<T as ReadTimeout>::set_read_timeout);
+    }
+
+    /// Sets whether CRC-16 and 1024-byte packets may be used. When disabled,
+    /// a receiver asks for checksums right away and a transmitter ignores
+    /// requests for CRC-16, waiting for a `NAK` as in the original protocol.
+    /// Has no effect once a transfer has started.
+    ///
+    /// By default, a transmitter uses CRC-16 if asked to, but a receiver only
+    /// asks for it if it can stop asking: if timeouts are enabled and there
+    /// is a start timeout. Otherwise it would wait forever on a transmitter
+    /// that ignores `C`. Enable CRC-16 if the inner stream times out reads by
+    /// itself or the transmitter is known to support it.
+    pub fn set_crc(&mut self, enabled: bool) {
+        self.crc_enabled = Some(enabled);
+    }
+
+    /// Whether a receiver asks for CRC-16 before falling back to checksums.
+    fn requests_crc(&self) -> bool {
+        match self.crc_enabled {
+            Some(enabled) => enabled,
+            None => self.set_timeout.is_some() && self.config.start_timeout.is_some()
+        }
+    }
+
+    /// Transmits the data yielded by `data` to the inner stream, as
+    /// `Xmodem::transmit` does. Returns the number of bytes of `data` sent,
+    /// excluding padding zeroes.
+    pub fn transmit_from<R: io::Read>(&mut self, data: R) -> io::Result<usize> {
+        let result = self.transmit_data(data);
+        self.report(result)
+    }
+
+    fn transmit_data<R: io::Read>(&mut self, mut data: R) -> io::Result<usize> {
+        if !self.started {
+            self.start_transmit()?;
+        }
+
+        let mut block = [0u8; 1024];
+        let mut written = 0;
+        loop {
+            let size = if self.crc { 1024 } else { 128 };
+            let n = data.read_max(&mut block[..size])?;
+            if n == 0 {
+                self.write_packet(&[])?;
+                return Ok(written);
+            }
+
+            // A short block is the last: send it as 128-byte packets so that
+            // at most 127 bytes of padding are sent.
+            let padded = n + (128 - n % 128) % 128;
+            block[n..padded].iter_mut().for_each(|b| *b = 0);
+            let packet_size = if n == 1024 { 1024 } else { 128 };
+            for packet in block[..padded].chunks(packet_size) {
+                self.write_packet_with_retries(packet)?;
+            }
+
+            written += n;
+            (self.progress)(Progress::Bytes(written));
+        }
+    }
+
+    /// Receives data from the inner stream and writes it into `into`, as
+    /// `Xmodem::receive` does. Returns the number of bytes received, a
+    /// multiple of 128.
+    pub fn receive_into<W: io::Write>(&mut self, into: W) -> io::Result<usize> {
+        let result = self.receive_data(into);
+        self.report(result)
+    }
+
+    fn receive_data<W: io::Write>(&mut self, mut into: W) -> io::Result<usize> {
+        let mut packet = [0u8; 1024];
+        let mut received = 0;
+        loop {
+            match self.read_packet_with_retries(&mut packet)? {
+                0 => return Ok(received),
+                n => {
+                    received += n;
+                    into.write_all(&packet[..n])?;
+                    (self.progress)(Progress::Bytes(received));
+                }
+            }
+        }
+    }
+
+    /// Reports the error of a failed transfer to the progress callback.
+    fn report<V>(&self, result: io::Result<V>) -> io::Result<V> {
+        if let Err(ref e) = result {
+            (self.progress)(Progress::Error(e.kind()));
+        }
+
+        result
+    }
+
+    /// Reads a packet into `buf`, trying again up to the configured number of
+    /// times in all if its checksum is bad or, once the transfer has started,
+    /// if it times out. A packet that times out is asked for again with `NAK`
+    /// once the line has gone quiet, so that what is left of it isn't taken
+    /// for the next packet.
+    fn read_packet_with_retries(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut timed_out = false;
+        for attempt in 0..self.config.retries {
+            if attempt > 0 {
+                (self.progress)(Progress::Retry(self.packet));
+            }
+
+            // Timeouts while starting are the start timeout's to handle.
+            match self.read_packet(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => timed_out = false,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut && self.started => {
+                    self.purge()?;
+                    self.write_byte(NAK)?;
+                    (self.progress)(Progress::Nak(self.packet));
+                    timed_out = true;
+                }
+                result => return result
+            }
+        }
+
+        if timed_out {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "transmitter stopped responding"))
+        } else {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad receive"))
+        }
+    }
+
+    /// Reads and throws away bytes until none arrives for a byte timeout, or
+    /// a packet timeout if there is no byte timeout.
+    fn purge(&mut self) -> io::Result<()> {
+        let timeout = self.config.byte_timeout.or(self.config.packet_timeout);
+        self.set_timeout(timeout)?;
+        loop {
+            match self.read_byte(false) {
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => return Ok(()),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    /// Writes `packet`, trying again up to the configured number of times in
+    /// all if the receiver reports a bad checksum or its reply times out.
+    fn write_packet_with_retries(&mut self, packet: &[u8]) -> io::Result<usize> {
+        let mut timed_out = false;
+        for attempt in 0..self.config.retries {
+            if attempt > 0 {
+                (self.progress)(Progress::Retry(self.packet));
+            }
+
+            match self.write_packet(packet) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => timed_out = false,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => timed_out = true,
+                result => return result
+            }
+        }
+
+        if timed_out {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "receiver stopped responding"))
+        } else {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad transmit"))
+        }
     }
 
     /// Reads a single byte from the inner I/O stream. If `abort_on_can` is
@@ -160,6 +347,28 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         self.inner.write_all(&[byte])
     }
 
+    /// Cancels the transfer by writing the configured number of `CAN` bytes.
+    fn cancel(&mut self) -> io::Result<()> {
+        for _ in 0..self.config.cancel_len {
+            self.write_byte(CAN)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the inner stream's read timeout to `milliseconds` if timeouts are
+    /// enabled and it isn't set to that already.
+    fn set_timeout(&mut self, milliseconds: Option<u32>) -> io::Result<()> {
+        match self.set_timeout {
+            Some(set) if self.timeout != Some(milliseconds) => {
+                set(&mut self.inner, milliseconds)?;
+                self.timeout = Some(milliseconds);
+                Ok(())
+            }
+            _ => Ok(())
+        }
+    }
+
     /// Reads a single byte from the inner I/O stream and compares it to `byte`.
     /// If the bytes match, the byte is returned as an `Ok`. If they differ and
     /// the read byte is not `CAN`, an error of `InvalidData` with the message
@@ -174,12 +383,11 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// or if writing the `CAN` byte failed on byte mismatch.
     fn expect_byte_or_cancel(&mut self, byte: u8, msg: &'static str) -> io::Result<u8> {
         let mut buffer = [0u8; 1];
-        self.inner.read(&mut buffer)?;
+        self.inner.read_exact(&mut buffer)?;
         if buffer[0] == byte {
             Ok(byte)
         } else {
-            let write_buf = [CAN];
-            self.inner.write(&write_buf)?;
+            self.cancel()?;
             if buffer[0] == CAN {
                 return Err(io::Error::new(io::ErrorKind::ConnectionAborted, msg))
             }
@@ -200,7 +408,7 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// `InvalidData`.
     fn expect_byte(&mut self, byte: u8, expected: &'static str) -> io::Result<u8> {
         let mut buffer = [0u8; 1];
-        self.inner.read(&mut buffer)?;
+        self.inner.read_exact(&mut buffer)?;
         if buffer[0] == byte {
             Ok(byte)
         } else {
@@ -211,90 +419,202 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         }
     }
 
+    /// Starts a reception: asks the transmitter for CRC-16 packets with `C`
+    /// up to `CRC_REQUESTS` times, waiting for a reply each time, then falls
+    /// back to asking for checksums with `NAK`. Returns the first byte the
+    /// transmitter replied with. The transfer has started once it has.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or writing to the inner stream fails, with
+    /// `TimedOut` if the transmitter doesn't reply to the `NAK` either, or
+    /// `ConnectionAborted` if it replies with `CAN`.
+    fn start_receive(&mut self) -> io::Result<u8> {
+        (self.progress)(Progress::Started);
+        if self.requests_crc() {
+            let timeout = self.config.start_timeout;
+            for _ in 0..CRC_REQUESTS {
+                self.write_byte(CRC)?;
+                self.set_timeout(timeout)?;
+                match self.read_byte(true) {
+                    Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(e) => return Err(e),
+                    Ok(byte) => {
+                        self.crc = true;
+                        self.started = true;
+                        return Ok(byte);
+                    }
+                }
+            }
+        }
+
+        self.crc = false;
+        self.write_byte(NAK)?;
+        let timeout = self.config.start_timeout;
+        self.set_timeout(timeout)?;
+        let byte = self.read_byte(true)?;
+        self.started = true;
+        Ok(byte)
+    }
+
+    /// Starts a transmission: waits for the receiver to ask for checksums
+    /// with `NAK` or, if enabled, for CRC-16 with `C`. Other requests for
+    /// CRC-16 are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading or writing to the inner stream fails. An
+    /// error of `ConnectionAborted` is returned if the receiver sends `CAN`.
+    /// If it sends anything else, a `CAN` byte is written out and an error of
+    /// `InvalidData` is returned.
+    fn start_transmit(&mut self) -> io::Result<()> {
+        (self.progress)(Progress::Waiting);
+        let timeout = self.config.start_timeout;
+        self.set_timeout(timeout)?;
+        loop {
+            match self.read_byte(false)? {
+                CRC if self.crc_enabled == Some(false) => continue,
+                CRC => self.crc = true,
+                NAK => self.crc = false,
+                CAN => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "received CAN"));
+                }
+                _ => {
+                    self.cancel()?;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "expected NAK or C"));
+                }
+            }
+
+            break;
+        }
+
+        self.started = true;
+        Ok(())
+    }
+
     /// Reads (downloads) a single packet from the inner stream using the XMODEM
-    /// protocol. On success, returns the number of bytes read (always 128).
+    /// protocol. On success, returns the number of bytes read: 128, or 1024
+    /// for an XMODEM-1K packet.
     ///
     /// The progress callback is called with `Progress::Start` when reception
     /// for the first packet has started and subsequently with
     /// `Progress::Packet` when a packet is received successfully.
     ///
+    /// A copy of the previous packet, sent again because its `ACK` was lost,
+    /// is acknowledged again and skipped.
+    ///
     /// # Errors
     ///
     /// Returns an error if reading or writing to the inner stream fails at any
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The sender's first byte for a packet isn't `EOT` or `SOH`.
+    ///   * The sender's first byte for a packet isn't `EOT`, `SOH` or `STX`.
     ///   * The sender doesn't send a second `EOT` after the first.
     ///   * The received packet numbers don't match the expected values.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is returned if a packet checksum or CRC
+    /// fails.
     ///
     /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
     /// received when not expected.
     ///
-    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`.
+    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`, or
+    /// if `buf.len() < 1024` and the sender sends a 1024-byte packet. In the
+    /// latter case, a `CAN` byte is written out to end the transfer.
     pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        // If receiver wasn't start, We would start 
-        if buf.len() != 128 {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Need to be at least 128"));
+        if buf.len() < 128 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "buffer holds less than 128 bytes"));
         }
-        if !self.started {
-            self.write_byte(NAK)?;
-            self.started = true;
-            (self.progress)(Progress::Started);
+
+        let mut header = if self.started {
+            let timeout = self.config.packet_timeout;
+            self.set_timeout(timeout)?;
+            self.read_byte(true)?
+        } else {
+            self.start_receive()?
         };
-        let header = self.read_byte(true)?;
-        match header {
-            SOH => {
-                let mut checksum: u32 = 0;
-                let pkt_num:u8 = self.packet;
-                self.expect_byte(pkt_num, "received wrong packet number")?;    
-                self.expect_byte(255-pkt_num, "Packet number and its complement missmatch")?;
-                //read 128 bytes
-                self.inner.read_exact(buf)?;
-                for idx in 0..128 {
-                  checksum += buf[idx] as u32;
-                }
-                checksum %= 256;
-                if checksum as u8 == self.read_byte(false)? {
-                    self.packet = self.packet.wrapping_add(1);
-                    self.write_byte(ACK)?;  
-                    (self.progress)(Progress::Packet(pkt_num));
-                    return Ok(128);
-                } else {
-                    self.write_byte(NAK)?; 
-                    return Err(io::Error::new(io::ErrorKind::Interrupted, "Checksum was incorrect"));
+
+        loop {
+            let timeout = self.config.byte_timeout;
+            self.set_timeout(timeout)?;
+            let len = match header {
+                SOH => 128,
+                STX if buf.len() >= 1024 => 1024,
+                STX => {
+                    self.cancel()?;
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "buffer holds less than 1024 bytes"));
                 }
-            },
-            EOT => {
-                self.write_byte(NAK)?;
-                match self.expect_byte(EOT, "Not EOT byte") {
-                  Ok(EOT) => {
+                EOT => {
+                    self.write_byte(NAK)?;
+                    self.expect_byte(EOT, "expected a second EOT")?;
                     self.write_byte(ACK)?;
                     return Ok(0);
-                  },
-                  _ => {
-                      return Err(io::Error::new(io::ErrorKind::InvalidData, "Waitting for End of transfer"));
-                  }
+                }
+                _ => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "expected SOH, STX or EOT"));
+                }
+            };
+
+            // The previous packet is sent again if its `ACK` was lost.
+            let pkt_num = self.packet;
+            let num = self.read_byte(false)?;
+            let duplicate = num == pkt_num.wrapping_sub(1);
+            if num != pkt_num && !duplicate {
+                let kind = match num {
+                    CAN => io::ErrorKind::ConnectionAborted,
+                    _ => io::ErrorKind::InvalidData
                 };
-            },
-            _ => {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "The first byte must be EOT or SOH"));
-            },
-        };
+
+                return Err(io::Error::new(kind, "received wrong packet number"));
+            }
+
+            self.expect_byte(255 - num, "packet number and its complement mismatch")?;
+
+            let data = &mut buf[..len];
+            self.inner.read_exact(data)?;
+            let valid = if self.crc {
+                let high = self.read_byte(false)? as u16;
+                let low = self.read_byte(false)? as u16;
+                crc16(data) == (high << 8 | low)
+            } else {
+                checksum(data) == self.read_byte(false)?
+            };
+
+            if !valid {
+                self.write_byte(NAK)?;
+                (self.progress)(Progress::Nak(num));
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "checksum was incorrect"));
+            }
+
+            if duplicate {
+                // Its data has been received already: acknowledge it again
+                // and wait for the packet after it.
+                self.write_byte(ACK)?;
+                let timeout = self.config.packet_timeout;
+                self.set_timeout(timeout)?;
+                header = self.read_byte(true)?;
+                continue;
+            }
+
+            self.packet = self.packet.wrapping_add(1);
+            self.write_byte(ACK)?;
+            (self.progress)(Progress::Packet(pkt_num));
+            return Ok(len);
+        }
     }
 
     /// Sends (uploads) a single packet to the inner stream using the XMODEM
     /// protocol. If `buf` is empty, end of transmissions is sent. Users of this
     /// interface should ensure that `write_packet(&[])` is called when data
     /// transmission is complete. On success, returns the number of bytes
-    /// written.
+    /// written: the first 1024 bytes of `buf` are sent as an XMODEM-1K packet
+    /// if there are that many and the receiver asked for CRC-16, and the first
+    /// 128 bytes otherwise.
     ///
     /// The progress callback is called with `Progress::Waiting` before waiting
-    /// for the receiver's `NAK`, `Progress::Start` when transmission of the
-    /// first packet has started and subsequently with `Progress::Packet` when a
-    /// packet is sent successfully.
+    /// for the receiver's `NAK` or `C`, and subsequently with
+    /// `Progress::Packet` when a packet is sent successfully.
     ///
     /// # Errors
     ///
@@ -302,7 +622,7 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The receiver's first byte isn't a `NAK`.
+    ///   * The receiver's first byte isn't a `NAK` or `C`.
     ///   * The receiver doesn't respond with a `NAK` to the first `EOT`.
     ///   * The receiver doesn't respond with an `ACK` to the second `EOT`.
     ///   * The receiver responds to a complete packet with something besides
@@ -316,52 +636,54 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     ///
     /// An error of kind `Interrupted` is returned if a packet checksum fails.
     pub fn write_packet(&mut self, buf: &[u8]) -> io::Result<usize> {
-      if buf.len() < 128 && buf.len()!= 0 {
-        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Length must be larger than 128 or equal to 0"));
-      }
+        if buf.len() < 128 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Length must be larger than 128 or equal to 0"));
+        }
+
+        if !self.started {
+            self.start_transmit()?;
+        }
+
+        let timeout = self.config.packet_timeout;
+        self.set_timeout(timeout)?;
+        if buf.is_empty() {
+            self.write_byte(EOT)?;
+            self.expect_byte_or_cancel(NAK, "Expected NAK byte")?;
+            self.write_byte(EOT)?;
+            self.expect_byte_or_cancel(ACK, "Expected for ACK byte")?;
+            return Ok(0);
+        }
+
+        let (header, data) = if buf.len() >= 1024 && self.crc {
+            (STX, &buf[..1024])
+        } else {
+            (SOH, &buf[..128])
+        };
 
-      if !self.started {
-        (self.progress)(Progress::Waiting);
-        self.expect_byte_or_cancel(NAK, "expected NAK")?;
-        self.started = true;
-        //(self.progress)(Progress::Started);
-      }
-
-      if buf.len() == 0 {
-        //end of trans
-        self.write_byte(EOT)?;
-        self.expect_byte_or_cancel(NAK, "Expected NAK byte")?;
-        self.write_byte(EOT)?;
-        self.expect_byte_or_cancel(ACK, "Expected for ACK byte")?;
-        return Ok(0);
-      } else {
         let pkt_num = self.packet;
-        self.write_byte(SOH)?;
+        self.write_byte(header)?;
         self.write_byte(pkt_num)?;
         self.write_byte(255 - pkt_num)?;
-        self.inner.write(buf)?;
-        //send checksum pending
-        let checksum: u8 = buf.iter().fold(0, |acc, &x| {
-          acc.wrapping_add(x)
-        });
-        self.write_byte(checksum)?;
-        /*self.expect_byte(ACK, "Expected ACK after sending packet")?;
-        */
-        let res = self.read_byte(true)?;
-        match res {
+        self.inner.write_all(data)?;
+        if self.crc {
+            let crc = crc16(data);
+            self.inner.write_all(&[(crc >> 8) as u8, crc as u8])?;
+        } else {
+            self.write_byte(checksum(data))?;
+        }
+
+        match self.read_byte(true)? {
             ACK => {
                 (self.progress)(Progress::Packet(pkt_num));
                 self.packet = self.packet.wrapping_add(1);
-                Ok(128)
-            },
+                Ok(data.len())
+            }
             NAK => {
+                (self.progress)(Progress::Nak(pkt_num));
                 Err(io::Error::new(io::ErrorKind::Interrupted, "Checksum is incorrect"))
-            },
-            _ => {
-                Err(io::Error::new(io::ErrorKind::InvalidData, "We was expecting for NAK or ACK"))
             }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "We was expecting for NAK or ACK"))
         }
-      }
     }
 
     /// Flush this output stream, ensuring that all intermediately buffered
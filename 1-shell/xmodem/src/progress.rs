@@ -1,3 +1,5 @@
+use std::io;
+
 /// Enum representing how much progress has been made transmitting/receiving.
 ///
 /// A value of this type is passed in to the progress callback supplied to
@@ -12,6 +14,15 @@ pub enum Progress {
     Started,
     /// Packet `.0` was transmitted/received.
     Packet(u8),
+    /// Packet `.0` is being transmitted/received again after a bad checksum
+    /// or a timeout.
+    Retry(u8),
+    /// A `NAK` for packet `.0` was sent/received.
+    Nak(u8),
+    /// `.0` bytes of data have been transmitted/received so far.
+    Bytes(usize),
+    /// The transfer failed with an error of kind `.0`.
+    Error(io::ErrorKind),
 }
 
 /// Type for progress callbacks.
@@ -1,19 +1,34 @@
 use super::*;
-use std::sync::mpsc::{Receiver, Sender, channel};
-use std::io::Cursor;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::io::{Cursor, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use zmodem::{self, crc32};
 
-struct Pipe(Sender<u8>, Receiver<u8>, Vec<u8>);
+/// One end of a pipe: the sending half, the receiving half, every byte
+/// written, and how long a read waits for its first byte, if not forever.
+struct Pipe(Sender<u8>, Receiver<u8>, Vec<u8>, Option<Duration>);
 
 fn pipe() -> (Pipe, Pipe) {
     let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
-    (Pipe(tx1, rx2, vec![]), Pipe(tx2, rx1, vec![]))
+    (Pipe(tx1, rx2, vec![], None), Pipe(tx2, rx1, vec![], None))
 }
 
 impl io::Read for Pipe {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        for i in 0..buf.len() {
-            match self.1.recv() {
-                Ok(byte) => buf[i] = byte,
+        for (i, slot) in buf.iter_mut().enumerate() {
+            let byte = match self.3 {
+                Some(timeout) if i == 0 => match self.1.recv_timeout(timeout) {
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "pipe timed out"));
+                    }
+                    result => result.map_err(|_| ())
+                },
+                _ => self.1.recv().map_err(|_| ())
+            };
+
+            match byte {
+                Ok(byte) => *slot = byte,
                 Err(_) => return Ok(i)
             }
         }
@@ -157,7 +172,7 @@ fn test_transmit_reported_bytes() {
 fn test_raw_transmission() {
     let mut input = [0u8; 256];
     let mut output = [0u8; 256];
-    (0..256usize).into_iter().enumerate().for_each(|(i, b)| input[i] = b as u8);
+    (0..256usize).enumerate().for_each(|(i, b)| input[i] = b as u8);
 
     let (mut tx, mut rx) = pipe();
     let tx_thread = std::thread::spawn(move || {
@@ -166,7 +181,11 @@ fn test_raw_transmission() {
     });
 
     let rx_thread = std::thread::spawn(move || {
-        Xmodem::receive(&mut tx, &mut output[..]).expect("receive okay");
+        {
+            let mut receiver = Xmodem::new(&mut tx);
+            receiver.set_crc(false);
+            receiver.receive_into(&mut output[..]).expect("receive okay");
+        }
         tx.2
     });
 
@@ -227,3 +246,838 @@ fn test_eot() {
 
     assert_eq!(&buffer[..], &[NAK, EOT, NAK, EOT, ACK]);
 }
+
+/// Transfers `input` from a transmitter to a receiver over a pipe, and returns
+/// the bytes each wrote and what the receiver received.
+fn transfer(input: Vec<u8>, transmitter_crc: bool, receiver_timeout: Option<Duration>)
+    -> (Vec<u8>, Vec<u8>, Vec<u8>)
+{
+    let (mut tx, mut rx) = pipe();
+    tx.3 = receiver_timeout;
+    let len = input.len();
+    let tx_thread = std::thread::spawn(move || {
+        {
+            let mut transmitter = Xmodem::new(&mut rx);
+            transmitter.set_crc(transmitter_crc);
+            let sent = transmitter.transmit_from(&input[..]).expect("transmit okay");
+            assert_eq!(sent, len);
+        }
+        rx.2
+    });
+
+    let rx_thread = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let received = {
+            let mut receiver = Xmodem::new(&mut tx);
+            receiver.set_crc(true);
+            receiver.receive_into(&mut output).expect("receive okay")
+        };
+        assert_eq!(received, output.len());
+        (tx.2, output)
+    });
+
+    let transmitted = tx_thread.join().expect("tx join okay");
+    let (responses, output) = rx_thread.join().expect("rx join okay");
+    (transmitted, responses, output)
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+#[test]
+fn test_crc16() {
+    assert_eq!(crc16(b""), 0);
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+    assert_eq!(crc16(&[0; 128]), 0);
+}
+
+#[test]
+fn test_raw_crc_transmission() {
+    let input = pattern(256);
+    let (transmitted, responses, output) = transfer(input.clone(), true, None);
+    assert_eq!(&output[..], &input[..]);
+
+    for (i, packet) in transmitted[..266].chunks(133).enumerate() {
+        let data = &input[(i * 128)..((i + 1) * 128)];
+        let crc = crc16(data);
+        assert_eq!(&packet[0..3], &[SOH, i as u8 + 1, 255 - (i as u8 + 1)]);
+        assert_eq!(&packet[3..131], data);
+        assert_eq!(&packet[131..], &[(crc >> 8) as u8, crc as u8]);
+    }
+
+    assert_eq!(&transmitted[266..], &[EOT, EOT]);
+    assert_eq!(&responses, &[CRC, ACK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn test_1k_transmission() {
+    // Two full 1024-byte packets, then the remaining 952 bytes as 8 128-byte
+    // packets, the last padded with zeroes.
+    let input = pattern(3000);
+    let (transmitted, responses, output) = transfer(input.clone(), true, None);
+    assert_eq!(output.len(), 3072);
+    assert_eq!(&output[..3000], &input[..]);
+    assert!(output[3000..].iter().all(|&b| b == 0));
+
+    assert_eq!(&transmitted[0..3], &[STX, 1, 254]);
+    assert_eq!(&transmitted[3..1027], &input[..1024]);
+    let crc = crc16(&input[..1024]);
+    assert_eq!(&transmitted[1027..1029], &[(crc >> 8) as u8, crc as u8]);
+    assert_eq!(&transmitted[1029..1032], &[STX, 2, 253]);
+    assert_eq!(&transmitted[2058..2061], &[SOH, 3, 252]);
+    assert_eq!(transmitted.len(), 2 * 1029 + 8 * 133 + 2);
+
+    let mut expected = vec![CRC];
+    expected.extend(vec![ACK; 10]);
+    expected.extend(&[NAK, ACK]);
+    assert_eq!(responses, expected);
+}
+
+#[test]
+fn test_checksum_fallback() {
+    // A transmitter without CRC-16 ignores the receiver's `C`s until it
+    // falls back to `NAK`.
+    let input = pattern(2048);
+    let timeout = Some(Duration::from_millis(20));
+    let (transmitted, responses, output) = transfer(input.clone(), false, timeout);
+    assert_eq!(&output[..], &input[..]);
+    assert_eq!(&transmitted[0..3], &[SOH, 1, 254]);
+    assert_eq!(transmitted[131], checksum(&input[..128]));
+    assert_eq!(transmitted.len(), 16 * 132 + 2);
+    assert_eq!(&responses[..5], &[CRC, CRC, CRC, NAK, ACK]);
+}
+
+#[test]
+fn test_checksum_only_transmitter() {
+    // A receiver that can't time out asks for checksums right away, rather
+    // than waiting forever for a transmitter that ignores `C`.
+    let input = pattern(512);
+    let (mut tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut transmitter = Xmodem::new(&mut rx);
+        transmitter.set_crc(false);
+        transmitter.transmit_from(&pattern(512)[..])
+    });
+
+    let (done, result) = channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let received = Xmodem::receive(&mut tx, &mut output).map(|_| output);
+        let _ = done.send((received, tx.2));
+    });
+
+    let (received, responses) = result.recv_timeout(Duration::from_secs(5))
+        .expect("receiver finished");
+    assert_eq!(received.expect("receive okay"), input);
+    assert_eq!(responses[0], NAK);
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 512);
+}
+
+#[test]
+fn test_checksum_receiver() {
+    // A receiver without CRC-16 gets 128-byte packets with checksums.
+    let input = pattern(1024);
+    let (mut tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&input[..], &mut rx).map(|_| rx.2));
+    let mut receiver = Xmodem::new(&mut tx);
+    receiver.set_crc(false);
+    let mut output = Vec::new();
+    assert_eq!(receiver.receive_into(&mut output).expect("receive okay"), 1024);
+
+    let transmitted = tx_thread.join().expect("tx join okay").expect("tx okay");
+    assert_eq!(transmitted.len(), 8 * 132 + 2);
+    assert_eq!(&output[..], &pattern(1024)[..]);
+}
+
+#[test]
+fn test_bad_crc() {
+    let data = [7u8; 128];
+    let crc = crc16(&data);
+    let mut buffer = vec![0, SOH, 1, 254];
+    buffer.extend(&data[..]);
+    buffer.extend(&[(crc >> 8) as u8, !crc as u8, 0]);
+
+    let mut packet = [0u8; 128];
+    let mut receiver = Xmodem::new(Cursor::new(buffer.as_mut_slice()));
+    receiver.set_crc(true);
+    let e = receiver.read_packet(&mut packet[..]).expect_err("bad CRC");
+
+    assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+    assert_eq!(buffer[0], CRC);
+    assert_eq!(buffer[buffer.len() - 1], NAK);
+}
+
+#[test]
+fn test_1k_packet_into_small_buffer() {
+    let mut buffer = vec![0, STX, 0];
+    let mut packet = [0u8; 128];
+    let mut receiver = Xmodem::new(Cursor::new(buffer.as_mut_slice()));
+    receiver.set_crc(true);
+    let e = receiver.read_packet(&mut packet[..]).expect_err("buffer too small");
+
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(&buffer[..], &[CRC, STX, CAN]);
+}
+
+impl ReadTimeout for Pipe {
+    fn set_read_timeout(&mut self, milliseconds: Option<u32>) -> io::Result<()> {
+        self.3 = milliseconds.map(|ms| Duration::from_millis(ms as u64));
+        Ok(())
+    }
+}
+
+static RETRIES: AtomicUsize = AtomicUsize::new(0);
+static NAKS: AtomicUsize = AtomicUsize::new(0);
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_retries(progress: Progress) {
+    match progress {
+        Progress::Retry(1) => RETRIES.fetch_add(1, Ordering::SeqCst),
+        Progress::Nak(1) => NAKS.fetch_add(1, Ordering::SeqCst),
+        Progress::Error(io::ErrorKind::BrokenPipe) => ERRORS.fetch_add(1, Ordering::SeqCst),
+        _ => 0
+    };
+}
+
+#[test]
+fn test_config_retries() {
+    let data = [7u8; 128];
+    let crc = crc16(&data);
+    let mut buffer = vec![0];
+    for _ in 0..3 {
+        buffer.extend(&[SOH, 1, 254]);
+        buffer.extend(&data[..]);
+        buffer.extend(&[(crc >> 8) as u8, !crc as u8, 0]);
+    }
+
+    let mut receiver = Xmodem::new_with_progress(Cursor::new(buffer.as_mut_slice()), count_retries);
+    receiver.set_config(XmodemConfig::new().retries(3));
+    receiver.set_crc(true);
+    let e = receiver.receive_into(Vec::new()).expect_err("bad CRCs");
+
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    assert_eq!(RETRIES.load(Ordering::SeqCst), 2);
+    assert_eq!(NAKS.load(Ordering::SeqCst), 3);
+    assert_eq!(ERRORS.load(Ordering::SeqCst), 1);
+    assert_eq!(buffer[buffer.len() - 1], NAK);
+}
+
+#[test]
+fn test_config_cancel_len() {
+    let mut buffer = vec![0, 0, 0, 0];
+    let mut transmitter = Xmodem::new(Cursor::new(buffer.as_mut_slice()));
+    transmitter.set_config(XmodemConfig::new().cancel_len(3));
+    let e = transmitter.transmit_from(&[0u8; 128][..]).expect_err("expected NAK or C");
+
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(&buffer[..], &[0, CAN, CAN, CAN]);
+}
+
+#[test]
+fn test_config_start_timeout() {
+    let (mut tx, _rx) = pipe();
+    let e = {
+        let mut receiver = Xmodem::new(&mut tx);
+        receiver.set_config(XmodemConfig::new().start_timeout(Some(10)));
+        receiver.enable_timeouts();
+        receiver.receive_into(Vec::new()).expect_err("no transmitter")
+    };
+
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(&tx.2[..], &[CRC, CRC, CRC, NAK]);
+    assert_eq!(tx.3, Some(Duration::from_millis(10)));
+}
+
+/// Returns packet `num` carrying `data`, with a CRC-16.
+fn crc_packet(num: u8, data: &[u8]) -> Vec<u8> {
+    let crc = crc16(data);
+    let mut packet = vec![SOH, num, 255 - num];
+    packet.extend(data);
+    packet.extend(&[(crc >> 8) as u8, crc as u8]);
+    packet
+}
+
+static TIMEOUT_RETRIES: AtomicUsize = AtomicUsize::new(0);
+
+fn count_timeout_retries(progress: Progress) {
+    if let Progress::Retry(2) = progress {
+        TIMEOUT_RETRIES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_config_packet_timeout_receiver() {
+    let (tx, mut rx) = pipe();
+    let rx_thread = std::thread::spawn(move || {
+        let mut tx = tx;
+        let mut output = Vec::new();
+        let result = {
+            let mut receiver = Xmodem::new_with_progress(&mut tx, count_timeout_retries);
+            receiver.set_config(XmodemConfig::new().packet_timeout(Some(100)));
+            receiver.set_crc(true);
+            receiver.enable_timeouts();
+            receiver.receive_into(&mut output)
+        };
+
+        (result, output, tx.2)
+    });
+
+    let mut reply = [0u8; 1];
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], CRC);
+    rx.write_all(&crc_packet(1, &[1; 128])).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], ACK);
+
+    // Nothing is sent until the receiver times out and asks again.
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], NAK);
+    rx.write_all(&crc_packet(2, &[2; 128])).expect("write okay");
+    rx.write_all(&[EOT, EOT]).expect("write okay");
+
+    let (result, output, written) = rx_thread.join().expect("rx join okay");
+    assert_eq!(result.expect("receive okay"), 256);
+    assert_eq!(&output[..128], &[1; 128][..]);
+    assert_eq!(&output[128..], &[2; 128][..]);
+    assert_eq!(&written[..], &[CRC, ACK, NAK, ACK, NAK, ACK]);
+    assert_eq!(TIMEOUT_RETRIES.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_config_packet_timeout_transmitter() {
+    let (tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let mut tx = tx;
+        let mut transmitter = Xmodem::new(&mut tx);
+        transmitter.set_config(XmodemConfig::new().packet_timeout(Some(100)));
+        transmitter.enable_timeouts();
+        transmitter.transmit_from(&[7u8; 128][..])
+    });
+
+    rx.write_all(&[CRC]).expect("write okay");
+    let packet = crc_packet(1, &[7; 128]);
+    let mut received = vec![0u8; packet.len()];
+    rx.read_exact(&mut received).expect("read okay");
+    assert_eq!(received, packet);
+
+    // The packet is sent again when no reply comes.
+    rx.read_exact(&mut received).expect("read okay");
+    assert_eq!(received, packet);
+    rx.write_all(&[ACK]).expect("write okay");
+
+    let mut eot = [0u8; 1];
+    rx.read_exact(&mut eot).expect("read okay");
+    rx.write_all(&[NAK]).expect("write okay");
+    rx.read_exact(&mut eot).expect("read okay");
+    rx.write_all(&[ACK]).expect("write okay");
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("transmit okay"), 128);
+}
+
+#[test]
+fn test_config_byte_timeout() {
+    let (tx, mut rx) = pipe();
+    let rx_thread = std::thread::spawn(move || {
+        let mut tx = tx;
+        let mut output = Vec::new();
+        let result = {
+            let mut receiver = Xmodem::new(&mut tx);
+            receiver.set_config(XmodemConfig::new().retries(2).byte_timeout(Some(100)));
+            receiver.set_crc(true);
+            receiver.enable_timeouts();
+            receiver.receive_into(&mut output)
+        };
+
+        (result, output, tx.2)
+    });
+
+    // The packet stalls after its first byte, until the receiver asks again.
+    let mut reply = [0u8; 1];
+    rx.read_exact(&mut reply).expect("read okay");
+    rx.write_all(&[SOH]).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], NAK);
+    rx.write_all(&crc_packet(1, &[3; 128])).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], ACK);
+
+    // Stalling on every attempt fails the transfer.
+    rx.write_all(&[SOH]).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], NAK);
+    rx.write_all(&[SOH]).expect("write okay");
+
+    let (result, output, written) = rx_thread.join().expect("rx join okay");
+    assert_eq!(result.expect_err("stalled").kind(), io::ErrorKind::TimedOut);
+    assert_eq!(output, vec![3; 128]);
+    assert_eq!(&written[..], &[CRC, NAK, ACK, NAK, NAK]);
+}
+
+#[test]
+fn test_lost_ack() {
+    let (tx, mut rx) = pipe();
+    let rx_thread = std::thread::spawn(move || {
+        let mut tx = tx;
+        let mut output = Vec::new();
+        let result = {
+            let mut receiver = Xmodem::new(&mut tx);
+            receiver.set_crc(true);
+            receiver.receive_into(&mut output)
+        };
+
+        (result, output, tx.2)
+    });
+
+    let mut reply = [0u8; 1];
+    rx.read_exact(&mut reply).expect("read okay");
+    rx.write_all(&crc_packet(1, &[1; 128])).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+
+    // The `ACK` is taken to be lost: the packet is sent again, and skipped.
+    rx.write_all(&crc_packet(1, &[1; 128])).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], ACK);
+    rx.write_all(&crc_packet(2, &[2; 128])).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    rx.write_all(&[EOT, EOT]).expect("write okay");
+
+    let (result, output, written) = rx_thread.join().expect("rx join okay");
+    assert_eq!(result.expect("receive okay"), 256);
+    assert_eq!(&output[..128], &[1; 128][..]);
+    assert_eq!(&output[128..], &[2; 128][..]);
+    assert_eq!(&written[..], &[CRC, ACK, ACK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn test_cut_off_packet() {
+    let (tx, mut rx) = pipe();
+    let rx_thread = std::thread::spawn(move || {
+        let mut tx = tx;
+        let mut output = Vec::new();
+        let result = {
+            let mut receiver = Xmodem::new(&mut tx);
+            receiver.set_config(XmodemConfig::new().byte_timeout(Some(100)));
+            receiver.set_crc(true);
+            receiver.enable_timeouts();
+            receiver.receive_into(&mut output)
+        };
+
+        (result, output, tx.2)
+    });
+
+    // The rest of the packet turns up after the receiver has timed out. It is
+    // thrown away before the packet is asked for again.
+    let packet = crc_packet(1, &[5; 128]);
+    let mut reply = [0u8; 1];
+    rx.read_exact(&mut reply).expect("read okay");
+    rx.write_all(&packet[..3]).expect("write okay");
+    std::thread::sleep(Duration::from_millis(150));
+    rx.write_all(&packet[3..]).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], NAK);
+
+    rx.write_all(&packet).expect("write okay");
+    rx.read_exact(&mut reply).expect("read okay");
+    assert_eq!(reply[0], ACK);
+    rx.write_all(&[EOT, EOT]).expect("write okay");
+
+    let (result, output, written) = rx_thread.join().expect("rx join okay");
+    assert_eq!(result.expect("receive okay"), 128);
+    assert_eq!(output, vec![5; 128]);
+    assert_eq!(&written[..], &[CRC, NAK, ACK, NAK, ACK]);
+}
+
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+fn count_bytes(progress: Progress) {
+    if let Progress::Bytes(bytes) = progress {
+        BYTES.store(bytes, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_bytes_progress() {
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || Xmodem::transmit(&pattern(3000)[..], rx));
+    let mut output = Vec::new();
+    Xmodem::receive_with_progress(tx, &mut output, count_bytes).expect("receive okay");
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 3000);
+    assert_eq!(BYTES.load(Ordering::SeqCst), output.len());
+    assert_eq!(output.len(), 3072);
+}
+
+/// A writer whose bytes can be read back after it has been moved away.
+#[derive(Clone)]
+struct SharedBuf(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ymodem_header() {
+    let mut info = FileInfo::new("kernel.bin", 3000);
+    info.mtime = Some(0o13714573461);
+    let header = info.encode().expect("encode");
+    assert_eq!(&header[..], &b"kernel.bin\x003000 13714573461\x00"[..]);
+    assert_eq!(FileInfo::decode(&header).expect("decode"), Some(info));
+
+    let mut header = b"dir/a.txt\x0012 0 100644 0\x00".to_vec();
+    header.resize(128, 0);
+    let info = FileInfo::decode(&header).expect("decode").expect("a file");
+    assert_eq!(info, FileInfo { name: "dir/a.txt".to_string(), len: Some(12), mtime: Some(0) });
+
+    let info = FileInfo::decode(&b"bare\x00\x00"[..]).expect("decode").expect("a file");
+    assert_eq!((info.len, info.mtime), (None, None));
+
+    assert_eq!(FileInfo::decode(&[0; 128]).expect("decode"), None);
+    let e = FileInfo::decode(&b"a\x00ten\x00"[..]).expect_err("bad length");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let e = FileInfo::new("a\0b", 1).encode().expect_err("NUL in name");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_ymodem_batch() {
+    let mut first = FileInfo::new("first.bin", 3000);
+    first.mtime = Some(1_500_000_000);
+    let files = vec![
+        (first, pattern(3000)),
+        (FileInfo::new("empty", 0), vec![]),
+        (FileInfo::new("last.txt", 5), b"hello".to_vec()),
+    ];
+
+    let expected = files.clone();
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let files = files.into_iter().map(|(info, data)| (info, Cursor::new(data)));
+        Ymodem::transmit(files, rx)
+    });
+
+    let mut outputs = Vec::new();
+    let infos = Ymodem::receive(tx, |info| {
+        let output = SharedBuf(Default::default());
+        outputs.push((info.name.clone(), output.clone()));
+        Ok(output)
+    }).expect("receive okay");
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 3);
+    assert_eq!(infos, expected.iter().map(|f| f.0.clone()).collect::<Vec<_>>());
+    assert_eq!(outputs.len(), 3);
+    for ((name, output), (info, data)) in outputs.iter().zip(expected.iter()) {
+        assert_eq!(name, &info.name);
+        assert_eq!(&output.0.borrow()[..], &data[..]);
+    }
+}
+
+#[test]
+fn test_ymodem_raw_transmission() {
+    let (mut tx, mut rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let files = vec![(FileInfo::new("a", 3), &b"abc"[..])];
+        Ymodem::transmit(files, &mut rx).expect("transmit okay");
+        rx.2
+    });
+
+    let rx_thread = std::thread::spawn(move || {
+        Ymodem::receive(&mut tx, |_| Ok(Vec::new())).expect("receive okay");
+        tx.2
+    });
+
+    let transmitted = tx_thread.join().expect("tx join okay");
+    let responses = rx_thread.join().expect("rx join okay");
+
+    // The header, the data, EOT, and the empty header.
+    assert_eq!(&transmitted[0..3], &[SOH, 0, 255]);
+    assert_eq!(&transmitted[3..8], &b"a\x003\x00\x00"[..]);
+    assert_eq!(&transmitted[133..136], &[SOH, 1, 254]);
+    assert_eq!(&transmitted[136..139], &b"abc"[..]);
+    assert_eq!(&transmitted[266..268], &[EOT, EOT]);
+    assert_eq!(&transmitted[268..271], &[SOH, 0, 255]);
+    assert!(transmitted[271..399].iter().all(|&b| b == 0));
+    assert_eq!(transmitted.len(), 401);
+
+    assert_eq!(&responses, &[CRC, ACK, CRC, ACK, NAK, ACK, CRC, ACK]);
+}
+
+#[test]
+fn test_ymodem_open_error_cancels() {
+    let (tx, rx) = pipe();
+    let tx_thread = std::thread::spawn(move || {
+        let files = vec![(FileInfo::new("a", 3), &b"abc"[..])];
+        Ymodem::transmit(files, rx)
+    });
+
+    let e = Ymodem::receive(tx, |_| -> io::Result<Vec<u8>> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only"))
+    }).expect_err("open fails");
+
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = tx_thread.join().expect("tx join okay").expect_err("cancelled");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+}
+
+/// One end of a duplex link that damages what is written to it: each byte is
+/// dropped, or corrupted, with a chance of `loss` in a million. Reads time
+/// out after 20 ms. If `cut_after` is set, the link hangs up once that many
+/// bytes have been written.
+struct Link {
+    tx: Option<Sender<u8>>,
+    rx: Receiver<u8>,
+    written: usize,
+    loss: u64,
+    seed: u64,
+    cut_after: Option<usize>,
+    damaged: usize,
+}
+
+fn link(loss: u64) -> (Link, Link) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    let end = |tx, rx, seed| {
+        Link { tx: Some(tx), rx, written: 0, loss, seed, cut_after: None, damaged: 0 }
+    };
+
+    (end(tx1, rx2, 0x2545_F491_4F6C_DD1D), end(tx2, rx1, 0x9E37_79B9_7F4A_7C15))
+}
+
+impl Link {
+    /// Returns the next number from a xorshift generator, so that every run
+    /// damages the same bytes.
+    fn random(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+impl io::Read for Link {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        match self.rx.recv_timeout(Duration::from_millis(20)) {
+            Ok(byte) => buf[0] = byte,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "link timed out"));
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(0)
+        }
+
+        let mut n = 1;
+        while n < buf.len() {
+            match self.rx.try_recv() {
+                Ok(byte) => buf[n] = byte,
+                Err(_) => break
+            }
+
+            n += 1;
+        }
+
+        Ok(n)
+    }
+}
+
+impl io::Write for Link {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if Some(self.written) == self.cut_after {
+                self.tx = None;
+            }
+
+            let tx = match self.tx {
+                Some(ref tx) => tx.clone(),
+                None => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "link is down"))
+            };
+
+            self.written += 1;
+            let chance = self.random() % 1_000_000;
+            let byte = if chance < self.loss {
+                self.damaged += 1;
+                continue;
+            } else if chance < 2 * self.loss {
+                self.damaged += 1;
+                byte ^ 0x55
+            } else {
+                byte
+            };
+
+            tx.send(byte).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "link is down"))?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Transfers `input` as a file named "data" over `link`, into `output` which
+/// already holds its first `offset` bytes. Returns the results of each end
+/// and the ends themselves.
+fn zmodem_transfer(input: Vec<u8>, (tx, rx): (Link, Link), output: &mut Vec<u8>, offset: u64)
+    -> (io::Result<u64>, io::Result<FileInfo>, Link, Link)
+{
+    let tx_thread = std::thread::spawn(move || {
+        let mut rx = rx;
+        let result = Zmodem::transmit("data", Cursor::new(input), &mut rx);
+        (result, rx)
+    });
+
+    let mut tx = tx;
+    let received = Zmodem::new(&mut tx).receive_into(&mut *output, offset);
+    let (sent, rx) = tx_thread.join().expect("tx join okay");
+    (sent, received, tx, rx)
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn test_zmodem_transmission() {
+    let input = pattern(10_000);
+    let mut output = Vec::new();
+    let (sent, received, _, _) = zmodem_transfer(input.clone(), link(0), &mut output, 0);
+
+    assert_eq!(sent.expect("transmit okay"), 10_000);
+    assert_eq!(received.expect("receive okay"), FileInfo::new("data", 10_000));
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_zmodem_empty_file() {
+    let mut output = Vec::new();
+    let (sent, received, _, _) = zmodem_transfer(Vec::new(), link(0), &mut output, 0);
+
+    assert_eq!(sent.expect("transmit okay"), 0);
+    assert_eq!(received.expect("receive okay"), FileInfo::new("data", 0));
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_zmodem_lossy_link() {
+    let input = pattern(64 * 1024);
+    let mut output = Vec::new();
+    let (sent, received, _, rx) = zmodem_transfer(input.clone(), link(100), &mut output, 0);
+
+    sent.expect("transmit okay");
+    received.expect("receive okay");
+    assert!(rx.damaged > 0);
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_zmodem_resume() {
+    let input = pattern(20_000);
+    let mut output = Vec::new();
+
+    // The link drops halfway through the data.
+    let (tx, mut rx) = link(0);
+    rx.cut_after = Some(10_000);
+    let (sent, received, _, _) = zmodem_transfer(input.clone(), (tx, rx), &mut output, 0);
+    sent.expect_err("link dropped");
+    received.expect_err("link dropped");
+    assert!(!output.is_empty() && output.len() < input.len());
+    assert_eq!(&output[..], &input[..output.len()]);
+
+    // Only the rest is sent again.
+    let offset = output.len();
+    let (sent, received, _, rx) = zmodem_transfer(input.clone(), link(0), &mut output, offset as u64);
+    assert_eq!(sent.expect("transmit okay"), 20_000);
+    received.expect("receive okay");
+    assert!(rx.written < input.len() - offset + 1024);
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_zmodem_window_and_rpos() {
+    let (mut tx, rx) = link(0);
+    let tx_thread = std::thread::spawn(move || {
+        Zmodem::transmit("data", Cursor::new(pattern(10_000)), rx)
+    });
+
+    let mut receiver = Zmodem::new(&mut tx);
+    receiver.write_frame(zmodem::ZRINIT, 4096, &[]).expect("write okay");
+    let file = receiver.read_frame().expect("read okay");
+    assert_eq!((file.kind, file.offset, &file.data[..]), (zmodem::ZFILE, 10_000, &b"data"[..]));
+    receiver.write_frame(zmodem::ZRPOS, 0, &[]).expect("write okay");
+
+    // A window's worth is sent without acknowledgements. Then nothing is,
+    // until the transmitter times out and sends it again.
+    for offset in (0..4096).step_by(1024).chain(Some(0)) {
+        let frame = loop {
+            match receiver.read_frame() {
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                result => break result.expect("read okay")
+            }
+        };
+
+        assert_eq!((frame.kind, frame.offset, frame.data.len()), (zmodem::ZDATA, offset, 1024));
+    }
+
+    // Asking for an offset resumes from there.
+    receiver.write_frame(zmodem::ZRPOS, 1024, &[]).expect("write okay");
+    let frame = receiver.read_frame().expect("read okay");
+    assert_eq!((frame.kind, frame.offset), (zmodem::ZDATA, 1024));
+    assert_eq!(&frame.data[..], &pattern(10_000)[1024..2048]);
+
+    receiver.cancel();
+    let e = tx_thread.join().expect("tx join okay").expect_err("cancelled");
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+}
+
+#[test]
+fn test_zmodem_stale_frame() {
+    let input = pattern(4096);
+    let (tx, mut rx) = link(0);
+    let rx_thread = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let received = Zmodem::new(tx).receive_into(&mut output, 0);
+        (received, output)
+    });
+
+    // Reads frames until one of type `kind` arrives, skipping `ZACK`s.
+    let mut transmitter = Zmodem::new(&mut rx);
+    let expect = |transmitter: &mut Zmodem<&mut Link>, kind: u8| loop {
+        match transmitter.read_frame() {
+            Ok(ref frame) if frame.kind != kind => continue,
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            result => break result.expect("read okay")
+        }
+    };
+
+    expect(&mut transmitter, zmodem::ZRINIT);
+    transmitter.write_frame(zmodem::ZFILE, 4096, b"data").expect("write okay");
+    assert_eq!(expect(&mut transmitter, zmodem::ZRPOS).offset, 0);
+    for offset in (0..3072).step_by(1024) {
+        let data = &input[offset..offset + 1024];
+        transmitter.write_frame(zmodem::ZDATA, offset as u32, data).expect("write okay");
+    }
+
+    // A frame the receiver has already moved past is answered with where it
+    // is, not written again.
+    transmitter.write_frame(zmodem::ZDATA, 0, &input[..1024]).expect("write okay");
+    assert_eq!(expect(&mut transmitter, zmodem::ZRPOS).offset, 3072);
+
+    transmitter.write_frame(zmodem::ZDATA, 3072, &input[3072..]).expect("write okay");
+    transmitter.write_frame(zmodem::ZFIN, 0, &[]).expect("write okay");
+    expect(&mut transmitter, zmodem::ZFIN);
+
+    let (received, output) = rx_thread.join().expect("rx join okay");
+    assert_eq!(received.expect("receive okay"), FileInfo::new("data", 4096));
+    assert_eq!(output, input);
+}
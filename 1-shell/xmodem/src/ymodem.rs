@@ -0,0 +1,260 @@
+use std::io;
+use std::str;
+
+use progress::{self, ProgressFn};
+use {Xmodem, CAN};
+
+/// The metadata of a file sent with YMODEM, carried by the header packet that
+/// precedes the file's data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    /// The file's name, as sent. It may contain `/`-separated directories.
+    pub name: String,
+    /// The file's exact length in bytes, if the sender gave it.
+    pub len: Option<u64>,
+    /// The file's modification time in seconds since the Unix epoch, if the
+    /// sender gave it.
+    pub mtime: Option<u64>,
+}
+
+impl FileInfo {
+    /// Returns the metadata of a file named `name` that is `len` bytes long,
+    /// with no modification time.
+    pub fn new(name: &str, len: u64) -> FileInfo {
+        FileInfo { name: name.to_string(), len: Some(len), mtime: None }
+    }
+
+    /// Returns the contents of the header packet for this file: the name and
+    /// a NUL, then the decimal length and the octal modification time
+    /// separated by a space, and a NUL.
+    pub(crate) fn encode(&self) -> io::Result<Vec<u8>> {
+        if self.name.is_empty() || self.name.contains('\0') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"));
+        }
+
+        let mut header = self.name.clone().into_bytes();
+        header.push(0);
+        if let Some(len) = self.len {
+            header.extend(len.to_string().bytes());
+            if let Some(mtime) = self.mtime {
+                header.extend(format!(" {:o}", mtime).bytes());
+            }
+        }
+
+        header.push(0);
+        Ok(header)
+    }
+
+    /// Parses the contents of a header packet. Returns `None` for the empty
+    /// header that ends a batch. Fields after the modification time, such as
+    /// the file's mode, are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the name is not UTF-8 or a field
+    /// is not a number.
+    pub(crate) fn decode(header: &[u8]) -> io::Result<Option<FileInfo>> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut fields = header.split(|&b| b == 0);
+        let name = match fields.next() {
+            Some(name) if !name.is_empty() => name,
+            _ => return Ok(None)
+        };
+
+        let name = str::from_utf8(name).map_err(|_| invalid("file name is not UTF-8"))?;
+        let rest = fields.next().unwrap_or(&[]);
+        let rest = str::from_utf8(rest).map_err(|_| invalid("file length is not a number"))?;
+        let mut numbers = rest.split_whitespace();
+
+        let len = match numbers.next() {
+            Some(len) => Some(len.parse().map_err(|_| invalid("file length is not a number"))?),
+            None => None
+        };
+
+        let mtime = match numbers.next() {
+            Some(mtime) => {
+                Some(u64::from_str_radix(mtime, 8).map_err(|_| invalid("file time is not a number"))?)
+            }
+            None => None
+        };
+
+        Ok(Some(FileInfo { name: name.to_string(), len, mtime }))
+    }
+}
+
+/// Writes to `inner` until `remaining` bytes have been written, then discards
+/// the rest: the padding of the last packet.
+struct Truncate<W> {
+    inner: W,
+    remaining: Option<u64>,
+}
+
+impl<W: io::Write> io::Write for Truncate<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let keep = match self.remaining {
+            Some(remaining) if remaining < buf.len() as u64 => remaining as usize,
+            _ => buf.len()
+        };
+
+        self.inner.write_all(&buf[..keep])?;
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= keep as u64;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Implementation of the YMODEM batch protocol on top of `Xmodem`.
+///
+/// Each file is preceded by a header packet, numbered 0, that carries its
+/// name, length and modification time. The receiver asks for the header, and
+/// again for the data, with `C`. The data is then sent as with XMODEM. After
+/// the last file, an empty header ends the batch.
+pub struct Ymodem;
+
+impl Ymodem {
+    /// Transmits each file in `files`, its metadata and a reader for its
+    /// data, to the receiver `to` using the YMODEM protocol.
+    ///
+    /// Returns the number of files sent.
+    #[inline]
+    pub fn transmit<I, R, W>(files: I, to: W) -> io::Result<usize>
+        where I: IntoIterator<Item = (FileInfo, R)>, R: io::Read, W: io::Read + io::Write
+    {
+        Ymodem::transmit_with_progress(files, to, progress::noop)
+    }
+
+    /// Transmits each file in `files`, its metadata and a reader for its
+    /// data, to the receiver `to` using the YMODEM protocol.
+    ///
+    /// The function `f` is used as a callback to indicate progress throughout
+    /// the transmission. See the [`Progress`] enum for more information.
+    /// Header packets are reported as packet 0.
+    ///
+    /// Returns the number of files sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if a file's name is empty, contains
+    /// a NUL, or makes its header longer than 1024 bytes, or longer than 128
+    /// bytes when the receiver did not ask for CRC-16. Otherwise returns the
+    /// errors of `Xmodem::transmit`.
+    pub fn transmit_with_progress<I, R, W>(files: I, to: W, f: ProgressFn) -> io::Result<usize>
+        where I: IntoIterator<Item = (FileInfo, R)>, R: io::Read, W: io::Read + io::Write
+    {
+        let mut transmitter = Xmodem::new_with_progress(to, f);
+        let mut sent = 0;
+        for (info, data) in files {
+            write_header(&mut transmitter, Some(&info))?;
+            transmitter.transmit_from(data)?;
+            sent += 1;
+        }
+
+        write_header(&mut transmitter, None)?;
+        Ok(sent)
+    }
+
+    /// Receives a batch of files from `from` using the YMODEM protocol. For
+    /// each file, `open` is called with its metadata and returns the writer
+    /// its data is written into. The padding of the last packet is not
+    /// written if the sender gave the file's length.
+    ///
+    /// Returns the metadata of the files received, in order.
+    #[inline]
+    pub fn receive<R, W, F>(from: R, open: F) -> io::Result<Vec<FileInfo>>
+        where R: io::Read + io::Write, W: io::Write, F: FnMut(&FileInfo) -> io::Result<W>
+    {
+        Ymodem::receive_with_progress(from, open, progress::noop)
+    }
+
+    /// Receives a batch of files from `from` using the YMODEM protocol. For
+    /// each file, `open` is called with its metadata and returns the writer
+    /// its data is written into. The padding of the last packet is not
+    /// written if the sender gave the file's length.
+    ///
+    /// The function `f` is used as a callback to indicate progress throughout
+    /// the reception. See the [`Progress`] enum for more information.
+    /// Header packets are reported as packet 0.
+    ///
+    /// Returns the metadata of the files received, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if a header can't be parsed, and the
+    /// error of `open` if it fails, in which case the transfer is cancelled.
+    /// Otherwise returns the errors of `Xmodem::receive`.
+    pub fn receive_with_progress<R, W, F>(from: R, mut open: F, f: ProgressFn)
+        -> io::Result<Vec<FileInfo>>
+        where R: io::Read + io::Write, W: io::Write, F: FnMut(&FileInfo) -> io::Result<W>
+    {
+        // Headers may take a 1024-byte packet, so CRC-16 is always asked for.
+        let mut receiver = Xmodem::new_with_progress(from, f);
+        receiver.set_crc(true);
+        let mut files = Vec::new();
+        let mut header = [0u8; 1024];
+        loop {
+            receiver.started = false;
+            receiver.packet = 0;
+            let n = receiver.read_packet_with_retries(&mut header)?;
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a header, got EOT"));
+            }
+
+            let info = match FileInfo::decode(&header[..n])? {
+                Some(info) => info,
+                None => return Ok(files)
+            };
+
+            let into = match open(&info) {
+                Ok(into) => into,
+                Err(e) => {
+                    // The transmitter may hang up after the first `CAN`, and
+                    // the error worth reporting is `open`'s in any case.
+                    let _ = receiver.inner.write_all(&[CAN, CAN]);
+                    return Err(e);
+                }
+            };
+
+            receiver.started = false;
+            receiver.receive_into(Truncate { inner: into, remaining: info.len })?;
+            files.push(info);
+        }
+    }
+}
+
+/// Waits for the receiver's `C` and sends the header packet for the file
+/// described by `info`, or the empty header that ends the batch if `info` is
+/// `None`.
+fn write_header<T: io::Read + io::Write>(transmitter: &mut Xmodem<T>, info: Option<&FileInfo>)
+    -> io::Result<()>
+{
+    let mut header = match info {
+        Some(info) => info.encode()?,
+        None => Vec::new()
+    };
+
+    if header.len() > 1024 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "file name is too long"));
+    }
+
+    transmitter.started = false;
+    transmitter.packet = 0;
+    transmitter.start_transmit()?;
+    if header.len() > 128 && !transmitter.crc {
+        transmitter.cancel()?;
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "file name is too long"));
+    }
+
+    let size = if header.len() > 128 { 1024 } else { 128 };
+    header.resize(size, 0);
+    transmitter.write_packet_with_retries(&header)?;
+
+    // The receiver asks for the data with another `C`.
+    transmitter.started = false;
+    Ok(())
+}
@@ -0,0 +1,533 @@
+use std::cmp;
+use std::io;
+
+use progress::{self, Progress, ProgressFn};
+use read_ext::ReadExt;
+use {FileInfo, CAN};
+
+/// Escapes bytes within a frame, and starts and ends frames. It is the same
+/// byte as `CAN`, so a run of them cancels the transfer.
+const ZDLE: u8 = CAN;
+/// Follows `ZDLE` to start a frame.
+const FRAME_START: u8 = b'C';
+/// Follows `ZDLE` to end a frame.
+const FRAME_END: u8 = b'k';
+/// Follows `ZDLE` for a `ZDLE` within a frame.
+const ESCAPED_ZDLE: u8 = ZDLE ^ 0x40;
+/// The number of consecutive `CAN` bytes that cancel a transfer. A cancel
+/// is sent with a few more, as `ZDLE` is `CAN`.
+const CANCEL_LEN: usize = 5;
+
+// Frame types, numbered as in ZMODEM.
+pub(crate) const ZRINIT: u8 = 1;
+const ZACK: u8 = 3;
+pub(crate) const ZFILE: u8 = 4;
+pub(crate) const ZFIN: u8 = 8;
+pub(crate) const ZRPOS: u8 = 9;
+pub(crate) const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+
+/// The largest amount of data carried by a single `ZDATA` frame.
+const MAX_DATA: usize = 1024;
+/// The largest frame: a type, an offset, the data and a CRC-32.
+const MAX_FRAME: usize = 1 + 4 + MAX_DATA + 4;
+/// The largest file that offsets can describe.
+const MAX_LEN: u64 = 0xFFFF_FFFF;
+/// The window a receiver advertises by default, in bytes.
+const DEFAULT_WINDOW: u32 = 8 * 1024;
+/// The number of times in a row a side times out or is ignored before giving
+/// up on the transfer.
+const RETRIES: usize = 10;
+
+/// Returns the CRC-32 of `data` used by ZMODEM, the same as Ethernet's and
+/// zlib's: reflected polynomial 0xEDB88320 with all bits set initially and
+/// inverted at the end.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 }
+        })
+    })
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend((0..4).map(|i| (value >> (8 * i)) as u8));
+}
+
+fn get_u32(buf: &[u8]) -> u32 {
+    buf[..4].iter().rev().fold(0, |value, &b| (value << 8) | b as u32)
+}
+
+fn timed_out(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, msg)
+}
+
+/// A frame whose CRC-32 checked out: its type, the offset or other number in
+/// its header, and the data that follows.
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub(crate) kind: u8,
+    pub(crate) offset: u32,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Implementation of a streaming protocol modelled on ZMODEM.
+///
+/// Every message is a binary frame checked with a CRC-32. The receiver starts
+/// with `ZRINIT`, advertising its window; the transmitter describes the file
+/// with `ZFILE`, and the receiver answers with `ZRPOS` and the offset to
+/// start from, which is how an interrupted transfer is resumed. The
+/// transmitter then streams `ZDATA` frames, each carrying its offset in the
+/// file, without waiting for acknowledgements until a window's worth is
+/// unacknowledged. The receiver acknowledges with `ZACK` as data arrives, and
+/// answers damaged or missing data with `ZRPOS` and the offset it needs. The
+/// transmitter ends the file with `ZEOF` and the session with `ZFIN`.
+///
+/// Recovery relies on reads from the inner stream timing out with an error
+/// of `TimedOut`, as a lost frame is otherwise never noticed.
+pub struct Zmodem<T> {
+    inner: T,
+    window: u32,
+    frames: u8,
+    progress: ProgressFn,
+}
+
+impl Zmodem<()> {
+    /// Transmits `data`, the contents of a file named `name`, to the receiver
+    /// `to`, from whichever offset the receiver asks for.
+    ///
+    /// Returns the length of the file.
+    #[inline]
+    pub fn transmit<R, W>(name: &str, data: R, to: W) -> io::Result<u64>
+        where R: io::Read + io::Seek, W: io::Read + io::Write
+    {
+        Zmodem::transmit_with_progress(name, data, to, progress::noop)
+    }
+
+    /// Transmits `data`, the contents of a file named `name`, to the receiver
+    /// `to`, from whichever offset the receiver asks for.
+    ///
+    /// The function `f` is used as a callback to indicate progress throughout
+    /// the transmission. See the [`Progress`] enum for more information.
+    /// Every `ZDATA` frame sent is reported as a packet.
+    ///
+    /// Returns the length of the file.
+    pub fn transmit_with_progress<R, W>(name: &str, data: R, to: W, f: ProgressFn)
+        -> io::Result<u64>
+        where R: io::Read + io::Seek, W: io::Read + io::Write
+    {
+        Zmodem::new_with_progress(to, f).transmit_from(name, data)
+    }
+
+    /// Receives a file from `from` and writes it into `into`.
+    ///
+    /// Returns the metadata the transmitter gave for the file.
+    #[inline]
+    pub fn receive<R, W>(from: R, into: W) -> io::Result<FileInfo>
+        where R: io::Read + io::Write, W: io::Write
+    {
+        Zmodem::receive_with_progress(from, into, progress::noop)
+    }
+
+    /// Receives a file from `from` and writes it into `into`.
+    ///
+    /// The function `f` is used as a callback to indicate progress throughout
+    /// the reception. See the [`Progress`] enum for more information. Every
+    /// `ZDATA` frame written into `into` is reported as a packet.
+    ///
+    /// Returns the metadata the transmitter gave for the file.
+    pub fn receive_with_progress<R, W>(from: R, into: W, f: ProgressFn) -> io::Result<FileInfo>
+        where R: io::Read + io::Write, W: io::Write
+    {
+        Zmodem::new_with_progress(from, f).receive_into(into, 0)
+    }
+}
+
+impl<T: io::Read + io::Write> Zmodem<T> {
+    /// Returns a new `Zmodem` instance with the internal reader/writer set to
+    /// `inner`. The returned instance can be used for both receiving and
+    /// sending.
+    pub fn new(inner: T) -> Self {
+        Zmodem::new_with_progress(inner, progress::noop)
+    }
+
+    /// Returns a new `Zmodem` instance with the internal reader/writer set to
+    /// `inner`. The function `f` is used as a callback to indicate progress
+    /// throughout the transfer. See the [`Progress`] enum for more
+    /// information.
+    pub fn new_with_progress(inner: T, f: ProgressFn) -> Self {
+        Zmodem { inner, window: DEFAULT_WINDOW, frames: 0, progress: f }
+    }
+
+    /// Sets the window a receiver advertises: how many bytes the transmitter
+    /// may send before waiting for an acknowledgement. It is 8 KiB by
+    /// default, and at least one frame's worth. Has no effect on a
+    /// transmitter, which uses the receiver's.
+    pub fn set_window(&mut self, bytes: u32) {
+        self.window = cmp::max(bytes, MAX_DATA as u32);
+    }
+
+    /// Transmits `data`, the contents of a file named `name`, to the inner
+    /// stream, as `Zmodem::transmit` does. Returns the length of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `name` is empty or longer than
+    /// 1024 bytes, or the file is 4 GiB or longer, of `TimedOut` if the
+    /// receiver stops responding, and of `ConnectionAborted` if it cancels.
+    /// Errors reading `data` cancel the transfer.
+    pub fn transmit_from<R>(&mut self, name: &str, mut data: R) -> io::Result<u64>
+        where R: io::Read + io::Seek
+    {
+        if name.is_empty() || name.len() > MAX_DATA {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"));
+        }
+
+        let len = data.seek(io::SeekFrom::End(0))?;
+        if len > MAX_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file is too large"));
+        }
+
+        let len = len as u32;
+        (self.progress)(Progress::Waiting);
+        let window = self.wait_for(ZRINIT, |_| Ok(()))?.offset;
+
+        // Describe the file until the receiver says where to start.
+        self.write_frame(ZFILE, len, name.as_bytes())?;
+        let start = self.wait_for(ZRPOS, |this| this.write_frame(ZFILE, len, name.as_bytes()))?;
+        if start.offset > len {
+            self.cancel();
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "receiver asked for a bad offset"));
+        }
+
+        let mut buf = [0u8; MAX_DATA];
+        let (mut pos, mut acked) = (start.offset, start.offset);
+        let mut eof_sent = false;
+        let mut retries = 0;
+        data.seek(io::SeekFrom::Start(pos as u64))?;
+        loop {
+            if pos < len && pos - acked < window {
+                let size = cmp::min(MAX_DATA as u32, len - pos) as usize;
+                let n = match data.read_max(&mut buf[..size]) {
+                    Ok(n) if n == size => n,
+                    Ok(_) => {
+                        self.cancel();
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank"));
+                    }
+                    Err(e) => {
+                        self.cancel();
+                        return Err(e);
+                    }
+                };
+
+                self.write_frame(ZDATA, pos, &buf[..n])?;
+                (self.progress)(Progress::Packet(self.frames));
+                self.frames = self.frames.wrapping_add(1);
+                pos += n as u32;
+                continue;
+            }
+
+            if acked == len {
+                break;
+            }
+
+            if pos == len && !eof_sent {
+                self.write_frame(ZEOF, len, &[])?;
+                eof_sent = true;
+            }
+
+            // The window is full or the file has been sent: wait for the
+            // receiver to acknowledge data or to ask for some again.
+            let rewind = match self.read_frame() {
+                Ok(Frame { kind: ZACK, offset, .. }) if offset > acked && offset <= len => {
+                    acked = offset;
+                    retries = 0;
+                    if offset > pos { Some(offset) } else { None }
+                }
+                // A request for less than has been acknowledged is stale.
+                Ok(Frame { kind: ZRPOS, offset, .. }) if offset >= acked && offset <= len => {
+                    if offset > acked {
+                        retries = 0;
+                    }
+
+                    acked = offset;
+                    Some(offset)
+                }
+                Ok(_) => None,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => None,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    retries += 1;
+                    if retries >= RETRIES {
+                        self.cancel();
+                        return Err(timed_out("receiver stopped responding"));
+                    }
+
+                    Some(acked)
+                }
+                Err(e) => return Err(e)
+            };
+
+            if let Some(offset) = rewind {
+                pos = offset;
+                eof_sent = false;
+                data.seek(io::SeekFrom::Start(pos as u64))?;
+            }
+        }
+
+        // All of the data has been acknowledged, so the transfer has
+        // succeeded even if the receiver's `ZFIN` never arrives.
+        self.write_frame(ZFIN, 0, &[])?;
+        let _ = self.wait_for(ZFIN, |this| this.write_frame(ZFIN, 0, &[]));
+        Ok(len as u64)
+    }
+
+    /// Receives a file from the inner stream and writes it into `into`, as
+    /// `Zmodem::receive` does. If `offset` is not zero, `into` already holds
+    /// the first `offset` bytes of the file from an interrupted transfer and
+    /// the transmitter is asked for the rest. Returns the metadata the
+    /// transmitter gave for the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the file is shorter than `offset`
+    /// or its name is not UTF-8, of `TimedOut` if the transmitter stops
+    /// responding, and of `ConnectionAborted` if it cancels. Errors writing
+    /// into `into` cancel the transfer. Whatever was written into `into` is
+    /// the start of the file, so a failed transfer can be resumed from there.
+    pub fn receive_into<W: io::Write>(&mut self, mut into: W, offset: u64) -> io::Result<FileInfo> {
+        if offset > MAX_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "offset is too large"));
+        }
+
+        let window = self.window;
+        let mut info: Option<FileInfo> = None;
+        let (mut pos, mut acked) = (offset as u32, offset as u32);
+        // Whether a `ZRPOS` has been sent that the transmitter has yet to
+        // act on, so that frames sent before it are dropped quietly.
+        let mut rewinding = false;
+        let mut retries = 0;
+
+        self.write_frame(ZRINIT, window, &[])?;
+        loop {
+            let frame = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                    if info.is_some() && !rewinding {
+                        self.write_frame(ZRPOS, pos, &[])?;
+                        rewinding = true;
+                    }
+
+                    continue;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    retries += 1;
+                    if retries >= RETRIES {
+                        self.cancel();
+                        return Err(timed_out("transmitter stopped responding"));
+                    }
+
+                    match info {
+                        Some(_) => self.write_frame(ZRPOS, pos, &[])?,
+                        None => self.write_frame(ZRINIT, window, &[])?
+                    }
+
+                    continue;
+                }
+                Err(e) => return Err(e)
+            };
+
+            if frame.kind == ZFILE && info.is_none() {
+                let name = String::from_utf8(frame.data).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "file name is not UTF-8")
+                })?;
+
+                if pos > frame.offset {
+                    self.cancel();
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              "file is shorter than the offset"));
+                }
+
+                info = Some(FileInfo::new(&name, frame.offset as u64));
+                (self.progress)(Progress::Started);
+                self.write_frame(ZRPOS, pos, &[])?;
+                retries = 0;
+                continue;
+            }
+
+            // Until the file has been described, only a `ZFILE` will do.
+            let len = match info {
+                Some(ref info) => info.len.unwrap_or(0) as u32,
+                None => continue
+            };
+
+            match frame.kind {
+                // The transmitter missed the first `ZRPOS`.
+                ZFILE => self.write_frame(ZRPOS, pos, &[])?,
+                ZDATA => {
+                    let end = frame.offset as u64 + frame.data.len() as u64;
+                    if frame.offset > pos || end > len as u64 {
+                        if !rewinding {
+                            self.write_frame(ZRPOS, pos, &[])?;
+                            rewinding = true;
+                        }
+
+                        continue;
+                    }
+
+                    if end <= pos as u64 {
+                        // The transmitter is behind, or this is a stale
+                        // frame sent again: tell it how far along this end
+                        // is.
+                        if !rewinding {
+                            self.write_frame(ZRPOS, pos, &[])?;
+                            rewinding = true;
+                        }
+
+                        continue;
+                    }
+
+                    // Data sent again after a rewind may overlap what has
+                    // already been written.
+                    let new = &frame.data[(pos - frame.offset) as usize..];
+                    if let Err(e) = into.write_all(new) {
+                        self.cancel();
+                        return Err(e);
+                    }
+
+                    (self.progress)(Progress::Packet(self.frames));
+                    self.frames = self.frames.wrapping_add(1);
+                    pos += new.len() as u32;
+                    rewinding = false;
+                    retries = 0;
+                    if pos - acked >= window / 4 || pos == len {
+                        self.write_frame(ZACK, pos, &[])?;
+                        acked = pos;
+                    }
+                }
+                ZEOF if pos == len => self.write_frame(ZACK, pos, &[])?,
+                ZEOF => self.write_frame(ZRPOS, pos, &[])?,
+                ZFIN if pos == len => {
+                    into.flush()?;
+                    self.write_frame(ZFIN, 0, &[])?;
+                    return Ok(info.unwrap());
+                }
+                ZFIN => self.write_frame(ZRPOS, pos, &[])?,
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads frames until one of type `kind` arrives and returns it. Each
+    /// time a read times out, `again` is called to prompt the other end.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `TimedOut` if `RETRIES` reads in a row time out
+    /// or bring the wrong frames.
+    fn wait_for<F>(&mut self, kind: u8, mut again: F) -> io::Result<Frame>
+        where F: FnMut(&mut Self) -> io::Result<()>
+    {
+        for _ in 0..RETRIES {
+            match self.read_frame() {
+                Ok(ref frame) if frame.kind != kind => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => again(self)?,
+                result => return result
+            }
+        }
+
+        Err(timed_out("the other end never responded"))
+    }
+
+    /// Writes a frame of type `kind` with `offset` in its header and `data`
+    /// after it, escaped and followed by its CRC-32.
+    pub(crate) fn write_frame(&mut self, kind: u8, offset: u32, data: &[u8]) -> io::Result<()> {
+        let mut raw = Vec::with_capacity(1 + 4 + data.len() + 4);
+        raw.push(kind);
+        put_u32(&mut raw, offset);
+        raw.extend_from_slice(data);
+        let crc = crc32(&raw);
+        put_u32(&mut raw, crc);
+
+        let mut frame = Vec::with_capacity(raw.len() + raw.len() / 8 + 4);
+        frame.extend_from_slice(&[ZDLE, FRAME_START]);
+        for &byte in &raw {
+            match byte {
+                ZDLE => frame.extend_from_slice(&[ZDLE, ESCAPED_ZDLE]),
+                byte => frame.push(byte)
+            }
+        }
+
+        frame.extend_from_slice(&[ZDLE, FRAME_END]);
+        self.inner.write_all(&frame)?;
+        self.inner.flush()
+    }
+
+    /// Skips to the start of the next frame, then reads the frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Interrupted` if the frame is damaged: its CRC-32
+    /// is wrong, it is malformed, or another frame starts within it. Returns
+    /// an error of `ConnectionAborted` if the other end cancels, and the
+    /// inner stream's errors, such as `TimedOut`, as they are.
+    pub(crate) fn read_frame(&mut self) -> io::Result<Frame> {
+        let damaged = |msg| Err(io::Error::new(io::ErrorKind::Interrupted, msg));
+        let mut cans = 0;
+        loop {
+            match self.read_byte()? {
+                ZDLE => {
+                    cans += 1;
+                    if cans == CANCEL_LEN {
+                        return Err(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                                  "received CAN"));
+                    }
+                }
+                FRAME_START if cans > 0 => break,
+                _ => cans = 0
+            }
+        }
+
+        let mut raw = Vec::with_capacity(MAX_FRAME);
+        loop {
+            match self.read_byte()? {
+                ZDLE => match self.read_byte()? {
+                    FRAME_END => break,
+                    ESCAPED_ZDLE => raw.push(ZDLE),
+                    // This frame lost its end, but the next may be whole.
+                    FRAME_START => raw.clear(),
+                    _ => return damaged("bad escape in frame")
+                },
+                byte => raw.push(byte)
+            }
+
+            if raw.len() > MAX_FRAME {
+                return damaged("frame is too long");
+            }
+        }
+
+        if raw.len() < 1 + 4 + 4 {
+            return damaged("frame is too short");
+        }
+
+        let (body, crc) = raw.split_at(raw.len() - 4);
+        if crc32(body) != get_u32(crc) {
+            return damaged("bad CRC-32");
+        }
+
+        Ok(Frame { kind: body[0], offset: get_u32(&body[1..]), data: body[5..].to_vec() })
+    }
+
+    /// Reads a single byte from the inner I/O stream.
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut buf = [0u8; 1];
+        self.inner.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Cancels the transfer. The other end may already have hung up, and the
+    /// error worth reporting is whatever caused the cancel in any case.
+    pub(crate) fn cancel(&mut self) {
+        let _ = self.inner.write_all(&[CAN; 8]);
+    }
+}
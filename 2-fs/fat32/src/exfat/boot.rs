@@ -0,0 +1,241 @@
+use std::{fmt, mem};
+
+use traits::BlockDevice;
+use exfat::Error;
+
+/// The number of sectors in a boot region, excluding its checksum sector.
+const BOOT_REGION_SECTORS: u64 = 11;
+
+/// Byte offsets in the main boot sector excluded from the boot checksum: the
+/// `VolumeFlags` and `PercentInUse` fields change without the checksum being
+/// rewritten.
+const CHECKSUM_SKIPPED: [usize; 3] = [106, 107, 112];
+
+#[repr(C, packed)]
+pub struct BootSector {
+    jump: [u8; 3],
+    file_system_name: [u8; 8],
+    must_be_zero: [u8; 53],
+    partition_offset: u64,
+    volume_length: u64,
+    fat_offset: u32,
+    fat_length: u32,
+    cluster_heap_offset: u32,
+    cluster_count: u32,
+    root_dir_cluster: u32,
+    serial_number: u32,
+    revision: u16,
+    volume_flags: u16,
+    bytes_per_sector_shift: u8,
+    sectors_per_cluster_shift: u8,
+    num_fats: u8,
+    drive_select: u8,
+    percent_in_use: u8,
+    reserved: [u8; 7],
+    boot_code: [u8; 390],
+    boot_signature: [u8; 2],
+}
+
+/// Computes the 32-bit exFAT checksum of `data`, ignoring the bytes at the
+/// indices in `skip`. Both the boot region and the up-case table use it.
+pub(crate) fn checksum(data: &[u8], skip: &[usize]) -> u32 {
+    let mut sum = 0u32;
+    for (i, &byte) in data.iter().enumerate() {
+        if skip.contains(&i) {
+            continue;
+        }
+
+        sum = sum.rotate_right(1).wrapping_add(byte as u32);
+    }
+
+    sum
+}
+
+impl BootSector {
+    /// Reads the main boot sector of the exFAT volume starting at sector
+    /// `sector` of `device` and verifies the checksum of its boot region. If
+    /// the main boot region is damaged, the backup boot region that follows it
+    /// is used instead.
+    ///
+    /// # Errors
+    ///
+    /// If the boot sector is not an exFAT boot sector, returns an error of
+    /// `BadSignature`. If neither boot region matches its checksum, returns an
+    /// error of `BadChecksum`.
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<BootSector, Error> {
+        let main = match BootSector::read_region(&mut device, sector) {
+            Err(Error::BadChecksum) => Error::BadChecksum,
+            Err(Error::BadSignature) => Error::BadSignature,
+            result => return result
+        };
+
+        // The backup region starts 12 logical sectors into the volume. The
+        // sector size may be unreadable from the main boot sector, so try
+        // each valid size and accept a backup that agrees with it.
+        let device_size = device.sector_size();
+        for shift in 9..13 {
+            let offset = (BOOT_REGION_SECTORS + 1) << shift;
+            if offset % device_size != 0 {
+                continue;
+            }
+
+            match BootSector::read_region(&mut device, sector + offset / device_size) {
+                Ok(boot) => if boot.bytes_per_sector_shift == shift {
+                    return Ok(boot);
+                },
+                Err(Error::Io(e)) => return Err(Error::Io(e)),
+                _ => {}
+            }
+        }
+
+        Err(main)
+    }
+
+    /// Reads and validates the single boot sector at `sector`.
+    fn read_sector<T: BlockDevice>(device: &mut T, sector: u64) -> Result<BootSector, Error> {
+        let mut buf = vec![0u8; device.sector_size() as usize];
+        device.read_sector(sector, &mut buf)?;
+
+        let mut raw = [0u8; 512];
+        raw.copy_from_slice(&buf[..512]);
+        let boot: BootSector = unsafe { mem::transmute(raw) };
+        if &boot.file_system_name != b"EXFAT   " || boot.boot_signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        if !boot.has_valid_geometry() {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(boot)
+    }
+
+    /// Reads the boot region starting at `sector` and checks it against its
+    /// checksum sector.
+    fn read_region<T: BlockDevice>(device: &mut T, sector: u64) -> Result<BootSector, Error> {
+        let boot = BootSector::read_sector(device, sector)?;
+        let sector_size = boot.bytes_per_sector() as usize;
+        let device_size = device.sector_size() as usize;
+        if sector_size < device_size {
+            return Err(Error::BadSignature);
+        }
+
+        let len = (BOOT_REGION_SECTORS as usize + 1) * sector_size;
+        let mut region = vec![0u8; len];
+        device.read_sectors(sector, (len / device_size) as u64, &mut region)?;
+
+        let (data, checksums) = region.split_at(BOOT_REGION_SECTORS as usize * sector_size);
+        let expected = checksum(data, &CHECKSUM_SKIPPED);
+        let valid = checksums.chunks(4).all(|bytes| {
+            bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16
+                | (bytes[3] as u32) << 24 == expected
+        });
+
+        match valid {
+            true => Ok(boot),
+            false => Err(Error::BadChecksum)
+        }
+    }
+
+    /// Returns `true` if the sector and cluster sizes and the layout of the
+    /// volume are within the limits allowed by the specification.
+    ///
+    /// This is checked before the boot region's checksum, so the arithmetic
+    /// is done in `u64` where any combination of on-disk values fits.
+    pub fn has_valid_geometry(&self) -> bool {
+        let bps_shift = self.bytes_per_sector_shift as u64;
+        let spc_shift = self.sectors_per_cluster_shift as u64;
+        let fats_end = self.fat_offset as u64 + self.fat_length as u64 * self.num_fats as u64;
+        bps_shift >= 9 && bps_shift <= 12
+            && bps_shift + spc_shift <= 25
+            && (self.num_fats == 1 || self.num_fats == 2)
+            && self.fat_offset >= 24
+            && self.fat_length > 0
+            && self.cluster_heap_offset as u64 >= fats_end
+            && self.cluster_count > 0
+            && self.root_dir_cluster >= 2
+            && (self.root_dir_cluster as u64) < self.cluster_count as u64 + 2
+    }
+
+    /// The number of bytes in a logical sector.
+    pub fn bytes_per_sector(&self) -> u64 {
+        1 << self.bytes_per_sector_shift
+    }
+
+    /// The number of logical sectors in a cluster.
+    pub fn sectors_per_cluster(&self) -> u64 {
+        1 << self.sectors_per_cluster_shift
+    }
+
+    /// The number of logical sectors in the volume.
+    pub fn volume_length(&self) -> u64 {
+        self.volume_length
+    }
+
+    /// The logical sector, relative to the start of the volume, of the first
+    /// FAT.
+    pub fn fat_offset(&self) -> u32 {
+        self.fat_offset
+    }
+
+    /// The number of logical sectors in each FAT.
+    pub fn fat_length(&self) -> u32 {
+        self.fat_length
+    }
+
+    /// The number of FATs: 1, or 2 for TexFAT volumes.
+    pub fn num_fats(&self) -> u8 {
+        self.num_fats
+    }
+
+    /// The index of the FAT and allocation bitmap in use.
+    pub fn active_fat(&self) -> u8 {
+        (self.volume_flags & 1) as u8
+    }
+
+    /// The logical sector, relative to the start of the volume, of cluster 2.
+    pub fn cluster_heap_offset(&self) -> u32 {
+        self.cluster_heap_offset
+    }
+
+    /// The number of clusters in the cluster heap.
+    pub fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+
+    /// The first cluster of the root directory.
+    pub fn root_dir_cluster(&self) -> u32 {
+        self.root_dir_cluster
+    }
+
+    /// The volume serial number.
+    pub fn serial_number(&self) -> u32 {
+        self.serial_number
+    }
+
+    /// The file system revision as a (major, minor) pair.
+    pub fn revision(&self) -> (u8, u8) {
+        ((self.revision >> 8) as u8, self.revision as u8)
+    }
+}
+
+impl fmt::Debug for BootSector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BootSector")
+            .field("partition_offset", &{ self.partition_offset })
+            .field("volume_length", &{ self.volume_length })
+            .field("fat_offset", &{ self.fat_offset })
+            .field("fat_length", &{ self.fat_length })
+            .field("cluster_heap_offset", &{ self.cluster_heap_offset })
+            .field("cluster_count", &{ self.cluster_count })
+            .field("root_dir_cluster", &{ self.root_dir_cluster })
+            .field("serial_number", &{ self.serial_number })
+            .field("revision", &self.revision())
+            .field("volume_flags", &{ self.volume_flags })
+            .field("bytes_per_sector", &self.bytes_per_sector())
+            .field("sectors_per_cluster", &self.sectors_per_cluster())
+            .field("num_fats", &{ self.num_fats })
+            .field("percent_in_use", &{ self.percent_in_use })
+            .finish()
+    }
+}
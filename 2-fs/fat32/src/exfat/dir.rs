@@ -0,0 +1,204 @@
+use std::ffi::OsStr;
+use std::io;
+use std::vec;
+
+use traits;
+use exfat::{ExFat, Shared, Stream, File, Entry, Metadata, Timestamp};
+use exfat::exfat::{read_u16, read_u32, read_u64};
+
+/// Directory entry types that make up a file's directory entry set.
+const ENTRY_END: u8 = 0x00;
+const ENTRY_FILE: u8 = 0x85;
+const ENTRY_STREAM: u8 = 0xC0;
+const ENTRY_NAME: u8 = 0xC1;
+
+/// The `GeneralSecondaryFlags` bit marking a stream's clusters as contiguous.
+const NO_FAT_CHAIN: u8 = 0x02;
+
+/// The number of UTF-16 code units held by one file name entry.
+const NAME_UNITS: usize = 15;
+
+#[derive(Debug)]
+pub struct Dir {
+    pub(crate) exfat: Shared<ExFat>,
+    pub(crate) stream: Stream,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+}
+
+/// The fields of a file directory entry set: a file entry, a stream
+/// extension entry, and one or more file name entries.
+#[derive(Debug)]
+struct EntrySet {
+    name: String,
+    metadata: Metadata,
+    stream: Stream,
+}
+
+/// Computes the checksum of the directory entry set `set`, skipping the
+/// `SetChecksum` field of the file entry.
+fn set_checksum(set: &[u8]) -> u16 {
+    let mut sum = 0u16;
+    for (i, &byte) in set.iter().enumerate() {
+        if i == 2 || i == 3 {
+            continue;
+        }
+
+        sum = sum.rotate_right(1).wrapping_add(byte as u16);
+    }
+
+    sum
+}
+
+impl EntrySet {
+    /// Parses the directory entry set `set`, which begins with a file entry.
+    /// Returns `None` if the set's checksum does not match or its secondary
+    /// entries are malformed.
+    fn parse(set: &[u8]) -> Option<EntrySet> {
+        if set.len() < 96 || set_checksum(set) != read_u16(set, 2) {
+            return None;
+        }
+
+        let stream_entry = &set[32..64];
+        if stream_entry[0] != ENTRY_STREAM {
+            return None;
+        }
+
+        let name_length = stream_entry[3] as usize;
+        let mut units = Vec::with_capacity(name_length);
+        for entry in set[64..].chunks(32) {
+            if entry[0] != ENTRY_NAME {
+                break;
+            }
+
+            for i in 0..NAME_UNITS {
+                units.push(read_u16(entry, 2 + i * 2));
+            }
+        }
+
+        if units.len() < name_length {
+            return None;
+        }
+
+        // The last access time has no 10ms increment field.
+        let timestamp = |raw: usize, increment: u8, offset: usize| {
+            Timestamp::new(read_u32(set, raw), increment, set[offset])
+        };
+
+        Some(EntrySet {
+            name: String::from_utf16_lossy(&units[..name_length]),
+            metadata: Metadata {
+                attributes: read_u16(set, 4),
+                created: timestamp(8, set[20], 22),
+                modified: timestamp(12, set[21], 23),
+                accessed: timestamp(16, 0, 24),
+            },
+            stream: Stream {
+                first_cluster: read_u32(stream_entry, 20),
+                data_length: read_u64(stream_entry, 24),
+                valid_length: read_u64(stream_entry, 8),
+                contiguous: stream_entry[1] & NO_FAT_CHAIN != 0,
+            }
+        })
+    }
+
+    /// Parses every valid entry set in the raw directory contents `data`.
+    /// Deleted entries, critical and benign entries that do not describe files,
+    /// and sets with a bad checksum are skipped.
+    fn parse_all(data: &[u8]) -> Vec<EntrySet> {
+        let mut sets = Vec::new();
+        let mut offset = 0;
+        while offset + 32 <= data.len() {
+            match data[offset] {
+                ENTRY_END => break,
+                ENTRY_FILE => {
+                    let end = offset + (data[offset + 1] as usize + 1) * 32;
+                    if end <= data.len() {
+                        if let Some(set) = EntrySet::parse(&data[offset..end]) {
+                            sets.push(set);
+                            offset = end;
+                            continue;
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            offset += 32;
+        }
+
+        sets
+    }
+}
+
+impl Dir {
+    /// Returns the root directory of `exfat`.
+    pub(crate) fn root(exfat: Shared<ExFat>) -> Dir {
+        let stream = exfat.borrow().root();
+        Dir {
+            exfat,
+            stream,
+            name: String::new(),
+            metadata: Metadata {
+                attributes: Metadata::DIRECTORY,
+                ..Metadata::default()
+            },
+        }
+    }
+
+    /// Returns `true` if this is the root directory.
+    pub fn is_root(&self) -> bool {
+        self.stream.first_cluster == self.exfat.borrow().root().first_cluster
+    }
+
+    /// Finds the entry named `name` in `self` and returns it. Names are
+    /// compared case-insensitively using the volume's up-case table.
+    ///
+    /// # Errors
+    ///
+    /// If no entry with name `name` exists in `self`, an error of `NotFound` is
+    /// returned.
+    ///
+    /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
+    /// is returned.
+    pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry> {
+        use traits::{Dir, Entry};
+
+        let name: Vec<u16> = name.as_ref().to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "name is not valid UTF-8"))?
+            .encode_utf16()
+            .collect();
+
+        let mut entries = self.entries()?;
+        let exfat = self.exfat.borrow();
+        entries
+            .find(|entry| {
+                let other: Vec<u16> = entry.name().encode_utf16().collect();
+                exfat.upcase().eq_ignore_case(&name, &other)
+            })
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, "entry not found"))
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = vec::IntoIter<Entry>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let data = self.exfat.borrow_mut().read_stream(&self.stream)?;
+        let entries: Vec<Entry> = EntrySet::parse_all(&data).into_iter()
+            .map(|set| match set.metadata.is_dir() {
+                true => Entry::Dir(Dir {
+                    exfat: self.exfat.clone(),
+                    stream: set.stream,
+                    name: set.name,
+                    metadata: set.metadata,
+                }),
+                false => Entry::File(File::new(self.exfat.clone(), set.name,
+                                               set.metadata, set.stream))
+            })
+            .collect();
+
+        Ok(entries.into_iter())
+    }
+}
@@ -0,0 +1,36 @@
+use std::io;
+
+use mbr;
+use gpt;
+
+#[derive(Debug)]
+pub enum Error {
+    Mbr(mbr::Error),
+    Gpt(gpt::Error),
+    Io(io::Error),
+    BadSignature,
+    /// Neither the main nor the backup boot region has a valid checksum, or
+    /// the up-case table does not match its recorded checksum.
+    BadChecksum,
+    /// The root directory lacks an allocation bitmap or up-case table entry.
+    MissingMetadata,
+    NotFound
+}
+
+impl From<mbr::Error> for Error {
+    fn from(error: mbr::Error) -> Error {
+        Error::Mbr(error)
+    }
+}
+
+impl From<gpt::Error> for Error {
+    fn from(error: gpt::Error) -> Error {
+        Error::Gpt(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
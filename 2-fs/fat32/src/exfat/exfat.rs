@@ -0,0 +1,473 @@
+use std::io;
+use std::path::{Component, Path};
+use std::cmp::min;
+
+use mbr::{self, MasterBootRecord};
+use gpt::{self, GuidPartitionTable};
+use vfat::{CachedDevice, CacheStats, Partition};
+use exfat::{Shared, BootSector, UpcaseTable, Error, Dir, File, Entry};
+use traits::{FileSystem, BlockDevice};
+
+/// Directory entry types found in the root directory.
+const ENTRY_END: u8 = 0x00;
+const ENTRY_BITMAP: u8 = 0x81;
+const ENTRY_UPCASE: u8 = 0x82;
+const ENTRY_LABEL: u8 = 0x83;
+
+/// FAT entry values that end a chain or mark a bad cluster.
+const FAT_EOC: u32 = 0xFFFF_FFFF;
+const FAT_BAD: u32 = 0xFFFF_FFF7;
+
+/// The clusters holding the contents of a file or directory, as described by
+/// a stream extension entry.
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct Stream {
+    /// The first cluster, or 0 if no clusters are allocated.
+    pub first_cluster: u32,
+    /// The number of bytes allocated to the stream.
+    pub data_length: u64,
+    /// The number of bytes that have been written. Bytes past this point read
+    /// as zeroes.
+    pub valid_length: u64,
+    /// Whether the clusters are consecutive and the FAT must not be consulted.
+    pub contiguous: bool,
+}
+
+#[derive(Debug)]
+pub struct ExFat {
+    device: CachedDevice,
+    bytes_per_sector: u64,
+    sectors_per_cluster: u64,
+    fat_start_sector: u64,
+    heap_start_sector: u64,
+    cluster_count: u32,
+    root: Stream,
+    serial_number: u32,
+    label: String,
+    bitmap: Vec<u8>,
+    upcase: UpcaseTable,
+}
+
+/// Returns the first sector and boot sector of the exFAT volume on `device`.
+/// Volumes are searched for in MBR partitions of type `0x7`, then in basic
+/// data partitions of a GUID partition table, and finally at sector 0 for
+/// devices without a partition table.
+pub(crate) fn find_volume<T: BlockDevice>(device: &mut T) -> Result<(u64, BootSector), Error> {
+    let candidates: Vec<u64> = match MasterBootRecord::from(&mut *device) {
+        Ok(mbr) => {
+            let starts: Vec<u64> = mbr.partitions().iter()
+                .filter(|partition| partition.is_exfat())
+                .map(|partition| partition.relative_sector() as u64)
+                .collect();
+
+            match starts.is_empty() {
+                false => starts,
+                true => match GuidPartitionTable::from(&mut *device) {
+                    Ok(gpt) => gpt.partitions()
+                        .filter(|partition| partition.is_fat())
+                        .map(|partition| partition.first_lba())
+                        .collect(),
+                    Err(gpt::Error::NoProtectiveMbr) => vec![0],
+                    Err(e) => return Err(e.into())
+                }
+            }
+        }
+        Err(mbr::Error::UnknownBootIndicator(_)) => vec![0],
+        Err(e) => return Err(e.into())
+    };
+
+    let mut error = Error::NotFound;
+    for start in candidates {
+        match BootSector::from(&mut *device, start) {
+            Ok(boot) => return Ok((start, boot)),
+            Err(Error::BadSignature) => continue,
+            Err(e) => error = e
+        }
+    }
+
+    Err(error)
+}
+
+/// Reads the little-endian `u16` at `offset` in `buf`.
+pub(crate) fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    buf[offset] as u16 | (buf[offset + 1] as u16) << 8
+}
+
+/// Reads the little-endian `u32` at `offset` in `buf`.
+pub(crate) fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    read_u16(buf, offset) as u32 | (read_u16(buf, offset + 2) as u32) << 16
+}
+
+/// Reads the little-endian `u64` at `offset` in `buf`.
+pub(crate) fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    read_u32(buf, offset) as u64 | (read_u32(buf, offset + 4) as u64) << 32
+}
+
+impl ExFat {
+    /// Mounts the exFAT volume on `device`.
+    ///
+    /// The boot region checksum is verified, falling back to the backup boot
+    /// region, and the allocation bitmap and up-case table are loaded from the
+    /// root directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NotFound` if the device holds no exFAT volume, `BadChecksum`
+    /// if the boot region or up-case table is corrupt, and `MissingMetadata`
+    /// if the root directory has no allocation bitmap or up-case table.
+    pub fn from<T>(mut device: T) -> Result<Shared<ExFat>, Error>
+        where T: BlockDevice + 'static
+    {
+        let (start, boot) = find_volume(&mut device)?;
+        let active_fat = min(boot.active_fat(), boot.num_fats() - 1) as u64;
+        let partition = Partition { start, sector_size: boot.bytes_per_sector() };
+
+        let mut exfat = ExFat {
+            device: CachedDevice::new(device, partition),
+            bytes_per_sector: boot.bytes_per_sector(),
+            sectors_per_cluster: boot.sectors_per_cluster(),
+            fat_start_sector: start + boot.fat_offset() as u64
+                + active_fat * boot.fat_length() as u64,
+            heap_start_sector: start + boot.cluster_heap_offset() as u64,
+            cluster_count: boot.cluster_count(),
+            root: Stream::default(),
+            serial_number: boot.serial_number(),
+            label: String::new(),
+            bitmap: Vec::new(),
+            upcase: UpcaseTable::identity(),
+        };
+
+        let clusters = exfat.chain_length(boot.root_dir_cluster())?;
+        exfat.root = Stream {
+            first_cluster: boot.root_dir_cluster(),
+            data_length: clusters * exfat.cluster_size() as u64,
+            valid_length: clusters * exfat.cluster_size() as u64,
+            contiguous: false,
+        };
+
+        exfat.load_metadata(active_fat as u8)?;
+        Ok(Shared::new(exfat))
+    }
+
+    /// Loads the allocation bitmap, up-case table, and volume label from the
+    /// critical entries in the root directory.
+    fn load_metadata(&mut self, active_fat: u8) -> Result<(), Error> {
+        let root = self.root;
+        let data = self.read_stream(&root)?;
+
+        let (mut bitmap, mut upcase) = (None, None);
+        for entry in data.chunks(32) {
+            let stream = Stream {
+                first_cluster: read_u32(entry, 20),
+                data_length: read_u64(entry, 24),
+                valid_length: read_u64(entry, 24),
+                contiguous: false
+            };
+
+            match entry[0] {
+                ENTRY_END => break,
+                ENTRY_BITMAP if entry[1] & 1 == active_fat => bitmap = Some(stream),
+                ENTRY_UPCASE => upcase = Some((stream, read_u32(entry, 4))),
+                ENTRY_LABEL => {
+                    let count = min(entry[1] as usize, 11);
+                    let units: Vec<u16> = (0..count).map(|i| read_u16(entry, 2 + i * 2)).collect();
+                    self.label = String::from_utf16_lossy(&units);
+                }
+                _ => {}
+            }
+        }
+
+        let (bitmap, (upcase, checksum)) = match (bitmap, upcase) {
+            (Some(bitmap), Some(upcase)) => (bitmap, upcase),
+            _ => return Err(Error::MissingMetadata)
+        };
+
+        self.bitmap = self.read_stream(&bitmap)?;
+        if (self.bitmap.len() as u64) * 8 < self.cluster_count as u64 {
+            return Err(Error::MissingMetadata);
+        }
+
+        let table = self.read_stream(&upcase)?;
+        self.upcase = UpcaseTable::from(&table, checksum).ok_or(Error::BadChecksum)?;
+        Ok(())
+    }
+
+    /// The volume label, or an empty string if the volume has none.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// The volume serial number.
+    pub fn serial_number(&self) -> u32 {
+        self.serial_number
+    }
+
+    /// The number of clusters in the cluster heap.
+    pub fn cluster_count(&self) -> u32 {
+        self.cluster_count
+    }
+
+    /// The up-case table used to compare file names.
+    pub fn upcase(&self) -> &UpcaseTable {
+        &self.upcase
+    }
+
+    /// Returns `true` if the allocation bitmap marks `cluster` as in use.
+    /// Clusters outside of the cluster heap are never allocated.
+    pub fn is_allocated(&self, cluster: u32) -> bool {
+        if cluster < 2 || cluster >= self.cluster_count + 2 {
+            return false;
+        }
+
+        let index = (cluster - 2) as usize;
+        self.bitmap[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// The number of clusters the allocation bitmap marks as free.
+    pub fn free_clusters(&self) -> u32 {
+        (2..self.cluster_count + 2).filter(|&cluster| !self.is_allocated(cluster)).count() as u32
+    }
+
+    /// Returns the hit, miss, eviction, and write-back counters of the sector
+    /// cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.device.stats()
+    }
+
+    pub(crate) fn root(&self) -> Stream {
+        self.root
+    }
+
+    pub(crate) fn cluster_size(&self) -> usize {
+        (self.bytes_per_sector * self.sectors_per_cluster) as usize
+    }
+
+    /// Returns an error if `cluster` is not in the cluster heap.
+    fn check_cluster(&self, cluster: u32) -> io::Result<()> {
+        if cluster < 2 || cluster >= self.cluster_count + 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster number"));
+        }
+
+        Ok(())
+    }
+
+    /// The logical sector where `cluster` begins.
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.heap_start_sector + (cluster - 2) as u64 * self.sectors_per_cluster
+    }
+
+    /// Reads from an offset of a cluster into a buffer. Reads at most until the
+    /// end of the cluster. Returns the number of bytes read.
+    pub(crate) fn read_cluster(
+        &mut self,
+        cluster: u32,
+        offset: usize,
+        buf: &mut [u8]
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let sector_size = self.bytes_per_sector as usize;
+        let first_sector = self.cluster_sector(cluster);
+        let to_read = min(buf.len(), self.cluster_size().saturating_sub(offset));
+        self.device.prefetch(first_sector, self.sectors_per_cluster)?;
+
+        let mut read = 0;
+        while read < to_read {
+            let position = offset + read;
+            let within = position % sector_size;
+            let amount = min(to_read - read, sector_size - within);
+            let data = self.device.get(first_sector + (position / sector_size) as u64)?;
+            buf[read..(read + amount)].copy_from_slice(&data[within..(within + amount)]);
+            read += amount;
+        }
+
+        Ok(read)
+    }
+
+    /// Returns the cluster following `cluster` in its FAT chain, or `None` if
+    /// `cluster` ends the chain.
+    ///
+    /// Returns an error of `InvalidData` if the FAT entry for `cluster` does
+    /// not mark a cluster in use.
+    pub(crate) fn next_cluster(&mut self, cluster: u32) -> io::Result<Option<u32>> {
+        self.check_cluster(cluster)?;
+        let position = cluster as u64 * 4;
+        let sector = self.fat_start_sector + position / self.bytes_per_sector;
+        let offset = (position % self.bytes_per_sector) as usize;
+        let next = read_u32(self.device.get(sector)?, offset);
+
+        match next {
+            FAT_EOC => Ok(None),
+            FAT_BAD => Err(io::Error::new(io::ErrorKind::InvalidData, "cluster is marked bad")),
+            next => {
+                self.check_cluster(next)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData,
+                                                "cluster chain is broken"))?;
+                Ok(Some(next))
+            }
+        }
+    }
+
+    /// Returns the cluster `index` clusters into `stream`, starting the walk
+    /// from the known position `from` if it is not past `index`.
+    pub(crate) fn cluster_at(
+        &mut self,
+        stream: &Stream,
+        index: u64,
+        from: Option<(u64, u32)>
+    ) -> io::Result<u32> {
+        if stream.first_cluster == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "stream has no clusters"));
+        }
+
+        if stream.contiguous {
+            let cluster = stream.first_cluster as u64 + index;
+            if cluster >= self.cluster_count as u64 + 2 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "stream is too long"));
+            }
+
+            return Ok(cluster as u32);
+        }
+
+        let (mut i, mut cluster) = match from {
+            Some((i, cluster)) if i <= index => (i, cluster),
+            _ => (0, stream.first_cluster)
+        };
+
+        while i < index {
+            cluster = self.next_cluster(cluster)?
+                .ok_or(io::Error::new(io::ErrorKind::InvalidData, "cluster chain too short"))?;
+            i += 1;
+        }
+
+        Ok(cluster)
+    }
+
+    /// Returns the number of clusters in the FAT chain starting at `first`.
+    fn chain_length(&mut self, first: u32) -> io::Result<u64> {
+        let mut clusters = 1;
+        let mut cluster = first;
+        while let Some(next) = self.next_cluster(cluster)? {
+            clusters += 1;
+            if clusters > self.cluster_count as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain has a cycle"));
+            }
+
+            cluster = next;
+        }
+
+        Ok(clusters)
+    }
+
+    /// Returns the number of clusters allocated to `stream`, or for a
+    /// contiguous stream, the number of clusters it can span.
+    fn stream_clusters(&mut self, stream: &Stream) -> io::Result<u64> {
+        match (stream.first_cluster, stream.contiguous) {
+            (0, _) => Ok(0),
+            (first, true) => Ok((self.cluster_count as u64 + 2).saturating_sub(first as u64)),
+            (first, false) => self.chain_length(first)
+        }
+    }
+
+    /// Reads the valid bytes of `stream` into a new vector.
+    ///
+    /// The stream's length comes from its directory entry; one longer than
+    /// the clusters it has is rejected before anything is allocated.
+    pub(crate) fn read_stream(&mut self, stream: &Stream) -> io::Result<Vec<u8>> {
+        let len = min(stream.valid_length, stream.data_length);
+        let cluster_size = self.cluster_size();
+        if len > self.stream_clusters(stream)? * cluster_size as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "stream is longer than its clusters"));
+        }
+
+        let len = len as usize;
+        let mut buf = vec![0u8; len];
+
+        let mut current = None;
+        let mut read = 0;
+        while read < len {
+            let index = (read / cluster_size) as u64;
+            let cluster = self.cluster_at(stream, index, current)?;
+            current = Some((index, cluster));
+            read += self.read_cluster(cluster, 0, &mut buf[read..])?;
+        }
+
+        Ok(buf)
+    }
+}
+
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "exFAT volumes are read-only")
+}
+
+impl<'a> FileSystem for &'a Shared<ExFat> {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    /// Opens the entry at `path`. exFAT directories have no `.` or `..`
+    /// entries, so `..` components are resolved against the directories
+    /// already walked.
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(invalid_input("path is not absolute"));
+        }
+
+        let mut parents: Vec<Dir> = Vec::new();
+        let mut entry = Entry::Dir(Dir::root(self.clone()));
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            let name = match component {
+                Component::RootDir | Component::CurDir => continue,
+                Component::ParentDir => "..".as_ref(),
+                Component::Normal(name) => name,
+                Component::Prefix(_) => return Err(invalid_input("path has a prefix"))
+            };
+
+            let dir = match entry {
+                Entry::Dir(dir) => dir,
+                Entry::File(_) => return Err(invalid_input("path component is not a directory"))
+            };
+
+            if component == Component::ParentDir {
+                entry = Entry::Dir(parents.pop().unwrap_or(dir));
+                continue;
+            }
+
+            entry = match dir.find(name) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound && components.peek().is_some() => {
+                    return Err(invalid_input("path component does not exist"));
+                }
+                result => result?
+            };
+            parents.push(dir);
+        }
+
+        Ok(entry)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
+        Err(read_only())
+    }
+
+    fn create_dir<P>(self, _path: P, _parents: bool) -> io::Result<Self::Dir>
+        where P: AsRef<Path>
+    {
+        Err(read_only())
+    }
+
+    fn rename<P, Q>(self, _from: P, _to: Q) -> io::Result<()>
+        where P: AsRef<Path>, Q: AsRef<Path>
+    {
+        Err(read_only())
+    }
+
+    fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
+        Err(read_only())
+    }
+}
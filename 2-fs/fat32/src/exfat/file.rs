@@ -0,0 +1,115 @@
+use std::cmp::min;
+use std::io::{self, SeekFrom};
+
+use traits;
+use exfat::{ExFat, Shared, Stream, Metadata};
+
+#[derive(Debug)]
+pub struct File {
+    pub(crate) exfat: Shared<ExFat>,
+    pub(crate) stream: Stream,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    /// The current position in the file.
+    offset: u64,
+    /// The index in the stream and number of the last cluster accessed.
+    current: Option<(u64, u32)>,
+}
+
+impl File {
+    pub(crate) fn new(exfat: Shared<ExFat>, name: String, metadata: Metadata, stream: Stream) -> File {
+        File { exfat, stream, name, metadata, offset: 0, current: None }
+    }
+
+    /// Returns the number of bytes that have been written to the file. Bytes
+    /// between this point and the end of the file read as zeroes.
+    pub fn valid_length(&self) -> u64 {
+        min(self.stream.valid_length, self.stream.data_length)
+    }
+}
+
+impl traits::File for File {
+    /// exFAT volumes are read-only, so there is never anything to write back.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the size of the file in bytes.
+    fn size(&self) -> u64 {
+        self.stream.data_length
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.stream.data_length;
+        let to_read = min(buf.len() as u64, size - min(self.offset, size)) as usize;
+
+        let exfat = self.exfat.clone();
+        let mut exfat = exfat.borrow_mut();
+        let cluster_size = exfat.cluster_size();
+        let valid = self.valid_length();
+
+        let mut read = 0;
+        while read < to_read {
+            let offset = self.offset;
+            let n = if offset >= valid {
+                let n = min(to_read - read, (size - offset) as usize);
+                for byte in &mut buf[read..(read + n)] {
+                    *byte = 0;
+                }
+                n
+            } else {
+                let index = offset / cluster_size as u64;
+                let cluster = exfat.cluster_at(&self.stream, index, self.current)?;
+                self.current = Some((index, cluster));
+
+                let within = (offset % cluster_size as u64) as usize;
+                let end = min(to_read, read + min(cluster_size - within, (valid - offset) as usize));
+                exfat.read_cluster(cluster, within, &mut buf[read..end])?
+            };
+
+            read += n;
+            self.offset += n as u64;
+        }
+
+        Ok(read)
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "exFAT volumes are read-only"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
+
+impl io::Seek for File {
+    /// Seek to offset `pos` in the file.
+    ///
+    /// A seek to the end of the file is allowed. A seek _beyond_ the end of the
+    /// file returns an `InvalidInput` error.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of a file or beyond the end of the file results
+    /// in an `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let size = self.stream.data_length as i64;
+        let offset = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => size + n,
+            SeekFrom::Current(n) => self.offset as i64 + n,
+        };
+
+        if offset < 0 || offset > size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek out of bounds"));
+        }
+
+        self.offset = offset as u64;
+        Ok(self.offset)
+    }
+}
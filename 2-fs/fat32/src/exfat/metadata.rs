@@ -0,0 +1,133 @@
+use std::fmt;
+
+use traits;
+
+/// A timestamp as stored in an exFAT file directory entry: a FAT date and
+/// time packed into 32 bits, a 10ms increment, and an offset from UTC.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timestamp {
+    pub(crate) raw: u32,
+    pub(crate) increment: u8,
+    pub(crate) utc_offset: u8,
+}
+
+/// Metadata for an exFAT directory entry.
+#[derive(Default, Debug, Clone)]
+pub struct Metadata {
+    pub(crate) attributes: u16,
+    pub(crate) created: Timestamp,
+    pub(crate) accessed: Timestamp,
+    pub(crate) modified: Timestamp,
+}
+
+impl Timestamp {
+    /// Returns the timestamp for the on-disk fields `raw`, `increment` (in
+    /// units of 10ms, up to 199) and `utc_offset`.
+    pub fn new(raw: u32, increment: u8, utc_offset: u8) -> Timestamp {
+        Timestamp { raw, increment, utc_offset }
+    }
+
+    /// The number of 10ms units past the second, in range [0, 100).
+    pub fn centiseconds(&self) -> u8 {
+        self.increment % 100
+    }
+
+    /// The offset from UTC in minutes, or `None` if the timestamp is in an
+    /// unspecified local time zone.
+    pub fn utc_offset(&self) -> Option<i16> {
+        match self.utc_offset & 0x80 {
+            0 => None,
+            _ => Some((((self.utc_offset << 1) as i8) >> 1) as i16 * 15)
+        }
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        1980 + (self.raw >> 25) as usize
+    }
+
+    fn month(&self) -> u8 {
+        ((self.raw >> 21) & 0xF) as u8
+    }
+
+    fn day(&self) -> u8 {
+        ((self.raw >> 16) & 0x1F) as u8
+    }
+
+    fn hour(&self) -> u8 {
+        ((self.raw >> 11) & 0x1F) as u8
+    }
+
+    fn minute(&self) -> u8 {
+        ((self.raw >> 5) & 0x3F) as u8
+    }
+
+    fn second(&self) -> u8 {
+        ((self.raw & 0x1F) * 2) as u8 + self.increment / 100
+    }
+}
+
+impl Metadata {
+    pub const READ_ONLY: u16 = 0x01;
+    pub const HIDDEN: u16 = 0x02;
+    pub const SYSTEM: u16 = 0x04;
+    pub const DIRECTORY: u16 = 0x10;
+    pub const ARCHIVE: u16 = 0x20;
+
+    /// Returns the raw attribute bits of the entry.
+    pub fn attributes(&self) -> u16 {
+        self.attributes
+    }
+
+    /// Returns `true` if the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.attributes & Metadata::DIRECTORY != 0
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes & Metadata::READ_ONLY != 0
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes & Metadata::HIDDEN != 0
+    }
+
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use traits::Timestamp;
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+               self.year(), self.month(), self.day(),
+               self.hour(), self.minute(), self.second())
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |bit, c| if self.attributes & bit != 0 { c } else { '-' };
+        write!(f, "{}{}{}{}{}  created {}  modified {}  accessed {}",
+               flag(Metadata::DIRECTORY, 'd'),
+               flag(Metadata::READ_ONLY, 'r'),
+               flag(Metadata::HIDDEN, 'h'),
+               flag(Metadata::SYSTEM, 's'),
+               flag(Metadata::ARCHIVE, 'a'),
+               self.created, self.modified, self.accessed)
+    }
+}
@@ -0,0 +1,20 @@
+pub(crate) mod boot;
+pub(crate) mod upcase;
+pub(crate) mod exfat;
+pub(crate) mod error;
+pub(crate) mod dir;
+pub(crate) mod file;
+pub(crate) mod entry;
+pub(crate) mod metadata;
+
+pub use self::boot::BootSector;
+pub use self::upcase::UpcaseTable;
+pub use self::exfat::ExFat;
+pub use self::error::Error;
+pub use self::dir::Dir;
+pub use self::file::File;
+pub use self::entry::Entry;
+pub use self::metadata::{Metadata, Timestamp};
+pub use vfat::Shared;
+
+pub(crate) use self::exfat::Stream;
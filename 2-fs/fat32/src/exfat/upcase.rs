@@ -0,0 +1,77 @@
+use exfat::boot::checksum;
+
+/// The marker that begins a run of identity mappings in a compressed up-case
+/// table. It is followed by the length of the run.
+const IDENTITY_RUN: u16 = 0xFFFF;
+
+/// The up-case table of an exFAT volume, mapping UTF-16 code units to their
+/// upper-case equivalents. File names are compared case-insensitively through
+/// this table.
+#[derive(Debug, Clone)]
+pub struct UpcaseTable {
+    map: Vec<u16>
+}
+
+impl UpcaseTable {
+    /// Parses the raw, possibly compressed, up-case table `data`.
+    ///
+    /// Returns `None` if `data` does not match `expected`, the checksum
+    /// recorded in the table's directory entry.
+    pub fn from(data: &[u8], expected: u32) -> Option<UpcaseTable> {
+        if checksum(data, &[]) != expected {
+            return None;
+        }
+
+        let mut map = Vec::with_capacity(data.len() / 2);
+        let mut units = data.chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| pair[0] as u16 | (pair[1] as u16) << 8);
+
+        while let Some(unit) = units.next() {
+            if unit == IDENTITY_RUN {
+                let run = units.next().unwrap_or(0) as usize;
+                for _ in 0..run {
+                    let identity = map.len() as u16;
+                    map.push(identity);
+                }
+            } else {
+                map.push(unit);
+            }
+        }
+
+        Some(UpcaseTable { map })
+    }
+
+    /// Returns a table that maps every code unit to itself.
+    pub(crate) fn identity() -> UpcaseTable {
+        UpcaseTable { map: Vec::new() }
+    }
+
+    /// Returns the upper-case equivalent of `unit`. Code units beyond the end
+    /// of the table map to themselves.
+    pub fn upcase(&self, unit: u16) -> u16 {
+        match self.map.get(unit as usize) {
+            Some(&upper) => upper,
+            None => unit
+        }
+    }
+
+    /// Returns `true` if the UTF-16 names `a` and `b` are equal ignoring case.
+    pub fn eq_ignore_case(&self, a: &[u16], b: &[u16]) -> bool {
+        a.len() == b.len()
+            && a.iter().zip(b.iter()).all(|(&x, &y)| self.upcase(x) == self.upcase(y))
+    }
+
+    /// Computes the hash stored in a stream extension entry for `name`.
+    pub fn name_hash(&self, name: &[u16]) -> u16 {
+        let mut hash = 0u16;
+        for &unit in name {
+            let upper = self.upcase(unit);
+            for &byte in &[upper as u8, (upper >> 8) as u8] {
+                hash = hash.rotate_right(1).wrapping_add(byte as u16);
+            }
+        }
+
+        hash
+    }
+}
@@ -0,0 +1,390 @@
+use std::{fmt, io};
+
+use traits::BlockDevice;
+use mbr::{self, MasterBootRecord};
+use util::crc32;
+
+/// The MBR partition type of the protective partition covering a GPT disk.
+const PROTECTIVE_PARTITION_TYPE: u8 = 0xEE;
+
+/// The signature at the start of a GPT header.
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// The size, in bytes, of the fields of a GPT header we know about.
+const MIN_HEADER_SIZE: usize = 92;
+
+/// The smallest valid size of a partition entry.
+const MIN_ENTRY_SIZE: usize = 128;
+
+/// The largest partition entry array we accept, in bytes: far more than the
+/// 128 entries of 128 bytes the specification calls for.
+const MAX_ENTRIES_SIZE: usize = 1 << 20;
+
+/// A globally unique identifier as stored on disk: the first three fields are
+/// little-endian, the remaining eight bytes are stored as-is.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// The type GUID of unused partition entries.
+    pub const UNUSED: Guid = Guid([0; 16]);
+
+    /// C12A7328-F81F-11D2-BA4B-00A0C93EC93B: EFI system partition.
+    pub const EFI_SYSTEM: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11,
+        0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B
+    ]);
+
+    /// EBD0A0A2-B9E5-4433-87C0-68B6B72699C7: Microsoft basic data partition,
+    /// used for FAT file systems.
+    pub const BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44,
+        0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7
+    ]);
+
+    /// 0FC63DAF-8483-4772-8E79-3D69D8477DE4: Linux file system data.
+    pub const LINUX_FILESYSTEM: Guid = Guid([
+        0xAF, 0x3D, 0xC6, 0x0F, 0x83, 0x84, 0x72, 0x47,
+        0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4
+    ]);
+}
+
+impl fmt::Display for Guid {
+    /// Formats the GUID in its canonical, upper-case form, for instance
+    /// `C12A7328-F81F-11D2-BA4B-00A0C93EC93B`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(f, "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+               b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9])?;
+        for byte in &b[10..] {
+            write!(f, "{:02X}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Guid({})", self)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// There was an I/O error while reading the partition table.
+    Io(io::Error),
+    /// The MBR could not be read.
+    Mbr(mbr::Error),
+    /// The MBR does not contain a protective partition, so the disk does not
+    /// use a GUID partition table.
+    NoProtectiveMbr,
+    /// The GPT header magic signature was invalid.
+    BadSignature,
+    /// The GPT header is malformed: its size, location, or partition entry
+    /// size is invalid.
+    BadHeader,
+    /// The CRC32 of the GPT header did not match.
+    BadHeaderCrc,
+    /// The CRC32 of the partition entry array did not match.
+    BadEntriesCrc,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<mbr::Error> for Error {
+    fn from(error: mbr::Error) -> Error {
+        Error::Mbr(error)
+    }
+}
+
+/// A GPT header.
+#[derive(Debug, Clone)]
+pub struct GptHeader {
+    revision: u32,
+    current_lba: u64,
+    backup_lba: u64,
+    first_usable_lba: u64,
+    last_usable_lba: u64,
+    disk_guid: Guid,
+    entries_lba: u64,
+    num_entries: u32,
+    entry_size: u32,
+    entries_crc32: u32,
+}
+
+impl GptHeader {
+    /// The GPT revision, `0x00010000` for version 1.0.
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// The LBA this header was read from.
+    pub fn current_lba(&self) -> u64 {
+        self.current_lba
+    }
+
+    /// The LBA of the other copy of this header.
+    pub fn backup_lba(&self) -> u64 {
+        self.backup_lba
+    }
+
+    /// The first LBA usable by partitions.
+    pub fn first_usable_lba(&self) -> u64 {
+        self.first_usable_lba
+    }
+
+    /// The last LBA usable by partitions.
+    pub fn last_usable_lba(&self) -> u64 {
+        self.last_usable_lba
+    }
+
+    /// The unique identifier of the disk.
+    pub fn disk_guid(&self) -> Guid {
+        self.disk_guid
+    }
+
+    /// Reads and validates the header at `lba`. The CRC32 of the partition
+    /// entry array is not checked.
+    fn read<T: BlockDevice>(device: &mut T, lba: u64) -> Result<GptHeader, Error> {
+        let mut buf = Vec::new();
+        device.read_all_sector(lba, &mut buf)?;
+        if buf.len() < MIN_HEADER_SIZE {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                "short read of GPT header")));
+        }
+
+        if &buf[..8] != SIGNATURE {
+            return Err(Error::BadSignature);
+        }
+
+        let header_size = read_u32(&buf, 12) as usize;
+        if header_size < MIN_HEADER_SIZE || header_size > buf.len() {
+            return Err(Error::BadHeader);
+        }
+
+        let expected_crc = read_u32(&buf, 16);
+        buf[16..20].copy_from_slice(&[0; 4]);
+        if crc32(&buf[..header_size]) != expected_crc {
+            return Err(Error::BadHeaderCrc);
+        }
+
+        let mut disk_guid = Guid::default();
+        disk_guid.0.copy_from_slice(&buf[56..72]);
+        let header = GptHeader {
+            revision: read_u32(&buf, 8),
+            current_lba: read_u64(&buf, 24),
+            backup_lba: read_u64(&buf, 32),
+            first_usable_lba: read_u64(&buf, 40),
+            last_usable_lba: read_u64(&buf, 48),
+            disk_guid,
+            entries_lba: read_u64(&buf, 72),
+            num_entries: read_u32(&buf, 80),
+            entry_size: read_u32(&buf, 84),
+            entries_crc32: read_u32(&buf, 88),
+        };
+
+        let entry_size = header.entry_size as usize;
+        if header.current_lba != lba || entry_size < MIN_ENTRY_SIZE || entry_size % 8 != 0 {
+            return Err(Error::BadHeader);
+        }
+
+        match (header.num_entries as usize).checked_mul(entry_size) {
+            Some(size) if size <= MAX_ENTRIES_SIZE => {}
+            _ => return Err(Error::BadHeader)
+        }
+
+        Ok(header)
+    }
+
+    /// Reads the partition entry array described by this header and validates
+    /// its CRC32. Its size was bounded by `read()`.
+    fn read_entries<T: BlockDevice>(&self, device: &mut T) -> Result<Vec<GptPartitionEntry>, Error> {
+        let entry_size = self.entry_size as usize;
+        let length = self.num_entries as usize * entry_size;
+        let sector_size = device.sector_size() as usize;
+
+        let mut buf = Vec::with_capacity(length + sector_size);
+        let mut lba = self.entries_lba;
+        while buf.len() < length {
+            if device.read_all_sector(lba, &mut buf)? == 0 {
+                return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                    "short read of GPT entries")));
+            }
+
+            lba += 1;
+        }
+
+        if crc32(&buf[..length]) != self.entries_crc32 {
+            return Err(Error::BadEntriesCrc);
+        }
+
+        Ok(buf[..length].chunks(entry_size).map(GptPartitionEntry::parse).collect())
+    }
+}
+
+/// An entry in the GPT partition entry array.
+#[derive(Debug, Clone)]
+pub struct GptPartitionEntry {
+    type_guid: Guid,
+    unique_guid: Guid,
+    first_lba: u64,
+    last_lba: u64,
+    attributes: u64,
+    name: String,
+}
+
+impl GptPartitionEntry {
+    fn parse(raw: &[u8]) -> GptPartitionEntry {
+        let (mut type_guid, mut unique_guid) = (Guid::default(), Guid::default());
+        type_guid.0.copy_from_slice(&raw[0..16]);
+        unique_guid.0.copy_from_slice(&raw[16..32]);
+
+        let name: Vec<u16> = raw[56..128].chunks(2)
+            .map(|pair| pair[0] as u16 | (pair[1] as u16) << 8)
+            .take_while(|&c| c != 0)
+            .collect();
+
+        GptPartitionEntry {
+            type_guid,
+            unique_guid,
+            first_lba: read_u64(raw, 32),
+            last_lba: read_u64(raw, 40),
+            attributes: read_u64(raw, 48),
+            name: String::from_utf16_lossy(&name),
+        }
+    }
+
+    /// The partition type GUID.
+    pub fn type_guid(&self) -> Guid {
+        self.type_guid
+    }
+
+    /// The unique identifier of this partition.
+    pub fn unique_guid(&self) -> Guid {
+        self.unique_guid
+    }
+
+    /// The first LBA of the partition.
+    pub fn first_lba(&self) -> u64 {
+        self.first_lba
+    }
+
+    /// The last LBA of the partition, inclusive.
+    pub fn last_lba(&self) -> u64 {
+        self.last_lba
+    }
+
+    /// The total number of sectors in the partition.
+    pub fn total_sectors(&self) -> u64 {
+        (self.last_lba + 1).saturating_sub(self.first_lba)
+    }
+
+    /// The raw attribute flags of the partition.
+    pub fn attributes(&self) -> u64 {
+        self.attributes
+    }
+
+    /// The human-readable name of the partition.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` if this entry describes a partition.
+    pub fn is_used(&self) -> bool {
+        self.type_guid != Guid::UNUSED
+    }
+
+    /// Returns `true` if the partition type is one that holds a FAT file
+    /// system: a basic data or an EFI system partition.
+    pub fn is_fat(&self) -> bool {
+        self.type_guid == Guid::BASIC_DATA || self.type_guid == Guid::EFI_SYSTEM
+    }
+}
+
+/// A GUID partition table (GPT).
+#[derive(Debug)]
+pub struct GuidPartitionTable {
+    header: GptHeader,
+    partitions: Vec<GptPartitionEntry>,
+    from_backup: bool,
+}
+
+impl GuidPartitionTable {
+    /// Reads and returns the GUID partition table from `device`.
+    ///
+    /// The primary header at LBA 1 is tried first. If it or its partition
+    /// entry array is corrupt, the backup header is used instead. The backup
+    /// header's location is taken from the primary header when that header is
+    /// intact and from the protective MBR partition otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NoProtectiveMbr` if the MBR doesn't have a protective GPT
+    /// partition. Otherwise, if neither table is valid, returns the error
+    /// encountered while reading the primary table.
+    pub fn from<T: BlockDevice>(mut device: T) -> Result<GuidPartitionTable, Error> {
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let protective = *mbr.partitions().iter()
+            .find(|p| p.partition_type() == PROTECTIVE_PARTITION_TYPE)
+            .ok_or(Error::NoProtectiveMbr)?;
+
+        let (error, backup_lba) = match GptHeader::read(&mut device, 1) {
+            Ok(header) => match header.read_entries(&mut device) {
+                Ok(partitions) => {
+                    return Ok(GuidPartitionTable { header, partitions, from_backup: false });
+                }
+                Err(e) => (e, Some(header.backup_lba))
+            },
+            Err(e) => (e, None)
+        };
+
+        let backup_lba = match backup_lba {
+            Some(lba) => lba,
+            None if protective.total_sectors() != 0xFFFFFFFF => {
+                protective.relative_sector() as u64 + protective.total_sectors() as u64 - 1
+            }
+            None => return Err(error)
+        };
+
+        GptHeader::read(&mut device, backup_lba)
+            .and_then(|header| {
+                let partitions = header.read_entries(&mut device)?;
+                Ok(GuidPartitionTable { header, partitions, from_backup: true })
+            })
+            .map_err(|_| error)
+    }
+
+    /// The header the table was read from.
+    pub fn header(&self) -> &GptHeader {
+        &self.header
+    }
+
+    /// Returns `true` if the primary table was corrupt and the backup table
+    /// was used instead.
+    pub fn is_from_backup(&self) -> bool {
+        self.from_backup
+    }
+
+    /// Returns the used entries of the partition table in on-disk order.
+    pub fn partitions<'a>(&'a self) -> impl Iterator<Item = &'a GptPartitionEntry> {
+        self.partitions.iter().filter(|p| p.is_used())
+    }
+
+    /// Returns the first partition that can hold a FAT file system, if any.
+    pub fn first_fat(&self) -> Option<&GptPartitionEntry> {
+        self.partitions().find(|p| p.is_fat())
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    (0..4).fold(0, |acc, i| acc | (buf[offset + i] as u32) << (i * 8))
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    read_u32(buf, offset) as u64 | (read_u32(buf, offset + 4) as u64) << 32
+}
@@ -11,5 +11,9 @@ mod util;
 
 pub mod vfat;
 pub mod traits;
+pub mod gpt;
+pub mod exfat;
+pub mod walk;
+pub mod volume;
 
 pub use mbr::*;
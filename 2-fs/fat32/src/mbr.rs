@@ -1,23 +1,119 @@
-use std::{fmt, io};
+use std::{fmt, io, mem};
 
 use traits::BlockDevice;
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct CHS {
-    // FIXME: Fill me in.
+    head: u8,
+    /// Bits 0-5 are the sector; bits 6-7 are the high bits of the cylinder.
+    sector_cylinder: u8,
+    /// The low 8 bits of the cylinder.
+    cylinder: u8,
+}
+
+impl CHS {
+    /// The head number.
+    pub fn head(&self) -> u8 {
+        self.head
+    }
+
+    /// The sector number. Always in range [1, 63] for valid entries.
+    pub fn sector(&self) -> u8 {
+        self.sector_cylinder & 0b111111
+    }
+
+    /// The 10-bit cylinder number.
+    pub fn cylinder(&self) -> u16 {
+        (((self.sector_cylinder as u16) & 0b11000000) << 2) | self.cylinder as u16
+    }
+}
+
+impl fmt::Debug for CHS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CHS")
+            .field("head", &self.head())
+            .field("sector", &self.sector())
+            .field("cylinder", &self.cylinder())
+            .finish()
+    }
 }
 
 #[repr(C, packed)]
-#[derive(Debug, Clone)]
+#[derive(Copy, Clone)]
 pub struct PartitionEntry {
-    // FIXME: Fill me in.
+    boot_indicator: u8,
+    starting_chs: CHS,
+    partition_type: u8,
+    ending_chs: CHS,
+    relative_sector: u32,
+    total_sectors: u32,
+}
+
+impl PartitionEntry {
+    /// Returns `true` if the partition is marked as bootable (active).
+    pub fn is_bootable(&self) -> bool {
+        self.boot_indicator == 0x80
+    }
+
+    /// The partition type (system ID).
+    pub fn partition_type(&self) -> u8 {
+        self.partition_type
+    }
+
+    /// Returns `true` if the partition type is one of the FAT32 types (`0xB`
+    /// or `0xC`).
+    pub fn is_fat32(&self) -> bool {
+        self.partition_type == 0xB || self.partition_type == 0xC
+    }
+
+    /// Returns `true` if the partition type is one of the FAT12, FAT16, or
+    /// FAT32 types.
+    pub fn is_fat(&self) -> bool {
+        match self.partition_type {
+            0x01 | 0x04 | 0x06 | 0x0E => true,
+            _ => self.is_fat32()
+        }
+    }
+
+    /// Returns `true` if the partition type is `0x7`, which is shared by exFAT
+    /// and NTFS.
+    pub fn is_exfat(&self) -> bool {
+        self.partition_type == 0x07
+    }
+
+    /// The offset, in sectors, from the start of the disk to the start of the
+    /// partition.
+    pub fn relative_sector(&self) -> u32 {
+        self.relative_sector
+    }
+
+    /// The total number of sectors in the partition.
+    pub fn total_sectors(&self) -> u32 {
+        self.total_sectors
+    }
+}
+
+impl fmt::Debug for PartitionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PartitionEntry")
+            .field("boot_indicator", &{ self.boot_indicator })
+            .field("starting_chs", &{ self.starting_chs })
+            .field("partition_type", &{ self.partition_type })
+            .field("ending_chs", &{ self.ending_chs })
+            .field("relative_sector", &{ self.relative_sector })
+            .field("total_sectors", &{ self.total_sectors })
+            .finish()
+    }
 }
 
 /// The master boot record (MBR).
 #[repr(C, packed)]
 pub struct MasterBootRecord {
-    // FIXME: Fill me in.
+    bootstrap: [u8; 436],
+    disk_id: [u8; 10],
+    partitions: [PartitionEntry; 4],
+    signature: [u8; 2],
 }
 
 #[derive(Debug)]
@@ -40,12 +136,51 @@ impl MasterBootRecord {
     /// boot indicator. Returns `Io(err)` if the I/O error `err` occured while
     /// reading the MBR.
     pub fn from<T: BlockDevice>(mut device: T) -> Result<MasterBootRecord, Error> {
-        unimplemented!("MasterBootRecord::from()")
+        let mut buf = [0u8; 512];
+        let read = device.read_sector(0, &mut buf).map_err(Error::Io)?;
+        if read != buf.len() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                "short read of MBR sector")));
+        }
+
+        let mbr: MasterBootRecord = unsafe { mem::transmute(buf) };
+        if mbr.signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        for (i, partition) in mbr.partitions.iter().enumerate() {
+            if partition.boot_indicator != 0 && partition.boot_indicator != 0x80 {
+                return Err(Error::UnknownBootIndicator(i as u8));
+            }
+        }
+
+        Ok(mbr)
+    }
+
+    /// Returns the four entries of the partition table.
+    pub fn partitions(&self) -> &[PartitionEntry; 4] {
+        &self.partitions
+    }
+
+    /// Returns the first partition entry that holds a FAT32 file system, if
+    /// any.
+    pub fn first_fat32(&self) -> Option<&PartitionEntry> {
+        self.partitions.iter().find(|p| p.is_fat32())
+    }
+
+    /// Returns the first partition entry that holds a FAT12, FAT16, or FAT32
+    /// file system, if any.
+    pub fn first_fat(&self) -> Option<&PartitionEntry> {
+        self.partitions.iter().find(|p| p.is_fat())
     }
 }
 
 impl fmt::Debug for MasterBootRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        unimplemented!("MasterBootRecord::fmt()")
+        f.debug_struct("MasterBootRecord")
+            .field("disk_id", &{ self.disk_id })
+            .field("partitions", &{ self.partitions })
+            .field("signature", &{ self.signature })
+            .finish()
     }
 }
@@ -1,8 +1,10 @@
 extern crate rand;
 
 use std::io::prelude::*;
-use std::io::Cursor;
+use std::io::{self, Cursor, SeekFrom};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use vfat::{Shared, VFat, BiosParameterBlock};
 use mbr::{MasterBootRecord, CHS, PartitionEntry};
@@ -349,3 +351,1468 @@ fn shared_fs_is_sync_send_static() {
     fn f<T: Sync + Send + 'static>() {  }
     f::<Shared<VFat>>();
 }
+
+/// Number of sectors in the FAT32 partition of `fat32_image()`. This is just
+/// large enough for the volume to have the 65525 clusters FAT32 requires.
+const IMAGE_SECTORS: u32 = 68000;
+
+/// Number of free clusters in an empty `fat32_image()`: every data cluster
+/// except the root directory's.
+const IMAGE_FREE_CLUSTERS: u32 = 66903;
+
+fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..(offset + bytes.len())].copy_from_slice(bytes);
+}
+
+fn u16_le(n: u16) -> [u8; 2] { [n as u8, (n >> 8) as u8] }
+fn u32_le(n: u32) -> [u8; 4] { [n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8] }
+fn u64_le(n: u64) -> [u8; 8] {
+    let (low, high) = (u32_le(n as u32), u32_le((n >> 32) as u32));
+    [low[0], low[1], low[2], low[3], high[0], high[1], high[2], high[3]]
+}
+
+/// Builds an empty FAT32 disk image with an MBR, one partition starting at
+/// sector 1, 512-byte sectors, 512-byte clusters, and two FATs.
+fn fat32_image() -> Cursor<Vec<u8>> {
+    Cursor::new(fat32_image_at(1))
+}
+
+/// Builds an empty FAT32 disk image like `fat32_image()` whose partition
+/// starts at sector `start`.
+fn fat32_image_at(start: usize) -> Vec<u8> {
+    let (reserved, sectors_per_fat) = (32usize, 532usize);
+    let mut data = vec![0u8; (start + IMAGE_SECTORS as usize) * 512];
+
+    // MBR with a single FAT32 (LBA) partition.
+    put(&mut data, 446 + 4, &[0x0C]);
+    put(&mut data, 446 + 8, &u32_le(start as u32));
+    put(&mut data, 446 + 12, &u32_le(IMAGE_SECTORS));
+    put(&mut data, 510, &[0x55, 0xAA]);
+
+    // Boot sector.
+    let boot = start * 512;
+    put(&mut data, boot, &[0xEB, 0x58, 0x90]);
+    put(&mut data, boot + 3, b"MSWIN4.1");
+    put(&mut data, boot + 11, &u16_le(512));
+    put(&mut data, boot + 13, &[1]);
+    put(&mut data, boot + 14, &u16_le(reserved as u16));
+    put(&mut data, boot + 16, &[2]);
+    put(&mut data, boot + 21, &[0xF8]);
+    put(&mut data, boot + 32, &u32_le(IMAGE_SECTORS));
+    put(&mut data, boot + 36, &u32_le(sectors_per_fat as u32));
+    put(&mut data, boot + 44, &u32_le(2));
+    put(&mut data, boot + 48, &u16_le(1));
+    put(&mut data, boot + 66, &[0x29]);
+    put(&mut data, boot + 71, b"NO NAME    FAT32   ");
+    put(&mut data, boot + 510, &[0x55, 0xAA]);
+
+    // FSInfo sector.
+    let fsinfo = boot + 512;
+    put(&mut data, fsinfo, &u32_le(0x41615252));
+    put(&mut data, fsinfo + 484, &u32_le(0x61417272));
+    put(&mut data, fsinfo + 488, &u32_le(IMAGE_FREE_CLUSTERS));
+    put(&mut data, fsinfo + 492, &u32_le(0xFFFFFFFF));
+    put(&mut data, fsinfo + 508, &u32_le(0xAA550000));
+
+    // Reserved entries and the root directory's single cluster in each FAT.
+    for fat in 0..2 {
+        let offset = boot + (reserved + fat * sectors_per_fat) * 512;
+        put(&mut data, offset, &u32_le(0x0FFFFFF8));
+        put(&mut data, offset + 4, &u32_le(0x0FFFFFFF));
+        put(&mut data, offset + 8, &u32_le(0x0FFFFFFF));
+    }
+
+    data
+}
+
+fn entry_names<P: AsRef<Path>>(vfat: &Shared<VFat>, path: P) -> Vec<String> {
+    let mut names: Vec<_> = vfat.open_dir(path)
+        .expect("directory exists")
+        .entries()
+        .expect("entries iterator")
+        .map(|entry| entry.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_create_file() {
+    let vfat = VFat::from(fat32_image()).expect("image mounts");
+    assert!(entry_names(&vfat, "/").is_empty());
+
+    let file = vfat.create_file("/HELLO.TXT").expect("created file");
+    assert_eq!(file.size(), 0);
+    vfat.create_file("/A much longer name.text").expect("created LFN file");
+    vfat.create_file("/lower.txt").expect("created lowercase file");
+
+    assert_eq!(entry_names(&vfat, "/"),
+               vec!["A much longer name.text", "HELLO.TXT", "lower.txt"]);
+
+    let entry = vfat.open("/a MUCH longer NAME.text").expect("case-insensitive lookup");
+    assert!(entry.is_file());
+    assert_eq!(entry.name(), "A much longer name.text");
+
+    let e = vfat.create_file("/hello.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+
+    let e = vfat.create_file("/missing/file").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    let e = vfat.create_file("/bad:name").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // The limit is 255 UTF-16 code units, not 255 bytes.
+    let name: String = ::std::iter::repeat('é').take(255).collect();
+    vfat.create_file(Path::new("/").join(&name)).expect("created 255-character name");
+    assert_eq!(vfat.open(Path::new("/").join(&name)).expect("opened").name(), name);
+
+    let e = vfat.create_file(Path::new("/").join(name + "é")).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_create_many_similar_names() {
+    let vfat = VFat::from(fat32_image()).expect("image mounts");
+
+    // Enough entries to spill the root directory into more clusters, all of
+    // which need distinct generated short names.
+    let names: Vec<_> = (0..40).map(|i| format!("Long File Name {:02}.data", i)).collect();
+    for name in &names {
+        vfat.create_file(Path::new("/").join(name)).expect("created file");
+    }
+
+    assert_eq!(entry_names(&vfat, "/"), names);
+}
+
+#[test]
+fn test_create_dir() {
+    let vfat = VFat::from(fat32_image()).expect("image mounts");
+
+    let e = vfat.create_dir("/a/b", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    vfat.create_dir("/a/b/c", true).expect("created with parents");
+    vfat.create_file("/a/b/c/file.bin").expect("created nested file");
+    vfat.create_dir("/a/Second Directory", false).expect("created sibling");
+
+    assert_eq!(entry_names(&vfat, "/"), vec!["a"]);
+    assert_eq!(entry_names(&vfat, "/a"), vec![".", "..", "Second Directory", "b"]);
+    assert_eq!(entry_names(&vfat, "/a/b/c"), vec![".", "..", "file.bin"]);
+    assert_eq!(entry_names(&vfat, "/a/b/c/../.."), entry_names(&vfat, "/a"));
+    assert_eq!(entry_names(&vfat, "/a/.."), entry_names(&vfat, "/"));
+
+    let e = vfat.create_dir("/a/b", true).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn test_rename() {
+    let vfat = VFat::from(fat32_image()).expect("image mounts");
+    vfat.create_dir("/src/inner", true).expect("created dirs");
+    vfat.create_dir("/dst", false).expect("created dir");
+    vfat.create_file("/src/inner/file.txt").expect("created file");
+
+    let modified = vfat.open("/src/inner/file.txt").unwrap().metadata().modified();
+    vfat.borrow_mut().set_clock(|| ::vfat::Timestamp::new(2020, 6, 15, 12, 30, 0));
+    vfat.rename("/src/inner/file.txt", "/src/inner/Renamed File.txt").expect("renamed file");
+    assert_eq!(entry_names(&vfat, "/src/inner"), vec![".", "..", "Renamed File.txt"]);
+
+    // Renaming keeps the file's timestamps.
+    let renamed = vfat.open("/src/inner/Renamed File.txt").unwrap();
+    assert_eq!(renamed.metadata().modified(), modified);
+
+    vfat.rename("/src/inner", "/dst/moved").expect("moved dir");
+    assert_eq!(entry_names(&vfat, "/src"), vec![".", ".."]);
+    assert_eq!(entry_names(&vfat, "/dst/moved"), vec![".", "..", "Renamed File.txt"]);
+    assert_eq!(entry_names(&vfat, "/dst/moved/.."), vec![".", "..", "moved"]);
+
+    let e = vfat.open("/src/inner").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+    let e = vfat.rename("/dst", "/dst/moved/dst").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // Names are compared without regard to case, and paths aren't normalized.
+    let e = vfat.rename("/dst", "/DST/moved/dst").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = vfat.rename("/dst/moved", "/dst/../dst/moved/again").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(entry_names(&vfat, "/dst"), vec![".", "..", "moved"]);
+
+    vfat.create_file("/taken").expect("created file");
+    let e = vfat.rename("/src", "/taken").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn test_remove() {
+    let vfat = VFat::from(fat32_image()).expect("image mounts");
+    vfat.create_dir("/dir/sub", true).expect("created dirs");
+    vfat.create_file("/dir/sub/A Long File Name").expect("created file");
+    vfat.create_file("/top").expect("created file");
+
+    vfat.remove("/top", false).expect("removed file");
+    assert_eq!(entry_names(&vfat, "/"), vec!["dir"]);
+
+    let e = vfat.remove("/dir", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+
+    vfat.remove("/dir", true).expect("removed dir");
+    assert!(entry_names(&vfat, "/").is_empty());
+
+    let e = vfat.remove("/", true).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // Freed clusters and directory slots are reused.
+    vfat.create_dir("/again/and/again", true).expect("created dirs");
+    assert_eq!(entry_names(&vfat, "/"), vec!["again"]);
+}
+
+/// A disk image that remains accessible after it is handed to `VFat::from`.
+/// Every read request made to the device is counted.
+#[derive(Clone)]
+struct SharedImage(Arc<Mutex<Cursor<Vec<u8>>>>, Arc<AtomicUsize>);
+
+impl SharedImage {
+    fn new() -> SharedImage {
+        SharedImage(Arc::new(Mutex::new(fat32_image())), Arc::new(AtomicUsize::new(0)))
+    }
+
+    fn read_requests(&self) -> usize {
+        self.1.load(Ordering::SeqCst)
+    }
+
+    fn mount(&self) -> Shared<VFat> {
+        VFat::from(self.clone()).expect("image mounts")
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.0.lock().unwrap().get_ref()[offset..(offset + len)].to_vec()
+    }
+
+    fn write(&self, offset: usize, bytes: &[u8]) {
+        put(self.0.lock().unwrap().get_mut(), offset, bytes);
+    }
+
+    fn free_clusters(&self) -> u32 {
+        let bytes = self.bytes(2 * 512 + 488, 4);
+        bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+    }
+}
+
+impl BlockDevice for SharedImage {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.lock().unwrap().read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write_sector(n, buf)
+    }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.lock().unwrap().read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write_sectors(start, count, buf)
+    }
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+#[test]
+fn test_file_write() {
+    let image = SharedImage::new();
+    let data = pattern(3000);
+    {
+        let vfat = image.mount();
+        let mut file = vfat.create_file("/data.bin").expect("created file");
+        file.write_all(&data).expect("wrote data");
+        assert_eq!(file.size(), 3000);
+
+        // Overwriting in the middle doesn't change the size.
+        file.seek(SeekFrom::Start(1000)).unwrap();
+        file.write_all(&[0xAA; 600]).unwrap();
+        assert_eq!(file.size(), 3000);
+        file.sync().expect("synced");
+
+        let mut file = vfat.open_file("/data.bin").expect("file exists");
+        assert_eq!(file.size(), 3000);
+    }
+
+    let vfat = image.mount();
+    let mut file = vfat.open_file("/data.bin").expect("file exists after remount");
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).expect("read file");
+
+    let mut expected = data.clone();
+    expected[1000..1600].copy_from_slice(&[0xAA; 600]);
+    assert_eq!(contents, expected);
+
+    // 3000 bytes take six 512-byte clusters.
+    assert_eq!(image.free_clusters(), IMAGE_FREE_CLUSTERS - 6);
+
+    // Both copies of the FAT are kept in sync.
+    let fat_size = 532 * 512;
+    let first_fat = image.bytes((1 + 32) * 512, fat_size);
+    assert_eq!(first_fat, image.bytes((1 + 32) * 512 + fat_size, fat_size));
+}
+
+#[test]
+fn test_file_append_many() {
+    let image = SharedImage::new();
+    let vfat = image.mount();
+    let mut file = vfat.create_file("/Appended To.log").expect("created file");
+    for i in 0..100 {
+        write!(file, "line {:03}\n", i).expect("appended line");
+    }
+
+    file.sync().unwrap();
+    let mut contents = String::new();
+    image.mount().open_file("/appended to.log").unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents.lines().count(), 100);
+    assert_eq!(contents.lines().last(), Some("line 099"));
+}
+
+#[test]
+fn test_file_set_len() {
+    let image = SharedImage::new();
+    let vfat = image.mount();
+    let data = pattern(2000);
+
+    let mut file = vfat.create_file("/file").expect("created file");
+    file.write_all(&data).unwrap();
+    file.set_len(600).expect("truncated");
+    assert_eq!(file.size(), 600);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 600);
+    file.sync().unwrap();
+    assert_eq!(image.free_clusters(), IMAGE_FREE_CLUSTERS - 2);
+
+    file.set_len(1100).expect("extended");
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).unwrap();
+    assert_eq!(&contents[..600], &data[..600]);
+    assert!(contents[600..].iter().all(|&b| b == 0));
+    assert_eq!(contents.len(), 1100);
+
+    file.set_len(0).expect("emptied");
+    file.sync().unwrap();
+    assert_eq!(image.free_clusters(), IMAGE_FREE_CLUSTERS);
+
+    let mut file = image.mount().open_file("/file").unwrap();
+    assert_eq!(file.size(), 0);
+    assert_eq!(file.read(&mut [0u8; 16]).unwrap(), 0);
+}
+
+#[test]
+fn test_fsinfo_stale_free_count() {
+    let image = SharedImage::new();
+    let clusters = image.mount().borrow().max_cluster() - 1;
+
+    // A count that is too low stops at zero instead of wrapping.
+    image.write(2 * 512 + 488, &u32_le(1));
+    {
+        let vfat = image.mount();
+        vfat.create_file("/data.bin").unwrap().write_all(&pattern(3000)).unwrap();
+    }
+    assert_eq!(image.free_clusters(), 0);
+
+    // One that is too high stops at the number of clusters.
+    image.write(2 * 512 + 488, &u32_le(clusters));
+    image.mount().remove("/data.bin", false).unwrap();
+    assert_eq!(image.free_clusters(), clusters);
+
+    // An unknown count is left unknown.
+    image.write(2 * 512 + 488, &u32_le(0xFFFFFFFF));
+    image.mount().create_file("/data.bin").unwrap().write_all(&[1]).unwrap();
+    assert_eq!(image.free_clusters(), 0xFFFFFFFF);
+}
+
+#[test]
+fn test_cache_writes_back_on_eviction() {
+    use vfat::{CachedDevice, Partition};
+
+    let image = SharedImage::new();
+    let partition = Partition { start: 1, sector_size: 512 };
+    let mut cache = CachedDevice::with_capacity(image.clone(), partition, 2);
+
+    cache.get_mut(100).unwrap()[0] = 0xAB;
+    cache.get(101).unwrap();
+    assert_eq!(image.bytes(100 * 512, 1), vec![0]);
+
+    // Sector 101 was used more recently than sector 100, so 100 is evicted.
+    cache.get(101).unwrap();
+    cache.get(102).unwrap();
+    assert_eq!(image.bytes(100 * 512, 1), vec![0xAB]);
+    assert_eq!(cache.get(100).unwrap()[0], 0xAB);
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 4));
+    assert_eq!((stats.evictions, stats.writebacks), (2, 1));
+
+    cache.get_mut(102).unwrap()[1] = 0xCD;
+    drop(cache);
+    assert_eq!(image.bytes(102 * 512, 2), vec![0, 0xCD]);
+}
+
+#[test]
+fn test_small_cache() {
+    let image = SharedImage::new();
+    let data = pattern(20000);
+    {
+        let vfat = image.mount();
+        vfat.borrow_mut().set_cache_capacity(4).unwrap();
+        vfat.create_dir("/a/b", true).unwrap();
+        vfat.create_file("/a/b/big").unwrap().write_all(&data).unwrap();
+
+        let mut contents = Vec::new();
+        vfat.open_file("/a/b/big").unwrap().read_to_end(&mut contents).unwrap();
+        assert!(contents == data);
+
+        let stats = vfat.borrow().cache_stats();
+        assert!(stats.evictions > 0 && stats.writebacks > 0);
+        assert!(stats.hits > 0);
+    }
+
+    // Dropping the file system flushes the remaining dirty sectors.
+    let mut contents = Vec::new();
+    image.mount().open_file("/a/b/big").unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == data);
+}
+
+/// A device implementing only the required `BlockDevice` methods.
+struct SectorAtATime(Vec<u8>);
+
+impl BlockDevice for SectorAtATime {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = n as usize * 512;
+        let amount = ::std::cmp::min(512, buf.len());
+        buf[..amount].copy_from_slice(&self.0[start..(start + amount)]);
+        Ok(amount)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let start = n as usize * 512;
+        let amount = ::std::cmp::min(512, buf.len());
+        self.0[start..(start + amount)].copy_from_slice(&buf[..amount]);
+        Ok(amount)
+    }
+}
+
+#[test]
+fn test_multi_sector_defaults() {
+    let mut device = SectorAtATime(vec![0; 512 * 8]);
+    let data = pattern(512 * 3);
+    assert_eq!(device.write_sectors(2, 3, &data).unwrap(), 512 * 3);
+    assert_eq!(&device.0[1024..(1024 + 1536)], &data[..]);
+
+    let mut buf = vec![0; 512 * 4];
+    assert_eq!(device.read_sectors(1, 4, &mut buf).unwrap(), 512 * 4);
+    assert!(buf[..512].iter().all(|&b| b == 0));
+    assert_eq!(&buf[512..], &data[..]);
+
+    let e = device.read_sectors(0, 2, &mut [0; 1000]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    let e = device.write_sectors(0, 2, &[0; 1000]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+/// A device that refuses writes, as the kernel's SD card driver does.
+struct ReadOnly(SharedImage);
+
+impl BlockDevice for ReadOnly {
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
+        panic!("a read-only device was written to")
+    }
+}
+
+#[test]
+fn test_read_only_device() {
+    use volume::Volume;
+
+    let image = SharedImage::new();
+    image.mount().create_file("/kept.txt").unwrap().write_all(b"data").unwrap();
+
+    let vfat = VFat::from(ReadOnly(image.clone())).expect("mounted read-only");
+    assert!(vfat.borrow().is_read_only());
+    let denied = io::ErrorKind::PermissionDenied;
+    assert_eq!(vfat.create_file("/new.txt").unwrap_err().kind(), denied);
+    assert_eq!(vfat.create_dir("/new", false).unwrap_err().kind(), denied);
+    assert_eq!(vfat.rename("/kept.txt", "/moved.txt").unwrap_err().kind(), denied);
+    assert_eq!(vfat.remove("/kept.txt", false).unwrap_err().kind(), denied);
+
+    let mut file = vfat.open_file("/kept.txt").unwrap();
+    assert_eq!(file.write_all(b"more").unwrap_err().kind(), denied);
+    assert_eq!(file.set_len(0).unwrap_err().kind(), denied);
+
+    // Refused changes leave nothing behind to write back later.
+    let mut contents = String::new();
+    vfat.open_file("/kept.txt").unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "data");
+    assert_eq!(entry_names(&vfat, "/"), vec!["kept.txt"]);
+    vfat.borrow_mut().set_cache_capacity(1).unwrap();
+    assert_eq!(vfat.borrow().cache_stats().writebacks, 0);
+
+    assert!(Volume::from(ReadOnly(image.clone())).unwrap().is_read_only());
+    assert!(!Volume::from(image).unwrap().is_read_only());
+}
+
+#[test]
+fn test_cluster_read_ahead() {
+    let image = SharedImage::new();
+    let data = pattern(64 * 1024);
+    image.mount().create_file("/firmware.bin").unwrap().write_all(&data).unwrap();
+
+    let vfat = image.mount();
+    let mut file = vfat.open_file("/firmware.bin").unwrap();
+    let before = image.read_requests();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).unwrap();
+    assert!(contents == data);
+
+    // 128 contiguous clusters are read in runs of up to half the cache
+    // rather than one request per sector. A few FAT sectors are also read.
+    let requests = image.read_requests() - before;
+    assert!(requests <= 8, "{} read requests", requests);
+    assert!(vfat.borrow().cache_stats().read_aheads >= 128);
+}
+
+/// Builds a GPT disk holding a FAT32 basic data partition at LBA 34 and an
+/// unused, Linux-typed partition after it. Returns the image and the LBA of
+/// the backup header.
+fn gpt_image() -> (Vec<u8>, usize) {
+    use util::crc32;
+    use gpt::Guid;
+
+    let mut data = fat32_image_at(34);
+    let fat_end = data.len() / 512;
+    data.resize((fat_end + 8 + 33) * 512, 0);
+    let sectors = data.len() / 512;
+    let backup = sectors - 1;
+
+    // Protective MBR covering the whole disk.
+    put(&mut data, 446, &[0; 16]);
+    put(&mut data, 446 + 4, &[0xEE]);
+    put(&mut data, 446 + 8, &u32_le(1));
+    put(&mut data, 446 + 12, &u32_le(sectors as u32 - 1));
+
+    let mut entries = vec![0u8; 128 * 128];
+    let name: Vec<u8> = "FIRMWARE".encode_utf16().flat_map(|c| vec![c as u8, (c >> 8) as u8]).collect();
+    put(&mut entries, 0, &Guid::BASIC_DATA.0);
+    put(&mut entries, 16, &[0x11; 16]);
+    put(&mut entries, 32, &u64_le(34));
+    put(&mut entries, 40, &u64_le(fat_end as u64 - 1));
+    put(&mut entries, 56, &name);
+    put(&mut entries, 128, &Guid::LINUX_FILESYSTEM.0);
+    put(&mut entries, 128 + 16, &[0x22; 16]);
+    put(&mut entries, 128 + 32, &u64_le(fat_end as u64));
+    put(&mut entries, 128 + 40, &u64_le(fat_end as u64 + 7));
+    let entries_crc = crc32(&entries);
+
+    let header = |current: usize, other: usize, entries_lba: usize| {
+        let mut header = vec![0u8; 512];
+        put(&mut header, 0, b"EFI PART");
+        put(&mut header, 8, &u32_le(0x00010000));
+        put(&mut header, 12, &u32_le(92));
+        put(&mut header, 24, &u64_le(current as u64));
+        put(&mut header, 32, &u64_le(other as u64));
+        put(&mut header, 40, &u64_le(34));
+        put(&mut header, 48, &u64_le(backup as u64 - 33));
+        put(&mut header, 56, &[0x33; 16]);
+        put(&mut header, 72, &u64_le(entries_lba as u64));
+        put(&mut header, 80, &u32_le(128));
+        put(&mut header, 84, &u32_le(128));
+        put(&mut header, 88, &u32_le(entries_crc));
+        let crc = crc32(&header[..92]);
+        put(&mut header, 16, &u32_le(crc));
+        header
+    };
+
+    let (primary, secondary) = (header(1, backup, 2), header(backup, 1, backup - 32));
+    put(&mut data, 512, &primary);
+    put(&mut data, 2 * 512, &entries);
+    put(&mut data, (backup - 32) * 512, &entries);
+    put(&mut data, backup * 512, &secondary);
+    (data, backup)
+}
+
+#[test]
+fn test_gpt() {
+    use gpt::{Guid, GuidPartitionTable};
+
+    let (data, backup) = gpt_image();
+    let gpt = GuidPartitionTable::from(Cursor::new(data)).expect("valid GPT");
+    assert!(!gpt.is_from_backup());
+    assert_eq!(gpt.header().backup_lba(), backup as u64);
+    assert_eq!(gpt.header().disk_guid(), Guid([0x33; 16]));
+
+    let partitions: Vec<_> = gpt.partitions().collect();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(partitions[0].type_guid(), Guid::BASIC_DATA);
+    assert_eq!(partitions[0].name(), "FIRMWARE");
+    assert_eq!(partitions[0].first_lba(), 34);
+    assert_eq!(partitions[0].total_sectors(), IMAGE_SECTORS as u64);
+    assert_eq!(partitions[1].type_guid(), Guid::LINUX_FILESYSTEM);
+    assert_eq!(partitions[1].total_sectors(), 8);
+    assert_eq!(gpt.first_fat().unwrap().unique_guid(), Guid([0x11; 16]));
+
+    assert_eq!(Guid::EFI_SYSTEM.to_string(), "C12A7328-F81F-11D2-BA4B-00A0C93EC93B");
+    assert_eq!(Guid::BASIC_DATA.to_string(), "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7");
+}
+
+#[test]
+fn test_gpt_backup_fallback() {
+    use gpt::{Error, GuidPartitionTable};
+
+    // A corrupt primary header: the backup is found through the protective MBR.
+    let (mut data, backup) = gpt_image();
+    data[512 + 40] ^= 0xFF;
+    let gpt = GuidPartitionTable::from(Cursor::new(data)).expect("backup GPT");
+    assert!(gpt.is_from_backup());
+    assert_eq!(gpt.header().current_lba(), backup as u64);
+    assert_eq!(gpt.partitions().count(), 2);
+
+    // A corrupt primary entry array: the backup is found through the header.
+    let (mut data, _) = gpt_image();
+    data[2 * 512 + 60] ^= 0xFF;
+    let gpt = GuidPartitionTable::from(Cursor::new(data)).expect("backup GPT");
+    assert!(gpt.is_from_backup());
+    assert_eq!(gpt.first_fat().unwrap().name(), "FIRMWARE");
+
+    // Both tables corrupt: the primary table's error is reported.
+    let (mut data, backup) = gpt_image();
+    data[512 + 40] ^= 0xFF;
+    data[backup * 512] = b'X';
+    let e = GuidPartitionTable::from(Cursor::new(data)).unwrap_err();
+    expect_variant!(e, Error::BadHeaderCrc);
+
+    let (mut data, _) = gpt_image();
+    data[2 * 512 + 60] ^= 0xFF;
+    data[(backup - 32) * 512 + 60] ^= 0xFF;
+    let e = GuidPartitionTable::from(Cursor::new(data)).unwrap_err();
+    expect_variant!(e, Error::BadEntriesCrc);
+
+    let e = GuidPartitionTable::from(fat32_image()).unwrap_err();
+    expect_variant!(e, Error::NoProtectiveMbr);
+}
+
+#[test]
+fn test_gpt_entries_bounded() {
+    use util::crc32;
+    use gpt::{Error, GuidPartitionTable};
+
+    // Headers with valid checksums but entry arrays far too large, or whose
+    // size overflows, are rejected rather than allocated.
+    for &(num_entries, entry_size) in &[(0x10_0000, 128), (0xFFFF_FFFF, 0xFFFF_FFF8)] {
+        let (mut data, backup) = gpt_image();
+        for &lba in &[1, backup] {
+            let header = lba * 512;
+            put(&mut data, header + 80, &u32_le(num_entries));
+            put(&mut data, header + 84, &u32_le(entry_size));
+            put(&mut data, header + 16, &[0; 4]);
+            let crc = crc32(&data[header..header + 92]);
+            put(&mut data, header + 16, &u32_le(crc));
+        }
+
+        let e = GuidPartitionTable::from(Cursor::new(data)).unwrap_err();
+        expect_variant!(e, Error::BadHeader);
+    }
+}
+
+#[test]
+fn test_vfat_on_gpt() {
+    let (data, _) = gpt_image();
+    let vfat = VFat::from(Cursor::new(data)).expect("mounts through GPT");
+    vfat.create_dir("/boot", false).unwrap();
+    vfat.create_file("/boot/kernel8.img").unwrap().write_all(b"kernel").unwrap();
+
+    let mut contents = String::new();
+    vfat.open_file("/boot/kernel8.img").unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "kernel");
+
+    // Neither a FAT32 MBR partition nor a protective MBR.
+    let mut data = fat32_image().into_inner();
+    data[446 + 4] = 0x83;
+    let e = VFat::from(Cursor::new(data)).unwrap_err();
+    expect_variant!(e, ::vfat::Error::NotFound);
+}
+
+/// Builds an empty FAT12 or FAT16 image with 512-byte sectors and clusters,
+/// one reserved sector, and two FATs of `sectors_per_fat` sectors. With
+/// `mbr`, the volume is in a partition starting at sector 1; otherwise, the
+/// image has no partition table, like a floppy disk.
+fn small_fat_image(total: usize, sectors_per_fat: usize, root_entries: usize, mbr: bool) -> Vec<u8> {
+    let start = if mbr { 1 } else { 0 };
+    let mut data = vec![0u8; (start + total) * 512];
+    if mbr {
+        put(&mut data, 446 + 4, &[0x06]);
+        put(&mut data, 446 + 8, &u32_le(start as u32));
+        put(&mut data, 446 + 12, &u32_le(total as u32));
+        put(&mut data, 510, &[0x55, 0xAA]);
+    }
+
+    let boot = start * 512;
+    put(&mut data, boot, &[0xEB, 0x3C, 0x90]);
+    put(&mut data, boot + 3, b"MSWIN4.1");
+    put(&mut data, boot + 11, &u16_le(512));
+    put(&mut data, boot + 13, &[1]);
+    put(&mut data, boot + 14, &u16_le(1));
+    put(&mut data, boot + 16, &[2]);
+    put(&mut data, boot + 17, &u16_le(root_entries as u16));
+    put(&mut data, boot + 19, &u16_le(total as u16));
+    put(&mut data, boot + 21, &[0xF0]);
+    put(&mut data, boot + 22, &u16_le(sectors_per_fat as u16));
+    put(&mut data, boot + 38, &[0x29]);
+    put(&mut data, boot + 43, b"NO NAME    FAT     ");
+    put(&mut data, boot + 510, &[0x55, 0xAA]);
+
+    let reserved: &[u8] = if total < 4085 { &[0xF0, 0xFF, 0xFF] } else { &[0xF0, 0xFF, 0xFF, 0xFF] };
+    for fat in 0..2 {
+        put(&mut data, boot + (1 + fat * sectors_per_fat) * 512, reserved);
+    }
+
+    data
+}
+
+#[test]
+fn test_fat_type_detection() {
+    use vfat::FatType;
+
+    assert_eq!(FatType::from_cluster_count(1), FatType::Fat12);
+    assert_eq!(FatType::from_cluster_count(4084), FatType::Fat12);
+    assert_eq!(FatType::from_cluster_count(4085), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65524), FatType::Fat16);
+    assert_eq!(FatType::from_cluster_count(65525), FatType::Fat32);
+
+    let vfat = VFat::from(fat32_image()).unwrap();
+    assert_eq!(vfat.borrow().fat_type(), FatType::Fat32);
+}
+
+#[test]
+fn test_fat16() {
+    use vfat::FatType;
+
+    let image = Arc::new(Mutex::new(Cursor::new(small_fat_image(8192, 32, 512, true))));
+    let mount = || VFat::from(SharedImage(image.clone(), Arc::new(AtomicUsize::new(0)))).unwrap();
+    let data = pattern(10000);
+    {
+        let vfat = mount();
+        assert_eq!(vfat.borrow().fat_type(), FatType::Fat16);
+        vfat.create_dir("/recovery/images", true).unwrap();
+        vfat.create_file("/recovery/images/A Long Image Name.img").unwrap()
+            .write_all(&data).unwrap();
+        vfat.create_file("/README.TXT").unwrap().write_all(b"fat16").unwrap();
+    }
+
+    let vfat = mount();
+    assert_eq!(entry_names(&vfat, "/"), vec!["README.TXT", "recovery"]);
+    assert_eq!(entry_names(&vfat, "/recovery/.."), entry_names(&vfat, "/"));
+    let mut contents = Vec::new();
+    vfat.open_file("/recovery/images/a long image name.img").unwrap()
+        .read_to_end(&mut contents).unwrap();
+    assert!(contents == data);
+
+    vfat.rename("/recovery/images", "/images").unwrap();
+    assert_eq!(entry_names(&vfat, "/images/.."), entry_names(&vfat, "/"));
+    vfat.remove("/recovery", true).unwrap();
+    vfat.remove("/images", true).unwrap();
+    assert_eq!(entry_names(&vfat, "/"), vec!["README.TXT"]);
+
+    // The root directory has room for exactly 512 entries and can't grow.
+    for i in 1..512 {
+        vfat.create_file(format!("/F{:04}", i)).unwrap();
+    }
+
+    let e = vfat.create_file("/F0512").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    vfat.create_file("/recovery/nested").unwrap_err();
+    assert_eq!(entry_names(&vfat, "/").len(), 512);
+}
+
+#[test]
+fn test_fat12_floppy() {
+    use vfat::FatType;
+
+    let image = Arc::new(Mutex::new(Cursor::new(small_fat_image(2880, 9, 224, false))));
+    let mount = || VFat::from(SharedImage(image.clone(), Arc::new(AtomicUsize::new(0)))).unwrap();
+    let (first, second) = (pattern(5000), pattern(1500));
+    {
+        let vfat = mount();
+        assert_eq!(vfat.borrow().fat_type(), FatType::Fat12);
+
+        // Interleave two files so that their chains are not contiguous.
+        let mut a = vfat.create_file("/first.bin").unwrap();
+        let mut b = vfat.create_file("/second.bin").unwrap();
+        for (x, y) in first.chunks(500).zip(second.chunks(150)) {
+            a.write_all(x).unwrap();
+            b.write_all(y).unwrap();
+        }
+
+        a.set_len(4000).unwrap();
+    }
+
+    let vfat = mount();
+    let mut contents = Vec::new();
+    vfat.open_file("/first.bin").unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(&contents[..], &first[..4000]);
+
+    contents.clear();
+    vfat.open_file("/second.bin").unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, second);
+
+    // Both FATs were updated alike.
+    let data = image.lock().unwrap().get_ref().clone();
+    assert_eq!(&data[512..(10 * 512)], &data[(10 * 512)..(19 * 512)]);
+}
+
+/// The number of clusters in the cluster heap of `exfat_image_at()`.
+const EXFAT_CLUSTERS: u32 = 256;
+
+/// The packed date and time 2024-03-15 10:20:30 used for every exFAT entry.
+const EXFAT_TIMESTAMP: u32 = (44 << 25) | (3 << 21) | (15 << 16) | (10 << 11) | (20 << 5) | 15;
+
+/// Builds a file directory entry set named `name` whose stream starts at
+/// cluster `first` and is `len` bytes long with `valid` bytes written.
+fn exfat_entry_set(name: &str, attributes: u16, first: u32, valid: u64, len: u64,
+                   contiguous: bool) -> Vec<u8> {
+    let units: Vec<u16> = name.encode_utf16().collect();
+    let names = (units.len() + 14) / 15;
+    let mut set = vec![0u8; 32 * (2 + names)];
+
+    put(&mut set, 0, &[0x85, 1 + names as u8]);
+    put(&mut set, 4, &u16_le(attributes));
+    for &offset in &[8, 12, 16] {
+        put(&mut set, offset, &u32_le(EXFAT_TIMESTAMP));
+    }
+    put(&mut set, 20, &[150, 0, 0x84, 0x84, 0x84]);
+
+    put(&mut set, 32, &[0xC0, if contiguous { 0x03 } else { 0x01 }, 0, units.len() as u8]);
+    put(&mut set, 40, &u64_le(valid));
+    put(&mut set, 52, &u32_le(first));
+    put(&mut set, 56, &u64_le(len));
+
+    for (i, chunk) in units.chunks(15).enumerate() {
+        let entry = 64 + i * 32;
+        set[entry] = 0xC1;
+        for (j, &unit) in chunk.iter().enumerate() {
+            put(&mut set, entry + 2 + j * 2, &u16_le(unit));
+        }
+    }
+
+    let mut checksum = 0u16;
+    for (i, &byte) in set.iter().enumerate() {
+        if i != 2 && i != 3 {
+            checksum = checksum.rotate_right(1).wrapping_add(byte as u16);
+        }
+    }
+
+    put(&mut set, 2, &u16_le(checksum));
+    set
+}
+
+fn exfat_checksum(data: &[u8], skip: &[usize]) -> u32 {
+    data.iter().enumerate()
+        .filter(|&(i, _)| !skip.contains(&i))
+        .fold(0u32, |sum, (_, &byte)| sum.rotate_right(1).wrapping_add(byte as u32))
+}
+
+/// Rewrites the checksum sector of the boot region starting at `region`.
+fn exfat_seal_boot_region(data: &mut [u8], region: usize) {
+    let checksum = exfat_checksum(&data[region..(region + 11 * 512)], &[106, 107, 112]);
+    for i in 0..128 {
+        put(data, region + 11 * 512 + i * 4, &u32_le(checksum));
+    }
+}
+
+/// Builds an exFAT volume with 512-byte sectors and clusters starting at
+/// sector `start`, preceded by an MBR if `start` is non-zero. The root
+/// directory spans two clusters and holds `Hello.txt` (contiguous), `Docs/notes.md` (a fragmented FAT
+/// chain), a file with a long name whose tail was never written, a deleted
+/// file, and a file whose entry set checksum is wrong.
+fn exfat_image_at(start: usize) -> Vec<u8> {
+    let (fat_offset, heap_offset) = (24usize, 32usize);
+    let volume_sectors = heap_offset + EXFAT_CLUSTERS as usize;
+    let mut data = vec![0u8; (start + volume_sectors) * 512];
+
+    if start > 0 {
+        put(&mut data, 446 + 4, &[0x07]);
+        put(&mut data, 446 + 8, &u32_le(start as u32));
+        put(&mut data, 446 + 12, &u32_le(volume_sectors as u32));
+        put(&mut data, 510, &[0x55, 0xAA]);
+    }
+
+    let volume = start * 512;
+    let sector = |n: usize| volume + n * 512;
+    let cluster = |n: usize| volume + (heap_offset + n - 2) * 512;
+
+    // Main boot region, then its backup.
+    put(&mut data, sector(0), &[0xEB, 0x76, 0x90]);
+    put(&mut data, sector(0) + 3, b"EXFAT   ");
+    put(&mut data, sector(0) + 64, &u64_le(start as u64));
+    put(&mut data, sector(0) + 72, &u64_le(volume_sectors as u64));
+    put(&mut data, sector(0) + 80, &u32_le(fat_offset as u32));
+    put(&mut data, sector(0) + 84, &u32_le(8));
+    put(&mut data, sector(0) + 88, &u32_le(heap_offset as u32));
+    put(&mut data, sector(0) + 92, &u32_le(EXFAT_CLUSTERS));
+    put(&mut data, sector(0) + 96, &u32_le(4));
+    put(&mut data, sector(0) + 100, &u32_le(0xCAFEF00D));
+    put(&mut data, sector(0) + 104, &u16_le(0x0100));
+    put(&mut data, sector(0) + 108, &[9, 0, 1, 0x80, 4]);
+    put(&mut data, sector(0) + 510, &[0x55, 0xAA]);
+    for n in 1..9 {
+        put(&mut data, sector(n) + 510, &[0x55, 0xAA]);
+    }
+    exfat_seal_boot_region(&mut data, sector(0));
+    let main: Vec<u8> = data[sector(0)..sector(12)].to_vec();
+    put(&mut data, sector(12), &main);
+
+    // FAT: media and reserved entries, then the chains that use it.
+    let fat = sector(fat_offset);
+    let links: &[(usize, u32)] = &[
+        (0, 0xFFFFFFF8), (1, 0xFFFFFFFF), (2, 0xFFFFFFFF), (3, 0xFFFFFFFF),
+        (4, 12), (12, 0xFFFFFFFF), (7, 0xFFFFFFFF), (8, 10), (10, 9), (9, 0xFFFFFFFF),
+    ];
+    for &(index, value) in links {
+        put(&mut data, fat + index * 4, &u32_le(value));
+    }
+
+    // Allocation bitmap in cluster 2: clusters 2 through 12 are in use.
+    put(&mut data, cluster(2), &[0xFF, 0x07]);
+
+    // Up-case table in cluster 3: identity up to 'a', then 'a'-'z' map to
+    // 'A'-'Z', and identity beyond the end of the table.
+    let mut table = vec![0xFF, 0xFF, 0x61, 0x00];
+    for c in b'A'..(b'Z' + 1) {
+        table.extend_from_slice(&u16_le(c as u16));
+    }
+    put(&mut data, cluster(3), &table);
+
+    // Root directory in clusters 4 and 12.
+    let mut root = Vec::new();
+    let mut label = vec![0u8; 32];
+    put(&mut label, 0, &[0x83, 6]);
+    for (i, unit) in "SDCARD".encode_utf16().enumerate() {
+        put(&mut label, 2 + i * 2, &u16_le(unit));
+    }
+    root.extend(label);
+
+    let mut bitmap = vec![0u8; 32];
+    put(&mut bitmap, 0, &[0x81]);
+    put(&mut bitmap, 20, &u32_le(2));
+    put(&mut bitmap, 24, &u64_le(32));
+    root.extend(bitmap);
+
+    let mut upcase = vec![0u8; 32];
+    put(&mut upcase, 0, &[0x82]);
+    put(&mut upcase, 4, &u32_le(exfat_checksum(&table, &[])));
+    put(&mut upcase, 20, &u32_le(3));
+    put(&mut upcase, 24, &u64_le(table.len() as u64));
+    root.extend(upcase);
+
+    root.extend(exfat_entry_set("Hello.txt", 0x20, 5, 700, 700, true));
+    let mut deleted = exfat_entry_set("gone.txt", 0x20, 13, 10, 10, true);
+    for entry in deleted.chunks_mut(32) {
+        entry[0] &= 0x7F;
+    }
+    root.extend(deleted);
+    let mut corrupt = exfat_entry_set("corrupt.txt", 0x20, 13, 10, 10, true);
+    corrupt[2] ^= 0xFF;
+    root.extend(corrupt);
+    root.extend(exfat_entry_set("Docs", 0x10, 7, 512, 512, false));
+    root.extend(exfat_entry_set("A file with a rather long name.txt", 0x21, 11, 4, 10, true));
+    put(&mut data, cluster(4), &root[..512]);
+    put(&mut data, cluster(12), &root[512..]);
+
+    // File contents and the `Docs` directory.
+    put(&mut data, cluster(5), &pattern(700));
+    put(&mut data, cluster(7), &exfat_entry_set("notes.md", 0x20, 8, 1200, 1200, false));
+    let notes = pattern(1500);
+    put(&mut data, cluster(8), &notes[..512]);
+    put(&mut data, cluster(10), &notes[512..1024]);
+    put(&mut data, cluster(9), &notes[1024..1200]);
+    put(&mut data, cluster(11), b"tail");
+
+    data
+}
+
+#[test]
+fn test_exfat_mount() {
+    use exfat::ExFat;
+
+    let exfat = ExFat::from(Cursor::new(exfat_image_at(0))).expect("mounted exFAT");
+    {
+        let exfat = exfat.borrow();
+        assert_eq!(exfat.label(), "SDCARD");
+        assert_eq!(exfat.serial_number(), 0xCAFEF00D);
+        assert_eq!(exfat.cluster_count(), EXFAT_CLUSTERS);
+        assert_eq!(exfat.free_clusters(), EXFAT_CLUSTERS - 11);
+        assert!(exfat.is_allocated(2) && exfat.is_allocated(12));
+        assert!(!exfat.is_allocated(13) && !exfat.is_allocated(1));
+    }
+
+    let mut names: Vec<String> = exfat.open_dir("/").unwrap().entries().unwrap()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["A file with a rather long name.txt", "Docs", "Hello.txt"]);
+}
+
+#[test]
+fn test_exfat_read_files() {
+    use exfat::ExFat;
+
+    let exfat = ExFat::from(Cursor::new(exfat_image_at(1))).expect("mounted exFAT");
+
+    let mut contents = Vec::new();
+    exfat.open_file("/HELLO.TXT").unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == pattern(700));
+
+    contents.clear();
+    let mut notes = exfat.open_file("/docs/../Docs/NOTES.md").unwrap();
+    assert_eq!(notes.size(), 1200);
+    notes.read_to_end(&mut contents).unwrap();
+    assert!(contents[..] == pattern(1500)[..1200]);
+
+    notes.seek(SeekFrom::Start(1000)).unwrap();
+    let mut buf = [0u8; 24];
+    notes.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &pattern(1500)[1000..1024]);
+    assert!(notes.seek(SeekFrom::Start(1201)).is_err());
+
+    // Bytes past the valid data length read as zeroes.
+    contents.clear();
+    let entry = exfat.open("/a file with a rather long name.TXT").unwrap();
+    assert!(entry.metadata().read_only());
+    entry.into_file().unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(&contents[..], b"tail\0\0\0\0\0\0");
+
+    let created = exfat.open("/Docs").unwrap().metadata().created();
+    assert_eq!((created.year(), created.month(), created.day()), (2024, 3, 15));
+    assert_eq!((created.hour(), created.minute(), created.second()), (10, 20, 31));
+    assert_eq!(created.utc_offset(), Some(60));
+
+    assert_eq!(exfat.open("/gone.txt").unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(exfat.open("/corrupt.txt").unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(exfat.open("/Hello.txt/x").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(exfat.open("/nope/x").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_exfat_stream_too_long() {
+    use exfat::ExFat;
+
+    // The `Docs` entry set, 384 bytes into the root directory, claims far
+    // more data than its single cluster holds.
+    let mut data = exfat_image_at(0);
+    let docs = exfat_entry_set("Docs", 0x10, 7, 1 << 40, 1 << 40, false);
+    put(&mut data, (32 + 4 - 2) * 512 + 384, &docs);
+
+    let exfat = ExFat::from(Cursor::new(data)).expect("mounted exFAT");
+    let e = exfat.open_dir("/Docs").unwrap().entries().err().expect("too long");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_exfat_is_read_only() {
+    use exfat::ExFat;
+
+    let exfat = ExFat::from(Cursor::new(exfat_image_at(0))).unwrap();
+    let denied = io::ErrorKind::PermissionDenied;
+    assert_eq!(exfat.create_file("/new.txt").unwrap_err().kind(), denied);
+    assert_eq!(exfat.create_dir("/new", false).unwrap_err().kind(), denied);
+    assert_eq!(exfat.rename("/Hello.txt", "/Bye.txt").unwrap_err().kind(), denied);
+    assert_eq!(exfat.remove("/Hello.txt", false).unwrap_err().kind(), denied);
+    let mut file = exfat.open_file("/Hello.txt").unwrap();
+    assert_eq!(file.write(b"x").unwrap_err().kind(), denied);
+}
+
+#[test]
+fn test_exfat_boot_checksum() {
+    use exfat::{ExFat, Error};
+
+    // Fields excluded from the checksum can change freely.
+    let mut data = exfat_image_at(0);
+    data[112] = 99;
+    data[106] ^= 1;
+    assert!(ExFat::from(Cursor::new(data.clone())).is_ok());
+
+    // A damaged main boot region falls back to the backup.
+    data[3 * 512 + 7] ^= 0xFF;
+    let exfat = ExFat::from(Cursor::new(data.clone())).expect("mounted from backup");
+    assert_eq!(exfat.borrow().label(), "SDCARD");
+
+    data[15 * 512 + 7] ^= 0xFF;
+    match ExFat::from(Cursor::new(data)) {
+        Err(Error::BadChecksum) => {}
+        other => panic!("expected a bad checksum, got {:?}", other.map(|_| ()))
+    }
+
+    // A FAT32 volume is not an exFAT volume, and vice versa.
+    match ExFat::from(fat32_image()) {
+        Err(Error::NotFound) => {}
+        other => panic!("expected no volume, got {:?}", other.map(|_| ()))
+    }
+    assert!(VFat::from(Cursor::new(exfat_image_at(1))).is_err());
+
+    // Geometry whose sums overflow their on-disk widths is rejected, not
+    // wrapped, before any checksum is computed.
+    let overflows: &[(usize, &[u8])] = &[
+        (108, &[12, 250]),
+        (84, &[0xFF, 0xFF, 0xFF, 0xFF]),
+        (92, &[0xFF, 0xFF, 0xFF, 0xFF]),
+    ];
+    for &(offset, bytes) in overflows {
+        let mut data = exfat_image_at(0);
+        put(&mut data, offset, bytes);
+        put(&mut data, 12 * 512 + offset, bytes);
+        assert!(ExFat::from(Cursor::new(data)).is_err());
+    }
+}
+
+#[test]
+fn test_exfat_upcase_checksum() {
+    use exfat::{ExFat, Error};
+
+    let mut data = exfat_image_at(0);
+    data[(32 + 1) * 512 + 10] ^= 1;
+    match ExFat::from(Cursor::new(data)) {
+        Err(Error::BadChecksum) => {}
+        other => panic!("expected a bad up-case checksum, got {:?}", other.map(|_| ()))
+    }
+}
+
+#[test]
+fn test_volume_mounts_either() {
+    use volume::{self, Volume};
+
+    let volume = Volume::from(Cursor::new(exfat_image_at(1))).expect("mounted exFAT");
+    expect_variant!(volume, Volume::ExFat(_));
+    let mut contents = Vec::new();
+    (&volume).open_file("/hello.txt").unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == pattern(700));
+    let docs = (&volume).open("/Docs").unwrap();
+    assert!(docs.is_dir() && docs.metadata().created().year() == 2024);
+    let names: Vec<_> = docs.into_dir().unwrap().entries().unwrap()
+        .map(|entry| entry.name().to_string())
+        .collect();
+    assert_eq!(names, vec!["notes.md"]);
+    let e = (&volume).create_file("/new.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+
+    for data in vec![fat32_image(), Cursor::new(gpt_image().0)] {
+        let volume = Volume::from(data).expect("mounted FAT");
+        expect_variant!(volume, Volume::Fat(_));
+        (&volume).create_file("/Some File.txt").unwrap().write_all(b"hello").unwrap();
+        let mut file = (&volume).open_file("/some file.TXT").unwrap();
+        assert_eq!(file.size(), 5);
+        file.set_len(2).unwrap();
+        assert_eq!((&volume).stat("/Some File.txt").unwrap().read_only(), false);
+        assert!(volume.cache_stats().hits + volume.cache_stats().misses > 0);
+    }
+
+    match Volume::from(Cursor::new(vec![0u8; 64 * 512])) {
+        Err(volume::Error::Fat(_)) => {}
+        other => panic!("expected no FAT volume, got {:?}", other.map(|_| ()))
+    }
+
+    // A damaged exFAT volume reports its own error rather than FAT's.
+    let mut data = exfat_image_at(0);
+    data[3 * 512 + 7] ^= 0xFF;
+    data[15 * 512 + 7] ^= 0xFF;
+    match Volume::from(Cursor::new(data)) {
+        Err(volume::Error::ExFat(::exfat::Error::BadChecksum)) => {}
+        other => panic!("expected a bad exFAT checksum, got {:?}", other.map(|_| ()))
+    }
+}
+
+/// Byte offsets of the two FATs and of a data cluster in `fat32_image()`.
+const IMAGE_FAT: usize = 33 * 512;
+const IMAGE_FAT_COPY: usize = (33 + 532) * 512;
+fn image_cluster(n: usize) -> usize {
+    (1097 + n - 2) * 512
+}
+
+/// Creates `/A.TXT` (clusters 3-4), `/B.TXT` (5), `/dir` (6) holding a file
+/// with a long name (7), and `/C.TXT` (8-9).
+fn fsck_image() -> SharedImage {
+    let image = SharedImage::new();
+    let vfat = image.mount();
+    vfat.create_file("/A.TXT").unwrap().write_all(&pattern(1000)).unwrap();
+    vfat.create_file("/B.TXT").unwrap().write_all(&pattern(300)).unwrap();
+    vfat.create_dir("/dir", false).unwrap();
+    vfat.create_file("/dir/A long file name.txt").unwrap().write_all(&pattern(100)).unwrap();
+    vfat.create_file("/C.TXT").unwrap().write_all(&pattern(600)).unwrap();
+    image
+}
+
+#[test]
+fn test_fsck_clean() {
+    use vfat::check;
+
+    let image = fsck_image();
+    let report = check(&image.mount(), false).unwrap();
+    assert!(report.is_clean(), "{}", report);
+    assert_eq!((report.files, report.dirs, report.used_clusters), (4, 1, 8));
+    assert_eq!(report.total_clusters, IMAGE_FREE_CLUSTERS + 1);
+}
+
+#[test]
+fn test_fsck_reports_and_repairs() {
+    use vfat::{check, Problem};
+
+    let image = fsck_image();
+    let set_fat = |cluster: usize, value: u32, fats: &[usize]| {
+        for &fat in fats {
+            image.write(fat + cluster * 4, &u32_le(value));
+        }
+    };
+
+    // The second FAT disagrees about cluster 3, cluster 100 is lost, and the
+    // chain of C.TXT runs into A.TXT's second cluster, losing cluster 9.
+    set_fat(3, 0x0FFFFFFF, &[IMAGE_FAT_COPY]);
+    set_fat(100, 0x0FFFFFFF, &[IMAGE_FAT, IMAGE_FAT_COPY]);
+    set_fat(8, 4, &[IMAGE_FAT, IMAGE_FAT_COPY]);
+
+    // A.TXT gets an invalid short name, B.TXT claims to be 2000 bytes, and the
+    // long name records in `/dir` no longer match their short name.
+    image.write(image_cluster(2) + 1, b"+");
+    image.write(image_cluster(2) + 32 + 28, &u32_le(2000));
+    for &record in &[2, 3] {
+        let offset = image_cluster(6) + record * 32 + 13;
+        let checksum = image.bytes(offset, 1)[0];
+        image.write(offset, &[checksum ^ 1]);
+    }
+
+    let expected = vec![
+        Problem::FatMismatch { fat: 1, cluster: 3 },
+        Problem::InvalidShortName { dir: "/".into(), index: 0, name: "A+      TXT".into() },
+        Problem::ChainLength { path: "/B.TXT".into(), size: 2000, clusters: 1, expected: 4 },
+        Problem::BadLfnChecksum { dir: "/dir".into(), first: 2, last: 3 },
+        Problem::CrossLinked { path: "/C.TXT".into(), other: "/A+.TXT".into(), cluster: 4 },
+        Problem::LostClusters { clusters: vec![9, 100] },
+    ];
+
+    let report = check(&image.mount(), false).unwrap();
+    assert_eq!(report.problems, expected);
+    assert!(!report.to_string().contains("repaired"));
+
+    let report = check(&image.mount(), true).unwrap();
+    assert_eq!(report.problems, expected);
+    assert!(report.to_string().contains("/C.TXT: cross-linked with /A+.TXT at cluster 4 (repaired)"));
+
+    // Only the short name is left, and the volume is usable again.
+    let vfat = image.mount();
+    let report = check(&vfat, false).unwrap();
+    assert_eq!(report.problems, &expected[1..2]);
+    assert_eq!(image.free_clusters(), IMAGE_FREE_CLUSTERS - 6);
+
+    let mut contents = Vec::new();
+    vfat.open_file("/A+.TXT").unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == pattern(1000));
+    assert_eq!(vfat.open_file("/B.TXT").unwrap().size(), 512);
+    assert_eq!(vfat.open_file("/C.TXT").unwrap().size(), 512);
+    assert_eq!(entry_names(&vfat, "/dir"), vec![".", "..", "ALONGF~1.TXT"]);
+}
+
+/// The number of sectors in a `formatted_image()`: just enough for FAT32 with
+/// one sector per cluster once the partition starts at sector 1.
+const FORMAT_SECTORS: u64 = 1 + 32 + 2 * 513 + 65600;
+
+fn formatted_image(options: &::vfat::FormatOptions) -> Cursor<Vec<u8>> {
+    let mut image = Cursor::new(vec![0u8; FORMAT_SECTORS as usize * 512]);
+    ::vfat::format(&mut image, FORMAT_SECTORS, options).expect("image formats");
+    image
+}
+
+#[test]
+fn test_format() {
+    use vfat::{check, FormatOptions};
+
+    let options = FormatOptions::new()
+        .partition_start(1)
+        .label("scratch")
+        .serial(0xDEADBEEF);
+    let image = formatted_image(&options);
+    let data = image.get_ref().clone();
+
+    let mbr = MasterBootRecord::from(Cursor::new(data[..512].to_vec())).unwrap();
+    let partition = &mbr.partitions()[0];
+    assert!(partition.is_fat32());
+    assert_eq!(partition.relative_sector(), 1);
+    assert_eq!(partition.total_sectors() as u64, FORMAT_SECTORS - 1);
+
+    // The boot sector, its backup and the FSInfo sector.
+    let boot = &data[512..1024];
+    assert_eq!(&boot[..], &data[7 * 512..8 * 512]);
+    assert_eq!(&boot[67..71], &u32_le(0xDEADBEEF));
+    assert_eq!(&boot[71..82], b"SCRATCH    ");
+    assert_eq!(&boot[82..90], b"FAT32   ");
+    assert_eq!(&data[2 * 512 + 488..2 * 512 + 492], &u32_le(65599));
+
+    let vfat = VFat::from(image).expect("formatted image mounts");
+    assert_eq!(vfat.borrow().fat_type(), ::vfat::FatType::Fat32);
+    let report = check(&vfat, false).unwrap();
+    assert!(report.is_clean(), "{}", report);
+    assert_eq!((report.files, report.dirs, report.used_clusters), (0, 0, 1));
+    assert_eq!(report.total_clusters, 65600);
+
+    // The label is stored in the root directory but is not listed.
+    assert!(entry_names(&vfat, "/").is_empty());
+    vfat.create_file("/hello.txt").unwrap().write_all(&pattern(3000)).unwrap();
+    vfat.create_dir("/dir", false).unwrap();
+    assert_eq!(entry_names(&vfat, "/"), vec!["dir", "hello.txt"]);
+    assert!(check(&vfat, false).unwrap().is_clean());
+}
+
+#[test]
+fn test_format_cluster_sizes() {
+    use vfat::{check, FormatOptions};
+
+    // Too few clusters remain for FAT32 once clusters are 1KiB.
+    let mut image = Cursor::new(vec![0u8; FORMAT_SECTORS as usize * 512]);
+    let options = FormatOptions::new().partition_start(1).sectors_per_cluster(2);
+    let e = ::vfat::format(&mut image, FORMAT_SECTORS, &options).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    let options = FormatOptions::new().partition_start(1).sectors_per_cluster(3);
+    let e = ::vfat::format(&mut image, FORMAT_SECTORS, &options).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    let options = FormatOptions::new().partition_start(1).label("bad.label");
+    let e = ::vfat::format(&mut image, FORMAT_SECTORS, &options).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // Twice as many sectors fit the same number of clusters twice as large.
+    let sectors = 2048 + 32 + 2 * 516 + 66000 * 2;
+    let mut image = Cursor::new(vec![0u8; sectors as usize * 512]);
+    let options = FormatOptions::new().sectors_per_cluster(2);
+    ::vfat::format(&mut image, sectors, &options).unwrap();
+    assert_eq!(&image.get_ref()[2048 * 512 + 71..2048 * 512 + 82], b"NO NAME    ");
+
+    let vfat = VFat::from(image).unwrap();
+    vfat.create_file("/big.bin").unwrap().write_all(&pattern(5000)).unwrap();
+    let report = check(&vfat, false).unwrap();
+    assert!(report.is_clean(), "{}", report);
+    assert_eq!(report.total_clusters, 66000);
+    assert_eq!(report.used_clusters, 1 + 5);
+
+    let mut contents = Vec::new();
+    vfat.open_file("/big.bin").unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == pattern(5000));
+}
+
+/// Creates `/boot/{kernel.bin,config.txt}`, `/boot/old/{kernel.bin,config.txt}`
+/// and `/data/notes.txt` on an empty volume.
+fn walk_image() -> Shared<VFat> {
+    let vfat = SharedImage::new().mount();
+    vfat.create_dir("/boot/old", true).unwrap();
+    vfat.create_dir("/data", false).unwrap();
+    for path in &["/boot/kernel.bin", "/boot/config.txt", "/boot/old/kernel.bin",
+                  "/boot/old/config.txt", "/data/notes.txt"] {
+        vfat.create_file(path).unwrap().write_all(path.as_bytes()).unwrap();
+    }
+
+    vfat
+}
+
+fn walk_paths<'a, E: Entry, I>(walk: I) -> Vec<String>
+    where I: Iterator<Item = io::Result<::walk::WalkEntry<E>>>
+{
+    walk.map(|entry| entry.unwrap().path().to_str().unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_walk() {
+    use walk::Order;
+
+    let vfat = walk_image();
+    assert_eq!(walk_paths(vfat.walk("/").unwrap()), vec![
+        "/boot", "/boot/old", "/boot/old/kernel.bin", "/boot/old/config.txt",
+        "/boot/kernel.bin", "/boot/config.txt", "/data", "/data/notes.txt",
+    ]);
+
+    assert_eq!(walk_paths(vfat.walk("/").unwrap().order(Order::BreadthFirst)), vec![
+        "/boot", "/data", "/boot/old", "/boot/kernel.bin", "/boot/config.txt",
+        "/data/notes.txt", "/boot/old/kernel.bin", "/boot/old/config.txt",
+    ]);
+
+    assert_eq!(walk_paths(vfat.walk("/boot").unwrap().max_depth(1)), vec![
+        "/boot/old", "/boot/kernel.bin", "/boot/config.txt",
+    ]);
+
+    let walk = vfat.walk("/").unwrap().prune(|entry| entry.entry().name() == "old");
+    assert_eq!(walk_paths(walk), vec![
+        "/boot", "/boot/kernel.bin", "/boot/config.txt", "/data", "/data/notes.txt",
+    ]);
+
+    let depths: Vec<usize> = vfat.walk("/boot").unwrap().map(|e| e.unwrap().depth()).collect();
+    assert_eq!(depths, vec![1, 2, 2, 1, 1]);
+
+    expect_variant!(vfat.walk("/boot/kernel.bin").map(|_| ()), Err(ref e) if e.kind() == io::ErrorKind::Other);
+}
+
+#[test]
+fn test_glob() {
+    use walk::{Glob, Pattern};
+
+    let vfat = walk_image();
+    assert_eq!(walk_paths(vfat.glob("/boot", "*.bin").unwrap()), vec!["/boot/kernel.bin"]);
+    assert_eq!(walk_paths(vfat.glob("/", "**/config.txt").unwrap()), vec![
+        "/boot/old/config.txt", "/boot/config.txt",
+    ]);
+    assert_eq!(walk_paths(vfat.glob("/data", "/*/*/KERNEL.BIN").unwrap()), vec![
+        "/boot/old/kernel.bin",
+    ]);
+    assert_eq!(walk_paths(vfat.glob("/", "**/*.txt").unwrap()).len(), 3);
+    // `**` also matches no components at all, so `boot/**` includes `boot`.
+    assert_eq!(walk_paths(vfat.glob("/", "boot/**").unwrap()).len(), 6);
+    assert!(walk_paths(vfat.glob("/", "*.bin").unwrap()).is_empty());
+
+    // A glob over a pruned walk keeps the walk's pruning.
+    let walk = vfat.walk("/").unwrap().prune(|entry| entry.entry().name() == "old");
+    let glob = Glob::new(walk, Pattern::new("**/config.txt"));
+    assert_eq!(walk_paths(glob), vec!["/boot/config.txt"]);
+}
+
+#[test]
+fn test_glob_pattern() {
+    use walk::Pattern;
+
+    let pattern = Pattern::new("**/c[aeiou]nf?g.*");
+    assert!(pattern.matches("config.txt"));
+    assert!(pattern.matches("a/b/CONFIG.TXT"));
+    assert!(!pattern.matches("a/b/cnfig.txt"));
+    assert!(pattern.matches_prefix("a/b"));
+
+    let pattern = Pattern::new("boot/[!a-k]*");
+    assert!(pattern.matches("boot/old"));
+    assert!(!pattern.matches("boot/kernel.bin"));
+    assert!(!pattern.matches("boot"));
+    assert!(pattern.matches_prefix("boot"));
+    assert!(!pattern.matches_prefix("data"));
+    assert!(!pattern.matches_prefix("boot/old/x"));
+}
+
+#[test]
+fn test_stat() {
+    let vfat = walk_image();
+    let metadata = vfat.stat("/boot/config.txt").unwrap();
+    assert!(!metadata.read_only());
+    assert!(!metadata.is_dir());
+    assert!(vfat.stat("/boot/old").unwrap().is_dir());
+    expect_variant!(vfat.stat("/boot/missing").map(|_| ()), Err(ref e) if e.kind() == io::ErrorKind::NotFound);
+}
@@ -8,6 +8,13 @@ pub trait BlockDevice: Send {
         512
     }
 
+    /// Whether the device refuses writes. A `CachedDevice` over such a
+    /// device refuses writes too, before any cached sector is changed.
+    /// Defaults to `false`.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
     /// Read sector number `n` into `buf`.
     ///
     /// `self.sector_size()` or `buf.len()` bytes, whichever is less, are read
@@ -52,9 +59,73 @@ pub trait BlockDevice: Send {
     /// error of `UnexpectedEof` if the length of `buf` is less than
     /// `self.sector_size()`.
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize>;
+
+    /// Reads `count` consecutive sectors starting at sector `start` into
+    /// `buf`.
+    ///
+    /// `count * self.sector_size()` bytes are read into the beginning of `buf`.
+    /// The number of bytes read is returned. The default implementation reads
+    /// one sector at a time with `read_sector()`; devices that can perform
+    /// multi-sector transfers should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or reading from `self` fails. Returns an
+    /// error of `UnexpectedEof` if the length of `buf` is less than
+    /// `count * self.sector_size()`.
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let total = count as usize * sector_size;
+        if buf.len() < total {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "buffer is smaller than the sectors"));
+        }
+
+        for (i, chunk) in buf[..total].chunks_mut(sector_size).enumerate() {
+            if self.read_sector(start + i as u64, chunk)? != sector_size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short sector read"));
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Overwrites `count` consecutive sectors starting at sector `start` with
+    /// the contents of `buf`.
+    ///
+    /// `count * self.sector_size()` bytes are written from the beginning of
+    /// `buf`. The number of bytes written is returned. The default
+    /// implementation writes one sector at a time with `write_sector()`;
+    /// devices that can perform multi-sector transfers should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking or writing to `self` fails. Returns an
+    /// error of `UnexpectedEof` if the length of `buf` is less than
+    /// `count * self.sector_size()`.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let total = count as usize * sector_size;
+        if buf.len() < total {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "buffer is smaller than the sectors"));
+        }
+
+        for (i, chunk) in buf[..total].chunks(sector_size).enumerate() {
+            if self.write_sector(start + i as u64, chunk)? != sector_size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "short sector write"));
+            }
+        }
+
+        Ok(total)
+    }
 }
 
 impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
+    fn is_read_only(&self) -> bool {
+        (**self).is_read_only()
+    }
+
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
         (*self).read_sector(n, buf)
     }
@@ -62,6 +133,14 @@ impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
         (*self).write_sector(n, buf)
     }
+
+    fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (*self).read_sectors(start, count, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        (*self).write_sectors(start, count, buf)
+    }
 }
 
 macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
@@ -83,6 +162,30 @@ macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
             self.write_all(&buf[..to_write])?;
             Ok(to_write)
         }
+
+        fn read_sectors(&mut self, start: u64, count: u64, buf: &mut [u8]) -> io::Result<usize> {
+            let total = (count * self.sector_size()) as usize;
+            if buf.len() < total {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          "buffer is smaller than the sectors"));
+            }
+
+            self.seek(io::SeekFrom::Start(start * self.sector_size()))?;
+            self.read_exact(&mut buf[..total])?;
+            Ok(total)
+        }
+
+        fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+            let total = (count * self.sector_size()) as usize;
+            if buf.len() < total {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          "buffer is smaller than the sectors"));
+            }
+
+            self.seek(io::SeekFrom::Start(start * self.sector_size()))?;
+            self.write_all(&buf[..total])?;
+            Ok(total)
+        }
     }
 }
 
@@ -2,6 +2,7 @@ use std::io;
 use std::path::Path;
 
 use traits::Metadata;
+use walk::{Walk, Glob, Pattern};
 
 /// Trait implemented by files in the file system.
 pub trait File: io::Read + io::Write + io::Seek + Sized {
@@ -115,6 +116,44 @@ pub trait FileSystem: Sized {
             .ok_or(io::Error::new(io::ErrorKind::Other, "not a directory"))
     }
 
+    /// Returns the metadata of the entry at `path`. `path` must be absolute.
+    ///
+    /// # Errors
+    ///
+    /// The error conditions are those of `open()`.
+    fn stat<P: AsRef<Path>>(self, path: P) -> io::Result<<Self::Entry as Entry>::Metadata> {
+        Ok(self.open(path)?.metadata().clone())
+    }
+
+    /// Returns a depth-first walk of the directory tree below `path`, not
+    /// including `path` itself. `path` must be absolute. The walk's order,
+    /// depth and pruning can be changed with the `Walk` builder methods.
+    ///
+    /// # Errors
+    ///
+    /// The error conditions are those of `open_dir()`.
+    fn walk<'a, P: AsRef<Path>>(self, path: P) -> io::Result<Walk<'a, Self::Entry>> {
+        let path = path.as_ref();
+        Ok(Walk::new(path, self.open_dir(path)?))
+    }
+
+    /// Returns the entries below the directory `path` whose paths relative to
+    /// `path` match the glob `pattern`, such as `*.bin` or `**/config.txt`.
+    /// If `pattern` begins with `/`, it is matched from the root directory
+    /// instead. See `Pattern` for the syntax.
+    ///
+    /// # Errors
+    ///
+    /// The error conditions are those of `open_dir()`.
+    fn glob<'a, P: AsRef<Path>>(self, path: P, pattern: &str) -> io::Result<Glob<'a, Self::Entry>> {
+        let walk = match pattern.starts_with('/') {
+            true => self.walk("/")?,
+            false => self.walk(path)?,
+        };
+
+        Ok(Glob::new(walk, Pattern::new(pattern)))
+    }
+
     /// Creates a new file at `path`, opens it, and returns it.
     ///
     /// `path` must be absolute.
@@ -24,7 +24,7 @@ pub trait Timestamp: Copy + Clone + Sized {
 }
 
 /// Trait for directory entry metadata.
-pub trait Metadata: Sized {
+pub trait Metadata: Clone + Sized {
     /// Type corresponding to a point in time.
     type Timestamp: Timestamp;
 
@@ -1,12 +1,33 @@
 use std::{io, fmt};
+use std::cmp::{min, max};
 use std::collections::HashMap;
 
 use traits::BlockDevice;
 
+/// The number of sectors cached by a `CachedDevice` created with `new()`.
+pub const DEFAULT_CAPACITY: usize = 256;
+
 #[derive(Debug)]
 struct CacheEntry {
     data: Vec<u8>,
-    dirty: bool
+    dirty: bool,
+    /// The value of the cache's clock when this entry was last accessed.
+    last_used: u64
+}
+
+/// Counters describing how effective a `CachedDevice` has been.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of accesses to a sector that was already cached.
+    pub hits: u64,
+    /// Number of accesses that required reading a sector from the disk.
+    pub misses: u64,
+    /// Number of sectors evicted to make room for other sectors.
+    pub evictions: u64,
+    /// Number of dirty sectors written back to the disk.
+    pub writebacks: u64,
+    /// Number of sectors read from the disk ahead of being accessed.
+    pub read_aheads: u64,
 }
 
 pub struct Partition {
@@ -19,7 +40,10 @@ pub struct Partition {
 pub struct CachedDevice {
     device: Box<BlockDevice>,
     cache: HashMap<u64, CacheEntry>,
-    partition: Partition
+    partition: Partition,
+    capacity: usize,
+    clock: u64,
+    stats: CacheStats
 }
 
 impl CachedDevice {
@@ -39,21 +63,72 @@ impl CachedDevice {
     /// `partition.sector_size` must be an integer multiple of
     /// `device.sector_size()`.
     ///
+    /// At most `DEFAULT_CAPACITY` sectors are cached at once. Dirty sectors
+    /// are written back to `device` when they are evicted, when `flush()` is
+    /// called, and when the `CachedDevice` is dropped.
+    ///
     /// # Panics
     ///
     /// Panics if the partition's sector size is < the device's sector size.
     pub fn new<T>(device: T, partition: Partition) -> CachedDevice
         where T: BlockDevice + 'static
+    {
+        CachedDevice::with_capacity(device, partition, DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new `CachedDevice` exactly like `new()` that caches at most
+    /// `capacity` sectors at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the partition's sector size is < the device's sector size or
+    /// if `capacity` is zero.
+    pub fn with_capacity<T>(device: T, partition: Partition, capacity: usize) -> CachedDevice
+        where T: BlockDevice + 'static
     {
         assert!(partition.sector_size >= device.sector_size());
+        assert!(capacity > 0);
 
         CachedDevice {
             device: Box::new(device),
             cache: HashMap::new(),
-            partition: partition
+            partition: partition,
+            capacity: capacity,
+            clock: 0,
+            stats: CacheStats::default()
         }
     }
 
+    /// The maximum number of sectors cached at once.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the maximum number of sectors cached at once, evicting sectors if
+    /// more than `capacity` sectors are currently cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing an evicted dirty sector back fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn set_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        assert!(capacity > 0);
+        self.capacity = capacity;
+        while self.cache.len() > self.capacity {
+            self.evict()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the hit, miss, eviction, and write-back counters.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
     /// Maps a user's request for a sector `virt` to the physical sector and
     /// number of physical sectors required to access `virt`.
     fn virtual_to_physical(&self, virt: u64) -> (u64, u64) {
@@ -79,9 +154,16 @@ impl CachedDevice {
     ///
     /// # Errors
     ///
-    /// Returns an error if there is an error reading the sector from the disk.
+    /// Returns an error if there is an error reading the sector from the disk,
+    /// or an error kind of `PermissionDenied` if the device is read-only.
     pub fn get_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
-        unimplemented!("CachedDevice::get_mut()")
+        if self.device.is_read_only() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "device is read-only"));
+        }
+
+        let entry = self.entry(sector)?;
+        entry.dirty = true;
+        Ok(&mut entry.data)
     }
 
     /// Returns a reference to the cached sector `sector`. If the sector is not
@@ -91,18 +173,205 @@ impl CachedDevice {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get(&mut self, sector: u64) -> io::Result<&[u8]> {
-        unimplemented!("CachedDevice::get()")
+        Ok(&self.entry(sector)?.data)
+    }
+
+    /// Returns the cache entry for `sector`, reading the sector from the disk
+    /// if it is not already cached.
+    /// If the cache is full, the least recently used sector is evicted first.
+    fn entry(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
+        self.clock += 1;
+        if self.cache.contains_key(&sector) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            while self.cache.len() >= self.capacity {
+                self.evict()?;
+            }
+
+            let (physical, count) = self.virtual_to_physical(sector);
+            let mut data = vec![0u8; (count * self.device.sector_size()) as usize];
+            self.device.read_sectors(physical, count, &mut data)?;
+
+            self.cache.insert(sector, CacheEntry { data, dirty: false, last_used: 0 });
+        }
+
+        let entry = self.cache.get_mut(&sector).expect("sector is cached");
+        entry.last_used = self.clock;
+        Ok(entry)
+    }
+
+    /// Reads the logical sectors `start..(start + count)` into the cache,
+    /// reading each run of uncached sectors with a single multi-sector request
+    /// to the device. Sectors that are already cached are not read again.
+    ///
+    /// At most half of the cache's capacity is read ahead at once. Sectors
+    /// before the start of the partition are never read ahead.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error reading from the disk or writing
+    /// an evicted dirty sector back to it.
+    pub fn prefetch(&mut self, start: u64, count: u64) -> io::Result<()> {
+        if start < self.partition.start {
+            return Ok(());
+        }
+
+        let end = start + min(count, max(self.capacity as u64 / 2, 1));
+        let mut sector = start;
+        while sector < end {
+            if self.cache.contains_key(&sector) {
+                sector += 1;
+                continue;
+            }
+
+            let mut run = 1;
+            while sector + run < end && !self.cache.contains_key(&(sector + run)) {
+                run += 1;
+            }
+
+            self.read_run(sector, run)?;
+            sector += run;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the `count` uncached logical sectors starting at `start` into the
+    /// cache with one request to the device.
+    fn read_run(&mut self, start: u64, count: u64) -> io::Result<()> {
+        while self.cache.len() + count as usize > self.capacity {
+            self.evict()?;
+        }
+
+        let (physical, factor) = self.virtual_to_physical(start);
+        let sector_size = self.partition.sector_size as usize;
+        let mut data = vec![0u8; count as usize * sector_size];
+        self.device.read_sectors(physical, count * factor, &mut data)?;
+
+        self.clock += 1;
+        for (i, chunk) in data.chunks(sector_size).enumerate() {
+            let entry = CacheEntry { data: chunk.to_vec(), dirty: false, last_used: self.clock };
+            self.cache.insert(start + i as u64, entry);
+        }
+
+        self.stats.read_aheads += count;
+        Ok(())
+    }
+
+    /// Removes the least recently used sector from the cache, writing it back
+    /// to the disk first if it is dirty.
+    fn evict(&mut self) -> io::Result<()> {
+        let victim = self.cache.iter()
+            .min_by_key(|&(_, entry)| entry.last_used)
+            .map(|(&sector, _)| sector);
+
+        if let Some(sector) = victim {
+            self.write_back(sector)?;
+            self.cache.remove(&sector);
+            self.stats.evictions += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every dirty cached sector back to the disk. Sectors remain
+    /// cached, but are no longer dirty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is an error writing a sector to the disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<u64> = self.cache.iter()
+            .filter(|&(_, entry)| entry.dirty)
+            .map(|(&sector, _)| sector)
+            .collect();
+
+        dirty.sort();
+        for sector in dirty {
+            self.write_back(sector)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the cached sector `sector` back to the disk if it is dirty.
+    fn write_back(&mut self, sector: u64) -> io::Result<()> {
+        let (physical, count) = self.virtual_to_physical(sector);
+        let entry = match self.cache.get_mut(&sector) {
+            Some(entry) => entry,
+            None => return Ok(())
+        };
+
+        if entry.dirty {
+            self.device.write_sectors(physical, count, &entry.data)?;
+
+            entry.dirty = false;
+            self.stats.writebacks += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockDevice for CachedDevice {
+    fn sector_size(&self) -> u64 {
+        self.partition.sector_size
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.device.is_read_only()
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector = self.get(n)?;
+        let amount = min(sector.len(), buf.len());
+        buf[..amount].copy_from_slice(&sector[..amount]);
+        Ok(amount)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector = self.get_mut(n)?;
+        if buf.len() < sector.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "buffer is smaller than a sector"));
+        }
+
+        let amount = sector.len();
+        sector.copy_from_slice(&buf[..amount]);
+        Ok(amount)
     }
 }
 
-// FIXME: Implement `BlockDevice` for `CacheDevice`. The `read_sector` and
-// `write_sector` methods should only read/write from/to cached sectors.
+impl Drop for CachedDevice {
+    fn drop(&mut self) {
+        // There is no way to report an error from `drop`. Callers that care
+        // about write errors should call `flush()` first.
+        let _ = self.flush();
+    }
+}
 
 impl fmt::Debug for CachedDevice {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CachedDevice")
             .field("device", &"<block device>")
             .field("cache", &self.cache)
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats)
             .finish()
     }
 }
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let accesses = self.hits + self.misses;
+        let rate = match accesses {
+            0 => 0,
+            n => self.hits * 100 / n
+        };
+
+        write!(f, "{} hits, {} misses ({}% hit rate), {} read ahead, {} evictions, \
+                   {} writebacks",
+               self.hits, self.misses, rate, self.read_aheads, self.evictions, self.writebacks)
+    }
+}
@@ -1,5 +1,3 @@
-use vfat::*;
-
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone, Hash)]
 pub struct Cluster(u32);
 
@@ -9,4 +7,21 @@ impl From<u32> for Cluster {
     }
 }
 
-// TODO: Implement any useful helper methods on `Cluster`.
+impl Cluster {
+    /// Returns the raw cluster number.
+    pub fn number(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns the index of this cluster in the data region. The first data
+    /// cluster, cluster 2, has index 0.
+    pub fn data_index(&self) -> u32 {
+        self.0 - 2
+    }
+
+    /// Returns `true` if this cluster number can refer to a data cluster.
+    /// Clusters 0 and 1 are reserved.
+    pub fn is_data(&self) -> bool {
+        self.0 >= 2
+    }
+}
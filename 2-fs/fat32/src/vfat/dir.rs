@@ -1,6 +1,5 @@
 use std::ffi::OsStr;
 use std::char::decode_utf16;
-use std::borrow::Cow;
 use std::io;
 
 use traits;
@@ -10,34 +9,477 @@ use vfat::{Metadata, Attributes, Timestamp, Time, Date};
 
 #[derive(Debug)]
 pub struct Dir {
-    // FIXME: Fill me in.
+    pub(crate) vfat: Shared<VFat>,
+    pub(crate) start: Cluster,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) location: Option<EntryLocation>,
+}
+
+/// The position of an entry's on-disk records inside of its parent directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct EntryLocation {
+    /// The first cluster of the parent directory.
+    pub dir: Cluster,
+    /// The index of the first record (long file name or regular) of the entry.
+    pub first: usize,
+    /// The index of the entry's regular record.
+    pub index: usize,
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatRegularDirEntry {
-    // FIXME: Fill me in.
+    name: [u8; 8],
+    extension: [u8; 3],
+    attributes: Attributes,
+    nt_flags: u8,
+    created_tenths: u8,
+    created_time: Time,
+    created_date: Date,
+    accessed_date: Date,
+    cluster_high: u16,
+    modified_time: Time,
+    modified_date: Date,
+    cluster_low: u16,
+    file_size: u32,
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatLfnDirEntry {
-    // FIXME: Fill me in.
+    sequence: u8,
+    name_1: [u16; 5],
+    attributes: Attributes,
+    kind: u8,
+    checksum: u8,
+    name_2: [u16; 6],
+    zero: u16,
+    name_3: [u16; 2],
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatUnknownDirEntry {
-    // FIXME: Fill me in.
+    id: u8,
+    _reserved_1: [u8; 10],
+    attributes: Attributes,
+    _reserved_2: [u8; 20],
 }
 
+#[derive(Copy, Clone)]
 pub union VFatDirEntry {
     unknown: VFatUnknownDirEntry,
     regular: VFatRegularDirEntry,
     long_filename: VFatLfnDirEntry,
 }
 
+/// First byte of a record marking the end of a directory.
+pub(crate) const END_OF_DIR: u8 = 0x00;
+/// First byte of a record marking a deleted (free) entry.
+pub(crate) const DELETED: u8 = 0xE5;
+/// Bit set in the sequence number of the last (first stored) LFN record.
+const LAST_LFN: u8 = 0x40;
+/// The number of UCS-2 characters stored in a single LFN record.
+const LFN_CHARS: usize = 13;
+/// NT flag indicating that the short base name is stored lowercase.
+const LOWERCASE_BASE: u8 = 0x08;
+/// NT flag indicating that the short extension is stored lowercase.
+const LOWERCASE_EXT: u8 = 0x10;
+
+impl VFatRegularDirEntry {
+    /// Returns a new regular record with short name `short`, NT case flags
+    /// `nt_flags`, attributes `attributes`, first cluster `cluster`, and size
+    /// `size`. All timestamps are set to `now`.
+    pub(crate) fn new(
+        short: [u8; 11],
+        nt_flags: u8,
+        attributes: Attributes,
+        cluster: Option<Cluster>,
+        size: u32,
+        now: Timestamp
+    ) -> VFatRegularDirEntry {
+        let mut name = [0u8; 8];
+        let mut extension = [0u8; 3];
+        name.copy_from_slice(&short[..8]);
+        extension.copy_from_slice(&short[8..]);
+
+        let mut entry = VFatRegularDirEntry {
+            name, extension, attributes, nt_flags,
+            created_tenths: 0,
+            created_time: now.time,
+            created_date: now.date,
+            accessed_date: now.date,
+            cluster_high: 0,
+            modified_time: now.time,
+            modified_date: now.date,
+            cluster_low: 0,
+            file_size: size,
+        };
+
+        entry.set_cluster(cluster);
+        entry
+    }
+
+    /// The raw 11-byte short name, as stored on disk.
+    pub(crate) fn short_name(&self) -> [u8; 11] {
+        let mut short = [0u8; 11];
+        short[..8].copy_from_slice(&{ self.name });
+        short[8..].copy_from_slice(&{ self.extension });
+        short
+    }
+
+    /// Sets the raw 11-byte short name and NT case flags.
+    pub(crate) fn set_short_name(&mut self, short: [u8; 11], nt_flags: u8) {
+        let (mut name, mut extension) = ([0u8; 8], [0u8; 3]);
+        name.copy_from_slice(&short[..8]);
+        extension.copy_from_slice(&short[8..]);
+        self.name = name;
+        self.extension = extension;
+        self.nt_flags = (self.nt_flags & !(LOWERCASE_BASE | LOWERCASE_EXT)) | nt_flags;
+    }
+
+    /// The short name formatted as `NAME.EXT`, honoring the NT case flags.
+    pub(crate) fn name(&self) -> String {
+        let mut base = { self.name };
+        if base[0] == 0x05 {
+            base[0] = DELETED;
+        }
+
+        let nt_flags = self.nt_flags;
+        let part = |bytes: &[u8], lower: bool| {
+            let end = bytes.iter().rposition(|&b| b != b' ').map(|i| i + 1).unwrap_or(0);
+            let mut part = String::from_utf8_lossy(&bytes[..end]).into_owned();
+            if lower {
+                part.make_ascii_lowercase();
+            }
+            part
+        };
+
+        let mut name = part(&base, nt_flags & LOWERCASE_BASE != 0);
+        let extension = part(&{ self.extension }, nt_flags & LOWERCASE_EXT != 0);
+        if !extension.is_empty() {
+            name.push('.');
+            name.push_str(&extension);
+        }
+
+        name
+    }
+
+    pub(crate) fn attributes(&self) -> Attributes {
+        self.attributes
+    }
+
+    /// The first cluster of the entry's data, if any.
+    pub(crate) fn cluster(&self) -> Option<Cluster> {
+        let raw = ((self.cluster_high as u32) << 16) | self.cluster_low as u32;
+        match raw {
+            0 => None,
+            raw => Some(Cluster::from(raw))
+        }
+    }
+
+    pub(crate) fn set_cluster(&mut self, cluster: Option<Cluster>) {
+        let raw = cluster.map(|c| c.number()).unwrap_or(0);
+        self.cluster_high = (raw >> 16) as u16;
+        self.cluster_low = raw as u16;
+    }
+
+    pub(crate) fn size(&self) -> u32 {
+        self.file_size
+    }
+
+    pub(crate) fn set_size(&mut self, size: u32) {
+        self.file_size = size;
+    }
+
+    /// Sets the last modification (and access) timestamp to `now`.
+    pub(crate) fn touch(&mut self, now: Timestamp) {
+        self.modified_time = now.time;
+        self.modified_date = now.date;
+        self.accessed_date = now.date;
+    }
+
+    pub(crate) fn metadata(&self) -> Metadata {
+        Metadata {
+            attributes: self.attributes,
+            created: Timestamp { date: self.created_date, time: self.created_time },
+            accessed: Timestamp { date: self.accessed_date, time: Time::default() },
+            modified: Timestamp { date: self.modified_date, time: self.modified_time },
+        }
+    }
+}
+
+impl VFatLfnDirEntry {
+    /// Returns a new LFN record with sequence number `sequence` holding
+    /// `chars`. `last` indicates whether this is the last record of the name.
+    fn new(sequence: u8, last: bool, chars: &[u16; LFN_CHARS], checksum: u8) -> VFatLfnDirEntry {
+        let (mut name_1, mut name_2, mut name_3) = ([0u16; 5], [0u16; 6], [0u16; 2]);
+        name_1.copy_from_slice(&chars[..5]);
+        name_2.copy_from_slice(&chars[5..11]);
+        name_3.copy_from_slice(&chars[11..]);
+
+        VFatLfnDirEntry {
+            sequence: if last { sequence | LAST_LFN } else { sequence },
+            name_1,
+            attributes: Attributes::new(Attributes::LFN),
+            kind: 0,
+            checksum,
+            name_2,
+            zero: 0,
+            name_3,
+        }
+    }
+
+    /// The 1-based position of this record's characters in the full name.
+    pub(crate) fn ordinal(&self) -> u8 {
+        self.sequence & 0x1F
+    }
+
+    pub(crate) fn is_last(&self) -> bool {
+        self.sequence & LAST_LFN != 0
+    }
+
+    pub(crate) fn checksum(&self) -> u8 {
+        self.checksum
+    }
+
+    pub(crate) fn chars(&self) -> [u16; LFN_CHARS] {
+        let mut chars = [0u16; LFN_CHARS];
+        chars[..5].copy_from_slice(&{ self.name_1 });
+        chars[5..11].copy_from_slice(&{ self.name_2 });
+        chars[11..].copy_from_slice(&{ self.name_3 });
+        chars
+    }
+}
+
+impl VFatDirEntry {
+    /// Returns a record marking the end of a directory.
+    pub(crate) fn end() -> VFatDirEntry {
+        VFatDirEntry {
+            unknown: VFatUnknownDirEntry {
+                id: END_OF_DIR,
+                _reserved_1: [0; 10],
+                attributes: Attributes::default(),
+                _reserved_2: [0; 20],
+            }
+        }
+    }
+
+    /// The first byte of the record.
+    pub(crate) fn id(&self) -> u8 {
+        unsafe { self.unknown.id }
+    }
+
+    /// Returns `true` if this record is free: either deleted or at/past the
+    /// end of the directory.
+    pub(crate) fn is_free(&self) -> bool {
+        self.id() == END_OF_DIR || self.id() == DELETED
+    }
+
+    /// Marks this record as deleted.
+    pub(crate) fn delete(&mut self) {
+        self.unknown.id = DELETED;
+    }
+
+    /// Returns the LFN view of this record if it is an LFN record.
+    pub(crate) fn lfn(&self) -> Option<VFatLfnDirEntry> {
+        unsafe {
+            match self.unknown.attributes.is_lfn() {
+                true => Some(self.long_filename),
+                false => None
+            }
+        }
+    }
+
+    /// Returns the regular view of this record if it is not an LFN record.
+    pub(crate) fn regular(&self) -> Option<VFatRegularDirEntry> {
+        unsafe {
+            match self.unknown.attributes.is_lfn() {
+                true => None,
+                false => Some(self.regular)
+            }
+        }
+    }
+}
+
+impl From<VFatRegularDirEntry> for VFatDirEntry {
+    fn from(regular: VFatRegularDirEntry) -> VFatDirEntry {
+        VFatDirEntry { regular }
+    }
+}
+
+impl From<VFatLfnDirEntry> for VFatDirEntry {
+    fn from(long_filename: VFatLfnDirEntry) -> VFatDirEntry {
+        VFatDirEntry { long_filename }
+    }
+}
+
+/// Computes the checksum of the 11-byte short name `short` stored in each of
+/// the name's LFN records.
+pub(crate) fn lfn_checksum(short: &[u8; 11]) -> u8 {
+    short.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    })
+}
+
+/// Returns `true` if `c` may appear in a short (8.3) name.
+pub(crate) fn is_short_name_char(c: u8) -> bool {
+    match c {
+        b'A'..=b'Z' | b'0'..=b'9' => true,
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' | b'-' => true,
+        b'@' | b'^' | b'_' | b'`' | b'{' | b'}' | b'~' => true,
+        _ => false
+    }
+}
+
+/// If `name` can be stored as a short name without an LFN, returns the
+/// 11-byte short name and the NT case flags needed to reproduce `name`.
+fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rfind('.') {
+        Some(i) => (&name[..i], &name[(i + 1)..]),
+        None => (name, "")
+    };
+
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || name.ends_with('.') {
+        return None;
+    }
+
+    fn part_flag(part: &str, flag: u8) -> Option<u8> {
+        let has_lower = part.bytes().any(|b| b.is_ascii_lowercase());
+        let has_upper = part.bytes().any(|b| b.is_ascii_uppercase());
+        let valid = part.bytes().all(|b| is_short_name_char(b.to_ascii_uppercase()));
+        match (valid, has_lower, has_upper) {
+            (false, _, _) | (true, true, true) => None,
+            (true, true, false) => Some(flag),
+            (true, false, _) => Some(0),
+        }
+    }
+
+    let flags = part_flag(base, LOWERCASE_BASE)? | part_flag(ext, LOWERCASE_EXT)?;
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.to_ascii_uppercase().as_bytes());
+    short[8..(8 + ext.len())].copy_from_slice(ext.to_ascii_uppercase().as_bytes());
+    if short[0] == DELETED {
+        short[0] = 0x05;
+    }
+
+    Some((short, flags))
+}
+
+/// Generates a short name with a numeric tail (`BASE~N.EXT`) for the long
+/// name `name` that does not collide with any name in `existing`.
+fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> io::Result<[u8; 11]> {
+    fn sanitize(part: &str) -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| match c.is_ascii() && is_short_name_char(c.to_ascii_uppercase() as u8) {
+                true => c.to_ascii_uppercase() as u8,
+                false => b'_'
+            })
+            .collect()
+    }
+
+    let trimmed = name.trim_left_matches('.');
+    let (base, ext) = match trimmed.rfind('.') {
+        Some(i) => (sanitize(&trimmed[..i]), sanitize(&trimmed[(i + 1)..])),
+        None => (sanitize(trimmed), vec![])
+    };
+
+    let mut short = [b' '; 11];
+    let ext_len = ::std::cmp::min(ext.len(), 3);
+    short[8..(8 + ext_len)].copy_from_slice(&ext[..ext_len]);
+
+    for n in 1..1000000u32 {
+        let tail = format!("~{}", n);
+        let base_len = ::std::cmp::min(base.len(), 8 - tail.len());
+        short[..8].copy_from_slice(b"        ");
+        short[..base_len].copy_from_slice(&base[..base_len]);
+        short[base_len..(base_len + tail.len())].copy_from_slice(tail.as_bytes());
+        if !existing.contains(&short) {
+            return Ok(short);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "no unique short name available"))
+}
+
+/// Builds the on-disk records (LFN records followed by the regular record)
+/// for an entry named `name` based on the regular record `template`.
+/// `existing` contains the short names already present in the directory.
+fn records_for(
+    name: &str,
+    mut template: VFatRegularDirEntry,
+    existing: &[[u8; 11]]
+) -> io::Result<Vec<VFatDirEntry>> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"));
+    }
+
+    if name.encode_utf16().count() > 255 || name.chars().any(|c| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"));
+    }
+
+    if let Some((short, flags)) = exact_short_name(name) {
+        if !existing.contains(&short) {
+            template.set_short_name(short, flags);
+            return Ok(vec![template.into()]);
+        }
+    }
+
+    let short = generate_short_name(name, existing)?;
+    template.set_short_name(short, 0);
+
+    let checksum = lfn_checksum(&short);
+    let mut utf16: Vec<u16> = name.encode_utf16().collect();
+    if utf16.len() % LFN_CHARS != 0 {
+        utf16.push(0x0000);
+        while utf16.len() % LFN_CHARS != 0 {
+            utf16.push(0xFFFF);
+        }
+    }
+
+    let count = utf16.len() / LFN_CHARS;
+    let mut records = Vec::with_capacity(count + 1);
+    for i in (0..count).rev() {
+        let mut chars = [0u16; LFN_CHARS];
+        chars.copy_from_slice(&utf16[(i * LFN_CHARS)..((i + 1) * LFN_CHARS)]);
+        let lfn = VFatLfnDirEntry::new((i + 1) as u8, i + 1 == count, &chars, checksum);
+        records.push(lfn.into());
+    }
+
+    records.push(template.into());
+    Ok(records)
+}
+
 impl Dir {
+    /// Returns the root directory of `vfat`.
+    pub(crate) fn root(vfat: Shared<VFat>) -> Dir {
+        let start = vfat.borrow().root_dir_cluster();
+        Dir {
+            vfat,
+            start,
+            name: String::new(),
+            metadata: Metadata {
+                attributes: Attributes::new(Attributes::DIRECTORY),
+                ..Metadata::default()
+            },
+            location: None,
+        }
+    }
+
+    /// Returns `true` if this is the root directory.
+    pub fn is_root(&self) -> bool {
+        self.start == self.vfat.borrow().root_dir_cluster()
+    }
+
+    /// Reads all of the raw records in this directory.
+    pub(crate) fn records(&self) -> io::Result<Vec<VFatDirEntry>> {
+        let mut data = Vec::new();
+        self.vfat.borrow_mut().read_chain(self.start, &mut data)?;
+        Ok(unsafe { data.cast() })
+    }
+
     /// Finds the entry named `name` in `self` and returns it. Comparison is
     /// case-insensitive.
     ///
@@ -49,8 +491,277 @@ impl Dir {
     /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
     /// is returned.
     pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry> {
-        unimplemented!("Dir::find()")
+        use traits::{Dir, Entry};
+
+        let name = name.as_ref().to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "name is not valid UTF-8"))?;
+
+        self.entries()?
+            .find(|entry| entry.name().eq_ignore_ascii_case(name))
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, "entry not found"))
+    }
+
+    /// Adds records for a new entry named `name` to this directory, extending
+    /// the directory if there is not enough free space. The new entry's
+    /// regular record is based on `template`; its name is replaced.
+    ///
+    /// Returns the location and regular record of the new entry.
+    ///
+    /// # Errors
+    ///
+    /// If an entry named `name` already exists, an error kind of
+    /// `AlreadyExists` is returned. If `name` is not a valid name, an error
+    /// kind of `InvalidInput` is returned.
+    pub(crate) fn insert(
+        &self,
+        name: &str,
+        template: VFatRegularDirEntry
+    ) -> io::Result<(EntryLocation, VFatRegularDirEntry)> {
+        match self.find(name) {
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry exists")),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e)
+        }
+
+        let records = self.records()?;
+        let existing: Vec<[u8; 11]> = records.iter()
+            .take_while(|r| r.id() != END_OF_DIR)
+            .filter(|r| !r.is_free())
+            .filter_map(|r| r.regular())
+            .map(|r| r.short_name())
+            .collect();
+
+        let new = records_for(name, template, &existing)?;
+
+        // Find the first run of free records long enough to hold the entry.
+        // Every record at or after an end-of-directory marker is free.
+        let mut start = records.len();
+        let (mut run, mut past_end) = (0, false);
+        for (i, record) in records.iter().enumerate() {
+            past_end = past_end || record.id() == END_OF_DIR;
+            if past_end || record.is_free() {
+                run += 1;
+                if run == new.len() {
+                    start = i + 1 - run;
+                    break;
+                }
+            } else {
+                run = 0;
+            }
+        }
+
+        if start == records.len() {
+            start = records.len() - run;
+        }
+
+        let mut vfat = self.vfat.borrow_mut();
+        let per_cluster = vfat.cluster_size() / ::std::mem::size_of::<VFatDirEntry>();
+        let mut capacity = records.len();
+        while capacity < start + new.len() {
+            vfat.extend_chain(self.start)?;
+            capacity += per_cluster;
+        }
+
+        for (i, record) in new.iter().enumerate() {
+            vfat.write_dir_record(self.start, start + i, record)?;
+        }
+
+        // If the entry consumed the end-of-directory marker, make sure the
+        // directory is still terminated after the new records.
+        let after = start + new.len();
+        let consumed_end = records.iter()
+            .skip(start)
+            .take(new.len())
+            .any(|r| r.id() == END_OF_DIR);
+        if consumed_end && after < records.len() && records[after].id() != END_OF_DIR {
+            vfat.write_dir_record(self.start, after, &VFatDirEntry::end())?;
+        }
+
+        let location = EntryLocation { dir: self.start, first: start, index: after - 1 };
+        let regular = new[new.len() - 1].regular().expect("regular record");
+        Ok((location, regular))
+    }
+
+    /// Creates a new, empty file named `name` in this directory.
+    ///
+    /// # Errors
+    ///
+    /// If an entry named `name` already exists, an error kind of
+    /// `AlreadyExists` is returned. If `name` is not a valid name, an error
+    /// kind of `InvalidInput` is returned.
+    pub fn create_file(&self, name: &str) -> io::Result<File> {
+        let now = self.vfat.borrow().now();
+        let attributes = Attributes::new(Attributes::ARCHIVE);
+        let template = VFatRegularDirEntry::new([b' '; 11], 0, attributes, None, 0, now);
+        let (location, regular) = self.insert(name, template)?;
+        Ok(File::new(self.vfat.clone(), name.to_string(), &regular, location))
+    }
+
+    /// Creates a new, empty directory named `name` in this directory.
+    ///
+    /// # Errors
+    ///
+    /// If an entry named `name` already exists, an error kind of
+    /// `AlreadyExists` is returned. If `name` is not a valid name, an error
+    /// kind of `InvalidInput` is returned.
+    pub fn create_dir(&self, name: &str) -> io::Result<Dir> {
+        match self.find(name) {
+            Ok(_) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry exists")),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e)
+        }
+
+        let (cluster, now) = {
+            let mut vfat = self.vfat.borrow_mut();
+            (vfat.alloc_cluster(None)?, vfat.now())
+        };
+
+        let attributes = Attributes::new(Attributes::DIRECTORY);
+        let parent = match self.is_root() {
+            true => None,
+            false => Some(self.start)
+        };
+
+        let dot = VFatRegularDirEntry::new(*b".          ", 0, attributes, Some(cluster), 0, now);
+        let dotdot = VFatRegularDirEntry::new(*b"..         ", 0, attributes, parent, 0, now);
+        {
+            let mut vfat = self.vfat.borrow_mut();
+            vfat.write_dir_record(cluster, 0, &dot.into())?;
+            vfat.write_dir_record(cluster, 1, &dotdot.into())?;
+        }
+
+        let template = VFatRegularDirEntry::new([b' '; 11], 0, attributes, Some(cluster), 0, now);
+        let (location, regular) = match self.insert(name, template) {
+            Ok(inserted) => inserted,
+            Err(e) => {
+                self.vfat.borrow_mut().free_chain(cluster)?;
+                return Err(e);
+            }
+        };
+
+        Ok(Dir {
+            vfat: self.vfat.clone(),
+            start: cluster,
+            name: name.to_string(),
+            metadata: regular.metadata(),
+            location: Some(location),
+        })
+    }
+}
+
+/// An iterator over the entries of a directory.
+pub struct EntryIter {
+    vfat: Shared<VFat>,
+    dir: Cluster,
+    root: Cluster,
+    records: Vec<VFatDirEntry>,
+    index: usize,
+}
+
+impl EntryIter {
+    /// Decodes the LFN characters in `chars`, stopping at the first NUL.
+    pub(crate) fn decode_lfn(chars: &[u16]) -> String {
+        let end = chars.iter().position(|&c| c == 0x0000 || c == 0xFFFF).unwrap_or(chars.len());
+        decode_utf16(chars[..end].iter().cloned())
+            .map(|c| c.unwrap_or(::std::char::REPLACEMENT_CHARACTER))
+            .collect()
     }
 }
 
-// FIXME: Implement `trait::Dir` for `Dir`.
+impl Iterator for EntryIter {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        let mut lfn: Vec<u16> = Vec::new();
+        let mut lfn_start = None;
+        let mut lfn_checksum = 0;
+        let mut expected = 0;
+
+        while self.index < self.records.len() {
+            let index = self.index;
+            let record = self.records[index];
+            self.index += 1;
+
+            match record.id() {
+                END_OF_DIR => {
+                    self.index = self.records.len();
+                    return None;
+                }
+                DELETED => {
+                    lfn_start = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(long) = record.lfn() {
+                if long.is_last() {
+                    lfn = vec![0xFFFF; long.ordinal() as usize * LFN_CHARS];
+                    lfn_start = Some(index);
+                    lfn_checksum = long.checksum();
+                    expected = long.ordinal();
+                }
+
+                if lfn_start.is_some() && long.ordinal() == expected && expected > 0
+                    && long.checksum() == lfn_checksum {
+                    let offset = (long.ordinal() as usize - 1) * LFN_CHARS;
+                    lfn[offset..(offset + LFN_CHARS)].copy_from_slice(&long.chars());
+                    expected -= 1;
+                } else {
+                    lfn_start = None;
+                }
+
+                continue;
+            }
+
+            let regular = record.regular().expect("regular record");
+            if regular.attributes().contains(Attributes::VOLUME_ID) {
+                lfn_start = None;
+                continue;
+            }
+
+            let valid_lfn = lfn_start.is_some() && expected == 0
+                && lfn_checksum == lfn_checksum_of(&regular);
+            let (name, first) = match (valid_lfn, lfn_start) {
+                (true, Some(first)) => (EntryIter::decode_lfn(&lfn), first),
+                _ => (regular.name(), index)
+            };
+
+            let location = EntryLocation { dir: self.dir, first, index };
+            let entry = match regular.attributes().contains(Attributes::DIRECTORY) {
+                true => Entry::Dir(Dir {
+                    vfat: self.vfat.clone(),
+                    start: regular.cluster().unwrap_or(self.root),
+                    name,
+                    metadata: regular.metadata(),
+                    location: Some(location),
+                }),
+                false => Entry::File(File::new(self.vfat.clone(), name, &regular, location))
+            };
+
+            return Some(entry);
+        }
+
+        None
+    }
+}
+
+fn lfn_checksum_of(regular: &VFatRegularDirEntry) -> u8 {
+    lfn_checksum(&regular.short_name())
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = EntryIter;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let root = self.vfat.borrow().root_dir_cluster();
+        Ok(EntryIter {
+            vfat: self.vfat.clone(),
+            dir: self.start,
+            root,
+            records: self.records()?,
+            index: 0,
+        })
+    }
+}
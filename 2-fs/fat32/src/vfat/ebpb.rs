@@ -1,11 +1,39 @@
-use std::fmt;
+use std::{fmt, mem};
 
 use traits::BlockDevice;
-use vfat::Error;
+use vfat::{Error, FatType};
 
 #[repr(C, packed)]
 pub struct BiosParameterBlock {
-    // FIXME: Fill me in.
+    jump: [u8; 3],
+    oem_id: [u8; 8],
+    bytes_per_sector: u16,
+    sectors_per_cluster: u8,
+    reserved_sectors: u16,
+    num_fats: u8,
+    max_dir_entries: u16,
+    total_logical_sectors_16: u16,
+    media_descriptor: u8,
+    sectors_per_fat_16: u16,
+    sectors_per_track: u16,
+    num_heads: u16,
+    hidden_sectors: u32,
+    total_logical_sectors_32: u32,
+    sectors_per_fat: u32,
+    flags: u16,
+    version: u16,
+    root_cluster: u32,
+    fsinfo_sector: u16,
+    backup_boot_sector: u16,
+    reserved: [u8; 12],
+    drive_number: u8,
+    nt_flags: u8,
+    signature: u8,
+    volume_id: u32,
+    volume_label: [u8; 11],
+    system_id: [u8; 8],
+    boot_code: [u8; 420],
+    bootable_signature: [u8; 2],
 }
 
 impl BiosParameterBlock {
@@ -19,12 +47,128 @@ impl BiosParameterBlock {
         mut device: T,
         sector: u64
     ) -> Result<BiosParameterBlock, Error> {
-        unimplemented!("BiosParameterBlock::from()")
+        let mut buf = [0u8; 512];
+        device.read_sector(sector, &mut buf)?;
+
+        let ebpb: BiosParameterBlock = unsafe { mem::transmute(buf) };
+        if ebpb.bootable_signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(ebpb)
+    }
+
+    /// The number of bytes in a logical sector.
+    pub fn bytes_per_sector(&self) -> u16 {
+        self.bytes_per_sector
+    }
+
+    /// The number of logical sectors in a cluster.
+    pub fn sectors_per_cluster(&self) -> u8 {
+        self.sectors_per_cluster
+    }
+
+    /// The number of logical sectors before the first FAT.
+    pub fn reserved_sectors(&self) -> u16 {
+        self.reserved_sectors
+    }
+
+    /// The number of copies of the FAT.
+    pub fn num_fats(&self) -> u8 {
+        self.num_fats
+    }
+
+    /// The total number of logical sectors in the file system.
+    pub fn total_sectors(&self) -> u32 {
+        match self.total_logical_sectors_16 {
+            0 => self.total_logical_sectors_32,
+            n => n as u32
+        }
+    }
+
+    /// The number of logical sectors occupied by one copy of the FAT.
+    pub fn sectors_per_fat(&self) -> u32 {
+        match self.sectors_per_fat_16 {
+            0 => self.sectors_per_fat,
+            n => n as u32
+        }
+    }
+
+    /// The maximum number of entries in the fixed-size root directory of a
+    /// FAT12 or FAT16 file system. Always 0 for FAT32.
+    pub fn max_dir_entries(&self) -> u16 {
+        self.max_dir_entries
+    }
+
+    /// The number of logical sectors occupied by the fixed-size root
+    /// directory of a FAT12 or FAT16 file system.
+    pub fn root_dir_sectors(&self) -> u32 {
+        let bytes_per_sector = self.bytes_per_sector as u32;
+        (self.max_dir_entries as u32 * 32 + bytes_per_sector - 1) / bytes_per_sector
+    }
+
+    /// The number of data clusters in the file system.
+    pub fn cluster_count(&self) -> u32 {
+        let metadata = self.reserved_sectors as u32
+            + self.num_fats as u32 * self.sectors_per_fat()
+            + self.root_dir_sectors();
+        self.total_sectors().saturating_sub(metadata) / self.sectors_per_cluster as u32
+    }
+
+    /// The type of FAT, determined from the number of data clusters.
+    pub fn fat_type(&self) -> FatType {
+        FatType::from_cluster_count(self.cluster_count())
+    }
+
+    /// Returns `true` if the sector and cluster sizes and number of FATs are
+    /// plausible. This distinguishes a boot sector from, for instance, an MBR.
+    pub fn has_valid_geometry(&self) -> bool {
+        let (bytes_per_sector, sectors_per_cluster) = (self.bytes_per_sector, self.sectors_per_cluster);
+        (self.jump[0] == 0xEB || self.jump[0] == 0xE9)
+            && bytes_per_sector >= 512 && bytes_per_sector <= 4096
+            && bytes_per_sector.is_power_of_two()
+            && sectors_per_cluster.is_power_of_two()
+            && self.reserved_sectors > 0
+            && self.num_fats > 0
+            && self.sectors_per_fat() > 0
+    }
+
+    /// The cluster number of the root directory.
+    pub fn root_cluster(&self) -> u32 {
+        self.root_cluster
+    }
+
+    /// The logical sector number of the FSInfo structure.
+    pub fn fsinfo_sector(&self) -> u16 {
+        self.fsinfo_sector
     }
 }
 
 impl fmt::Debug for BiosParameterBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        unimplemented!("BiosParameterBlock::debug()")
+        f.debug_struct("BiosParameterBlock")
+            .field("oem_id", &String::from_utf8_lossy(&{ self.oem_id }))
+            .field("bytes_per_sector", &{ self.bytes_per_sector })
+            .field("sectors_per_cluster", &{ self.sectors_per_cluster })
+            .field("reserved_sectors", &{ self.reserved_sectors })
+            .field("num_fats", &{ self.num_fats })
+            .field("max_dir_entries", &{ self.max_dir_entries })
+            .field("total_sectors", &self.total_sectors())
+            .field("media_descriptor", &{ self.media_descriptor })
+            .field("sectors_per_fat", &self.sectors_per_fat())
+            .field("sectors_per_track", &{ self.sectors_per_track })
+            .field("num_heads", &{ self.num_heads })
+            .field("hidden_sectors", &{ self.hidden_sectors })
+            .field("flags", &{ self.flags })
+            .field("version", &{ self.version })
+            .field("root_cluster", &{ self.root_cluster })
+            .field("fsinfo_sector", &{ self.fsinfo_sector })
+            .field("backup_boot_sector", &{ self.backup_boot_sector })
+            .field("drive_number", &{ self.drive_number })
+            .field("signature", &{ self.signature })
+            .field("volume_id", &{ self.volume_id })
+            .field("volume_label", &String::from_utf8_lossy(&{ self.volume_label }))
+            .field("system_id", &String::from_utf8_lossy(&{ self.system_id }))
+            .finish()
     }
 }
@@ -19,20 +19,85 @@ pub enum Status {
     Eoc(u32)
 }
 
+/// The variant of FAT used by a volume.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32
+}
+
+impl FatType {
+    /// Returns the FAT type of a volume with `clusters` data clusters. Per the
+    /// FAT specification, the cluster count alone determines the type.
+    pub fn from_cluster_count(clusters: u32) -> FatType {
+        if clusters < 4085 {
+            FatType::Fat12
+        } else if clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    /// The number of bits in one FAT entry.
+    pub fn entry_bits(&self) -> u32 {
+        match *self {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32
+        }
+    }
+}
+
 #[repr(C, packed)]
+#[derive(Copy, Clone)]
 pub struct FatEntry(pub u32);
 
 impl FatEntry {
+    /// The value written to mark the last cluster in a chain.
+    pub const EOC: u32 = 0x0FFFFFFF;
+
+    /// Returns the entry with the raw value `raw` read from a FAT of type
+    /// `fat_type`. The reserved, bad, and end-of-chain values of 12 and 16-bit
+    /// entries are widened to their FAT32 equivalents so that `status()`
+    /// interprets entries of every FAT type alike.
+    pub fn from_raw(fat_type: FatType, raw: u32) -> FatEntry {
+        let mask = match fat_type {
+            FatType::Fat32 => return FatEntry(raw),
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF
+        };
+
+        match raw & mask {
+            value if value >= mask - 0xF => FatEntry(0x0FFFFFFF - (mask - value)),
+            value => FatEntry(value)
+        }
+    }
+
     /// Returns the `Status` of the FAT entry `self`.
     pub fn status(&self) -> Status {
-        unimplemented!("FatEntry::status()")
+        match self.0 & 0x0FFFFFFF {
+            0x00000000 => Free,
+            0x00000001 => Reserved,
+            value @ 0x00000002..=0x0FFFFFEF => Data(Cluster::from(value)),
+            0x0FFFFFF0..=0x0FFFFFF6 => Reserved,
+            0x0FFFFFF7 => Bad,
+            value => Eoc(value)
+        }
+    }
+
+    /// Sets the 28-bit value of this entry to `value`, preserving the upper
+    /// four reserved bits.
+    pub fn set(&mut self, value: u32) {
+        self.0 = (self.0 & 0xF0000000) | (value & 0x0FFFFFFF);
     }
 }
 
 impl fmt::Debug for FatEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("FatEntry")
-            .field("value", &self.0)
+            .field("value", &{ self.0 })
             .field("status", &self.status())
             .finish()
     }
@@ -1,15 +1,235 @@
-use std::cmp::{min, max};
+use std::cmp::min;
 use std::io::{self, SeekFrom};
 
 use traits;
-use vfat::{VFat, Shared, Cluster, Metadata};
+use vfat::{VFat, Shared, Cluster, FatEntry, Metadata};
+use vfat::dir::{EntryLocation, VFatRegularDirEntry};
 
 #[derive(Debug)]
 pub struct File {
-    // FIXME: Fill me in.
+    pub(crate) vfat: Shared<VFat>,
+    pub(crate) first_cluster: Option<Cluster>,
+    pub(crate) name: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) size: u32,
+    pub(crate) location: EntryLocation,
+    /// The current position in the file.
+    offset: u32,
+    /// The index in the chain and number of the last cluster accessed.
+    current: Option<(u32, Cluster)>,
+    /// The offset just past the clusters that were last read ahead.
+    read_ahead_end: u32,
 }
 
-// FIXME: Implement `traits::File` (and its supertraits) for `File`.
+impl File {
+    /// Returns a handle to the file described by the regular record `regular`
+    /// found at `location`.
+    pub(crate) fn new(
+        vfat: Shared<VFat>,
+        name: String,
+        regular: &VFatRegularDirEntry,
+        location: EntryLocation
+    ) -> File {
+        File {
+            vfat,
+            first_cluster: regular.cluster(),
+            name,
+            metadata: regular.metadata(),
+            size: regular.size(),
+            location,
+            offset: 0,
+            current: None,
+            read_ahead_end: 0,
+        }
+    }
+
+    /// Returns the cluster holding the byte at `offset`, walking the chain
+    /// forward from the last accessed cluster when possible. If `grow` is
+    /// `true`, clusters are allocated as needed to reach `offset`.
+    fn cluster_at(&mut self, vfat: &mut VFat, offset: u32, grow: bool) -> io::Result<Cluster> {
+        let index = offset / vfat.cluster_size() as u32;
+        let (mut i, mut cluster) = match self.current {
+            Some((i, cluster)) if i <= index => (i, cluster),
+            _ => match self.first_cluster {
+                Some(first) => (0, first),
+                None if grow => {
+                    let first = vfat.alloc_cluster(None)?;
+                    self.first_cluster = Some(first);
+                    (0, first)
+                }
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  "file has no clusters"))
+            }
+        };
+
+        while i < index {
+            cluster = match vfat.next_cluster(cluster)? {
+                Some(next) => next,
+                None if grow => vfat.alloc_cluster(Some(cluster))?,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                  "cluster chain too short"))
+            };
+            i += 1;
+        }
+
+        self.current = Some((i, cluster));
+        Ok(cluster)
+    }
+
+    /// Writes `buf` at the current position, growing the file as needed.
+    /// Returns the number of bytes written. The directory entry is not
+    /// updated.
+    fn write_at(&mut self, vfat: &mut VFat, buf: &[u8]) -> io::Result<usize> {
+        let cluster_size = vfat.cluster_size();
+        let to_write = min(buf.len(), (u32::max_value() - self.offset) as usize);
+
+        let mut written = 0;
+        while written < to_write {
+            let offset = self.offset;
+            let cluster = self.cluster_at(vfat, offset, true)?;
+            let within = offset as usize % cluster_size;
+            let end = min(to_write, written + cluster_size - within);
+            let n = vfat.write_cluster(cluster, within, &buf[written..end])?;
+            written += n;
+            self.offset += n as u32;
+            self.size = ::std::cmp::max(self.size, self.offset);
+        }
+
+        Ok(written)
+    }
+
+    /// Writes this file's first cluster, size, and a new modification time to
+    /// its directory entry.
+    fn update_entry(&mut self, vfat: &mut VFat) -> io::Result<()> {
+        let location = self.location;
+        let mut regular = vfat.read_dir_record(location.dir, location.index)?
+            .regular()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidData, "file entry was removed"))?;
+
+        regular.set_cluster(self.first_cluster);
+        regular.set_size(self.size);
+        regular.touch(vfat.now());
+        vfat.write_dir_record(location.dir, location.index, &regular.into())?;
+        self.metadata = regular.metadata();
+        Ok(())
+    }
+
+    /// Truncates or extends this file so that it is `len` bytes long.
+    ///
+    /// When the file shrinks, clusters past the new end of the file are freed
+    /// and the current position is moved to the end of the file if it was
+    /// beyond it. When the file grows, the new bytes are zeroes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `len` is larger than the maximum
+    /// file size of 4GiB - 1.
+    pub fn set_len(&mut self, len: u64) -> io::Result<()> {
+        if len > u32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file is too large"));
+        }
+
+        let len = len as u32;
+        let vfat = self.vfat.clone();
+        let mut vfat = vfat.borrow_mut();
+        let cluster_size = vfat.cluster_size() as u32;
+
+        if len > self.size {
+            let offset = self.offset;
+            let zeroes = vec![0u8; cluster_size as usize];
+            self.offset = self.size;
+            while self.offset < len {
+                let amount = min(len - self.offset, cluster_size) as usize;
+                self.write_at(&mut vfat, &zeroes[..amount])?;
+            }
+
+            self.offset = offset;
+        } else if len < self.size {
+            let keep = (len + cluster_size - 1) / cluster_size;
+            match self.first_cluster {
+                Some(first) if keep == 0 => {
+                    vfat.free_chain(first)?;
+                    self.first_cluster = None;
+                }
+                Some(_) => {
+                    let last = self.cluster_at(&mut vfat, (keep - 1) * cluster_size, false)?;
+                    if let Some(next) = vfat.next_cluster(last)? {
+                        vfat.set_fat_entry(last, FatEntry::EOC)?;
+                        vfat.free_chain(next)?;
+                    }
+                }
+                None => {}
+            }
+
+            self.current = None;
+            self.size = len;
+            self.offset = min(self.offset, len);
+        }
+
+        self.update_entry(&mut vfat)
+    }
+}
+
+impl traits::File for File {
+    /// Writes any buffered data to disk.
+    fn sync(&mut self) -> io::Result<()> {
+        self.vfat.borrow_mut().flush()
+    }
+
+    /// Returns the size of the file in bytes.
+    fn size(&self) -> u64 {
+        self.size as u64
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = (self.size - min(self.offset, self.size)) as usize;
+        let to_read = min(buf.len(), remaining);
+
+        let vfat = self.vfat.clone();
+        let mut vfat = vfat.borrow_mut();
+        let cluster_size = vfat.cluster_size();
+
+        let mut read = 0;
+        while read < to_read {
+            let offset = self.offset;
+            let cluster = self.cluster_at(&mut vfat, offset, false)?;
+            let within = offset as usize % cluster_size;
+            if offset >= self.read_ahead_end {
+                let remaining = (self.size - offset) as usize;
+                let clusters = (within + remaining + cluster_size - 1) / cluster_size;
+                let run = vfat.read_ahead(cluster, clusters)?;
+                self.read_ahead_end = (offset - within as u32)
+                    .saturating_add((run * cluster_size) as u32);
+            }
+            let end = min(to_read, read + cluster_size - within);
+            let n = vfat.read_cluster(cluster, within, &mut buf[read..end])?;
+            read += n;
+            self.offset += n as u32;
+        }
+
+        Ok(read)
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let vfat = self.vfat.clone();
+        let mut vfat = vfat.borrow_mut();
+        let written = self.write_at(&mut vfat, buf)?;
+        self.update_entry(&mut vfat)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
 
 impl io::Seek for File {
     /// Seek to offset `pos` in the file.
@@ -26,6 +246,17 @@ impl io::Seek for File {
     /// Seeking before the start of a file or beyond the end of the file results
     /// in an `InvalidInput` error.
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        unimplemented!("File::seek()")
+        let offset = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => self.size as i64 + n,
+            SeekFrom::Current(n) => self.offset as i64 + n,
+        };
+
+        if offset < 0 || offset > self.size as i64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek out of bounds"));
+        }
+
+        self.offset = offset as u32;
+        Ok(self.offset as u64)
     }
 }
@@ -0,0 +1,540 @@
+use std::fmt;
+use std::io;
+
+use vfat::{VFat, Shared, Dir, Cluster, Status, Attributes};
+use vfat::dir::{EntryIter, VFatRegularDirEntry, lfn_checksum, is_short_name_char};
+use vfat::dir::{END_OF_DIR, DELETED};
+
+/// An inconsistency found by `check()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The entry for `cluster` in FAT number `fat` differs from the first FAT.
+    FatMismatch { fat: u8, cluster: u32 },
+    /// The chain of `path` runs into `cluster`, which already belongs to
+    /// `other`. If the chain loops onto itself, `other` is `path`.
+    CrossLinked { path: String, other: String, cluster: u32 },
+    /// The FAT entry for `cluster` in the chain of `path` is free, reserved,
+    /// bad, or points outside of the data region. If the entry's first cluster
+    /// is itself invalid, `cluster` is that first cluster.
+    BrokenChain { path: String, cluster: u32 },
+    /// The chain of the file `path` has `clusters` clusters, but its size of
+    /// `size` bytes requires `expected` clusters.
+    ChainLength { path: String, size: u32, clusters: u32, expected: u32 },
+    /// Clusters marked in use by the FAT that belong to no entry.
+    LostClusters { clusters: Vec<u32> },
+    /// Records `first` through `last` of directory `dir` are long file name
+    /// records whose checksum or sequence doesn't match the entry after them.
+    BadLfnChecksum { dir: String, first: usize, last: usize },
+    /// Record `index` of directory `dir` has a short name with characters that
+    /// are not allowed in 8.3 names.
+    InvalidShortName { dir: String, index: usize, name: String },
+}
+
+impl Problem {
+    /// Returns `true` if `check()` can repair this problem.
+    pub fn is_repairable(&self) -> bool {
+        match *self {
+            Problem::InvalidShortName { .. } => false,
+            _ => true
+        }
+    }
+}
+
+/// The result of checking a file system with `check()`.
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    /// Every problem found, in the order found.
+    pub problems: Vec<Problem>,
+    /// Whether repairable problems were repaired.
+    pub repaired: bool,
+    /// The number of regular files found.
+    pub files: usize,
+    /// The number of directories found, excluding the root directory.
+    pub dirs: usize,
+    /// The number of clusters that belong to an entry.
+    pub used_clusters: u32,
+    /// The number of clusters in the data region.
+    pub total_clusters: u32,
+}
+
+impl Report {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// The long file name records seen since the last regular record.
+struct PendingLfn {
+    first: usize,
+    checksum: u8,
+    /// The sequence number of the next record, or 0 once the name is complete.
+    expected: u8,
+    chars: Vec<u16>,
+}
+
+struct Checker<'a> {
+    vfat: &'a Shared<VFat>,
+    repair: bool,
+    report: Report,
+    /// For each cluster, 1 + the index in `paths` of the entry owning it, or
+    /// 0 if no entry owns it yet.
+    owners: Vec<u32>,
+    paths: Vec<String>,
+}
+
+/// Checks the consistency of `vfat`.
+///
+/// Every FAT copy is compared against the first FAT, every entry's cluster
+/// chain is followed from the root directory to find cross-linked, broken,
+/// and lost chains, each file's chain length is compared with its size, and
+/// each directory's records are checked for orphaned long file name records
+/// and invalid short names.
+///
+/// If `repair` is `true`, repairable problems are fixed as they are found:
+/// FAT copies are rewritten from the first FAT, cross-linked and broken
+/// chains are truncated before the bad cluster, chains are trimmed or file
+/// sizes reduced so that they agree, lost clusters are freed, and orphaned
+/// long file name records are deleted. The free cluster count is then
+/// recomputed and all changes are written to the device.
+///
+/// # Errors
+///
+/// Returns an error if reading or writing the device fails.
+pub fn check(vfat: &Shared<VFat>, repair: bool) -> io::Result<Report> {
+    let max_cluster = vfat.borrow().max_cluster();
+    let mut checker = Checker {
+        vfat,
+        repair,
+        report: Report { repaired: repair, total_clusters: max_cluster - 1, ..Report::default() },
+        owners: vec![0; max_cluster as usize + 1],
+        paths: Vec::new(),
+    };
+
+    checker.check_fat_copies()?;
+
+    let root = Dir::root(vfat.clone());
+    let root_usable = match root.start.is_data() {
+        true => checker.check_chain("/", Some(root.start), None, None)?,
+        false => true
+    };
+
+    if root_usable {
+        checker.check_dir(&root, "/")?;
+    }
+
+    checker.check_lost_clusters()?;
+    checker.report.used_clusters = checker.owners.iter().filter(|&&owner| owner != 0).count() as u32;
+
+    if repair {
+        let mut vfat = vfat.borrow_mut();
+        let mut free = 0;
+        for n in 2..(max_cluster + 1) {
+            if vfat.fat_entry(Cluster::from(n))?.status() == Status::Free {
+                free += 1;
+            }
+        }
+
+        vfat.set_free_count(free)?;
+        vfat.flush()?;
+    }
+
+    Ok(checker.report)
+}
+
+/// Returns `true` if every character of the 11-byte short name `short` is
+/// allowed in an 8.3 name. Names are padded with trailing spaces; characters
+/// above `0x7F` are code page characters and are always allowed.
+fn is_valid_short_name(short: &[u8; 11]) -> bool {
+    fn valid_part(part: &[u8]) -> bool {
+        let end = part.iter().rposition(|&b| b != b' ').map(|i| i + 1).unwrap_or(0);
+        part[..end].iter().all(|&b| b >= 0x80 || is_short_name_char(b))
+    }
+
+    // A leading 0x05 stands for 0xE5, which marks deleted entries.
+    let first = match short[0] {
+        0x05 => DELETED,
+        b => b
+    };
+
+    first != b' ' && valid_part(&[first]) && valid_part(&short[1..8]) && valid_part(&short[8..])
+}
+
+/// Returns the path of the entry `name` inside the directory at `dir`.
+fn join(dir: &str, name: &str) -> String {
+    match dir {
+        "/" => format!("/{}", name),
+        dir => format!("{}/{}", dir, name)
+    }
+}
+
+impl<'a> Checker<'a> {
+    /// Records `problem`.
+    fn found(&mut self, problem: Problem) {
+        self.report.problems.push(problem);
+    }
+
+    /// Compares every FAT copy against the first FAT.
+    fn check_fat_copies(&mut self) -> io::Result<()> {
+        let shared = self.vfat;
+        let mut vfat = shared.borrow_mut();
+        for fat in 1..vfat.num_fats() {
+            for n in 2..(vfat.max_cluster() + 1) {
+                let cluster = Cluster::from(n);
+                let first = vfat.fat_entry_in(cluster, 0)?.0 & 0x0FFFFFFF;
+                let copy = vfat.fat_entry_in(cluster, fat)?.0 & 0x0FFFFFFF;
+                if first != copy {
+                    self.found(Problem::FatMismatch { fat, cluster: n });
+                    if self.repair {
+                        vfat.set_fat_entry(cluster, first)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Follows the chain of the entry at `path` starting at `first`, claiming
+    /// its clusters. `size` is the size of a file, or `None` for directories.
+    /// `record` is the directory and index of the entry's regular record, or
+    /// `None` for the root directory.
+    ///
+    /// Returns `true` if the chain can be read safely: either it had no
+    /// problems, or they were repaired.
+    fn check_chain(
+        &mut self,
+        path: &str,
+        first: Option<Cluster>,
+        size: Option<u32>,
+        record: Option<(Cluster, usize)>
+    ) -> io::Result<bool> {
+        self.paths.push(path.to_string());
+        let id = self.paths.len() as u32;
+
+        let shared = self.vfat;
+        let mut vfat = shared.borrow_mut();
+        let max_cluster = vfat.max_cluster();
+        let mut chain: Vec<Cluster> = Vec::new();
+        let mut problem = None;
+        let mut current = first;
+
+        while let Some(cluster) = current {
+            let n = cluster.number();
+            if n < 2 || n > max_cluster {
+                let last = chain.last().map(|c| c.number()).unwrap_or(n);
+                problem = Some(Problem::BrokenChain { path: path.to_string(), cluster: last });
+                break;
+            }
+
+            match self.owners[n as usize] {
+                0 => self.owners[n as usize] = id,
+                owner => {
+                    let other = self.paths[owner as usize - 1].clone();
+                    problem = Some(Problem::CrossLinked { path: path.to_string(), other, cluster: n });
+                    break;
+                }
+            }
+
+            chain.push(cluster);
+            current = match vfat.fat_entry(cluster)?.status() {
+                Status::Data(next) => Some(next),
+                Status::Eoc(_) => None,
+                _ => {
+                    problem = Some(Problem::BrokenChain { path: path.to_string(), cluster: n });
+                    None
+                }
+            };
+        }
+
+        let cluster_size = vfat.cluster_size() as u64;
+        let has_problem = problem.is_some();
+        if let Some(problem) = problem {
+            self.found(problem);
+            if !self.repair {
+                return Ok(false);
+            }
+
+            // Cut the chain just before the bad link. The size of a file is
+            // reduced to what the remaining chain can hold.
+            match chain.last() {
+                Some(&last) => vfat.set_fat_entry(last, ::vfat::FatEntry::EOC)?,
+                None => if let Some(record) = record {
+                    update_record(&mut vfat, record, |regular| {
+                        regular.set_cluster(None);
+                        regular.set_size(0);
+                    })?;
+                }
+            }
+
+            if let (Some(size), Some(record)) = (size, record) {
+                let capacity = chain.len() as u64 * cluster_size;
+                if size as u64 > capacity {
+                    update_record(&mut vfat, record, |regular| regular.set_size(capacity as u32))?;
+                }
+            }
+        }
+
+        let size = match size {
+            Some(size) if !has_problem => size,
+            _ => return Ok(true)
+        };
+
+        let expected = ((size as u64 + cluster_size - 1) / cluster_size) as usize;
+        if chain.len() == expected {
+            return Ok(true);
+        }
+
+        self.found(Problem::ChainLength {
+            path: path.to_string(),
+            size,
+            clusters: chain.len() as u32,
+            expected: expected as u32,
+        });
+
+        if !self.repair {
+            return Ok(true);
+        }
+
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(true)
+        };
+
+        if chain.len() > expected {
+            // Free the clusters past the end of the file.
+            match expected {
+                0 => update_record(&mut vfat, record, |regular| regular.set_cluster(None))?,
+                n => vfat.set_fat_entry(chain[n - 1], ::vfat::FatEntry::EOC)?
+            }
+
+            for &cluster in &chain[expected..] {
+                vfat.set_fat_entry(cluster, 0)?;
+                self.owners[cluster.number() as usize] = 0;
+            }
+        } else {
+            // The chain can't hold the whole file: keep what it holds.
+            let capacity = chain.len() as u64 * cluster_size;
+            update_record(&mut vfat, record, |regular| regular.set_size(capacity as u32))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Checks the records of `dir`, found at `path`, and every entry in it,
+    /// recursing into subdirectories.
+    fn check_dir(&mut self, dir: &Dir, path: &str) -> io::Result<()> {
+        let records = dir.records()?;
+        let mut pending: Option<PendingLfn> = None;
+
+        for (index, record) in records.iter().enumerate() {
+            match record.id() {
+                END_OF_DIR => break,
+                DELETED => {
+                    if let Some(lfn) = pending.take() {
+                        self.orphaned_lfn(dir, path, lfn.first, index - 1)?;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(long) = record.lfn() {
+                let chars = long.chars();
+                let continues = match pending {
+                    Some(ref lfn) => !long.is_last() && lfn.expected > 0
+                        && long.ordinal() == lfn.expected && long.checksum() == lfn.checksum,
+                    None => false
+                };
+
+                if continues {
+                    let lfn = pending.as_mut().expect("pending LFN");
+                    let offset = (long.ordinal() as usize - 1) * chars.len();
+                    lfn.chars[offset..(offset + chars.len())].copy_from_slice(&chars);
+                    lfn.expected -= 1;
+                } else if let Some(lfn) = pending.take() {
+                    self.orphaned_lfn(dir, path, lfn.first, index - 1)?;
+                }
+
+                if long.is_last() && long.ordinal() > 0 {
+                    let mut lfn_chars = vec![0xFFFF; long.ordinal() as usize * chars.len()];
+                    let offset = lfn_chars.len() - chars.len();
+                    lfn_chars[offset..].copy_from_slice(&chars);
+                    pending = Some(PendingLfn {
+                        first: index,
+                        checksum: long.checksum(),
+                        expected: long.ordinal() - 1,
+                        chars: lfn_chars,
+                    });
+                } else if !continues {
+                    self.orphaned_lfn(dir, path, index, index)?;
+                }
+
+                continue;
+            }
+
+            let regular = record.regular().expect("regular record");
+            let short = regular.short_name();
+            let mut name = None;
+            if let Some(lfn) = pending.take() {
+                if lfn.expected == 0 && lfn.checksum == lfn_checksum(&short) {
+                    name = Some(EntryIter::decode_lfn(&lfn.chars));
+                } else {
+                    self.orphaned_lfn(dir, path, lfn.first, index - 1)?;
+                }
+            }
+
+            if regular.attributes().contains(Attributes::VOLUME_ID)
+                || &short == b".          " || &short == b"..         " {
+                continue;
+            }
+
+            if !is_valid_short_name(&short) {
+                let name = String::from_utf8_lossy(&short).into_owned();
+                self.found(Problem::InvalidShortName { dir: path.to_string(), index, name });
+            }
+
+            let name = name.unwrap_or_else(|| regular.name());
+            let child = join(path, &name);
+            let record = Some((dir.start, index));
+            if regular.attributes().contains(Attributes::DIRECTORY) {
+                self.report.dirs += 1;
+                let start = match regular.cluster() {
+                    Some(start) => start,
+                    None => continue
+                };
+
+                if self.check_chain(&child, Some(start), None, record)? {
+                    let subdir = Dir {
+                        vfat: self.vfat.clone(),
+                        start,
+                        name,
+                        metadata: regular.metadata(),
+                        location: None,
+                    };
+
+                    self.check_dir(&subdir, &child)?;
+                }
+            } else {
+                self.report.files += 1;
+                self.check_chain(&child, regular.cluster(), Some(regular.size()), record)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records that records `first` through `last` of `dir` are orphaned long
+    /// file name records, deleting them when repairing.
+    fn orphaned_lfn(&mut self, dir: &Dir, path: &str, first: usize, last: usize) -> io::Result<()> {
+        self.found(Problem::BadLfnChecksum { dir: path.to_string(), first, last });
+        if self.repair {
+            let mut vfat = self.vfat.borrow_mut();
+            for index in first..(last + 1) {
+                let mut record = vfat.read_dir_record(dir.start, index)?;
+                record.delete();
+                vfat.write_dir_record(dir.start, index, &record)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds clusters in use by the FAT that belong to no entry, freeing them
+    /// when repairing.
+    fn check_lost_clusters(&mut self) -> io::Result<()> {
+        let shared = self.vfat;
+        let mut vfat = shared.borrow_mut();
+        let mut lost = Vec::new();
+        for n in 2..(vfat.max_cluster() + 1) {
+            if self.owners[n as usize] != 0 {
+                continue;
+            }
+
+            match vfat.fat_entry(Cluster::from(n))?.status() {
+                Status::Free | Status::Bad => {}
+                _ => lost.push(n)
+            }
+        }
+
+        if lost.is_empty() {
+            return Ok(());
+        }
+
+        if self.repair {
+            for &n in &lost {
+                vfat.set_fat_entry(Cluster::from(n), 0)?;
+            }
+        }
+
+        self.found(Problem::LostClusters { clusters: lost });
+        Ok(())
+    }
+}
+
+/// Reads the regular record at `record`, applies `f` to it, and writes it
+/// back.
+fn update_record<F>(vfat: &mut VFat, record: (Cluster, usize), f: F) -> io::Result<()>
+    where F: FnOnce(&mut VFatRegularDirEntry)
+{
+    let (dir, index) = record;
+    let mut regular = vfat.read_dir_record(dir, index)?
+        .regular()
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "record is not a regular record"))?;
+
+    f(&mut regular);
+    vfat.write_dir_record(dir, index, &regular.into())
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::FatMismatch { fat, cluster } => {
+                write!(f, "FAT {} differs from FAT 0 at cluster {}", fat, cluster)
+            }
+            Problem::CrossLinked { ref path, ref other, cluster } if path == other => {
+                write!(f, "{}: cluster chain loops at cluster {}", path, cluster)
+            }
+            Problem::CrossLinked { ref path, ref other, cluster } => {
+                write!(f, "{}: cross-linked with {} at cluster {}", path, other, cluster)
+            }
+            Problem::BrokenChain { ref path, cluster } => {
+                write!(f, "{}: cluster chain is broken at cluster {}", path, cluster)
+            }
+            Problem::ChainLength { ref path, size, clusters, expected } => {
+                write!(f, "{}: size of {} bytes needs {} clusters, but the chain has {}",
+                       path, size, expected, clusters)
+            }
+            Problem::LostClusters { ref clusters } => {
+                write!(f, "{} lost clusters, starting at cluster {}", clusters.len(), clusters[0])
+            }
+            Problem::BadLfnChecksum { ref dir, first, last } => {
+                write!(f, "{}: orphaned long file name records {} to {}", dir, first, last)
+            }
+            Problem::InvalidShortName { ref dir, index, ref name } => {
+                write!(f, "{}: record {} has an invalid short name {:?}", dir, index, name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} files, {} directories, {}/{} clusters in use",
+                 self.files, self.dirs, self.used_clusters, self.total_clusters)?;
+
+        for problem in &self.problems {
+            let status = match (self.repaired, problem.is_repairable()) {
+                (true, true) => " (repaired)",
+                (true, false) => " (not repairable)",
+                (false, _) => ""
+            };
+            writeln!(f, "{}{}", problem, status)?;
+        }
+
+        match self.problems.len() {
+            0 => write!(f, "no problems found"),
+            n => write!(f, "{} problems found", n)
+        }
+    }
+}
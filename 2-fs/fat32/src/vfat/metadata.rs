@@ -27,11 +27,155 @@ pub struct Timestamp {
 /// Metadata for a directory entry.
 #[derive(Default, Debug, Clone)]
 pub struct Metadata {
-    // FIXME: Fill me in.
+    pub(crate) attributes: Attributes,
+    pub(crate) created: Timestamp,
+    pub(crate) accessed: Timestamp,
+    pub(crate) modified: Timestamp,
 }
 
-// FIXME: Implement `traits::Timestamp` for `Timestamp`.
+impl Date {
+    /// Returns the on-disk representation of the date `year`-`month`-`day`.
+    /// `year` must be in range [1980, 2107].
+    pub fn new(year: usize, month: u8, day: u8) -> Date {
+        let year = (year - 1980) as u16;
+        Date((year << 9) | ((month as u16 & 0xF) << 5) | (day as u16 & 0x1F))
+    }
+}
+
+impl Time {
+    /// Returns the on-disk representation of the time `hour`:`minute`:`second`.
+    /// The second is stored with a granularity of two seconds.
+    pub fn new(hour: u8, minute: u8, second: u8) -> Time {
+        let (hour, minute, second) = (hour as u16, minute as u16, second as u16);
+        Time(((hour & 0x1F) << 11) | ((minute & 0x3F) << 5) | ((second / 2) & 0x1F))
+    }
+}
+
+impl Attributes {
+    pub const READ_ONLY: u8 = 0x01;
+    pub const HIDDEN: u8 = 0x02;
+    pub const SYSTEM: u8 = 0x04;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+    pub const LFN: u8 = 0x0F;
+
+    /// Returns attributes with the raw attribute bits `bits`.
+    pub fn new(bits: u8) -> Attributes {
+        Attributes(bits)
+    }
+
+    /// Returns the raw attribute bits.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns `true` if all of the bits in `bits` are set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+
+    /// Returns `true` if these are the attributes of a long file name entry.
+    pub fn is_lfn(&self) -> bool {
+        self.0 & 0x3F == Attributes::LFN
+    }
+}
+
+impl Timestamp {
+    /// Returns a timestamp for the given date and time.
+    pub fn new(year: usize, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Timestamp {
+        Timestamp {
+            date: Date::new(year, month, day),
+            time: Time::new(hour, minute, second)
+        }
+    }
+
+    /// Returns the FAT epoch: January 1st, 1980 at midnight.
+    pub fn epoch() -> Timestamp {
+        Timestamp::new(1980, 1, 1, 0, 0, 0)
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        1980 + (self.date.0 >> 9) as usize
+    }
+
+    fn month(&self) -> u8 {
+        ((self.date.0 >> 5) & 0xF) as u8
+    }
+
+    fn day(&self) -> u8 {
+        (self.date.0 & 0x1F) as u8
+    }
+
+    fn hour(&self) -> u8 {
+        (self.time.0 >> 11) as u8
+    }
 
-// FIXME: Implement `traits::Metadata` for `Metadata`.
+    fn minute(&self) -> u8 {
+        ((self.time.0 >> 5) & 0x3F) as u8
+    }
 
-// FIXME: Implement `fmt::Display` (to your liking) for `Metadata`.
+    fn second(&self) -> u8 {
+        ((self.time.0 & 0x1F) * 2) as u8
+    }
+}
+
+impl Metadata {
+    /// Returns the raw attributes of the entry.
+    pub fn attributes(&self) -> Attributes {
+        self.attributes
+    }
+
+    /// Returns `true` if the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.attributes.contains(Attributes::DIRECTORY)
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes.contains(Attributes::READ_ONLY)
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes.contains(Attributes::HIDDEN)
+    }
+
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use traits::Timestamp;
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+               self.year(), self.month(), self.day(),
+               self.hour(), self.minute(), self.second())
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |bit, c| if self.attributes.contains(bit) { c } else { '-' };
+        write!(f, "{}{}{}{}{}  created {}  modified {}  accessed {}",
+               flag(Attributes::DIRECTORY, 'd'),
+               flag(Attributes::READ_ONLY, 'r'),
+               flag(Attributes::HIDDEN, 'h'),
+               flag(Attributes::SYSTEM, 's'),
+               flag(Attributes::ARCHIVE, 'a'),
+               self.created, self.modified, self.accessed)
+    }
+}
@@ -0,0 +1,312 @@
+use std::io;
+use std::cmp::min;
+
+use traits::BlockDevice;
+use vfat::FatEntry;
+use vfat::dir::is_short_name_char;
+
+/// The number of reserved sectors before the first FAT.
+const RESERVED_SECTORS: u64 = 32;
+/// The number of copies of the FAT.
+const NUM_FATS: u64 = 2;
+/// The logical sectors of the FSInfo sector and of the backup boot sector.
+const FSINFO_SECTOR: u64 = 1;
+const BACKUP_BOOT_SECTOR: u64 = 6;
+/// The first cluster of the root directory.
+const ROOT_CLUSTER: u32 = 2;
+/// The smallest and largest number of clusters in a FAT32 volume.
+const MIN_CLUSTERS: u64 = 65525;
+const MAX_CLUSTERS: u64 = 0x0FFFFFF5;
+/// The number of sectors written with one request when zeroing regions.
+const ZERO_CHUNK: u64 = 64;
+
+/// Options for `format()`.
+///
+/// The defaults place the partition at sector 2048, pick the cluster size
+/// from the size of the volume, and use the label `NO NAME` with serial
+/// number 0.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    sectors_per_cluster: Option<u8>,
+    label: Option<String>,
+    serial: u32,
+    partition_start: u64,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            sectors_per_cluster: None,
+            label: None,
+            serial: 0,
+            partition_start: 2048,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Returns the default options.
+    pub fn new() -> FormatOptions {
+        FormatOptions::default()
+    }
+
+    /// Sets the number of sectors in a cluster. Must be a power of two.
+    pub fn sectors_per_cluster(mut self, sectors: u8) -> FormatOptions {
+        self.sectors_per_cluster = Some(sectors);
+        self
+    }
+
+    /// Sets the volume label: at most 11 characters that are valid in a short
+    /// name, or spaces. Lowercase letters are converted to uppercase.
+    pub fn label(mut self, label: &str) -> FormatOptions {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// Sets the volume serial number.
+    pub fn serial(mut self, serial: u32) -> FormatOptions {
+        self.serial = serial;
+        self
+    }
+
+    /// Sets the sector at which the partition begins.
+    pub fn partition_start(mut self, sector: u64) -> FormatOptions {
+        self.partition_start = sector;
+        self
+    }
+}
+
+/// The geometry of the volume being formatted.
+struct Layout {
+    sectors_per_cluster: u64,
+    start: u64,
+    total_sectors: u64,
+    sectors_per_fat: u64,
+    clusters: u64,
+}
+
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..(offset + bytes.len())].copy_from_slice(bytes);
+}
+
+fn le16(n: u16) -> [u8; 2] {
+    [n as u8, (n >> 8) as u8]
+}
+
+fn le32(n: u32) -> [u8; 4] {
+    [n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]
+}
+
+/// Returns the cluster size, in bytes, that Microsoft's tools use for a FAT32
+/// volume of `bytes` bytes.
+fn default_cluster_bytes(bytes: u64) -> u64 {
+    const MB: u64 = 1024 * 1024;
+    match bytes {
+        b if b <= 260 * MB => 512,
+        b if b <= 8 * 1024 * MB => 4096,
+        b if b <= 16 * 1024 * MB => 8192,
+        b if b <= 32 * 1024 * MB => 16384,
+        _ => 32768
+    }
+}
+
+/// Returns the 11-byte label for `label`, padded with spaces.
+fn parse_label(label: Option<&str>) -> io::Result<[u8; 11]> {
+    let label = match label {
+        Some(label) => label.to_ascii_uppercase(),
+        None => return Ok(*b"NO NAME    ")
+    };
+
+    if label.len() > 11 || label.starts_with(' ')
+        || !label.bytes().all(|b| b == b' ' || is_short_name_char(b)) {
+        return Err(invalid_input("invalid volume label"));
+    }
+
+    let mut bytes = [b' '; 11];
+    bytes[..label.len()].copy_from_slice(label.as_bytes());
+    Ok(bytes)
+}
+
+impl Layout {
+    /// Computes the layout of a volume spanning from `start` to the end of a
+    /// device of `sectors` sectors.
+    fn new(bytes_per_sector: u64, sectors: u64, options: &FormatOptions) -> io::Result<Layout> {
+        let start = options.partition_start;
+        if start == 0 || start >= sectors {
+            return Err(invalid_input("partition must start after the MBR and on the device"));
+        }
+
+        let total_sectors = min(sectors - start, u32::max_value() as u64);
+        let sectors_per_cluster = match options.sectors_per_cluster {
+            Some(n) if n.is_power_of_two() => n as u64,
+            Some(_) => return Err(invalid_input("sectors per cluster must be a power of two")),
+            None => {
+                let bytes = default_cluster_bytes(total_sectors * bytes_per_sector);
+                ::std::cmp::max(1, bytes / bytes_per_sector)
+            }
+        };
+
+        // The FAT's size depends on the number of clusters, which depends on
+        // the space left after the FATs. Start from an overestimate and
+        // shrink the FATs until they stop changing.
+        let entries_per_sector = bytes_per_sector / 4;
+        let available = total_sectors.saturating_sub(RESERVED_SECTORS);
+        let mut sectors_per_fat = (available / sectors_per_cluster + 2 + entries_per_sector - 1)
+            / entries_per_sector;
+        loop {
+            let data = available.saturating_sub(NUM_FATS * sectors_per_fat);
+            let clusters = data / sectors_per_cluster;
+            let needed = (clusters + 2 + entries_per_sector - 1) / entries_per_sector;
+            if needed >= sectors_per_fat {
+                break;
+            }
+
+            sectors_per_fat = needed;
+        }
+
+        let data = available.saturating_sub(NUM_FATS * sectors_per_fat);
+        let clusters = min(data / sectors_per_cluster, sectors_per_fat * entries_per_sector - 2);
+        if clusters < MIN_CLUSTERS {
+            return Err(invalid_input("volume is too small for FAT32 with this cluster size"));
+        }
+
+        if clusters > MAX_CLUSTERS {
+            return Err(invalid_input("volume is too large for FAT32 with this cluster size"));
+        }
+
+        Ok(Layout {
+            sectors_per_cluster,
+            start,
+            total_sectors,
+            sectors_per_fat,
+            clusters,
+        })
+    }
+
+    fn fat_start(&self) -> u64 {
+        self.start + RESERVED_SECTORS
+    }
+
+    fn data_start(&self) -> u64 {
+        self.fat_start() + NUM_FATS * self.sectors_per_fat
+    }
+}
+
+/// Writes `count` zeroed sectors starting at `start`.
+fn zero_sectors<T: BlockDevice>(device: &mut T, start: u64, count: u64) -> io::Result<()> {
+    let sector_size = device.sector_size();
+    let zeroes = vec![0u8; (min(count, ZERO_CHUNK) * sector_size) as usize];
+    let mut sector = start;
+    while sector < start + count {
+        let n = min(ZERO_CHUNK, start + count - sector);
+        device.write_sectors(sector, n, &zeroes[..(n * sector_size) as usize])?;
+        sector += n;
+    }
+
+    Ok(())
+}
+
+/// Formats `device`, which holds `sectors` sectors, as a FAT32 volume in a
+/// single MBR partition.
+///
+/// The MBR's partition table is replaced with one FAT32 (LBA) partition that
+/// spans from `options.partition_start` to the end of the device, or to the
+/// 2TiB limit of an MBR partition. The volume receives a boot sector, an
+/// FSInfo sector, backup copies of both at sector 6, two FATs, and an empty
+/// root directory holding only the volume label. The MBR's boot code and
+/// disk signature are left untouched.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if the device's sector size is not a
+/// power of two between 512 and 4096, if the partition does not fit on the
+/// device, if the cluster size or label is invalid, or if the resulting
+/// number of clusters is outside of the range allowed for FAT32.
+pub fn format<T: BlockDevice>(mut device: T, sectors: u64, options: &FormatOptions) -> io::Result<()> {
+    let bytes_per_sector = device.sector_size();
+    if bytes_per_sector < 512 || bytes_per_sector > 4096 || !bytes_per_sector.is_power_of_two() {
+        return Err(invalid_input("unsupported sector size"));
+    }
+
+    let label = parse_label(options.label.as_ref().map(|label| label.as_str()))?;
+    let layout = Layout::new(bytes_per_sector, sectors, options)?;
+    let sector_size = bytes_per_sector as usize;
+
+    // Partition table: one FAT32 (LBA) partition with LBA-only CHS values.
+    let mut mbr = vec![0u8; sector_size];
+    device.read_sector(0, &mut mbr)?;
+    for byte in &mut mbr[446..510] {
+        *byte = 0;
+    }
+    put(&mut mbr, 446, &[0x00, 0xFE, 0xFF, 0xFF, 0x0C, 0xFE, 0xFF, 0xFF]);
+    put(&mut mbr, 446 + 8, &le32(layout.start as u32));
+    put(&mut mbr, 446 + 12, &le32(layout.total_sectors as u32));
+    put(&mut mbr, 510, &[0x55, 0xAA]);
+    device.write_sector(0, &mbr)?;
+
+    // Boot sector with the FAT32 extended BIOS parameter block.
+    let mut boot = vec![0u8; sector_size];
+    put(&mut boot, 0, &[0xEB, 0x58, 0x90]);
+    put(&mut boot, 3, b"MSWIN4.1");
+    put(&mut boot, 11, &le16(bytes_per_sector as u16));
+    boot[13] = layout.sectors_per_cluster as u8;
+    put(&mut boot, 14, &le16(RESERVED_SECTORS as u16));
+    boot[16] = NUM_FATS as u8;
+    boot[21] = 0xF8;
+    put(&mut boot, 24, &le16(63));
+    put(&mut boot, 26, &le16(255));
+    put(&mut boot, 28, &le32(layout.start as u32));
+    put(&mut boot, 32, &le32(layout.total_sectors as u32));
+    put(&mut boot, 36, &le32(layout.sectors_per_fat as u32));
+    put(&mut boot, 44, &le32(ROOT_CLUSTER));
+    put(&mut boot, 48, &le16(FSINFO_SECTOR as u16));
+    put(&mut boot, 50, &le16(BACKUP_BOOT_SECTOR as u16));
+    boot[64] = 0x80;
+    boot[66] = 0x29;
+    put(&mut boot, 67, &le32(options.serial));
+    put(&mut boot, 71, &label);
+    put(&mut boot, 82, b"FAT32   ");
+    put(&mut boot, 510, &[0x55, 0xAA]);
+
+    // FSInfo sector. Only the root directory's cluster is in use.
+    let mut fsinfo = vec![0u8; sector_size];
+    put(&mut fsinfo, 0, &le32(0x41615252));
+    put(&mut fsinfo, 484, &le32(0x61417272));
+    put(&mut fsinfo, 488, &le32(layout.clusters as u32 - 1));
+    put(&mut fsinfo, 492, &le32(ROOT_CLUSTER + 1));
+    put(&mut fsinfo, 508, &le32(0xAA550000));
+
+    zero_sectors(&mut device, layout.start, RESERVED_SECTORS)?;
+    for &base in &[0, BACKUP_BOOT_SECTOR] {
+        device.write_sector(layout.start + base, &boot)?;
+        device.write_sector(layout.start + base + FSINFO_SECTOR, &fsinfo)?;
+    }
+
+    // Both FATs: the media descriptor, the reserved entry, and the root
+    // directory's single cluster.
+    let mut first_fat_sector = vec![0u8; sector_size];
+    put(&mut first_fat_sector, 0, &le32(0x0FFFFFF8));
+    put(&mut first_fat_sector, 4, &le32(0x0FFFFFFF));
+    put(&mut first_fat_sector, 8, &le32(FatEntry::EOC));
+    for fat in 0..NUM_FATS {
+        let start = layout.fat_start() + fat * layout.sectors_per_fat;
+        zero_sectors(&mut device, start, layout.sectors_per_fat)?;
+        device.write_sector(start, &first_fat_sector)?;
+    }
+
+    // The root directory, holding only the volume label.
+    let mut root = vec![0u8; sector_size];
+    if options.label.is_some() {
+        put(&mut root, 0, &label);
+        root[11] = 0x08;
+    }
+
+    let root_start = layout.data_start() + (ROOT_CLUSTER as u64 - 2) * layout.sectors_per_cluster;
+    zero_sectors(&mut device, root_start, layout.sectors_per_cluster)?;
+    device.write_sector(root_start, &root)?;
+    Ok(())
+}
@@ -9,6 +9,8 @@ pub(crate) mod entry;
 pub(crate) mod metadata;
 pub(crate) mod cache;
 pub(crate) mod shared;
+pub(crate) mod fsck;
+pub(crate) mod mkfs;
 
 pub use self::ebpb::BiosParameterBlock;
 pub use self::file::File;
@@ -18,7 +20,11 @@ pub use self::vfat::VFat;
 pub use self::entry::Entry;
 pub use self::metadata::{Metadata, Attributes, Date, Time, Timestamp};
 pub use self::shared::Shared;
+pub use self::cache::CacheStats;
+pub use self::fsck::{check, Problem, Report};
+pub use self::mkfs::{format, FormatOptions};
 
 pub(crate) use self::cache::{CachedDevice, Partition};
 pub(crate) use self::fat::{Status, FatEntry};
+pub use self::fat::FatType;
 pub(crate) use self::cluster::Cluster;
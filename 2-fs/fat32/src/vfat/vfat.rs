@@ -1,84 +1,848 @@
 use std::io;
-use std::path::Path;
+use std::path::{Component, Path};
 use std::mem::size_of;
-use std::cmp::min;
+use std::cmp::{max, min};
 
 use util::SliceExt;
-use mbr::MasterBootRecord;
-use vfat::{Shared, Cluster, File, Dir, Entry, FatEntry, Error, Status};
-use vfat::{BiosParameterBlock, CachedDevice, Partition};
-use traits::{FileSystem, BlockDevice};
+use mbr::{self, MasterBootRecord};
+use gpt::{self, GuidPartitionTable};
+use vfat::{Shared, Cluster, File, Dir, Entry, FatEntry, FatType, Error, Status};
+use vfat::{BiosParameterBlock, CachedDevice, CacheStats, Partition, Timestamp};
+use vfat::dir::{EntryLocation, VFatDirEntry};
+use traits::{FileSystem, BlockDevice, Entry as EntryTrait};
+
+/// Offsets and signatures of the fields of the FSInfo sector.
+const FSINFO_LEAD_SIGNATURE: u32 = 0x41615252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x61417272;
+const FSINFO_FREE_COUNT: usize = 488;
+const FSINFO_NEXT_FREE: usize = 492;
 
 #[derive(Debug)]
 pub struct VFat {
     device: CachedDevice,
+    fat_type: FatType,
     bytes_per_sector: u16,
     sectors_per_cluster: u8,
     sectors_per_fat: u32,
     fat_start_sector: u64,
     data_start_sector: u64,
     root_dir_cluster: Cluster,
+    /// The first sector and number of entries of the fixed-size root
+    /// directory of a FAT12 or FAT16 file system.
+    root_dir_start_sector: u64,
+    root_dir_entries: u32,
+    num_fats: u8,
+    max_cluster: u32,
+    fsinfo_sector: Option<u64>,
+    next_free: u32,
+    clock: fn() -> Timestamp,
+}
+
+/// Returns the first sector of the FAT volume on `device`. The volume is
+/// searched for in the MBR's partition table, then in a GUID partition table,
+/// and finally at sector 0 for devices without a partition table.
+fn find_volume<T: BlockDevice>(device: &mut T) -> Result<u64, Error> {
+    let start = match MasterBootRecord::from(&mut *device) {
+        Ok(mbr) => match mbr.first_fat() {
+            Some(partition) => Some(partition.relative_sector() as u64),
+            None => match GuidPartitionTable::from(&mut *device) {
+                Ok(gpt) => Some(gpt.first_fat().ok_or(Error::NotFound)?.first_lba()),
+                Err(gpt::Error::NoProtectiveMbr) => None,
+                Err(e) => return Err(e.into())
+            }
+        },
+        Err(mbr::Error::UnknownBootIndicator(_)) => None,
+        Err(e) => return Err(e.into())
+    };
+
+    match start {
+        Some(start) => Ok(start),
+        None => match BiosParameterBlock::from(&mut *device, 0) {
+            Ok(ref ebpb) if ebpb.has_valid_geometry() => Ok(0),
+            _ => Err(Error::NotFound)
+        }
+    }
 }
 
 impl VFat {
     pub fn from<T>(mut device: T) -> Result<Shared<VFat>, Error>
         where T: BlockDevice + 'static
     {
-        unimplemented!("VFat::from()")
-    }
-
-    // TODO: The following methods may be useful here:
-    //
-    //  * A method to read from an offset of a cluster into a buffer.
-    //
-    //    fn read_cluster(
-    //        &mut self,
-    //        cluster: Cluster,
-    //        offset: usize,
-    //        buf: &mut [u8]
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to read all of the clusters chained from a starting cluster
-    //    into a vector.
-    //
-    //    fn read_chain(
-    //        &mut self,
-    //        start: Cluster,
-    //        buf: &mut Vec<u8>
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to return a reference to a `FatEntry` for a cluster where the
-    //    reference points directly into a cached sector.
-    //
-    //    fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry>;
+        let start = find_volume(&mut device)?;
+        let ebpb = BiosParameterBlock::from(&mut device, start)?;
+        if !ebpb.has_valid_geometry() {
+            return Err(Error::BadSignature);
+        }
+
+        let fat_type = ebpb.fat_type();
+        let bytes_per_sector = ebpb.bytes_per_sector();
+        let sectors_per_cluster = ebpb.sectors_per_cluster();
+        let fat_start_sector = start + ebpb.reserved_sectors() as u64;
+        let root_dir_start_sector = fat_start_sector
+            + ebpb.num_fats() as u64 * ebpb.sectors_per_fat() as u64;
+        let data_start_sector = root_dir_start_sector + ebpb.root_dir_sectors() as u64;
+
+        // Clusters are numbered starting at 2. The FAT itself may be too small
+        // to describe every cluster in the data region.
+        let fat_entries = ebpb.sectors_per_fat() as u64 * bytes_per_sector as u64 * 8
+            / fat_type.entry_bits() as u64;
+        let max_cluster = min(ebpb.cluster_count() as u64 + 1, fat_entries - 1);
+
+        // FAT12 and FAT16 have neither a root directory cluster nor FSInfo.
+        // Their fixed root directory is represented by cluster 0, which is
+        // also how `..` entries refer to the root directory on disk.
+        let (root_dir_cluster, fsinfo_sector) = match fat_type {
+            FatType::Fat32 => (Cluster::from(ebpb.root_cluster()), match ebpb.fsinfo_sector() {
+                0 | 0xFFFF => None,
+                n => Some(start + n as u64)
+            }),
+            _ => (Cluster::from(0), None)
+        };
+
+        let partition = Partition { start, sector_size: bytes_per_sector as u64 };
+        Ok(Shared::new(VFat {
+            device: CachedDevice::new(device, partition),
+            fat_type,
+            bytes_per_sector,
+            sectors_per_cluster,
+            sectors_per_fat: ebpb.sectors_per_fat(),
+            fat_start_sector,
+            data_start_sector,
+            root_dir_cluster,
+            root_dir_start_sector,
+            root_dir_entries: ebpb.max_dir_entries() as u32,
+            num_fats: ebpb.num_fats(),
+            max_cluster: max_cluster as u32,
+            fsinfo_sector,
+            next_free: 2,
+            clock: Timestamp::epoch,
+        }))
+    }
+
+    /// The type of FAT used by this file system.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// Returns `true` if `dir` refers to the fixed-size root directory of a
+    /// FAT12 or FAT16 file system rather than to a cluster chain.
+    fn is_fixed_root(&self, dir: Cluster) -> bool {
+        self.fat_type != FatType::Fat32 && dir.number() == 0
+    }
+
+    /// Sets the function used to timestamp created and modified entries.
+    ///
+    /// By default, all entries are timestamped with the FAT epoch.
+    pub fn set_clock(&mut self, clock: fn() -> Timestamp) {
+        self.clock = clock;
+    }
+
+    /// Returns the current time according to this file system's clock.
+    pub(crate) fn now(&self) -> Timestamp {
+        (self.clock)()
+    }
+
+    /// Writes all modified sectors back to the underlying device.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.device.flush()
+    }
+
+    /// Sets the maximum number of sectors kept in the sector cache.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        self.device.set_capacity(capacity)
+    }
+
+    /// Returns statistics about the sector cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.device.stats()
+    }
+
+    /// Returns `true` if the underlying device refuses writes, in which case
+    /// every change to the file system fails with `PermissionDenied`.
+    pub fn is_read_only(&self) -> bool {
+        self.device.is_read_only()
+    }
+
+    /// The first cluster of the root directory.
+    pub(crate) fn root_dir_cluster(&self) -> Cluster {
+        self.root_dir_cluster
+    }
+
+    /// The highest cluster number described by the FAT.
+    pub(crate) fn max_cluster(&self) -> u32 {
+        self.max_cluster
+    }
+
+    /// The number of copies of the FAT.
+    pub(crate) fn num_fats(&self) -> u8 {
+        self.num_fats
+    }
+
+    /// The number of bytes in a cluster.
+    pub(crate) fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// Returns an error if `cluster` does not refer to a data cluster.
+    fn check_cluster(&self, cluster: Cluster) -> io::Result<()> {
+        if !cluster.is_data() || cluster.number() > self.max_cluster {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster number"));
+        }
+
+        Ok(())
+    }
+
+    /// The logical sector where `cluster` begins.
+    fn cluster_sector(&self, cluster: Cluster) -> u64 {
+        self.data_start_sector
+            + cluster.data_index() as u64 * self.sectors_per_cluster as u64
+    }
+
+    /// Reads from an offset of a cluster into a buffer. Reads at most until the
+    /// end of the cluster. Returns the number of bytes read.
+    pub(crate) fn read_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &mut [u8]
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let sector_size = self.bytes_per_sector as usize;
+        let first_sector = self.cluster_sector(cluster);
+        let to_read = min(buf.len(), self.cluster_size().saturating_sub(offset));
+        self.device.prefetch(first_sector, self.sectors_per_cluster as u64)?;
+
+        let mut read = 0;
+        while read < to_read {
+            let position = offset + read;
+            let within = position % sector_size;
+            let amount = min(to_read - read, sector_size - within);
+            let data = self.device.get(first_sector + (position / sector_size) as u64)?;
+            buf[read..(read + amount)].copy_from_slice(&data[within..(within + amount)]);
+            read += amount;
+        }
+
+        Ok(read)
+    }
+
+    /// Writes a buffer into a cluster starting at an offset. Writes at most
+    /// until the end of the cluster. Returns the number of bytes written.
+    pub(crate) fn write_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &[u8]
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let sector_size = self.bytes_per_sector as usize;
+        let first_sector = self.cluster_sector(cluster);
+        let to_write = min(buf.len(), self.cluster_size().saturating_sub(offset));
+
+        let mut written = 0;
+        while written < to_write {
+            let position = offset + written;
+            let within = position % sector_size;
+            let amount = min(to_write - written, sector_size - within);
+            let data = self.device.get_mut(first_sector + (position / sector_size) as u64)?;
+            data[within..(within + amount)].copy_from_slice(&buf[written..(written + amount)]);
+            written += amount;
+        }
+
+        Ok(written)
+    }
+
+    /// Reads the run of consecutively numbered clusters in the chain starting
+    /// at `cluster` into the sector cache ahead of their use. At most `limit`
+    /// clusters are read ahead, and never more than the cache can sensibly
+    /// hold. Returns the number of clusters read ahead, which is at least 1.
+    pub(crate) fn read_ahead(&mut self, cluster: Cluster, limit: usize) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let per_cluster = self.sectors_per_cluster as usize;
+        let limit = ::std::cmp::max(1, min(limit, self.device.capacity() / 2 / per_cluster));
+
+        let mut run = 1;
+        let mut current = cluster;
+        while run < limit {
+            match self.next_cluster(current)? {
+                Some(next) if next.number() == current.number() + 1 => {
+                    current = next;
+                    run += 1;
+                }
+                _ => break
+            }
+        }
+
+        let first_sector = self.cluster_sector(cluster);
+        self.device.prefetch(first_sector, (run * per_cluster) as u64)?;
+        Ok(run)
+    }
+
+    /// Reads all of the clusters chained from a starting cluster into a
+    /// vector. Returns the number of bytes read.
+    ///
+    /// If `start` refers to the fixed-size root directory of a FAT12 or FAT16
+    /// file system, the root directory region is read instead.
+    pub(crate) fn read_chain(&mut self, start: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.is_fixed_root(start) {
+            return self.read_fixed_root(buf);
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut cluster = Some(start);
+        let mut read = 0;
+        let mut length = 0;
+        let mut ahead = 0;
+
+        while let Some(current) = cluster {
+            length += 1;
+            if length > self.max_cluster {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+
+            if ahead == 0 {
+                ahead = self.read_ahead(current, usize::max_value())?;
+            }
+
+            ahead -= 1;
+            let end = buf.len();
+            buf.resize(end + cluster_size, 0);
+            read += self.read_cluster(current, 0, &mut buf[end..])?;
+            cluster = self.next_cluster(current)?;
+        }
+
+        Ok(read)
+    }
+
+    /// Returns the byte offset of the entry for `cluster` from the start of a
+    /// FAT and the number of bytes spanned by the entry. A FAT12 entry shares
+    /// its bytes with a neighbouring entry and may cross a sector boundary.
+    fn fat_entry_span(&self, cluster: Cluster) -> (u64, usize) {
+        let n = cluster.number() as u64;
+        match self.fat_type {
+            FatType::Fat12 => (n + n / 2, 2),
+            FatType::Fat16 => (n * 2, 2),
+            FatType::Fat32 => (n * size_of::<FatEntry>() as u64, 4)
+        }
+    }
+
+    /// Returns the logical sector and offset within it of byte `offset` of
+    /// FAT number `fat`.
+    fn fat_byte_position(&self, fat: u8, offset: u64) -> (u64, usize) {
+        let sector = self.fat_start_sector
+            + fat as u64 * self.sectors_per_fat as u64
+            + offset / self.bytes_per_sector as u64;
+        (sector, (offset % self.bytes_per_sector as u64) as usize)
+    }
+
+    /// Reads the raw, little-endian FAT entry for `cluster` from FAT number
+    /// `fat`. The bytes of a FAT12 entry's neighbour are included.
+    fn read_fat_raw(&mut self, cluster: Cluster, fat: u8) -> io::Result<u32> {
+        let (offset, len) = self.fat_entry_span(cluster);
+        let mut raw = 0;
+        for i in 0..len {
+            let (sector, within) = self.fat_byte_position(fat, offset + i as u64);
+            raw |= (self.device.get(sector)?[within] as u32) << (i * 8);
+        }
+
+        Ok(raw)
+    }
+
+    /// Writes the raw, little-endian FAT entry for `cluster` to FAT number
+    /// `fat`.
+    fn write_fat_raw(&mut self, cluster: Cluster, fat: u8, raw: u32) -> io::Result<()> {
+        let (offset, len) = self.fat_entry_span(cluster);
+        for i in 0..len {
+            let (sector, within) = self.fat_byte_position(fat, offset + i as u64);
+            self.device.get_mut(sector)?[within] = (raw >> (i * 8)) as u8;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the `FatEntry` for `cluster` in the first FAT. Entries of FAT12
+    /// and FAT16 file systems are widened to their FAT32 equivalents.
+    pub(crate) fn fat_entry(&mut self, cluster: Cluster) -> io::Result<FatEntry> {
+        self.fat_entry_in(cluster, 0)
+    }
+
+    /// Returns the `FatEntry` for `cluster` in FAT number `fat`.
+    pub(crate) fn fat_entry_in(&mut self, cluster: Cluster, fat: u8) -> io::Result<FatEntry> {
+        if cluster.number() > self.max_cluster || fat >= self.num_fats {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cluster out of range"));
+        }
+
+        let raw = self.read_fat_raw(cluster, fat)?;
+        let raw = match (self.fat_type, cluster.number() % 2) {
+            (FatType::Fat12, 1) => raw >> 4,
+            _ => raw
+        };
+
+        Ok(FatEntry::from_raw(self.fat_type, raw))
+    }
+
+    /// Sets the value of the FAT entry for `cluster` in every copy of the FAT.
+    /// `value` is truncated to the width of a FAT entry; the reserved upper
+    /// four bits of FAT32 entries are preserved.
+    pub(crate) fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        self.check_cluster(cluster)?;
+        for fat in 0..self.num_fats {
+            let old = self.read_fat_raw(cluster, fat)?;
+            let raw = match (self.fat_type, cluster.number() % 2) {
+                (FatType::Fat12, 0) => (old & 0xF000) | (value & 0xFFF),
+                (FatType::Fat12, _) => (old & 0x000F) | ((value & 0xFFF) << 4),
+                (FatType::Fat16, _) => value & 0xFFFF,
+                (FatType::Fat32, _) => {
+                    let mut entry = FatEntry(old);
+                    entry.set(value);
+                    entry.0
+                }
+            };
+
+            self.write_fat_raw(cluster, fat, raw)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the cluster following `cluster` in its chain, or `None` if
+    /// `cluster` is the last cluster in its chain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the FAT entry for `cluster` does
+    /// not mark a cluster in use.
+    pub(crate) fn next_cluster(&mut self, cluster: Cluster) -> io::Result<Option<Cluster>> {
+        match self.fat_entry(cluster)?.status() {
+            Status::Data(next) => {
+                self.check_cluster(next)?;
+                Ok(Some(next))
+            }
+            Status::Eoc(_) => Ok(None),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain is broken"))
+        }
+    }
+
+    /// Allocates a free cluster, marks it as the end of a chain, and zeroes
+    /// it. If `prev` is `Some`, the new cluster is linked after `prev`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if there are no free clusters.
+    pub(crate) fn alloc_cluster(&mut self, prev: Option<Cluster>) -> io::Result<Cluster> {
+        let count = self.max_cluster - 1;
+        let mut candidate = self.next_free;
+        let mut found = None;
+        for _ in 0..count {
+            if candidate < 2 || candidate > self.max_cluster {
+                candidate = 2;
+            }
+
+            if self.fat_entry(Cluster::from(candidate))?.status() == Status::Free {
+                found = Some(Cluster::from(candidate));
+                break;
+            }
+
+            candidate += 1;
+        }
+
+        let cluster = found.ok_or(io::Error::new(io::ErrorKind::Other, "file system is full"))?;
+        self.set_fat_entry(cluster, FatEntry::EOC)?;
+        if let Some(prev) = prev {
+            self.set_fat_entry(prev, cluster.number())?;
+        }
+
+        let zeroes = vec![0u8; self.cluster_size()];
+        self.write_cluster(cluster, 0, &zeroes)?;
+
+        self.next_free = cluster.number() + 1;
+        self.update_fsinfo(-1)?;
+        Ok(cluster)
+    }
+
+    /// Appends a newly allocated cluster to the chain starting at `start`.
+    /// Returns the new cluster.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if `start` refers to the fixed-size
+    /// root directory of a FAT12 or FAT16 file system, which cannot grow.
+    pub(crate) fn extend_chain(&mut self, start: Cluster) -> io::Result<Cluster> {
+        if self.is_fixed_root(start) {
+            return Err(io::Error::new(io::ErrorKind::Other, "root directory is full"));
+        }
+
+        let mut last = start;
+        while let Some(next) = self.next_cluster(last)? {
+            last = next;
+        }
+
+        self.alloc_cluster(Some(last))
+    }
+
+    /// Frees every cluster in the chain starting at `start`.
+    pub(crate) fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        let mut cluster = Some(start);
+        let mut freed = 0;
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current)?;
+            self.set_fat_entry(current, 0)?;
+            freed += 1;
+        }
+
+        if start.number() < self.next_free {
+            self.next_free = start.number();
+        }
+
+        self.update_fsinfo(freed)
+    }
+
+    /// Adjusts the free cluster count in the FSInfo sector by `delta` and
+    /// records the next free cluster hint. Does nothing if the volume has no
+    /// valid FSInfo sector or if the free count is unknown. The count is only
+    /// a hint and may be wrong, so the result is kept within the number of
+    /// clusters on the volume.
+    fn update_fsinfo(&mut self, delta: i64) -> io::Result<()> {
+        match self.fsinfo_free_count()? {
+            Some(0xFFFFFFFF) => self.set_free_count(0xFFFFFFFF),
+            Some(free) => {
+                let clusters = self.max_cluster as i64 - 1;
+                let free = min(max(free as i64 + delta, 0), clusters);
+                self.set_free_count(free as u32)
+            }
+            None => Ok(())
+        }
+    }
+
+    /// Returns the free cluster count recorded in the FSInfo sector, or
+    /// `None` if the volume has no valid FSInfo sector.
+    fn fsinfo_free_count(&mut self) -> io::Result<Option<u32>> {
+        let sector = match self.fsinfo_sector {
+            Some(sector) => sector,
+            None => return Ok(None)
+        };
+
+        let read_u32 = |data: &[u8], offset: usize| {
+            let bytes = &data[offset..(offset + 4)];
+            (bytes[0] as u32) | (bytes[1] as u32) << 8
+                | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+        };
+
+        let data = self.device.get(sector)?;
+        if read_u32(data, 0) != FSINFO_LEAD_SIGNATURE
+            || read_u32(data, 484) != FSINFO_STRUCT_SIGNATURE {
+            return Ok(None);
+        }
+
+        Ok(Some(read_u32(data, FSINFO_FREE_COUNT)))
+    }
+
+    /// Records `free` as the free cluster count in the FSInfo sector, along
+    /// with the next free cluster hint. Does nothing if the volume has no
+    /// valid FSInfo sector.
+    pub(crate) fn set_free_count(&mut self, free: u32) -> io::Result<()> {
+        let sector = match (self.fsinfo_free_count()?, self.fsinfo_sector) {
+            (Some(_), Some(sector)) => sector,
+            _ => return Ok(())
+        };
+
+        let next_free = self.next_free;
+        let data = self.device.get_mut(sector)?;
+        for i in 0..4 {
+            data[FSINFO_FREE_COUNT + i] = (free >> (i * 8)) as u8;
+            data[FSINFO_NEXT_FREE + i] = (next_free >> (i * 8)) as u8;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the fixed-size root directory of a FAT12 or FAT16 file system
+    /// into `buf`. Returns the number of bytes read.
+    fn read_fixed_root(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let sector_size = self.bytes_per_sector as usize;
+        let length = self.root_dir_entries as usize * size_of::<VFatDirEntry>();
+        let sectors = (length + sector_size - 1) / sector_size;
+        self.device.prefetch(self.root_dir_start_sector, sectors as u64)?;
+
+        let start = buf.len();
+        for i in 0..sectors {
+            let data = self.device.get(self.root_dir_start_sector + i as u64)?;
+            buf.extend_from_slice(data);
+        }
+
+        buf.truncate(start + length);
+        Ok(length)
+    }
+
+    /// Returns the logical sector and byte offset of record `index` of the
+    /// directory starting at `dir`.
+    fn dir_record_position(&mut self, dir: Cluster, index: usize) -> io::Result<(u64, usize)> {
+        let offset = index * size_of::<VFatDirEntry>();
+        let sector_size = self.bytes_per_sector as usize;
+        if self.is_fixed_root(dir) {
+            if index >= self.root_dir_entries as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "record out of bounds"));
+            }
+
+            let sector = self.root_dir_start_sector + (offset / sector_size) as u64;
+            return Ok((sector, offset % sector_size));
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut cluster = dir;
+        for _ in 0..(offset / cluster_size) {
+            cluster = self.next_cluster(cluster)?
+                .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "record out of bounds"))?;
+        }
+
+        self.check_cluster(cluster)?;
+        let within = offset % cluster_size;
+        Ok((self.cluster_sector(cluster) + (within / sector_size) as u64, within % sector_size))
+    }
+
+    /// Reads record `index` of the directory starting at `dir`.
+    pub(crate) fn read_dir_record(&mut self, dir: Cluster, index: usize) -> io::Result<VFatDirEntry> {
+        let (sector, offset) = self.dir_record_position(dir, index)?;
+        let data = self.device.get(sector)?;
+        let records: &[VFatDirEntry] = unsafe {
+            data[offset..(offset + size_of::<VFatDirEntry>())].cast()
+        };
+
+        Ok(records[0])
+    }
+
+    /// Overwrites record `index` of the directory starting at `dir`.
+    pub(crate) fn write_dir_record(
+        &mut self,
+        dir: Cluster,
+        index: usize,
+        record: &VFatDirEntry
+    ) -> io::Result<()> {
+        let (sector, offset) = self.dir_record_position(dir, index)?;
+        let data = self.device.get_mut(sector)?;
+        let records: &mut [VFatDirEntry] = unsafe {
+            data[offset..(offset + size_of::<VFatDirEntry>())].cast_mut()
+        };
+
+        records[0] = *record;
+        Ok(())
+    }
+
+    /// Marks every record of the entry at `location` as deleted.
+    pub(crate) fn delete_records(&mut self, location: EntryLocation) -> io::Result<()> {
+        for index in location.first..(location.index + 1) {
+            let mut record = self.read_dir_record(location.dir, index)?;
+            record.delete();
+            self.write_dir_record(location.dir, index, &record)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns an error of kind `InvalidInput` with message `msg`.
+fn invalid_input(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Splits the absolute path `path` into its parent directory and final
+/// component. The final component must be a regular name.
+fn split_path(path: &Path) -> io::Result<(&Path, &str)> {
+    if !path.is_absolute() {
+        return Err(invalid_input("path is not absolute"));
+    }
+
+    let name = match path.components().next_back() {
+        Some(Component::Normal(name)) => name.to_str()
+            .ok_or(invalid_input("path is not valid UTF-8"))?,
+        _ => return Err(invalid_input("path does not name an entry"))
+    };
+
+    Ok((path.parent().ok_or(invalid_input("path has no parent"))?, name))
+}
+
+/// Opens the directory `parent` for creating an entry inside of it. Missing
+/// directories result in an `InvalidInput` error.
+fn open_parent(vfat: &Shared<VFat>, parent: &Path) -> io::Result<Dir> {
+    match vfat.open(parent) {
+        Ok(Entry::Dir(dir)) => Ok(dir),
+        Ok(Entry::File(_)) => Err(invalid_input("parent is not a directory")),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(invalid_input("parent directory does not exist"))
+        }
+        Err(e) => Err(e)
+    }
+}
+
+/// Returns `true` if the directory starting at `dir` is the directory starting
+/// at `ancestor` or lies somewhere beneath it. Paths are neither normalized
+/// nor compared by case, so this follows `..` entries up to the root instead.
+fn is_within(vfat: &Shared<VFat>, mut dir: Cluster, ancestor: Cluster) -> io::Result<bool> {
+    let (root, max_cluster) = {
+        let vfat = vfat.borrow();
+        (vfat.root_dir_cluster(), vfat.max_cluster())
+    };
+
+    // A corrupt volume may hold a cycle of `..` entries of its own.
+    for _ in 0..max_cluster {
+        if dir == ancestor {
+            return Ok(true);
+        } else if dir == root {
+            return Ok(false);
+        }
+
+        let dotdot = vfat.borrow_mut().read_dir_record(dir, 1)?.regular()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidData, "missing '..' entry"))?;
+        dir = dotdot.cluster().unwrap_or(root);
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "directory tree has a cycle"))
+}
+
+/// Frees the clusters of `entry` and, recursively, of all of its children.
+fn free_tree(vfat: &Shared<VFat>, entry: &Entry) -> io::Result<()> {
+    use traits::{Dir, Entry as EntryTrait};
+
+    let cluster = match *entry {
+        Entry::File(ref file) => file.first_cluster,
+        Entry::Dir(ref dir) => {
+            for child in dir.entries()? {
+                if child.name() != "." && child.name() != ".." {
+                    free_tree(vfat, &child)?;
+                }
+            }
+
+            Some(dir.start)
+        }
+    };
+
+    match cluster {
+        Some(cluster) => vfat.borrow_mut().free_chain(cluster),
+        None => Ok(())
+    }
 }
 
 impl<'a> FileSystem for &'a Shared<VFat> {
-    type File = ::traits::Dummy;
-    type Dir = ::traits::Dummy;
-    type Entry = ::traits::Dummy;
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
 
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        unimplemented!("FileSystem::open()")
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(invalid_input("path is not absolute"));
+        }
+
+        let mut entry = Entry::Dir(Dir::root(self.clone()));
+        let mut components = path.components().peekable();
+        while let Some(component) = components.next() {
+            let name = match component {
+                Component::RootDir | Component::CurDir => continue,
+                Component::ParentDir => "..".as_ref(),
+                Component::Normal(name) => name,
+                Component::Prefix(_) => return Err(invalid_input("path has a prefix"))
+            };
+
+            let dir = match entry {
+                Entry::Dir(dir) => dir,
+                Entry::File(_) => return Err(invalid_input("path component is not a directory"))
+            };
+
+            if component == Component::ParentDir && dir.is_root() {
+                entry = Entry::Dir(dir);
+                continue;
+            }
+
+            entry = match dir.find(name) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound && components.peek().is_some() => {
+                    return Err(invalid_input("path component does not exist"));
+                }
+                result => result?
+            };
+        }
+
+        Ok(entry)
     }
 
-    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
-        unimplemented!("read only file system")
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (parent, name) = split_path(path.as_ref())?;
+        open_parent(self, parent)?.create_file(name)
     }
 
-    fn create_dir<P>(self, _path: P, _parents: bool) -> io::Result<Self::Dir>
+    fn create_dir<P>(self, path: P, parents: bool) -> io::Result<Self::Dir>
         where P: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        let (parent, name) = split_path(path.as_ref())?;
+        if parents {
+            let mut dir = Dir::root(self.clone());
+            for component in parent.components() {
+                if let Component::Normal(part) = component {
+                    let part = part.to_str().ok_or(invalid_input("path is not valid UTF-8"))?;
+                    dir = match dir.find(part) {
+                        Ok(Entry::Dir(existing)) => existing,
+                        Ok(Entry::File(_)) => return Err(invalid_input("path component is a file")),
+                        Err(ref e) if e.kind() == io::ErrorKind::NotFound => dir.create_dir(part)?,
+                        Err(e) => return Err(e)
+                    };
+                }
+            }
+        }
+
+        open_parent(self, parent)?.create_dir(name)
     }
 
-    fn rename<P, Q>(self, _from: P, _to: Q) -> io::Result<()>
+    fn rename<P, Q>(self, from: P, to: Q) -> io::Result<()>
         where P: AsRef<Path>, Q: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        let (from, to) = (from.as_ref(), to.as_ref());
+        split_path(from)?;
+        let (to_parent, to_name) = split_path(to)?;
+
+        let entry = self.open(from)?;
+        let location = entry.location().ok_or(invalid_input("cannot rename the root"))?;
+        let target = open_parent(self, to_parent)?;
+        if let Entry::Dir(ref dir) = entry {
+            if is_within(self, target.start, dir.start)? {
+                return Err(invalid_input("cannot move a directory into itself"));
+            }
+        }
+
+        let record = self.borrow_mut().read_dir_record(location.dir, location.index)?;
+        let regular = record.regular().expect("regular record");
+        target.insert(to_name, regular)?;
+        self.borrow_mut().delete_records(location)?;
+
+        // A moved directory's `..` entry must point at its new parent.
+        if let Entry::Dir(ref dir) = entry {
+            if location.dir != target.start {
+                let parent = match target.is_root() {
+                    true => None,
+                    false => Some(target.start)
+                };
+
+                let mut vfat = self.borrow_mut();
+                let mut dotdot = vfat.read_dir_record(dir.start, 1)?.regular()
+                    .ok_or(io::Error::new(io::ErrorKind::InvalidData, "missing '..' entry"))?;
+                dotdot.set_cluster(parent);
+                vfat.write_dir_record(dir.start, 1, &dotdot.into())?;
+            }
+        }
+
+        Ok(())
     }
 
-    fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
-        unimplemented!("read only file system")
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        split_path(path.as_ref())?;
+        let entry = self.open(path)?;
+        let location = entry.location().ok_or(invalid_input("cannot remove the root"))?;
+        if entry.is_dir() && !children {
+            return Err(io::Error::new(io::ErrorKind::Other, "entry is a directory"));
+        }
+
+        free_tree(self, &entry)?;
+        self.borrow_mut().delete_records(location)
     }
 }
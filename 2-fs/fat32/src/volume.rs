@@ -0,0 +1,395 @@
+use std::{fmt, io, vec};
+use std::path::Path;
+
+use traits::{self, BlockDevice, FileSystem};
+use vfat::{self, CacheStats, Shared, VFat};
+use exfat::{self, ExFat};
+
+/// A mounted volume of either format: FAT12, FAT16 or FAT32 through `VFat`,
+/// or exFAT through `ExFat`.
+///
+/// `&Volume` implements `FileSystem` by forwarding to the mounted file
+/// system, so that code written against the traits works with either.
+#[derive(Debug, Clone)]
+pub enum Volume {
+    Fat(Shared<VFat>),
+    ExFat(Shared<ExFat>),
+}
+
+/// The error returned by `Volume::from()`: that of the file system the
+/// device was taken to hold.
+#[derive(Debug)]
+pub enum Error {
+    Fat(vfat::Error),
+    ExFat(exfat::Error),
+}
+
+impl Volume {
+    /// Mounts the volume on `device`: the exFAT volume if there is one,
+    /// otherwise the FAT volume.
+    ///
+    /// # Errors
+    ///
+    /// If an exFAT volume is found, even one whose boot region is corrupt,
+    /// its mount errors are returned as `Error::ExFat`. Otherwise, those of
+    /// mounting a FAT volume are returned as `Error::Fat`.
+    pub fn from<T>(mut device: T) -> Result<Volume, Error>
+        where T: BlockDevice + 'static
+    {
+        match exfat::exfat::find_volume(&mut device) {
+            Ok(_) | Err(exfat::Error::BadChecksum) => {
+                ExFat::from(device).map(Volume::ExFat).map_err(Error::ExFat)
+            }
+            Err(_) => VFat::from(device).map(Volume::Fat).map_err(Error::Fat)
+        }
+    }
+
+    /// Returns `true` if the volume cannot be changed: exFAT volumes are
+    /// always read-only, FAT volumes are if their device is.
+    pub fn is_read_only(&self) -> bool {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.borrow().is_read_only(),
+            Volume::ExFat(_) => true
+        }
+    }
+
+    /// Returns statistics about the mounted file system's sector cache.
+    pub fn cache_stats(&self) -> CacheStats {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.borrow().cache_stats(),
+            Volume::ExFat(ref exfat) => exfat.borrow().cache_stats()
+        }
+    }
+}
+
+/// A file on a `Volume`.
+#[derive(Debug)]
+pub enum File {
+    Fat(vfat::File),
+    ExFat(exfat::File),
+}
+
+impl File {
+    /// Truncates or extends the file to `len` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Files on exFAT volumes are read-only: an error kind of
+    /// `PermissionDenied` is returned for them.
+    pub fn set_len(&mut self, len: u64) -> io::Result<()> {
+        match *self {
+            File::Fat(ref mut file) => file.set_len(len),
+            File::ExFat(_) => Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                                 "exFAT volumes are read-only"))
+        }
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        match *self {
+            File::Fat(ref mut file) => file.sync(),
+            File::ExFat(ref mut file) => file.sync()
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match *self {
+            File::Fat(ref file) => file.size(),
+            File::ExFat(ref file) => file.size()
+        }
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            File::Fat(ref mut file) => file.read(buf),
+            File::ExFat(ref mut file) => file.read(buf)
+        }
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            File::Fat(ref mut file) => file.write(buf),
+            File::ExFat(ref mut file) => file.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            File::Fat(ref mut file) => file.flush(),
+            File::ExFat(ref mut file) => file.flush()
+        }
+    }
+}
+
+impl io::Seek for File {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        match *self {
+            File::Fat(ref mut file) => file.seek(pos),
+            File::ExFat(ref mut file) => file.seek(pos)
+        }
+    }
+}
+
+/// A directory on a `Volume`.
+#[derive(Debug)]
+pub enum Dir {
+    Fat(vfat::Dir),
+    ExFat(exfat::Dir),
+}
+
+/// An iterator over the entries of a directory on a `Volume`.
+pub struct EntryIter(Iter);
+
+enum Iter {
+    Fat(vfat::dir::EntryIter),
+    ExFat(vec::IntoIter<exfat::Entry>),
+}
+
+impl Iterator for EntryIter {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        match self.0 {
+            Iter::Fat(ref mut iter) => iter.next().map(Entry::from),
+            Iter::ExFat(ref mut iter) => iter.next().map(Entry::from)
+        }
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = EntryIter;
+
+    fn entries(&self) -> io::Result<EntryIter> {
+        Ok(EntryIter(match *self {
+            Dir::Fat(ref dir) => Iter::Fat(dir.entries()?),
+            Dir::ExFat(ref dir) => Iter::ExFat(dir.entries()?)
+        }))
+    }
+}
+
+/// A point in time recorded by a `Volume`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Timestamp {
+    Fat(vfat::Timestamp),
+    ExFat(exfat::Timestamp),
+}
+
+macro timestamp_field($name:ident, $T:ty) {
+    fn $name(&self) -> $T {
+        match *self {
+            Timestamp::Fat(ref timestamp) => timestamp.$name(),
+            Timestamp::ExFat(ref timestamp) => timestamp.$name()
+        }
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    timestamp_field!(year, usize);
+    timestamp_field!(month, u8);
+    timestamp_field!(day, u8);
+    timestamp_field!(hour, u8);
+    timestamp_field!(minute, u8);
+    timestamp_field!(second, u8);
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Timestamp::Fat(ref timestamp) => timestamp.fmt(f),
+            Timestamp::ExFat(ref timestamp) => timestamp.fmt(f)
+        }
+    }
+}
+
+/// The metadata of an entry on a `Volume`.
+#[derive(Debug, Clone)]
+pub enum Metadata {
+    Fat(vfat::Metadata),
+    ExFat(exfat::Metadata),
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        match *self {
+            Metadata::Fat(ref metadata) => metadata.read_only(),
+            Metadata::ExFat(ref metadata) => metadata.read_only()
+        }
+    }
+
+    fn hidden(&self) -> bool {
+        match *self {
+            Metadata::Fat(ref metadata) => metadata.hidden(),
+            Metadata::ExFat(ref metadata) => metadata.hidden()
+        }
+    }
+
+    fn created(&self) -> Timestamp {
+        match *self {
+            Metadata::Fat(ref metadata) => Timestamp::Fat(metadata.created()),
+            Metadata::ExFat(ref metadata) => Timestamp::ExFat(metadata.created())
+        }
+    }
+
+    fn accessed(&self) -> Timestamp {
+        match *self {
+            Metadata::Fat(ref metadata) => Timestamp::Fat(metadata.accessed()),
+            Metadata::ExFat(ref metadata) => Timestamp::ExFat(metadata.accessed())
+        }
+    }
+
+    fn modified(&self) -> Timestamp {
+        match *self {
+            Metadata::Fat(ref metadata) => Timestamp::Fat(metadata.modified()),
+            Metadata::ExFat(ref metadata) => Timestamp::ExFat(metadata.modified())
+        }
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Metadata::Fat(ref metadata) => metadata.fmt(f),
+            Metadata::ExFat(ref metadata) => metadata.fmt(f)
+        }
+    }
+}
+
+/// A directory entry on a `Volume`. The metadata is kept alongside the file
+/// or directory so that `metadata()` can return a reference to it.
+#[derive(Debug)]
+pub struct Entry {
+    metadata: Metadata,
+    item: Item,
+}
+
+#[derive(Debug)]
+enum Item {
+    File(File),
+    Dir(Dir),
+}
+
+impl From<vfat::Entry> for Entry {
+    fn from(entry: vfat::Entry) -> Entry {
+        match entry {
+            vfat::Entry::File(file) => Entry {
+                metadata: Metadata::Fat(file.metadata.clone()),
+                item: Item::File(File::Fat(file))
+            },
+            vfat::Entry::Dir(dir) => Entry {
+                metadata: Metadata::Fat(dir.metadata.clone()),
+                item: Item::Dir(Dir::Fat(dir))
+            }
+        }
+    }
+}
+
+impl From<exfat::Entry> for Entry {
+    fn from(entry: exfat::Entry) -> Entry {
+        match entry {
+            exfat::Entry::File(file) => Entry {
+                metadata: Metadata::ExFat(file.metadata.clone()),
+                item: Item::File(File::ExFat(file))
+            },
+            exfat::Entry::Dir(dir) => Entry {
+                metadata: Metadata::ExFat(dir.metadata.clone()),
+                item: Item::Dir(Dir::ExFat(dir))
+            }
+        }
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match self.item {
+            Item::File(File::Fat(ref file)) => &file.name,
+            Item::File(File::ExFat(ref file)) => &file.name,
+            Item::Dir(Dir::Fat(ref dir)) => &dir.name,
+            Item::Dir(Dir::ExFat(ref dir)) => &dir.name
+        }
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match self.item {
+            Item::File(ref file) => Some(file),
+            Item::Dir(_) => None
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match self.item {
+            Item::File(_) => None,
+            Item::Dir(ref dir) => Some(dir)
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self.item {
+            Item::File(file) => Some(file),
+            Item::Dir(_) => None
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self.item {
+            Item::File(_) => None,
+            Item::Dir(dir) => Some(dir)
+        }
+    }
+}
+
+impl<'a> FileSystem for &'a Volume {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Entry> {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.open(path).map(Entry::from),
+            Volume::ExFat(ref exfat) => exfat.open(path).map(Entry::from)
+        }
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<File> {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.create_file(path).map(File::Fat),
+            Volume::ExFat(ref exfat) => exfat.create_file(path).map(File::ExFat)
+        }
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P, parents: bool) -> io::Result<Dir> {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.create_dir(path, parents).map(Dir::Fat),
+            Volume::ExFat(ref exfat) => exfat.create_dir(path, parents).map(Dir::ExFat)
+        }
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.rename(from, to),
+            Volume::ExFat(ref exfat) => exfat.rename(from, to)
+        }
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        match *self {
+            Volume::Fat(ref vfat) => vfat.remove(path, children),
+            Volume::ExFat(ref exfat) => exfat.remove(path, children)
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use traits::{Dir, Entry};
+
+/// The order in which a `Walk` visits entries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Order {
+    /// A directory's contents are visited immediately after the directory,
+    /// before any of its siblings that come after it.
+    DepthFirst,
+    /// Every entry at one depth is visited before any entry one level deeper.
+    BreadthFirst,
+}
+
+/// An entry visited by a `Walk`, along with its path.
+#[derive(Debug)]
+pub struct WalkEntry<E> {
+    path: PathBuf,
+    depth: usize,
+    entry: E,
+}
+
+impl<E: Entry> WalkEntry<E> {
+    /// The path of the entry: the walk's starting path joined with the names
+    /// of every directory leading up to the entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The depth of the entry below the walk's starting directory. Entries in
+    /// the starting directory have depth 1.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The entry itself.
+    pub fn entry(&self) -> &E {
+        &self.entry
+    }
+
+    /// Consumes `self` and returns the entry.
+    pub fn into_entry(self) -> E {
+        self.entry
+    }
+}
+
+/// A recursive traversal of a directory tree.
+///
+/// The walk yields every entry below the starting directory, not including
+/// the starting directory itself. The `.` and `..` entries are skipped.
+/// Directory contents are read lazily as the walk reaches them; if reading a
+/// directory fails, the directory is yielded followed by the error, and the
+/// walk continues with the next entry.
+pub struct Walk<'a, E: Entry> {
+    root: Option<E::Dir>,
+    root_path: PathBuf,
+    pending: VecDeque<WalkEntry<E>>,
+    order: Order,
+    max_depth: Option<usize>,
+    prune: Vec<Box<FnMut(&WalkEntry<E>) -> bool + 'a>>,
+    error: Option<io::Error>,
+}
+
+impl<'a, E: Entry> Walk<'a, E> where E::Dir: Dir<Entry = E> {
+    /// Returns a depth-first walk of `dir`, whose path is `path`.
+    pub fn new<P: Into<PathBuf>>(path: P, dir: E::Dir) -> Walk<'a, E> {
+        Walk {
+            root: Some(dir),
+            root_path: path.into(),
+            pending: VecDeque::new(),
+            order: Order::DepthFirst,
+            max_depth: None,
+            prune: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Sets the order in which entries are visited.
+    pub fn order(mut self, order: Order) -> Walk<'a, E> {
+        self.order = order;
+        self
+    }
+
+    /// Stops the walk from descending into directories at depth `depth`, so
+    /// that no entry deeper than `depth` is visited.
+    pub fn max_depth(mut self, depth: usize) -> Walk<'a, E> {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Adds a callback that is called for every entry before it is visited.
+    /// If it returns `true`, the entry is skipped and, if it is a directory,
+    /// none of its contents are visited. With several callbacks, an entry is
+    /// skipped if any of them returns `true`.
+    pub fn prune<F: FnMut(&WalkEntry<E>) -> bool + 'a>(mut self, prune: F) -> Walk<'a, E> {
+        self.prune.push(Box::new(prune));
+        self
+    }
+
+    /// The path of the directory being walked.
+    pub fn root_path(&self) -> &Path {
+        &self.root_path
+    }
+
+    /// Queues the contents of `dir`, which is at `path` and `depth`.
+    fn expand(&mut self, path: &Path, depth: usize, dir: &E::Dir) -> io::Result<()> {
+        let children: Vec<WalkEntry<E>> = dir.entries()?
+            .filter(|entry| entry.name() != "." && entry.name() != "..")
+            .map(|entry| WalkEntry {
+                path: path.join(entry.name()),
+                depth: depth + 1,
+                entry
+            })
+            .collect();
+
+        match self.order {
+            Order::DepthFirst => self.pending.extend(children.into_iter().rev()),
+            Order::BreadthFirst => self.pending.extend(children),
+        }
+
+        Ok(())
+    }
+
+    fn next_pending(&mut self) -> Option<WalkEntry<E>> {
+        match self.order {
+            Order::DepthFirst => self.pending.pop_back(),
+            Order::BreadthFirst => self.pending.pop_front(),
+        }
+    }
+}
+
+impl<'a, E: Entry> Iterator for Walk<'a, E> where E::Dir: Dir<Entry = E> {
+    type Item = io::Result<WalkEntry<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            let path = self.root_path.clone();
+            if let Err(e) = self.expand(&path, 0, &root) {
+                return Some(Err(e));
+            }
+        }
+
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+
+        loop {
+            let next = self.next_pending()?;
+            if self.prune.iter_mut().any(|prune| prune(&next)) {
+                continue;
+            }
+
+            let descend = self.max_depth.map_or(true, |max| next.depth < max);
+            if descend {
+                if let Some(dir) = next.entry.as_dir() {
+                    if let Err(e) = self.expand(&next.path, next.depth, dir) {
+                        self.error = Some(e);
+                    }
+                }
+            }
+
+            return Some(Ok(next));
+        }
+    }
+}
+
+/// A shell-style pattern matched against paths, one component at a time.
+///
+/// Within a component, `*` matches any run of characters, `?` matches any
+/// single character, and `[...]` matches one of the enclosed characters or
+/// ranges (`[a-z]`), or any character but those if the set begins with `!`.
+/// A component that is exactly `**` matches any number of components,
+/// including none. Matching ignores ASCII case, as do the FAT file systems.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    components: Vec<Vec<char>>,
+}
+
+impl Pattern {
+    /// Parses `pattern`. Empty components, such as those produced by a
+    /// leading or doubled `/`, are ignored.
+    pub fn new(pattern: &str) -> Pattern {
+        Pattern {
+            components: pattern.split('/')
+                .filter(|c| !c.is_empty())
+                .map(|c| c.chars().collect())
+                .collect()
+        }
+    }
+
+    /// Returns `true` if the relative path `path` matches the pattern.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = components(path.as_ref());
+        match_components(&self.components, &path, false)
+    }
+
+    /// Returns `true` if some path below the relative path `path`, or `path`
+    /// itself, could match the pattern.
+    pub fn matches_prefix<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = components(path.as_ref());
+        match_components(&self.components, &path, true)
+    }
+}
+
+fn is_any(pattern: &[char]) -> bool {
+    pattern == ['*', '*']
+}
+
+fn components(path: &Path) -> Vec<Vec<char>> {
+    path.iter()
+        .map(|c| c.to_string_lossy().chars().collect())
+        .filter(|c: &Vec<char>| !c.is_empty() && c[..] != ['/'])
+        .collect()
+}
+
+fn match_components(pattern: &[Vec<char>], path: &[Vec<char>], prefix: bool) -> bool {
+    if path.is_empty() {
+        return prefix || pattern.iter().all(|p| is_any(p));
+    }
+
+    match pattern.split_first() {
+        None => false,
+        Some((first, rest)) if is_any(first) => {
+            match_components(rest, path, prefix) || match_components(pattern, &path[1..], prefix)
+        }
+        Some((first, rest)) => {
+            match_name(first, &path[0]) && match_components(rest, &path[1..], prefix)
+        }
+    }
+}
+
+/// Matches the class beginning after the `[` at the start of `pattern`
+/// against `c`. Returns whether `c` matched and the length of the class,
+/// including the brackets, or `None` if the class is never closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let negated = pattern.get(1) == Some(&'!');
+    let mut i = if negated { 2 } else { 1 };
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let (low, high) = (pattern[i].to_ascii_lowercase(), pattern[i + 2].to_ascii_lowercase());
+            matched |= low <= c && c <= high;
+            i += 3;
+        } else {
+            matched |= pattern[i].to_ascii_lowercase() == c;
+            i += 1;
+        }
+
+        first = false;
+    }
+
+    None
+}
+
+fn match_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..(name.len() + 1)).any(|i| match_name(rest, &name[i..])),
+        Some(_) if name.is_empty() => false,
+        Some((&'?', rest)) => match_name(rest, &name[1..]),
+        Some((&'[', _)) => {
+            let c = name[0].to_ascii_lowercase();
+            match match_class(pattern, c) {
+                Some((true, len)) => match_name(&pattern[len..], &name[1..]),
+                Some((false, _)) => false,
+                None => c == '[' && match_name(&pattern[1..], &name[1..]),
+            }
+        }
+        Some((&p, rest)) => {
+            p.to_ascii_lowercase() == name[0].to_ascii_lowercase() && match_name(rest, &name[1..])
+        }
+    }
+}
+
+/// The entries below a directory whose paths match a `Pattern`.
+///
+/// Paths are matched relative to the directory being searched, and
+/// directories that cannot lead to a match are not read.
+pub struct Glob<'a, E: Entry> {
+    walk: Walk<'a, E>,
+    pattern: Pattern,
+}
+
+impl<'a, E: Entry> Glob<'a, E> where E::Dir: Dir<Entry = E> {
+    /// Returns the entries found by `walk` that match `pattern`. Entries
+    /// pruned by `walk` stay pruned.
+    pub fn new(walk: Walk<'a, E>, pattern: Pattern) -> Glob<'a, E> {
+        let (root, prune_pattern) = (walk.root_path().to_path_buf(), pattern.clone());
+        let walk = walk.prune(move |entry| {
+            let path = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            !prune_pattern.matches_prefix(path)
+        });
+
+        Glob { walk, pattern }
+    }
+}
+
+impl<'a, E: Entry> Iterator for Glob<'a, E> where E::Dir: Dir<Entry = E> {
+    type Item = io::Result<WalkEntry<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.walk.next()? {
+                Ok(entry) => {
+                    let matched = {
+                        let root = self.walk.root_path();
+                        let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                        self.pattern.matches(path)
+                    };
+
+                    if matched {
+                        return Some(Ok(entry));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
@@ -50,7 +50,11 @@ pub extern "C" fn kmain() {
         if led_on { led.clear() } else { led.set() }
         led_on = !led_on;
 
-        match xmodem::Xmodem::receive(&mut uart, &mut buf) {
+        // The UART times reads out, so the receiver can fall back to
+        // checksums if the transmitter ignores requests for CRC-16.
+        let mut receiver = xmodem::Xmodem::new(&mut uart);
+        receiver.set_crc(true);
+        match receiver.receive_into(&mut buf) {
             Ok(_) => {
                 led.clear();
                 blink(3, 300);
@@ -3,6 +3,7 @@ use alloc::heap::{AllocErr, Layout};
 
 use allocator::util::*;
 use allocator::linked_list::LinkedList;
+use allocator::stats::Stats;
 
 const MIN_ORDER: usize = 3; //minimum allocation: 8bytes;
 const MIN_SIZE: usize = 8;
@@ -12,6 +13,8 @@ pub struct Allocator {
     // FIXME: Add the necessary fields.
     free_list: [LinkedList; 32],
     allocated: usize,
+    peak: usize,
+    allocs: usize,
     total: usize,
     highest_order: usize,
 }
@@ -42,27 +45,35 @@ impl Allocator {
     /// Creates a new bin allocator that will allocate memory from the region
     /// starting at address `start` and ending at address `end`.
     pub fn new(start: usize, end: usize) -> Allocator {
-        let mut total = 0;
-        let mut highest_order = 0;
-        let mut free_list = [LinkedList::new(); 32];
+        let mut allocator = Allocator {
+            free_list: [LinkedList::new(); 32],
+            allocated: 0,
+            peak: 0,
+            allocs: 0,
+            total: 0,
+            highest_order: 0,
+        };
+
+        allocator.add_region(start, end);
+        allocator
+    }
+
+    /// Adds the region starting at address `start` and ending at address
+    /// `end`, which must not overlap any other, to the memory the allocator
+    /// allocates from.
+    pub fn add_region(&mut self, start: usize, end: usize) {
         let mut lower_bound: usize = start;
         while lower_bound + MIN_SIZE <= end {
             let (order, block_size) = lower_power_of_two(end - lower_bound);
             unsafe{
-                free_list[order].push(lower_bound as *mut usize);
+                self.free_list[order].push(lower_bound as *mut usize);
             }
-            total += block_size;
+            self.total += block_size;
             lower_bound += block_size;
-            if highest_order == 0 {
-                highest_order = order;
+            if order > self.highest_order {
+                self.highest_order = order;
             }
         }
-        Allocator {
-            free_list,
-            allocated: 0,
-            total,
-            highest_order,
-        }
     }
 
     /// Allocates memory. Returns a pointer meeting the size and alignment
@@ -96,6 +107,8 @@ impl Allocator {
         //check in free list
         if !self.free_list[order].is_empty() {
             self.allocated += block_size;
+            self.peak = ::std::cmp::max(self.peak, self.allocated);
+            self.allocs += 1;
             unsafe{
                 return Ok(self.free_list[order].pop().unwrap() as *mut u8);
             }
@@ -125,6 +138,8 @@ impl Allocator {
         }
         if !self.free_list[order].is_empty() {
             self.allocated += block_size;
+            self.peak = ::std::cmp::max(self.peak, self.allocated);
+            self.allocs += 1;
             unsafe{
                 return Ok(self.free_list[order].pop().unwrap() as *mut u8);
             }
@@ -149,6 +164,26 @@ impl Allocator {
     pub fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
         unimplemented!("bin deallocation")
     }
+
+    /// Returns the state of the heap. Nothing is ever deallocated.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            total: self.total,
+            in_use: self.allocated,
+            peak: self.peak,
+            allocs: self.allocs,
+            ..Stats::default()
+        };
+
+        for (order, list) in self.free_list.iter().enumerate() {
+            stats.free_blocks[order] = list.iter().count();
+            if stats.free_blocks[order] > 0 {
+                stats.largest_free = 1 << order;
+            }
+        }
+
+        stats
+    }
 }
 //
 // FIXME: Implement `Debug` for `Allocator`.
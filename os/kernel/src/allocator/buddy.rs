@@ -0,0 +1,194 @@
+use std::fmt;
+use std::cmp::max;
+use std::mem::size_of;
+use alloc::heap::{AllocErr, Layout};
+
+use allocator::linked_list::LinkedList;
+use allocator::stats::{Stats, ORDERS};
+
+/// The smallest block: one that can hold a free list link.
+const MIN_SIZE: usize = size_of::<usize>();
+
+/// Returns the order of the smallest block that fits `layout`: blocks of
+/// order `k` are `1 << k` bytes large and aligned to `1 << k`, so a block at
+/// least as large as the layout's alignment is aligned to it as well.
+fn order_of(layout: &Layout) -> usize {
+    let size = max(max(layout.size(), layout.align()), MIN_SIZE);
+    size.next_power_of_two().trailing_zeros() as usize
+}
+
+/// A buddy allocator: memory is handed out in power-of-two blocks that are
+/// split in halves on allocation, and merged with their free "buddy" half
+/// on deallocation, so that freed memory is available for large requests
+/// again no matter the order in which it is freed.
+pub struct Allocator {
+    free_list: [LinkedList; ORDERS],
+    allocated: usize,
+    peak: usize,
+    allocs: usize,
+    deallocs: usize,
+    total: usize,
+}
+
+impl Allocator {
+    /// Creates a new buddy allocator that will allocate memory from the region
+    /// starting at address `start` and ending at address `end`.
+    pub fn new(start: usize, end: usize) -> Allocator {
+        let mut allocator = Allocator {
+            free_list: [LinkedList::new(); ORDERS],
+            allocated: 0,
+            peak: 0,
+            allocs: 0,
+            deallocs: 0,
+            total: 0,
+        };
+
+        allocator.add_region(start, end);
+        allocator
+    }
+
+    /// Adds the region starting at address `start` and ending at address
+    /// `end`, which must not overlap any other, to the memory the allocator
+    /// allocates from.
+    pub fn add_region(&mut self, start: usize, end: usize) {
+        // Carve the region into the largest blocks that are aligned to their
+        // own size, which is what makes a block's buddy a single XOR away.
+        let mut current = start + (MIN_SIZE - start % MIN_SIZE) % MIN_SIZE;
+        while current + MIN_SIZE <= end {
+            let mut order = (current.trailing_zeros() as usize).min(ORDERS - 1);
+            while current + (1 << order) > end {
+                order -= 1;
+            }
+
+            unsafe { self.free_list[order].push(current as *mut usize); }
+            self.total += 1 << order;
+            current += 1 << order;
+        }
+    }
+
+    /// Allocates memory. Returns a pointer meeting the size and alignment
+    /// properties of `layout.size()` and `layout.align()`.
+    ///
+    /// If this method returns an `Ok(addr)`, `addr` will be non-null address
+    /// pointing to a block of storage suitable for holding an instance of
+    /// `layout`. In particular, the block will be at least `layout.size()`
+    /// bytes large and will be aligned to `layout.align()`. The returned block
+    /// of storage may or may not have its contents initialized or zeroed.
+    ///
+    /// # Safety
+    ///
+    /// The _caller_ must ensure that `layout.size() > 0` and that
+    /// `layout.align()` is a power of two. Parameters not meeting these
+    /// conditions may result in undefined behavior.
+    ///
+    /// # Errors
+    ///
+    /// Returning `Err` indicates that either memory is exhausted
+    /// (`AllocError::Exhausted`) or `layout` does not meet this allocator's
+    /// size or alignment constraints (`AllocError::Unsupported`).
+    pub fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        if layout.align() == 0 || layout.align() & (layout.align() - 1) != 0 {
+            return Err(AllocErr::Unsupported { details: "alignment is not a power of two" });
+        }
+
+        let order = order_of(&layout);
+        if order >= ORDERS {
+            return Err(AllocErr::Unsupported { details: "size exceeds the largest block" });
+        }
+
+        let mut available = order;
+        while available < ORDERS && self.free_list[available].is_empty() {
+            available += 1;
+        }
+
+        if available == ORDERS {
+            return Err(AllocErr::Exhausted { request: layout });
+        }
+
+        // Split the block found until it is as small as it can be, freeing
+        // the upper half at each step.
+        let block = self.free_list[available].pop().unwrap() as usize;
+        while available > order {
+            available -= 1;
+            unsafe { self.free_list[available].push((block + (1 << available)) as *mut usize); }
+        }
+
+        self.allocated += 1 << order;
+        self.peak = max(self.peak, self.allocated);
+        self.allocs += 1;
+        Ok(block as *mut u8)
+    }
+
+    /// Deallocates the memory referenced by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The _caller_ must ensure the following:
+    ///
+    ///   * `ptr` must denote a block of memory currently allocated via this
+    ///     allocator
+    ///   * `layout` must properly represent the original layout used in the
+    ///     allocation call that returned `ptr`
+    ///
+    /// Parameters not meeting these conditions may result in undefined
+    /// behavior.
+    pub fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let mut order = order_of(&layout);
+        let mut block = ptr as usize;
+        self.allocated -= 1 << order;
+        self.deallocs += 1;
+
+        // Merge with the buddy for as long as it is free. A buddy outside of
+        // the region is never on a free list, so it is never merged with.
+        while order < ORDERS - 1 && self.remove(order, block ^ (1 << order)) {
+            block &= !(1 << order);
+            order += 1;
+        }
+
+        unsafe { self.free_list[order].push(block as *mut usize); }
+    }
+
+    /// Removes the free block at `address` from the free list of `order`.
+    /// Returns whether it was there.
+    fn remove(&mut self, order: usize, address: usize) -> bool {
+        for node in self.free_list[order].iter_mut() {
+            if node.value() as usize == address {
+                node.pop();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Returns the state of the heap. The allocator has no caches of its own.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            total: self.total,
+            in_use: self.allocated,
+            peak: self.peak,
+            allocs: self.allocs,
+            deallocs: self.deallocs,
+            ..Stats::default()
+        };
+
+        for (order, list) in self.free_list.iter().enumerate() {
+            stats.free_blocks[order] = list.iter().count();
+            if stats.free_blocks[order] > 0 {
+                stats.largest_free = 1 << order;
+            }
+        }
+
+        stats
+    }
+}
+
+impl fmt::Debug for Allocator {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("BuddyAllocator")
+            .field("allocated", &self.allocated)
+            .field("total", &self.total)
+            .field("free_blocks", &&self.stats().free_blocks[..])
+            .finish()
+    }
+}
@@ -1,12 +1,15 @@
 use alloc::heap::{AllocErr, Layout};
 
 use allocator::util::*;
+use allocator::stats::Stats;
 
 /// A "bump" allocator: allocates memory by bumping a pointer; never frees.
 #[derive(Debug)]
 pub struct Allocator {
+    start: usize,
     current: usize,
     end: usize,
+    allocs: usize,
 }
 
 impl Allocator {
@@ -14,8 +17,22 @@ impl Allocator {
     /// starting at address `start` and ending at address `end`.
     pub fn new(start: usize, end: usize) -> Allocator {
         Allocator{
+            start,
             current: start,
-            end
+            end,
+            allocs: 0,
+        }
+    }
+
+    /// Offers the region starting at address `start` and ending at address
+    /// `end` to the allocator. A bump allocator only allocates from a single
+    /// region, so it moves on to the new region if that is larger than what
+    /// remains of the current one, and ignores it otherwise.
+    pub fn add_region(&mut self, start: usize, end: usize) {
+        if end.saturating_sub(start) > self.end - self.current {
+            self.start = start;
+            self.current = start;
+            self.end = end;
         }
     }
 
@@ -50,6 +67,7 @@ impl Allocator {
         }
         let return_addr = align_up(self.current, layout.align());
         self.current = return_addr + act_size;
+        self.allocs += 1;
         Ok(return_addr as *mut u8)
     }
 
@@ -75,4 +93,18 @@ impl Allocator {
             panic!("Out of bound");
         }
     }
+
+    /// Returns the state of the heap. Nothing is ever freed, so all that is
+    /// free is the block past the last allocation. Only the region currently
+    /// allocated from is described: one left behind by `add_region()` is not.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            total: self.end - self.start,
+            in_use: self.current - self.start,
+            peak: self.current - self.start,
+            allocs: self.allocs,
+            largest_free: self.end - self.current,
+            ..Stats::default()
+        }
+    }
 }
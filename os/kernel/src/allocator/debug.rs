@@ -0,0 +1,193 @@
+use std::fmt;
+use std::cmp::max;
+use std::mem::size_of;
+use std::ptr;
+use alloc::heap::{AllocErr, Layout};
+
+use allocator::slab;
+use allocator::util::*;
+use allocator::stats::Stats;
+
+/// The bytes at the start of each block that hold no guard: the first word
+/// is left to the inner allocator, whose free lists link through the start
+/// of free blocks, and the second holds the block's state.
+const HEADER: usize = 2 * size_of::<usize>();
+
+/// The number of guard bytes after each block, and the least before it.
+const GUARD: usize = 16;
+
+/// The pattern guard bytes hold.
+const GUARD_BYTE: u8 = 0xFD;
+/// The pattern newly allocated memory is filled with.
+const FRESH_BYTE: u8 = 0xCD;
+/// The pattern freed memory is filled with.
+const FREED_BYTE: u8 = 0xDD;
+
+/// The state of a block that is allocated.
+const LIVE: usize = 0xA110_CA7E;
+/// The state of a block that has been freed.
+const FREED: usize = 0xF4EE_D0FF;
+
+/// A debugging allocator on top of the slab allocator: each block is
+/// surrounded by guard bytes, filled with `0xCD` when allocated and poisoned
+/// with `0xDD` when freed.
+///
+/// Deallocation checks that the block is allocated and that its guards are
+/// intact, and panics with a report of what was overwritten otherwise, so
+/// that an overrun is caught when the block is freed rather than when the
+/// corrupted free list it spilled into is next used.
+///
+/// A double free is caught as long as the block has not been handed out
+/// again in the meantime.
+pub struct Allocator {
+    inner: slab::Allocator,
+}
+
+/// Returns the offset of the block within the inner allocation for `layout`:
+/// room for the header and guard, aligned to the layout's alignment.
+fn front(layout: &Layout) -> usize {
+    align_up(HEADER + GUARD, layout.align())
+}
+
+/// Returns the layout of the inner allocation for `layout`.
+fn inner_layout(layout: &Layout) -> Option<Layout> {
+    let size = front(layout).checked_add(layout.size())?.checked_add(GUARD)?;
+    Layout::from_size_align(size, max(layout.align(), size_of::<usize>()))
+}
+
+unsafe fn fill(start: usize, len: usize, byte: u8) {
+    ptr::write_bytes(start as *mut u8, byte, len);
+}
+
+/// Checks that the `len` bytes of guard at `start` are intact, and panics
+/// with a report of what was overwritten otherwise. `side` tells whether the
+/// guard is `"before"` or `"after"` the block at `ptr`.
+unsafe fn check_guard(start: usize, len: usize, ptr: usize, layout: &Layout, side: &str) {
+    let guard = ::std::slice::from_raw_parts(start as *const u8, len);
+    let overwritten = guard.iter().filter(|&&byte| byte != GUARD_BYTE).count();
+    if overwritten == 0 {
+        return;
+    }
+
+    // Report the overwritten byte nearest to the block.
+    let (offset, byte) = if side == "before" {
+        let i = guard.iter().rposition(|&byte| byte != GUARD_BYTE).unwrap();
+        (ptr - (start + i), guard[i])
+    } else {
+        let i = guard.iter().position(|&byte| byte != GUARD_BYTE).unwrap();
+        (i + 1, guard[i])
+    };
+
+    panic!("heap corruption: {} of the {} guard bytes {} the block at {:#x} \
+            ({} bytes, align {}) were overwritten; the byte {} {} it holds {:#04x}",
+           overwritten, len, side, ptr, layout.size(), layout.align(), offset, side, byte);
+}
+
+impl Allocator {
+    /// Creates a new debugging allocator that will allocate memory from the
+    /// region starting at address `start` and ending at address `end`.
+    pub fn new(start: usize, end: usize) -> Allocator {
+        Allocator { inner: slab::Allocator::new(start, end) }
+    }
+
+    /// Adds the region starting at address `start` and ending at address
+    /// `end`, which must not overlap any other, to the inner allocator.
+    pub fn add_region(&mut self, start: usize, end: usize) {
+        self.inner.add_region(start, end);
+    }
+
+    /// Allocates memory. Returns a pointer meeting the size and alignment
+    /// properties of `layout.size()` and `layout.align()`.
+    ///
+    /// If this method returns an `Ok(addr)`, `addr` will be non-null address
+    /// pointing to a block of storage suitable for holding an instance of
+    /// `layout`. In particular, the block will be at least `layout.size()`
+    /// bytes large and will be aligned to `layout.align()`. The returned block
+    /// of storage is filled with `0xCD`.
+    ///
+    /// # Safety
+    ///
+    /// The _caller_ must ensure that `layout.size() > 0` and that
+    /// `layout.align()` is a power of two. Parameters not meeting these
+    /// conditions may result in undefined behavior.
+    ///
+    /// # Errors
+    ///
+    /// Returning `Err` indicates that either memory is exhausted
+    /// (`AllocError::Exhausted`) or `layout` does not meet this allocator's
+    /// size or alignment constraints (`AllocError::Unsupported`).
+    pub fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let inner = inner_layout(&layout)
+            .ok_or(AllocErr::Unsupported { details: "size is too large" })?;
+        let base = match self.inner.alloc(inner) {
+            Ok(base) => base as usize,
+            Err(AllocErr::Exhausted { .. }) => return Err(AllocErr::Exhausted { request: layout }),
+            Err(e) => return Err(e),
+        };
+
+        let (front, size) = (front(&layout), layout.size());
+        unsafe {
+            *((base + size_of::<usize>()) as *mut usize) = LIVE;
+            fill(base + HEADER, front - HEADER, GUARD_BYTE);
+            fill(base + front, size, FRESH_BYTE);
+            fill(base + front + size, GUARD, GUARD_BYTE);
+        }
+
+        Ok((base + front) as *mut u8)
+    }
+
+    /// Deallocates the memory referenced by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The _caller_ must ensure the following:
+    ///
+    ///   * `ptr` must denote a block of memory currently allocated via this
+    ///     allocator
+    ///   * `layout` must properly represent the original layout used in the
+    ///     allocation call that returned `ptr`
+    ///
+    /// Parameters not meeting these conditions may result in undefined
+    /// behavior.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ptr` was already freed, if it was not allocated by this
+    /// allocator, or if the guards around it were overwritten.
+    pub fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let (front, size) = (front(&layout), layout.size());
+        let (ptr, base) = (ptr as usize, ptr as usize - front);
+        let state = (base + size_of::<usize>()) as *mut usize;
+        unsafe {
+            match *state {
+                LIVE => {}
+                FREED => panic!("double free of the block at {:#x} ({} bytes, align {})",
+                                ptr, size, layout.align()),
+                other => panic!("free of {:#x} ({} bytes, align {}), which is not an \
+                                 allocated block: its header holds {:#x}",
+                                ptr, size, layout.align(), other)
+            }
+
+            check_guard(base + HEADER, front - HEADER, ptr, &layout, "before");
+            check_guard(ptr + size, GUARD, ptr, &layout, "after");
+
+            *state = FREED;
+            fill(base + HEADER, front - HEADER + size + GUARD, FREED_BYTE);
+        }
+
+        self.inner.dealloc(base as *mut u8, inner_layout(&layout).unwrap());
+    }
+
+    /// Returns the state of the heap, guards included.
+    pub fn stats(&self) -> Stats {
+        self.inner.stats()
+    }
+}
+
+impl fmt::Debug for Allocator {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DebugAllocator")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt;
+use alloc::heap::Layout;
+
+/// The number of live allocations the leak tracker can hold.
+pub const MAX_RECORDS: usize = 128;
+
+/// The number of return addresses recorded for each allocation.
+pub const BACKTRACE_FRAMES: usize = 4;
+
+/// A live allocation, as recorded by the leak tracker.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    /// The address of the allocation.
+    pub ptr: usize,
+    /// The size requested, in bytes.
+    pub size: usize,
+    /// The alignment requested, in bytes.
+    pub align: usize,
+    /// The return addresses of the functions that led to the allocation,
+    /// innermost first, or `0` past the last one known. `addr2line` maps
+    /// them to the code that allocated.
+    pub backtrace: [usize; BACKTRACE_FRAMES],
+    /// How many allocations were recorded before this one.
+    pub serial: usize,
+}
+
+const EMPTY: Record = Record {
+    ptr: 0, size: 0, align: 0, backtrace: [0; BACKTRACE_FRAMES], serial: 0
+};
+
+/// Records allocations while enabled, and forgets them as they are freed:
+/// what remains after a workload has finished is what it leaked.
+///
+/// Records are kept in a fixed table, as the tracker cannot allocate. Once
+/// the table is full, further allocations are only counted.
+#[derive(Clone, Copy)]
+pub struct Leaks {
+    enabled: bool,
+    records: [Record; MAX_RECORDS],
+    len: usize,
+    serial: usize,
+    untracked: usize,
+}
+
+impl Leaks {
+    /// Returns a disabled, empty tracker.
+    pub const fn new() -> Leaks {
+        Leaks {
+            enabled: false,
+            records: [EMPTY; MAX_RECORDS],
+            len: 0,
+            serial: 0,
+            untracked: 0,
+        }
+    }
+
+    /// Forgets every record and starts recording allocations.
+    pub fn enable(&mut self) {
+        *self = Leaks::new();
+        self.enabled = true;
+    }
+
+    /// Stops recording allocations. Records are still forgotten as they are
+    /// freed.
+    pub fn disable(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Returns `true` if allocations are being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Records the allocation of `layout` at `ptr`, made from the code that
+    /// `backtrace` leads to, if enabled.
+    pub fn record(&mut self, ptr: *mut u8, layout: &Layout, backtrace: [usize; BACKTRACE_FRAMES]) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.len == MAX_RECORDS {
+            self.untracked += 1;
+            return;
+        }
+
+        self.records[self.len] = Record {
+            ptr: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            backtrace,
+            serial: self.serial,
+        };
+
+        self.len += 1;
+        self.serial += 1;
+    }
+
+    /// Forgets the allocation at `ptr`, if it was recorded.
+    pub fn forget(&mut self, ptr: *mut u8) {
+        let len = self.len;
+        if let Some(i) = self.records[..len].iter().position(|r| r.ptr == ptr as usize) {
+            self.records[i] = self.records[len - 1];
+            self.len -= 1;
+        }
+    }
+
+    /// Returns the allocations recorded and not yet freed, in no particular
+    /// order.
+    pub fn records(&self) -> &[Record] {
+        &self.records[..self.len]
+    }
+
+    /// Returns the number of allocations made while the table was full.
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+}
+
+impl fmt::Debug for Leaks {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Leaks")
+            .field("enabled", &self.enabled)
+            .field("records", &self.records())
+            .field("untracked", &self.untracked)
+            .finish()
+    }
+}
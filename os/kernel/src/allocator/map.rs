@@ -0,0 +1,210 @@
+use std::fmt;
+use std::cmp::{max, min};
+
+/// The most usable regions, and the most reserved ranges, a `MemoryMap`
+/// holds.
+pub const MAX_REGIONS: usize = 16;
+
+/// A range of addresses, from `start` up to but excluding `end`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Region {
+    /// Returns the range `[start, end)`.
+    pub fn new(start: usize, end: usize) -> Region {
+        Region { start, end }
+    }
+
+    /// Returns the number of bytes in the region.
+    pub fn size(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+}
+
+/// A range of addresses the allocator must not hand out, and what uses it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Reserved {
+    pub name: &'static str,
+    pub region: Region,
+}
+
+/// The memory the allocator manages: the memory the system has, less the
+/// ranges reserved for other uses, as sorted, disjoint regions.
+///
+/// The map is kept in fixed-size tables as it is built before there is a
+/// heap. Once `MAX_REGIONS` usable regions are found, the smallest ones are
+/// left out; leaving memory unused is always safe.
+#[derive(Clone, Copy)]
+pub struct MemoryMap {
+    memory: [Region; MAX_REGIONS],
+    memory_len: usize,
+    reserved: [Reserved; MAX_REGIONS],
+    reserved_len: usize,
+    usable: [Region; MAX_REGIONS],
+    usable_len: usize,
+}
+
+/// Adds `region` to the sorted, disjoint regions `regions[..*len]`, merging
+/// it with those it overlaps or touches. If there is no room left, the
+/// smallest region is dropped.
+fn insert(regions: &mut [Region; MAX_REGIONS], len: &mut usize, mut region: Region) {
+    if region.is_empty() {
+        return;
+    }
+
+    let mut merged = [Region::default(); MAX_REGIONS];
+    let mut n = 0;
+    for &other in regions[..*len].iter() {
+        if other.end < region.start || region.end < other.start {
+            merged[n] = other;
+            n += 1;
+        } else {
+            region = Region::new(min(region.start, other.start), max(region.end, other.end));
+        }
+    }
+
+    if n == MAX_REGIONS {
+        let smallest = (0..n).min_by_key(|&i| merged[i].size()).unwrap();
+        if merged[smallest].size() >= region.size() {
+            return;
+        }
+
+        merged[smallest] = merged[n - 1];
+        n -= 1;
+    }
+
+    merged[n] = region;
+    n += 1;
+    merged[..n].sort_unstable_by_key(|region| region.start);
+    *regions = merged;
+    *len = n;
+}
+
+impl MemoryMap {
+    /// Returns an empty map.
+    pub fn new() -> MemoryMap {
+        MemoryMap {
+            memory: [Region::default(); MAX_REGIONS],
+            memory_len: 0,
+            reserved: [Reserved::default(); MAX_REGIONS],
+            reserved_len: 0,
+            usable: [Region::default(); MAX_REGIONS],
+            usable_len: 0,
+        }
+    }
+
+    /// Adds the memory `[start, end)` to the map. Any of it that is reserved
+    /// is left out of the usable regions.
+    pub fn add_memory(&mut self, start: usize, end: usize) {
+        insert(&mut self.memory, &mut self.memory_len, Region::new(start, end));
+        self.update();
+    }
+
+    /// Reserves `[start, end)` for `name`, leaving it out of the usable
+    /// regions. Reserving more than `MAX_REGIONS` ranges panics.
+    pub fn reserve(&mut self, name: &'static str, start: usize, end: usize) {
+        assert!(self.reserved_len < MAX_REGIONS, "too many reserved ranges");
+        self.reserved[self.reserved_len] = Reserved { name, region: Region::new(start, end) };
+        self.reserved_len += 1;
+        self.update();
+    }
+
+    /// Recomputes the usable regions: the memory less every reserved range.
+    fn update(&mut self) {
+        let mut usable = [Region::default(); MAX_REGIONS];
+        let mut len = 0;
+        for &memory in self.memory[..self.memory_len].iter() {
+            self.subtract(memory, 0, &mut usable, &mut len);
+        }
+
+        self.usable = usable;
+        self.usable_len = len;
+    }
+
+    /// Adds what is left of `region` once the reserved ranges from `first` on
+    /// are taken out of it to `usable`.
+    fn subtract(&self, region: Region, first: usize,
+                usable: &mut [Region; MAX_REGIONS], len: &mut usize) {
+        if region.is_empty() {
+            return;
+        }
+
+        let reserved = match self.reserved[first..self.reserved_len].iter()
+            .position(|r| r.region.start < region.end && region.start < r.region.end)
+        {
+            Some(i) => first + i,
+            None => return insert(usable, len, region),
+        };
+
+        let hole = self.reserved[reserved].region;
+        self.subtract(Region::new(region.start, hole.start), reserved + 1, usable, len);
+        self.subtract(Region::new(hole.end, region.end), reserved + 1, usable, len);
+    }
+
+    /// Returns the usable regions, sorted by address.
+    pub fn regions(&self) -> &[Region] {
+        &self.usable[..self.usable_len]
+    }
+
+    /// Returns the reserved ranges, in the order they were reserved.
+    pub fn reserved(&self) -> &[Reserved] {
+        &self.reserved[..self.reserved_len]
+    }
+
+    /// Returns the number of usable bytes.
+    pub fn total(&self) -> usize {
+        self.regions().iter().map(|region| region.size()).sum()
+    }
+}
+
+impl fmt::Debug for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryMap")
+            .field("regions", &self.regions())
+            .field("reserved", &self.reserved())
+            .finish()
+    }
+}
+
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for reserved in self.reserved() {
+            writeln!(f, "  {:#010x}-{:#010x} reserved {:>8} KiB  {}", reserved.region.start,
+                     reserved.region.end, reserved.region.size() / 1024, reserved.name)?;
+        }
+
+        for region in self.regions() {
+            writeln!(f, "  {:#010x}-{:#010x} usable   {:>8} KiB", region.start, region.end,
+                     region.size() / 1024)?;
+        }
+
+        write!(f, "  {} KiB usable in {} regions", self.total() / 1024, self.regions().len())
+    }
+}
+
+/// Returns the VideoCore's memory, which holds the framebuffer, as described
+/// by the firmware on the kernel command line: `vc_mem.mem_base` is where it
+/// starts and `vc_mem.mem_size` where it, and all memory, ends.
+pub fn videocore_memory(cmdline: &str) -> Option<Region> {
+    let value = |key: &str| {
+        cmdline.split_whitespace()
+            .filter_map(|arg| {
+                let mut parts = arg.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(k), Some(v)) if k == key => Some(v),
+                    _ => None
+                }
+            })
+            .next()
+            .and_then(|v| usize::from_str_radix(v.trim_left_matches("0x"), 16).ok())
+    };
+
+    Some(Region::new(value("vc_mem.mem_base")?, value("vc_mem.mem_size")?))
+}
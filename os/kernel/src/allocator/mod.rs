@@ -1,8 +1,23 @@
 mod linked_list;
 mod util;
+mod stats;
+mod leaks;
+mod map;
 
-#[path = "bin.rs"]
+// The page-level allocator under the slab caches: `buddy.rs`, `bin.rs` or
+// `bump.rs`.
+#[path = "buddy.rs"]
 mod imp;
+mod slab;
+#[cfg(feature = "debug-heap")]
+mod debug;
+
+// The heap: the slab allocator, or with the `debug-heap` feature, the slab
+// allocator with guards around each block, checked as the block is freed.
+#[cfg(not(feature = "debug-heap"))]
+use self::slab::Allocator as Heap;
+#[cfg(feature = "debug-heap")]
+use self::debug::Allocator as Heap;
 
 #[cfg(test)]
 mod tests;
@@ -12,9 +27,22 @@ use alloc::heap::{Alloc, AllocErr, Layout};
 use std::cmp::max;
 extern crate pi;
 
+pub use self::stats::{CacheStats, Stats, CACHES, ORDERS};
+pub use self::leaks::{Leaks, Record, BACKTRACE_FRAMES, MAX_RECORDS};
+pub use self::map::{MemoryMap, Region, Reserved, MAX_REGIONS};
+
+/// Where the bootloader is loaded, kept clear so that it survives the kernel.
+const BOOTLOADER_START_ADDR: usize = 0x4000000;
+/// The room kept for the bootloader: far more than its image takes.
+const BOOTLOADER_SIZE: usize = 1 << 20;
+
 /// Thread-safe (locking) wrapper around a particular memory allocator.
 #[derive(Debug)]
-pub struct Allocator(Mutex<Option<imp::Allocator>>);
+pub struct Allocator {
+    heap: Mutex<Option<Heap>>,
+    leaks: Mutex<Leaks>,
+    map: Mutex<Option<MemoryMap>>,
+}
 
 impl Allocator {
     /// Returns an uninitialized `Allocator`.
@@ -22,18 +50,112 @@ impl Allocator {
     /// The allocator must be initialized by calling `initialize()` before the
     /// first memory allocation. Failure to do will result in panics.
     pub const fn uninitialized() -> Self {
-        Allocator(Mutex::new(None))
+        Allocator {
+            heap: Mutex::new(None),
+            leaks: Mutex::new(Leaks::new()),
+            map: Mutex::new(None),
+        }
     }
 
-    /// Initializes the memory allocator.
+    /// Initializes the memory allocator with every usable region of the
+    /// system's memory map.
     ///
     /// # Panics
     ///
-    /// Panics if the system's memory map could not be retrieved.
+    /// Panics if the system's memory map could not be retrieved, or leaves no
+    /// memory usable.
     pub fn initialize(&self) {
-        let (start, end) = memory_map().expect("failed to find memory map");
-        *self.0.lock() = Some(imp::Allocator::new(start, end));
+        let map = memory_map().expect("failed to find memory map");
+        let mut regions = map.regions().iter();
+        let first = regions.next().expect("no usable memory in the memory map");
+        let mut heap = Heap::new(first.start, first.end);
+        for region in regions {
+            heap.add_region(region.start, region.end);
+        }
+
+        *self.heap.lock() = Some(heap);
+        *self.map.lock() = Some(map);
+    }
+
+    /// Returns the memory map the allocator was initialized with, or `None`
+    /// if it is uninitialized.
+    pub fn memory_map(&self) -> Option<MemoryMap> {
+        *self.map.lock()
+    }
+
+    /// Returns the state of the heap, or `None` if it is uninitialized.
+    pub fn stats(&self) -> Option<Stats> {
+        self.heap.lock().as_ref().map(|heap| heap.stats())
+    }
+
+    /// Starts recording allocations, forgetting earlier records, if `enabled`
+    /// is `true`, or stops otherwise. Recorded allocations are forgotten as
+    /// they are freed, so that `leaks()` returns those still live.
+    pub fn track_leaks(&self, enabled: bool) {
+        let mut leaks = self.leaks.lock();
+        if enabled {
+            leaks.enable();
+        } else {
+            leaks.disable();
+        }
+    }
+
+    /// Returns a copy of the leak tracker's records.
+    pub fn leaks(&self) -> Leaks {
+        *self.leaks.lock()
+    }
+}
+
+/// The frames between `alloc` and the code that asked for memory that
+/// `backtrace()` leaves out: the return into the shim that
+/// `#[global_allocator]` generates, `__rg_alloc`, which `__rust_alloc` calls.
+const SHIM_FRAMES: usize = 1;
+
+/// Returns the return addresses of the `BACKTRACE_FRAMES` functions that led
+/// to the calling function, past the global allocator's shim, innermost
+/// first. Only meaningful inlined into `alloc`, which must not be inlined.
+///
+/// Frames are found by following the frame records that x29 links, which the
+/// target keeps. The first address is thus in the code that called
+/// `__rust_alloc` (usually `RawVec`, or `Box::new` inlined into its caller),
+/// or for a reallocation in the default `realloc`; the rest are its callers.
+/// The walk stops early at a record that isn't on the stack, which grows down
+/// from `_start` and ends with the null frame pointer `init.S` sets; the
+/// addresses past it are `0`.
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+fn backtrace() -> [usize; BACKTRACE_FRAMES] {
+    let mut frames = [0; BACKTRACE_FRAMES];
+    let stack_top = unsafe { &_start as *const u8 as usize };
+    let mut fp: usize;
+    unsafe { asm!("mov $0, x29" : "=r"(fp) ::: "volatile"); }
+
+    for i in 0..SHIM_FRAMES + BACKTRACE_FRAMES {
+        if fp == 0 || fp % 8 != 0 || fp >= stack_top {
+            break;
+        }
+
+        // A frame record is the caller's frame pointer and the return address.
+        let (next, address) = unsafe { (*(fp as *const usize), *((fp + 8) as *const usize)) };
+        if i >= SHIM_FRAMES {
+            frames[i - SHIM_FRAMES] = address;
+        }
+
+        // The stack grows down, so each caller's record is above its callee's.
+        if next <= fp {
+            break;
+        }
+
+        fp = next;
     }
+
+    frames
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+#[inline(always)]
+fn backtrace() -> [usize; BACKTRACE_FRAMES] {
+    [0; BACKTRACE_FRAMES]
 }
 
 unsafe impl<'a> Alloc for &'a Allocator {
@@ -57,8 +179,13 @@ unsafe impl<'a> Alloc for &'a Allocator {
     /// Returning `Err` indicates that either memory is exhausted
     /// (`AllocError::Exhausted`) or `layout` does not meet this allocator's
     /// size or alignment constraints (`AllocError::Unsupported`).
+    #[inline(never)]
     unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
-        self.0.lock().as_mut().expect("allocator uninitialized").alloc(layout)
+        let backtrace = backtrace();
+        let ptr = self.heap.lock().as_mut().expect("allocator uninitialized")
+            .alloc(layout.clone())?;
+        self.leaks.lock().record(ptr, &layout, backtrace);
+        Ok(ptr)
     }
 
     /// Deallocates the memory referenced by `ptr`.
@@ -75,27 +202,51 @@ unsafe impl<'a> Alloc for &'a Allocator {
     /// Parameters not meeting these conditions may result in undefined
     /// behavior.
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
-        self.0.lock().as_mut().expect("allocator uninitialized").dealloc(ptr, layout);
+        self.heap.lock().as_mut().expect("allocator uninitialized").dealloc(ptr, layout);
+        self.leaks.lock().forget(ptr);
     }
 }
 
 extern "C" {
+    static _start: u8;
     static _end: u8;
 }
 
-/// Returns the (start address, end address) of the available memory on this
-/// system if it can be determined. If it cannot, `None` is returned.
+/// Returns the memory map of this system: the memory of every `Mem` ATAG,
+/// less the ranges in use by the kernel's stack and image, the ATAGS, the
+/// bootloader and the VideoCore, which holds the framebuffer. If there is no
+/// `Mem` ATAG, `None` is returned.
 ///
 /// This function is expected to return `Some` under all normal cirumstances.
-fn memory_map() -> Option<(usize, usize)> {
-    let binary_end = unsafe { (&_end as *const u8) as u32 };
-    let atags = pi::atags::Atags::get();
-    for x in atags {
-        if x.mem().is_some() {
-            let cur = x.mem().unwrap();
-            let end_addr = cur.start + cur.size;
-            return Some((binary_end as usize, end_addr as usize))
+fn memory_map() -> Option<MemoryMap> {
+    let (binary_start, binary_end) = unsafe {
+        (&_start as *const u8 as usize, &_end as *const u8 as usize)
+    };
+
+    let mut map = MemoryMap::new();
+    let mut cmdline = None;
+    for atag in pi::atags::Atags::get() {
+        if let Some(mem) = atag.mem() {
+            let start = mem.start as usize;
+            map.add_memory(start, start + mem.size as usize);
         }
+
+        cmdline = cmdline.or(atag.cmd());
     }
-    None
+
+    if map.regions().is_empty() {
+        return None;
+    }
+
+    // The stack grows down from the start of the kernel.
+    let (atags_start, atags_end) = pi::atags::Atags::get().extent();
+    map.reserve("stack", 0, binary_start);
+    map.reserve("kernel", binary_start, binary_end);
+    map.reserve("atags", atags_start, atags_end);
+    map.reserve("bootloader", BOOTLOADER_START_ADDR, BOOTLOADER_START_ADDR + BOOTLOADER_SIZE);
+    if let Some(videocore) = cmdline.and_then(map::videocore_memory) {
+        map.reserve("videocore", videocore.start, videocore.end);
+    }
+
+    Some(map)
 }
@@ -0,0 +1,348 @@
+use std::fmt;
+use std::ptr;
+use std::cmp::max;
+use std::mem::size_of;
+use alloc::heap::{AllocErr, Layout};
+
+use allocator::imp;
+use allocator::util::*;
+use allocator::linked_list::LinkedList;
+use allocator::stats::{CacheStats, Stats, CACHES};
+
+/// The object size of the smallest cache.
+const MIN_ORDER: usize = 4;
+
+/// The object size of the largest cache. Larger requests go to the page
+/// allocator.
+const MAX_ORDER: usize = MIN_ORDER + CACHES - 1;
+
+/// The smallest slab, in bytes. Larger objects get slabs eight objects large,
+/// less the room taken by the slab's header.
+const MIN_SLAB_SIZE: usize = 4096;
+
+/// How many empty slabs a cache keeps before returning them to the page
+/// allocator.
+const MAX_EMPTY: usize = 1;
+
+/// The header at the start of every slab. Slabs are aligned to their size,
+/// so the slab holding an object is found by aligning the object down.
+struct Slab {
+    prev: *mut Slab,
+    next: *mut Slab,
+    free: LinkedList,
+    in_use: usize,
+}
+
+/// An intrusive, doubly-linked list of slabs.
+#[derive(Clone, Copy)]
+struct SlabList {
+    head: *mut Slab,
+    len: usize,
+}
+
+unsafe impl Send for SlabList {}
+
+impl SlabList {
+    fn new() -> SlabList {
+        SlabList { head: ptr::null_mut(), len: 0 }
+    }
+
+    fn first(&self) -> Option<*mut Slab> {
+        if self.head.is_null() { None } else { Some(self.head) }
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.head;
+        if !self.head.is_null() {
+            (*self.head).prev = slab;
+        }
+
+        self.head = slab;
+        self.len += 1;
+    }
+
+    /// Removes `slab`, which must be on this list.
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.head = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+
+        self.len -= 1;
+    }
+}
+
+/// A cache of objects of one size, carved out of slabs.
+struct Cache {
+    object_size: usize,
+    slab_size: usize,
+    /// The offset of the first object in a slab, past its header.
+    offset: usize,
+    full: SlabList,
+    partial: SlabList,
+    empty: SlabList,
+    in_use: usize,
+    allocs: usize,
+    deallocs: usize,
+}
+
+impl Cache {
+    fn new(order: usize) -> Cache {
+        let object_size = 1 << order;
+        let offset = align_up(size_of::<Slab>(), object_size);
+        Cache {
+            object_size,
+            slab_size: max(MIN_SLAB_SIZE, 8 * object_size),
+            offset,
+            full: SlabList::new(),
+            partial: SlabList::new(),
+            empty: SlabList::new(),
+            in_use: 0,
+            allocs: 0,
+            deallocs: 0,
+        }
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (self.slab_size - self.offset) / self.object_size
+    }
+
+    fn slab_layout(&self) -> Layout {
+        Layout::from_size_align(self.slab_size, self.slab_size).unwrap()
+    }
+
+    /// Returns a slab with a free object, making one from the page allocator
+    /// if there is none.
+    fn slab(&mut self, pages: &mut imp::Allocator) -> Option<*mut Slab> {
+        if let Some(slab) = self.partial.first() {
+            return Some(slab);
+        }
+
+        unsafe {
+            if let Some(slab) = self.empty.first() {
+                self.empty.remove(slab);
+                self.partial.push(slab);
+                return Some(slab);
+            }
+
+            let start = pages.alloc(self.slab_layout()).ok()? as usize;
+            let slab = start as *mut Slab;
+            ptr::write(slab, Slab {
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                free: LinkedList::new(),
+                in_use: 0,
+            });
+
+            // Push in reverse so that objects are handed out in order.
+            for i in (0..self.objects_per_slab()).rev() {
+                (*slab).free.push((start + self.offset + i * self.object_size) as *mut usize);
+            }
+
+            self.partial.push(slab);
+            Some(slab)
+        }
+    }
+
+    fn alloc(&mut self, pages: &mut imp::Allocator) -> Option<*mut u8> {
+        let slab = self.slab(pages)?;
+        unsafe {
+            let object = (*slab).free.pop().unwrap();
+            (*slab).in_use += 1;
+            if (*slab).free.is_empty() {
+                self.partial.remove(slab);
+                self.full.push(slab);
+            }
+
+            self.in_use += 1;
+            self.allocs += 1;
+            Some(object as *mut u8)
+        }
+    }
+
+    fn dealloc(&mut self, pages: &mut imp::Allocator, ptr: *mut u8) {
+        let slab = align_down(ptr as usize, self.slab_size) as *mut Slab;
+        unsafe {
+            if (*slab).free.is_empty() {
+                self.full.remove(slab);
+                self.partial.push(slab);
+            }
+
+            (*slab).free.push(ptr as *mut usize);
+            (*slab).in_use -= 1;
+            if (*slab).in_use == 0 {
+                self.partial.remove(slab);
+                self.empty.push(slab);
+            }
+        }
+
+        self.in_use -= 1;
+        self.deallocs += 1;
+        if self.empty.len > MAX_EMPTY {
+            self.release(pages, MAX_EMPTY);
+        }
+    }
+
+    /// Returns empty slabs to the page allocator until `keep` are left.
+    fn release(&mut self, pages: &mut imp::Allocator, keep: usize) {
+        while self.empty.len > keep {
+            let slab = self.empty.first().unwrap();
+            unsafe { self.empty.remove(slab); }
+            pages.dealloc(slab as *mut u8, self.slab_layout());
+        }
+    }
+
+    fn stats(&self) -> CacheStats {
+        let slabs = self.full.len + self.partial.len + self.empty.len;
+        CacheStats {
+            object_size: self.object_size,
+            slab_size: self.slab_size,
+            full_slabs: self.full.len,
+            partial_slabs: self.partial.len,
+            empty_slabs: self.empty.len,
+            objects_in_use: self.in_use,
+            objects_total: slabs * self.objects_per_slab(),
+            allocs: self.allocs,
+            deallocs: self.deallocs,
+        }
+    }
+}
+
+/// Returns the index of the cache that fits `layout`, if any does.
+fn cache_of(layout: &Layout) -> Option<usize> {
+    let size = max(layout.size(), layout.align()).next_power_of_two();
+    let order = max(size.trailing_zeros() as usize, MIN_ORDER);
+    if order <= MAX_ORDER { Some(order - MIN_ORDER) } else { None }
+}
+
+/// A slab allocator: small requests are served from caches of fixed-size
+/// objects, each carved out of slabs taken from the page allocator, and
+/// larger requests go to the page allocator directly.
+pub struct Allocator {
+    caches: [Cache; CACHES],
+    pages: imp::Allocator,
+}
+
+impl Allocator {
+    /// Creates a new slab allocator that will allocate memory from the region
+    /// starting at address `start` and ending at address `end`.
+    pub fn new(start: usize, end: usize) -> Allocator {
+        Allocator {
+            caches: [
+                Cache::new(4), Cache::new(5), Cache::new(6), Cache::new(7),
+                Cache::new(8), Cache::new(9), Cache::new(10), Cache::new(11),
+            ],
+            pages: imp::Allocator::new(start, end),
+        }
+    }
+
+    /// Adds the region starting at address `start` and ending at address
+    /// `end`, which must not overlap any other, to the page allocator.
+    pub fn add_region(&mut self, start: usize, end: usize) {
+        self.pages.add_region(start, end);
+    }
+
+    /// Allocates memory. Returns a pointer meeting the size and alignment
+    /// properties of `layout.size()` and `layout.align()`.
+    ///
+    /// If this method returns an `Ok(addr)`, `addr` will be non-null address
+    /// pointing to a block of storage suitable for holding an instance of
+    /// `layout`. In particular, the block will be at least `layout.size()`
+    /// bytes large and will be aligned to `layout.align()`. The returned block
+    /// of storage may or may not have its contents initialized or zeroed.
+    ///
+    /// # Safety
+    ///
+    /// The _caller_ must ensure that `layout.size() > 0` and that
+    /// `layout.align()` is a power of two. Parameters not meeting these
+    /// conditions may result in undefined behavior.
+    ///
+    /// # Errors
+    ///
+    /// Returning `Err` indicates that either memory is exhausted
+    /// (`AllocError::Exhausted`) or `layout` does not meet this allocator's
+    /// size or alignment constraints (`AllocError::Unsupported`).
+    pub fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        // Should the page allocator run out, empty slabs kept for later are
+        // given back to it and the request retried once.
+        match self.try_alloc(&layout) {
+            Err(AllocErr::Exhausted { .. }) if self.has_empty() => {
+                self.release_empty();
+                self.try_alloc(&layout)
+            }
+            result => result,
+        }
+    }
+
+    fn try_alloc(&mut self, layout: &Layout) -> Result<*mut u8, AllocErr> {
+        match cache_of(layout) {
+            Some(i) => self.caches[i].alloc(&mut self.pages)
+                .ok_or_else(|| AllocErr::Exhausted { request: layout.clone() }),
+            None => self.pages.alloc(layout.clone()),
+        }
+    }
+
+    fn has_empty(&self) -> bool {
+        self.caches.iter().any(|cache| cache.empty.len > 0)
+    }
+
+    /// Returns every empty slab to the page allocator.
+    fn release_empty(&mut self) {
+        for cache in self.caches.iter_mut() {
+            cache.release(&mut self.pages, 0);
+        }
+    }
+
+    /// Deallocates the memory referenced by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The _caller_ must ensure the following:
+    ///
+    ///   * `ptr` must denote a block of memory currently allocated via this
+    ///     allocator
+    ///   * `layout` must properly represent the original layout used in the
+    ///     allocation call that returned `ptr`
+    ///
+    /// Parameters not meeting these conditions may result in undefined
+    /// behavior.
+    pub fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        match cache_of(&layout) {
+            Some(i) => self.caches[i].dealloc(&mut self.pages, ptr),
+            None => self.pages.dealloc(ptr, layout),
+        }
+    }
+
+    /// Returns the state of each cache, from the smallest objects to the
+    /// largest.
+    pub fn cache_stats(&self) -> [CacheStats; CACHES] {
+        let mut stats = [CacheStats::default(); CACHES];
+        for (stats, cache) in stats.iter_mut().zip(self.caches.iter()) {
+            *stats = cache.stats();
+        }
+
+        stats
+    }
+
+    /// Returns the state of the page allocator and of each cache.
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.pages.stats();
+        stats.caches = self.cache_stats();
+        stats
+    }
+}
+
+impl fmt::Debug for Allocator {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SlabAllocator")
+            .field("caches", &&self.cache_stats()[..])
+            .field("pages", &self.pages)
+            .finish()
+    }
+}
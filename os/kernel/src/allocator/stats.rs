@@ -0,0 +1,55 @@
+/// The number of block orders the page allocators track: blocks of order
+/// `i` are `1 << i` bytes large.
+pub const ORDERS: usize = 32;
+
+/// The number of slab caches: one for each power of two from 16 to 2048
+/// bytes.
+pub const CACHES: usize = 8;
+
+/// The state of one cache, as returned by `Allocator::cache_stats()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The size of each object, in bytes.
+    pub object_size: usize,
+    /// The size of each slab, in bytes.
+    pub slab_size: usize,
+    /// The number of slabs with no free objects.
+    pub full_slabs: usize,
+    /// The number of slabs with both free and allocated objects.
+    pub partial_slabs: usize,
+    /// The number of slabs with no allocated objects.
+    pub empty_slabs: usize,
+    /// The number of objects allocated.
+    pub objects_in_use: usize,
+    /// The number of objects in all slabs, allocated or not.
+    pub objects_total: usize,
+    /// The number of allocations ever made from this cache.
+    pub allocs: usize,
+    /// The number of deallocations ever made to this cache.
+    pub deallocs: usize,
+}
+
+/// A snapshot of the heap, as returned by `Allocator::stats()`.
+///
+/// Byte counts are those of the page allocator, for which a slab counts as
+/// in use as a whole; `caches` tells how full the slabs are.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    /// The number of bytes the heap manages.
+    pub total: usize,
+    /// The number of bytes allocated.
+    pub in_use: usize,
+    /// The most bytes ever allocated at once.
+    pub peak: usize,
+    /// The number of allocations made.
+    pub allocs: usize,
+    /// The number of deallocations made.
+    pub deallocs: usize,
+    /// The size of the largest free block, in bytes: the largest allocation
+    /// that can currently succeed.
+    pub largest_free: usize,
+    /// The number of free blocks of each order.
+    pub free_blocks: [usize; ORDERS],
+    /// The state of each slab cache, from the smallest objects to the largest.
+    pub caches: [CacheStats; CACHES],
+}
@@ -58,13 +58,17 @@ mod align_util {
 mod allocator {
     #[allow(dead_code)] mod bump;
     #[allow(dead_code)] mod bin;
+    #[allow(dead_code)] mod buddy;
+    #[allow(dead_code)] mod slab;
+    #[allow(dead_code)] mod debug;
 
     use alloc::allocator::{AllocErr, Layout};
     use alloc::raw_vec::RawVec;
 
     macro test_allocators {
-        (@$kind:ident, $name:ident, $mem:expr, |$info:pat| $block:expr) => {
+        ($(#[$attr:meta])* @$kind:ident, $name:ident, $mem:expr, |$info:pat| $block:expr) => {
             #[test]
+            $(#[$attr])*
             fn $name() {
                 let mem: RawVec<u8> = RawVec::with_capacity($mem);
                 let start = mem.ptr() as usize;
@@ -76,9 +80,8 @@ mod allocator {
             }
         },
 
-        ($bin:ident, $bump:ident, $mem:expr, |$info:pat| $block:expr) => (
-            test_allocators!(@bin, $bin, $mem, |$info| $block);
-            test_allocators!(@bump, $bump, $mem, |$info| $block);
+        ($(@$kind:ident $name:ident),+, $mem:expr, |$info:pat| $block:expr) => (
+            $(test_allocators!(@$kind, $name, $mem, |$info| $block);)+
         )
     }
 
@@ -117,12 +120,14 @@ mod allocator {
         }
     }
 
-    test_allocators!(bin_exhausted, bump_exhausted, 128, |(_, _, mut a)| {
+    test_allocators!(@bin bin_exhausted, @bump bump_exhausted, @buddy buddy_exhausted,
+                     @slab slab_exhausted, @debug debug_exhausted, 128, |(_, _, mut a)| {
         let e = a.alloc(layout!(1024, 128)).unwrap_err();
         assert_eq!(e, AllocErr::Exhausted { request: layout!(1024, 128) })
     });
 
-    test_allocators!(bin_alloc, bump_alloc, 8 * (1 << 20), |(start, end, a)| {
+    test_allocators!(@bin bin_alloc, @bump bump_alloc, @buddy buddy_alloc, @slab slab_alloc,
+                     @debug debug_alloc, 8 * (1 << 20), |(start, end, a)| {
         let layouts = [
             layout!(16, 16),
             layout!(16, 128),
@@ -148,7 +153,8 @@ mod allocator {
         test_layouts!(layouts, start, end, a);
     });
 
-    test_allocators!(bin_alloc_2, bump_alloc_2, 16 * (1 << 20), |(start, end, a)| {
+    test_allocators!(@bin bin_alloc_2, @bump bump_alloc_2, @buddy buddy_alloc_2,
+                     @slab slab_alloc_2, @debug debug_alloc_2, 16 * (1 << 20), |(start, end, a)| {
         let mut layouts = vec![];
         for i in 1..1024 {
             layouts.push(layout!(i * 8, 16));
@@ -162,7 +168,8 @@ mod allocator {
         unsafe { ::std::ptr::write_bytes(ptr, 0xAF, size); }
     }
 
-    test_allocators!(bin_dealloc_s, bump_dealloc_s, 4096, |(_, _, mut a)| {
+    test_allocators!(@bin bin_dealloc_s, @bump bump_dealloc_s, @buddy buddy_dealloc_s,
+                     4096, |(_, _, mut a)| {
         let layouts = [
             layout!(16, 16),
             layout!(16, 128),
@@ -183,7 +190,8 @@ mod allocator {
         }
     });
 
-    test_allocators!(@bin, bin_dealloc_1, 65536, |(_, _, mut a)| {
+    test_allocators!(@bin bin_dealloc_1, @buddy buddy_dealloc_1, @slab slab_dealloc_1,
+                     65536, |(_, _, mut a)| {
         let layouts = [
             layout!(16, 16),
             layout!(16, 256),
@@ -221,7 +229,7 @@ mod allocator {
         }
     });
 
-    test_allocators!(@bin, bin_dealloc_2, 8192, |(_, _, mut a)| {
+    test_allocators!(@bin bin_dealloc_2, @buddy buddy_dealloc_2, 8192, |(_, _, mut a)| {
         let layouts = [
             layout!(3072, 16),
             layout!(512, 32),
@@ -243,6 +251,289 @@ mod allocator {
             }
         }
     });
+
+    /// Returns the largest power-of-two layout `a` can allocate right now.
+    fn largest(a: &mut buddy::Allocator) -> Layout {
+        let mut size = 1 << 30;
+        loop {
+            match a.alloc(layout!(size, 8)) {
+                Ok(ptr) => {
+                    a.dealloc(ptr, layout!(size, 8));
+                    return layout!(size, 8);
+                }
+                Err(_) => size /= 2
+            }
+        }
+    }
+
+    test_allocators!(@buddy, buddy_fragmentation_1, 1 << 16, |(_, _, mut a)| {
+        let before = largest(&mut a);
+
+        // Fill the heap with small blocks, then free the lower half of every
+        // pair: the holes left can't merge, so not even 32 bytes are free.
+        let mut ptrs = vec![];
+        while let Ok(ptr) = a.alloc(layout!(16, 16)) {
+            scribble(ptr, 16);
+            ptrs.push(ptr);
+        }
+
+        assert!(ptrs.len() >= (1 << 16) / 2 / 16, "only {} allocations", ptrs.len());
+        let (lower, upper): (Vec<_>, Vec<_>) = ptrs.into_iter()
+            .partition(|&ptr| ptr as usize & 16 == 0);
+        for &ptr in &lower {
+            a.dealloc(ptr, layout!(16, 16));
+        }
+
+        a.alloc(layout!(32, 8)).unwrap_err();
+        a.alloc(before.clone()).unwrap_err();
+
+        // Once the rest is freed, in reverse, the blocks merge back together.
+        for &ptr in upper.iter().rev() {
+            a.dealloc(ptr, layout!(16, 16));
+        }
+
+        assert_eq!(largest(&mut a), before);
+    });
+
+    test_allocators!(@buddy, buddy_fragmentation_2, 1 << 20, |(start, end, mut a)| {
+        let before = largest(&mut a);
+
+        // Interleave allocations of varied sizes and alignments with frees
+        // in a pseudo-random order, tagging each allocation with its own
+        // pattern to catch any overlap.
+        let mut seed: u32 = 0x2545_F491;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize
+        };
+
+        let mut live: Vec<(*mut u8, Layout, u8)> = vec![];
+        for i in 0..20000 {
+            if live.is_empty() || random() % 3 != 0 {
+                let layout = layout!(1 + random() % 4096, 1 << (random() % 9));
+                if let Ok(ptr) = a.alloc(layout.clone()) {
+                    let (addr, tag) = (ptr as usize, i as u8);
+                    assert!(addr >= start && addr + layout.size() <= end);
+                    assert!(addr % layout.align() == 0,
+                        "{:x} is not aligned to {}", addr, layout.align());
+                    unsafe { ::std::ptr::write_bytes(ptr, tag, layout.size()); }
+                    live.push((ptr, layout, tag));
+                    continue;
+                }
+            }
+
+            let (ptr, layout, tag) = live.swap_remove(random() % live.len());
+            let bytes = unsafe { ::std::slice::from_raw_parts(ptr, layout.size()) };
+            assert!(bytes.iter().all(|&b| b == tag), "allocation at {:x} overwritten", ptr as usize);
+            a.dealloc(ptr, layout);
+        }
+
+        for (ptr, layout, _) in live {
+            a.dealloc(ptr, layout);
+        }
+
+        assert_eq!(largest(&mut a), before);
+    });
+
+    test_allocators!(@buddy, buddy_stats, 1 << 16, |(_, _, mut a)| {
+        let before = a.stats();
+        assert!(before.total > (1 << 15) && before.total <= 1 << 16);
+        assert_eq!(before.in_use, 0);
+
+        let total: usize = before.free_blocks.iter().enumerate()
+            .map(|(order, &count)| count << order)
+            .sum();
+        assert_eq!(total, before.total);
+
+        let x = a.alloc(layout!(100, 8)).unwrap();
+        let y = a.alloc(layout!(8, 8)).unwrap();
+        let stats = a.stats();
+        assert_eq!(stats.in_use, 128 + 8);
+        assert_eq!(stats.peak, 128 + 8);
+
+        a.dealloc(x, layout!(100, 8));
+        let stats = a.stats();
+        assert_eq!(stats.in_use, 8);
+        assert_eq!(stats.peak, 128 + 8);
+        assert_eq!(stats.deallocs, 1);
+
+        a.dealloc(y, layout!(8, 8));
+        let stats = a.stats();
+        assert_eq!(stats.free_blocks, before.free_blocks);
+        assert_eq!(stats.largest_free, largest(&mut a).size());
+    });
+
+    test_allocators!(@buddy, buddy_regions, 1 << 16, |(start, end, _)| {
+        // Two regions with a hole between them that is never handed out.
+        let middle = start + (end - start) / 2;
+        let mut a = buddy::Allocator::new(start, middle - 4096);
+        a.add_region(middle, end);
+
+        let mut ptrs = vec![];
+        while let Ok(ptr) = a.alloc(layout!(1024, 8)) {
+            let addr = ptr as usize;
+            assert!(addr + 1024 <= middle - 4096 || addr >= middle,
+                "{:x} is in the hole before {:x}", addr, middle);
+            ptrs.push(ptr);
+        }
+
+        // Both regions were used, nearly in full.
+        assert!(ptrs.iter().any(|&ptr| (ptr as usize) < middle));
+        assert!(ptrs.iter().any(|&ptr| (ptr as usize) >= middle));
+        assert!(ptrs.len() * 1024 >= (end - start) / 2, "only {} allocations", ptrs.len());
+
+        for ptr in ptrs {
+            a.dealloc(ptr, layout!(1024, 8));
+        }
+
+        assert_eq!(a.stats().in_use, 0);
+    });
+
+    test_allocators!(@bump, bump_regions, 1 << 16, |(start, end, _)| {
+        // The allocator moves from a small region to a larger one below it.
+        let middle = start + (end - start) / 2;
+        let mut a = bump::Allocator::new(middle, middle + 1024);
+        a.alloc(layout!(512, 8)).unwrap();
+        a.add_region(start, middle);
+
+        let stats = a.stats();
+        assert_eq!(stats.total, middle - start);
+        assert_eq!(stats.in_use, 0);
+        assert_eq!(stats.largest_free, middle - start);
+
+        let ptr = a.alloc(layout!(1024, 8)).unwrap() as usize;
+        assert!(ptr >= start && ptr + 1024 <= middle);
+        assert_eq!(a.stats().in_use, 1024);
+    });
+
+    test_allocators!(@slab, slab_caches, 1 << 20, |(_, _, mut a)| {
+        let stats = a.cache_stats();
+        assert_eq!(stats[0].object_size, 16);
+        assert_eq!(stats[stats.len() - 1].object_size, 2048);
+
+        // Small objects of the same size share slabs, packed one after the
+        // other rather than each rounded up to a block of their own.
+        let per_slab = {
+            let ptr = a.alloc(layout!(24, 8)).unwrap();
+            a.dealloc(ptr, layout!(24, 8));
+            a.cache_stats()[1].objects_total
+        };
+
+        let mut ptrs = vec![];
+        for _ in 0..per_slab {
+            let ptr = a.alloc(layout!(24, 8)).unwrap();
+            assert!(ptr as usize % 32 == 0, "{:x} is not aligned to 32", ptr as usize);
+            scribble(ptr, 24);
+            ptrs.push(ptr as usize);
+        }
+
+        for window in ptrs.windows(2) {
+            assert_eq!(window[1] - window[0], 32);
+        }
+
+        let stats = a.cache_stats()[1];
+        assert_eq!((stats.full_slabs, stats.partial_slabs, stats.empty_slabs), (1, 0, 0));
+        assert_eq!(stats.objects_in_use, per_slab);
+
+        // One more object starts a second slab.
+        let extra = a.alloc(layout!(32, 32)).unwrap();
+        let stats = a.cache_stats()[1];
+        assert_eq!((stats.full_slabs, stats.partial_slabs, stats.empty_slabs), (1, 1, 0));
+        assert_eq!(stats.objects_total, 2 * per_slab);
+
+        // Freeing an object of the full slab makes it partial, and freeing
+        // everything leaves one empty slab cached and returns the other.
+        a.dealloc(ptrs.pop().unwrap() as *mut u8, layout!(24, 8));
+        let stats = a.cache_stats()[1];
+        assert_eq!((stats.full_slabs, stats.partial_slabs, stats.empty_slabs), (0, 2, 0));
+
+        a.dealloc(extra, layout!(32, 32));
+        for ptr in ptrs {
+            a.dealloc(ptr as *mut u8, layout!(24, 8));
+        }
+
+        let stats = a.cache_stats()[1];
+        assert_eq!((stats.full_slabs, stats.partial_slabs, stats.empty_slabs), (0, 0, 1));
+        assert_eq!(stats.objects_in_use, 0);
+        assert_eq!((stats.allocs, stats.deallocs), (per_slab + 2, per_slab + 2));
+
+        // Large requests bypass the caches.
+        let ptr = a.alloc(layout!(4096, 4096)).unwrap();
+        assert!(ptr as usize % 4096 == 0);
+        assert!(a.cache_stats().iter().all(|stats| stats.objects_in_use == 0));
+        a.dealloc(ptr, layout!(4096, 4096));
+    });
+
+    test_allocators!(@slab, slab_reclaim, 1 << 16, |(_, _, mut a)| {
+        // Leave an empty slab cached in each cache...
+        for size in [16, 32, 64, 128, 256, 512, 1024, 2048].iter() {
+            let ptr = a.alloc(layout!(*size, 8)).unwrap();
+            a.dealloc(ptr, layout!(*size, 8));
+        }
+
+        assert!(a.cache_stats().iter().all(|stats| stats.empty_slabs == 1));
+
+        // ...which are handed back to the page allocator once it runs out.
+        let mut ptrs = vec![];
+        while let Ok(ptr) = a.alloc(layout!(4096, 4096)) {
+            ptrs.push(ptr);
+        }
+
+        assert!(a.cache_stats().iter().all(|stats| stats.empty_slabs == 0));
+        for ptr in ptrs {
+            a.dealloc(ptr, layout!(4096, 4096));
+        }
+    });
+
+    test_allocators!(@debug, debug_patterns, 1 << 16, |(_, _, mut a)| {
+        let layout = layout!(24, 8);
+        let ptr = a.alloc(layout.clone()).unwrap();
+        let bytes = unsafe { ::std::slice::from_raw_parts(ptr, 24) };
+        assert!(bytes.iter().all(|&b| b == 0xCD), "fresh memory is not 0xCD");
+
+        // Writing every byte of the block is fine, as is freeing it.
+        scribble(ptr, 24);
+        a.dealloc(ptr, layout);
+        assert!(bytes[8..].iter().all(|&b| b == 0xDD), "freed memory is not poisoned");
+    });
+
+    test_allocators!(#[should_panic(expected = "1 of the 16 guard bytes after the block")]
+                     @debug, debug_overrun, 1 << 16, |(_, _, mut a)| {
+        let ptr = a.alloc(layout!(24, 8)).unwrap();
+        scribble(ptr, 25);
+        a.dealloc(ptr, layout!(24, 8));
+    });
+
+    test_allocators!(#[should_panic(expected = "guard bytes before the block")]
+                     @debug, debug_underrun, 1 << 16, |(_, _, mut a)| {
+        let ptr = a.alloc(layout!(64, 64)).unwrap();
+        unsafe { *ptr.offset(-3) = 0; }
+        a.dealloc(ptr, layout!(64, 64));
+    });
+
+    test_allocators!(#[should_panic(expected = "the byte 3 before it holds 0x00")]
+                     @debug, debug_underrun_report, 1 << 16, |(_, _, mut a)| {
+        let ptr = a.alloc(layout!(100, 4)).unwrap();
+        unsafe { *ptr.offset(-3) = 0; }
+        a.dealloc(ptr, layout!(100, 4));
+    });
+
+    test_allocators!(#[should_panic(expected = "double free of the block")]
+                     @debug, debug_double_free, 1 << 16, |(_, _, mut a)| {
+        let ptr = a.alloc(layout!(512, 16)).unwrap();
+        let other = a.alloc(layout!(512, 16)).unwrap();
+        a.dealloc(ptr, layout!(512, 16));
+        a.dealloc(other, layout!(512, 16));
+        a.dealloc(ptr, layout!(512, 16));
+    });
+
+    test_allocators!(#[should_panic(expected = "which is not an allocated block")]
+                     @debug, debug_wild_free, 1 << 16, |(_, _, mut a)| {
+        let ptr = a.alloc(layout!(256, 8)).unwrap();
+        a.dealloc(unsafe { ptr.offset(64) }, layout!(128, 8));
+    });
 }
 
 mod linked_list {
@@ -337,3 +628,82 @@ mod linked_list {
         assert_eq!(iter.next(), None);
     }
 }
+
+mod memory_map {
+    use allocator::map::{videocore_memory, MemoryMap, Region, MAX_REGIONS};
+
+    #[test]
+    fn test_reserved_ranges() {
+        let mut map = MemoryMap::new();
+        map.add_memory(0, 0x3b40_0000);
+        map.reserve("stack", 0, 0x8_0000);
+        map.reserve("kernel", 0x8_0000, 0xa_1230);
+        map.reserve("atags", 0x100, 0x264);
+        map.reserve("bootloader", 0x400_0000, 0x410_0000);
+        map.reserve("videocore", 0x3b40_0000, 0x4000_0000);
+
+        assert_eq!(map.regions(), &[
+            Region::new(0xa_1230, 0x400_0000),
+            Region::new(0x410_0000, 0x3b40_0000),
+        ]);
+
+        assert_eq!(map.total(), 0x3b40_0000 - 0xa_1230 - 0x10_0000);
+        assert_eq!(map.reserved().len(), 5);
+        assert_eq!(map.reserved()[1].name, "kernel");
+    }
+
+    #[test]
+    fn test_multiple_regions() {
+        let mut map = MemoryMap::new();
+        map.add_memory(0x1000_0000, 0x2000_0000);
+        map.reserve("hole", 0x1800_0000, 0x1900_0000);
+        map.add_memory(0, 0x800_0000);
+
+        // Regions that overlap or touch are merged, and reservations apply
+        // to memory added after them too.
+        map.add_memory(0x2000_0000, 0x2100_0000);
+        map.add_memory(0x1f00_0000, 0x2000_0000);
+        map.reserve("low", 0, 0x1000);
+
+        assert_eq!(map.regions(), &[
+            Region::new(0x1000, 0x800_0000),
+            Region::new(0x1000_0000, 0x1800_0000),
+            Region::new(0x1900_0000, 0x2100_0000),
+        ]);
+
+        // Disjoint results even with overlapping reservations.
+        map.reserve("overlap", 0x17ff_0000, 0x1901_0000);
+        assert_eq!(map.regions()[1], Region::new(0x1000_0000, 0x17ff_0000));
+        assert_eq!(map.regions()[2], Region::new(0x1901_0000, 0x2100_0000));
+
+        // A reservation covering a region removes it.
+        map.reserve("all", 0x1000_0000, 0x1800_0000);
+        assert_eq!(map.regions().len(), 2);
+    }
+
+    #[test]
+    fn test_too_many_regions() {
+        let mut map = MemoryMap::new();
+        for i in 0..(MAX_REGIONS + 4) {
+            map.add_memory(i << 20, (i << 20) + 0x1000 * (i + 1));
+        }
+
+        // The smallest regions are left out.
+        assert_eq!(map.regions().len(), MAX_REGIONS);
+        assert_eq!(map.regions()[0], Region::new(4 << 20, (4 << 20) + 0x5000));
+        let largest = MAX_REGIONS + 3;
+        assert_eq!(map.regions()[MAX_REGIONS - 1],
+                   Region::new(largest << 20, (largest << 20) + 0x1000 * (largest + 1)));
+    }
+
+    #[test]
+    fn test_videocore_memory() {
+        let cmdline = "dma.dmachans=0x7f35 bcm2709.boardrev=0xa02082 \
+                       bcm2708_fb.fbwidth=656 vc_mem.mem_base=0x3ec00000 \
+                       vc_mem.mem_size=0x40000000  console=ttyS0,115200";
+
+        assert_eq!(videocore_memory(cmdline), Some(Region::new(0x3ec0_0000, 0x4000_0000)));
+        assert_eq!(videocore_memory("console=ttyS0,115200"), None);
+        assert_eq!(videocore_memory("vc_mem.mem_base=0x3ec00000 vc_mem.mem_size=x"), None);
+    }
+}
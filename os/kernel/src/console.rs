@@ -2,6 +2,7 @@ use std::io;
 use std::fmt;
 
 use pi::uart::MiniUart;
+use xmodem::ReadTimeout;
 
 use mutex::Mutex;
 
@@ -42,6 +43,19 @@ impl Console {
     }
 }
 
+/// Sets the timeout of reads through `io::Read`. `read_byte` is unaffected and
+/// always blocks.
+impl ReadTimeout for Console {
+    fn set_read_timeout(&mut self, milliseconds: Option<u32>) -> io::Result<()> {
+        match milliseconds {
+            Some(milliseconds) => self.inner().set_read_timeout(milliseconds),
+            None => self.inner().clear_read_timeout()
+        }
+
+        Ok(())
+    }
+}
+
 impl io::Read for Console {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.inner().read(buf)
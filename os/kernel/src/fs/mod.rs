@@ -3,13 +3,15 @@ pub mod sd;
 use std::io;
 use std::path::Path;
 
-use fat32::vfat::{self, Shared, VFat};
+use fat32::vfat::CacheStats;
+use fat32::volume::{self, Volume};
 pub use fat32::traits;
+use self::traits::FileSystem as FileSystemTrait;
 
 use mutex::Mutex;
 use self::sd::Sd;
 
-pub struct FileSystem(Mutex<Option<Shared<VFat>>>);
+pub struct FileSystem(Mutex<Option<Volume>>);
 
 impl FileSystem {
     /// Returns an uninitialized `FileSystem`.
@@ -20,14 +22,63 @@ impl FileSystem {
         FileSystem(Mutex::new(None))
     }
 
-    /// Initializes the file system.
+    /// Initializes the file system by mounting the FAT or exFAT volume on
+    /// the SD card.
     ///
     /// # Panics
     ///
     /// Panics if the underlying disk or file sytem failed to initialize.
     pub fn initialize(&self) {
-        unimplemented!("FileSystem::initialize()")
+        let sd = Sd::new().expect("failed to initialize SD card");
+        let volume = Volume::from(sd).expect("failed to mount FAT or exFAT file system");
+        *self.0.lock() = Some(volume);
+    }
+
+    /// Returns `true` if the file system cannot be changed, in which case
+    /// changes fail with an error kind of `PermissionDenied`. Only exFAT
+    /// volumes are; an uninitialized file system is too.
+    pub fn is_read_only(&self) -> bool {
+        self.0.lock().as_ref().map_or(true, |volume| volume.is_read_only())
+    }
+
+    /// Returns the sector cache's hit, miss and write-back counters, or
+    /// `None` if the file system is uninitialized.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.0.lock().as_ref().map(|volume| volume.cache_stats())
+    }
+
+    /// Returns a handle to the mounted file system.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file system has not been initialized.
+    fn volume(&self) -> Volume {
+        self.0.lock().as_ref().expect("file system uninitialized").clone()
     }
 }
 
-// FIXME: Implement `fat32::traits::FileSystem` for a useful type.
+impl<'a> traits::FileSystem for &'a FileSystem {
+    type File = volume::File;
+    type Dir = volume::Dir;
+    type Entry = volume::Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        (&self.volume()).open(path)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        (&self.volume()).create_file(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P, parents: bool) -> io::Result<Self::Dir> {
+        (&self.volume()).create_dir(path, parents)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        (&self.volume()).rename(from, to)
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        (&self.volume()).remove(path, children)
+    }
+}
@@ -1,5 +1,7 @@
 use std::io;
 use fat32::traits::BlockDevice;
+use pi::emmc::{self, Emmc};
+use pi::timer::spin_sleep_us;
 
 extern "C" {
     /// A global representing the last SD controller error that occured.
@@ -25,22 +27,74 @@ extern "C" {
     fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
 }
 
-// FIXME: Define a `#[no_mangle]` `wait_micros` function for use by `libsd`.
-// The `wait_micros` C signature is: `void wait_micros(unsigned int);`
+/// Sleeps for `us` microseconds. Called by `libsd` while it waits on the SD
+/// controller.
+#[no_mangle]
+pub extern "C" fn wait_micros(us: u32) {
+    spin_sleep_us(us as u64);
+}
 
 #[derive(Debug)]
 pub enum Error {
-    // FIXME: Fill me in.
+    /// A timeout occured while initializing the controller.
+    Timeout,
+    /// An error occured while sending commands to the controller.
+    SendingCommand,
+    /// Any other error, with the code returned by `sd_init`.
+    Unknown(i32),
+}
+
+impl From<emmc::Error> for Error {
+    fn from(error: emmc::Error) -> Error {
+        match error {
+            emmc::Error::Timeout => Error::Timeout,
+            _ => Error::SendingCommand
+        }
+    }
 }
 
-/// A handle to an SD card controller.
+/// A handle to an SD card controller. Sectors are read by `libsd` and
+/// written by `pi::emmc`.
 #[derive(Debug)]
-pub struct Sd;
+pub struct Sd {
+    emmc: Emmc,
+}
 
 impl Sd {
     /// Initializes the SD card controller and returns a handle to it.
     pub fn new() -> Result<Sd, Error> {
-        unimplemented!("Sd::new()")
+        match unsafe { sd_init() } {
+            0 => Ok(Sd { emmc: Emmc::new()? }),
+            -1 => Err(Error::Timeout),
+            -2 => Err(Error::SendingCommand),
+            code => Err(Error::Unknown(code))
+        }
+    }
+
+    /// Writes `count` sectors from `buf` starting at sector `start`.
+    fn write(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        let total = count as usize * 512;
+        if buf.len() < total {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "buffer is smaller than the sectors"));
+        }
+
+        if start + count > i32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "sector out of range"));
+        }
+
+        match self.emmc.write_blocks(start as u32, &buf[..total]) {
+            Ok(()) => Ok(total),
+            Err(emmc::Error::Timeout) => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "SD card write timed out"))
+            }
+            Err(emmc::Error::InvalidArgument) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "sector out of range"))
+            }
+            Err(emmc::Error::Command) => {
+                Err(io::Error::new(io::ErrorKind::Other, "SD card write failed"))
+            }
+        }
     }
 }
 
@@ -58,10 +112,50 @@ impl BlockDevice for Sd {
     ///
     /// An error of kind `Other` is returned for all other errors.
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
-        unimplemented!("Sd::read_sector()")
+        if buf.len() < 512 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "buffer is too small"));
+        }
+
+        if n > i32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "sector out of range"));
+        }
+
+        let read = unsafe { sd_readsector(n as i32, buf.as_mut_ptr()) };
+        if read > 0 {
+            return Ok(read as usize);
+        }
+
+        match unsafe { sd_err } {
+            -1 => Err(io::Error::new(io::ErrorKind::TimedOut, "SD card read timed out")),
+            _ => Err(io::Error::new(io::ErrorKind::Other, "SD card read failed"))
+        }
     }
 
-    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+    /// Overwrites sector `n` of the SD card with the first 512 bytes of
+    /// `buf`, returning once the card has taken them. On success, the number
+    /// of bytes written is returned.
+    ///
+    /// # Errors
+    ///
+    /// An I/O error of kind `UnexpectedEof` is returned if `buf.len() < 512`,
+    /// and one of kind `InvalidInput` if `n > 2^31 - 1`, as for reads.
+    ///
+    /// An error of kind `TimedOut` is returned if a timeout occurs while
+    /// writing to the SD card.
+    ///
+    /// An error of kind `Other` is returned for all other errors.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.write(n, 1, buf)
+    }
+
+    /// Overwrites `count` sectors starting at sector `start` with the
+    /// contents of `buf` in a single multi-block transfer. Fails as
+    /// `write_sector()` does.
+    fn write_sectors(&mut self, start: u64, count: u64, buf: &[u8]) -> io::Result<usize> {
+        if count == 0 {
+            return Ok(0);
+        }
+
+        self.write(start, count, buf)
     }
 }
@@ -14,6 +14,7 @@
 extern crate alloc;
 extern crate pi;
 extern crate stack_vec;
+extern crate xmodem;
 extern crate fat32;
 
 pub mod allocator;
@@ -23,12 +24,12 @@ pub mod console;
 pub mod shell;
 pub mod fs;
 
-#[cfg(not(test))]
 use allocator::Allocator;
+#[cfg(not(test))]
+use console::kprintln;
 use fs::FileSystem;
 
-#[cfg(not(test))]
-#[global_allocator]
+#[cfg_attr(not(test), global_allocator)]
 pub static ALLOCATOR: Allocator = Allocator::uninitialized();
 
 pub static FILE_SYSTEM: FileSystem = FileSystem::uninitialized();
@@ -36,15 +37,11 @@ pub static FILE_SYSTEM: FileSystem = FileSystem::uninitialized();
 #[no_mangle]
 #[cfg(not(test))]
 pub extern "C" fn kmain() {
-    let my_atags = pi::atags::Atags::get();
-    use console::kprint;
-    for x in my_atags {
-        kprint!("{:#?}\n", x);
-    }
     ALLOCATOR.initialize();
-    let mut v = vec![];
-    for i in 0..1000 {
-        v.push(i);
-        kprint!("{:?}\n", v);
+    if let Some(map) = ALLOCATOR.memory_map() {
+        kprintln!("memory map:\n{}", map);
     }
+
+    FILE_SYSTEM.initialize();
+    shell::shell("> ");
 }
@@ -0,0 +1,246 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use fat32::vfat::CacheStats;
+use fs::traits::{Dir, Entry, File, FileSystem, Metadata};
+use shell::{Command, CommandError, Descriptor, Shell, Stdio, COMMANDS};
+use shell::transfer::{rx, tx};
+use shell::meminfo::meminfo;
+use FILE_SYSTEM;
+
+/// The commands built into the shell.
+pub const BUILTINS: &[Descriptor] = &[
+    Descriptor {
+        name: "cat",
+        usage: "cat [path]...",
+        help: "print the contents of files, or the input",
+        handler: cat,
+    },
+    Descriptor {
+        name: "cd",
+        usage: "cd [path]",
+        help: "change the working directory, to / by default",
+        handler: cd,
+    },
+    Descriptor {
+        name: "echo",
+        usage: "echo [arg]...",
+        help: "print the arguments, separated by spaces",
+        handler: echo,
+    },
+    Descriptor {
+        name: "fsinfo",
+        usage: "fsinfo",
+        help: "print the file system's sector cache statistics",
+        handler: fsinfo,
+    },
+    Descriptor {
+        name: "help",
+        usage: "help [command]",
+        help: "list the commands, or describe one",
+        handler: help,
+    },
+    Descriptor {
+        name: "ls",
+        usage: "ls [-a] [path]",
+        help: "list a directory; -a includes hidden entries",
+        handler: ls,
+    },
+    Descriptor {
+        name: "meminfo",
+        usage: "meminfo [track|untrack|leaks]",
+        help: "print heap statistics, or track allocations to find leaks",
+        handler: meminfo,
+    },
+    Descriptor {
+        name: "pwd",
+        usage: "pwd",
+        help: "print the working directory",
+        handler: pwd,
+    },
+    Descriptor {
+        name: "rx",
+        usage: "rx <path>",
+        help: "receive a file over XMODEM on the console",
+        handler: rx,
+    },
+    Descriptor {
+        name: "source",
+        usage: "source <path>",
+        help: "run the commands in a file, one per line",
+        handler: source,
+    },
+    Descriptor {
+        name: "stat",
+        usage: "stat <path>",
+        help: "print the size, attributes and timestamps of an entry",
+        handler: stat,
+    },
+    Descriptor {
+        name: "tx",
+        usage: "tx <path>",
+        help: "send a file over XMODEM on the console",
+        handler: tx,
+    },
+];
+
+/// Returns `true` if `ls` should only show `entry` when given `-a`: if it is
+/// marked hidden or its name begins with a `.`.
+pub fn is_hidden<E: Entry>(entry: &E) -> bool {
+    entry.metadata().hidden() || entry.name().starts_with('.')
+}
+
+fn echo(cmd: &Command, _shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    writeln!(stdio.stdout, "{}", cmd.args().join(" "))?;
+    Ok(())
+}
+
+fn help(cmd: &Command, _shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    match cmd.args().len() {
+        0 => {
+            for command in COMMANDS.all() {
+                writeln!(stdio.stdout, "{:<20} {}", command.usage, command.help)?;
+            }
+
+            writeln!(stdio.stdout)?;
+            writeln!(stdio.stdout, "{:<20} {}", "cmd > path", "write the output of cmd to path")?;
+            writeln!(stdio.stdout, "{:<20} {}", "cmd >> path", "append it to path instead")?;
+            writeln!(stdio.stdout, "{:<20} {}", "", "path must be on a writable file system")?;
+        }
+        1 => {
+            let command = COMMANDS.find(&cmd.args()[0])
+                .ok_or(io::Error::new(io::ErrorKind::NotFound, "no such command"))?;
+            writeln!(stdio.stdout, "usage: {}", command.usage)?;
+            writeln!(stdio.stdout, "{}", command.help)?;
+        }
+        _ => return Err(CommandError::Usage)
+    }
+
+    Ok(())
+}
+
+fn pwd(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if !cmd.args().is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    writeln!(stdio.stdout, "{}", shell.cwd().display())?;
+    Ok(())
+}
+
+fn cd(cmd: &Command, shell: &mut Shell, _stdio: &mut Stdio) -> Result<(), CommandError> {
+    let path = match cmd.args().len() {
+        0 => shell.resolve("/"),
+        1 => shell.resolve(&cmd.args()[0]),
+        _ => return Err(CommandError::Usage)
+    };
+
+    (&FILE_SYSTEM).open_dir(&path)?;
+    shell.cwd = path;
+    Ok(())
+}
+
+/// Writes the contents of the directory at `path`, or the entry itself if it
+/// is a file, to `out`.
+fn list(path: &Path, all: bool, out: &mut Write) -> io::Result<()> {
+    let entry = (&FILE_SYSTEM).open(path)?;
+    let dir = match entry.as_dir() {
+        Some(dir) => dir,
+        None => {
+            return writeln!(out, "{}", entry.name());
+        }
+    };
+
+    for entry in dir.entries()? {
+        if all || !is_hidden(&entry) {
+            let suffix = if entry.is_dir() { "/" } else { "" };
+            writeln!(out, "{}{}", entry.name(), suffix)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn ls(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    let mut all = false;
+    let mut path = None;
+    for arg in cmd.args() {
+        match arg.as_str() {
+            "-a" => all = true,
+            arg if arg.starts_with('-') || path.is_some() => return Err(CommandError::Usage),
+            arg => path = Some(shell.resolve(arg)),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| shell.cwd().to_path_buf());
+    Ok(list(&path, all, stdio.stdout)?)
+}
+
+fn cat(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if cmd.args().is_empty() {
+        io::copy(stdio.stdin, stdio.stdout)?;
+        return Ok(());
+    }
+
+    for path in cmd.args() {
+        let mut file = (&FILE_SYSTEM).open_file(shell.resolve(path))?;
+        io::copy(&mut file, stdio.stdout)?;
+    }
+
+    Ok(())
+}
+
+fn stat(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if cmd.args().len() != 1 {
+        return Err(CommandError::Usage);
+    }
+
+    let path = shell.resolve(&cmd.args()[0]);
+    let entry = (&FILE_SYSTEM).open(&path)?;
+    writeln!(stdio.stdout, "  File: {}", path.display())?;
+    match entry.as_file() {
+        Some(file) => writeln!(stdio.stdout, "  Size: {}", file.size())?,
+        None => writeln!(stdio.stdout, "  Size: - (directory)")?
+    }
+
+    writeln!(stdio.stdout, "  Info: {}", entry.metadata())?;
+    Ok(())
+}
+
+/// Writes the sector cache's counters to `out` as `fsinfo` prints them.
+pub fn write_cache_stats(stats: &CacheStats, out: &mut Write) -> io::Result<()> {
+    let accesses = stats.hits + stats.misses;
+    let rate = if accesses == 0 { 0 } else { stats.hits * 100 / accesses };
+    writeln!(out, "cache:   {} hits, {} misses ({}% hit rate)", stats.hits, stats.misses, rate)?;
+    writeln!(out, "         {} evictions, {} write-backs, {} sectors read ahead",
+             stats.evictions, stats.writebacks, stats.read_aheads)
+}
+
+fn fsinfo(cmd: &Command, _shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if !cmd.args().is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    let stats = FILE_SYSTEM.cache_stats()
+        .ok_or(io::Error::new(io::ErrorKind::Other, "file system is uninitialized"))?;
+    Ok(write_cache_stats(&stats, stdio.stdout)?)
+}
+
+/// Runs each line of the file at the single argument as a command, with its
+/// output going to this command's output. A failing line does not stop the
+/// script; its error is reported and `$?` holds its status. The status of
+/// `source` is that of the script's last line.
+fn source(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if cmd.args().len() != 1 {
+        return Err(CommandError::Usage);
+    }
+
+    let mut script = String::new();
+    (&FILE_SYSTEM).open_file(shell.resolve(&cmd.args()[0]))?
+        .read_to_string(&mut script)?;
+
+    match shell.run_script(&script, stdio.stdout, stdio.stderr) {
+        0 => Ok(()),
+        status => Err(CommandError::Status(status))
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use shell::ExitStatus;
+
+/// The most arguments, including the command's name, a command may have.
+const MAX_ARGS: usize = 64;
+
+/// Error type for `Command` and `Pipeline` parse failures.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Empty,
+    TooManyArgs,
+    /// A `'` or `"` was never closed.
+    UnterminatedQuote,
+    /// The line ends in a `\` with nothing to escape.
+    TrailingEscape,
+    /// A `|` is not between two commands.
+    MissingCommand,
+    /// A `>` or `>>` is not followed by a path.
+    MissingPath,
+    /// A `|`, `>` or `>>` appears where it is not allowed.
+    UnexpectedOperator,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::Empty => "empty command",
+            Error::TooManyArgs => "too many arguments",
+            Error::UnterminatedQuote => "unterminated quote",
+            Error::TrailingEscape => "trailing backslash",
+            Error::MissingCommand => "missing command around '|'",
+            Error::MissingPath => "missing path after '>'",
+            Error::UnexpectedOperator => "unexpected '|' or '>'",
+        })
+    }
+}
+
+/// A structure representing a single shell command.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Command {
+    args: Vec<String>
+}
+
+/// Where the output of a pipeline is written.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Redirect {
+    /// The path of the file, as written.
+    pub path: String,
+    /// Whether the output is appended to the file (`>>`) rather than
+    /// replacing its contents (`>`).
+    pub append: bool,
+}
+
+/// A sequence of commands, each reading the output of the one before it,
+/// with the output of the last optionally redirected to a file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    pub redirect: Option<Redirect>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Pipe,
+    Redirect { append: bool },
+}
+
+/// Returns the character that the escape sequence `\c` stands for.
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        c => c
+    }
+}
+
+/// Appends what the `$` just read from `chars` stands for to `word`: the
+/// exit status `status` if the `$` begins `$?` and `status` is `Some`, or a
+/// plain `$` otherwise.
+fn dollar(chars: &mut Peekable<Chars>, status: Option<ExitStatus>, word: &mut String) {
+    match (status, chars.peek() == Some(&'?')) {
+        (Some(status), true) => {
+            chars.next();
+            word.push_str(&status.to_string());
+        }
+        _ => word.push('$')
+    }
+}
+
+/// Splits `s` into words and operators. If `status` is `Some`, unquoted and
+/// double-quoted occurrences of `$?` are replaced with it.
+fn tokenize(s: &str, status: Option<ExitStatus>) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => tokens.extend(word.take().map(Token::Word)),
+            '#' if word.is_none() => break,
+            '|' => {
+                tokens.extend(word.take().map(Token::Word));
+                tokens.push(Token::Pipe);
+            }
+            '>' => {
+                tokens.extend(word.take().map(Token::Word));
+                let append = chars.peek() == Some(&'>');
+                if append {
+                    chars.next();
+                }
+                tokens.push(Token::Redirect { append });
+            }
+            '$' => dollar(&mut chars, status, word.get_or_insert_with(String::new)),
+            '\\' => {
+                let c = chars.next().ok_or(Error::TrailingEscape)?;
+                word.get_or_insert_with(String::new).push(unescape(c));
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(Error::UnterminatedQuote)
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('$') => dollar(&mut chars, status, word),
+                        Some('\\') => {
+                            let c = chars.next().ok_or(Error::UnterminatedQuote)?;
+                            word.push(unescape(c));
+                        }
+                        Some(c) => word.push(c),
+                        None => return Err(Error::UnterminatedQuote)
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    tokens.extend(word.map(Token::Word));
+    Ok(tokens)
+}
+
+impl Command {
+    /// Parse a command from a string `s`.
+    ///
+    /// Arguments are separated by spaces or tabs. Text in single quotes is
+    /// taken literally. Text in double quotes may contain spaces and
+    /// backslash escapes. Outside of single quotes, `\n`, `\t`, `\r` and `\0`
+    /// stand for the control characters, and a backslash before any other
+    /// character, such as a space or a quote, makes it part of the argument.
+    /// A `#` at the start of an argument begins a comment that runs to the
+    /// end of the line.
+    ///
+    /// # Errors
+    ///
+    /// If `s` contains no arguments, returns `Error::Empty`. If there are more
+    /// than 64 arguments, returns `Error::TooManyArgs`. Unclosed quotes and a
+    /// trailing backslash return `Error::UnterminatedQuote` and
+    /// `Error::TrailingEscape`. An unquoted `|` or `>` returns
+    /// `Error::UnexpectedOperator`; use `Pipeline::parse` for those.
+    pub fn parse(s: &str) -> Result<Command, Error> {
+        let args = tokenize(s, None)?.into_iter()
+            .map(|token| match token {
+                Token::Word(word) => Ok(word),
+                _ => Err(Error::UnexpectedOperator)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Command::new(args)
+    }
+
+    /// Returns a command with the arguments `args`, the first of which is the
+    /// command's path.
+    fn new(args: Vec<String>) -> Result<Command, Error> {
+        if args.len() > MAX_ARGS {
+            return Err(Error::TooManyArgs);
+        }
+
+        if args.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        Ok(Command { args })
+    }
+
+    /// Returns this command's path. This is equivalent to the first argument.
+    pub fn path(&self) -> &str {
+        &self.args[0]
+    }
+
+    /// Returns the arguments following the command's path.
+    pub fn args(&self) -> &[String] {
+        &self.args[1..]
+    }
+}
+
+impl Pipeline {
+    /// Parses a pipeline from the line `s`: commands separated by `|`,
+    /// optionally followed by `> path` or `>> path`. Each command is parsed
+    /// as by `Command::parse`, except that `$?` outside of single quotes is
+    /// replaced with `status`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `Command::parse`. Returns `Error::Empty` if `s`
+    /// holds no commands, `Error::MissingCommand` if a command is missing
+    /// before or after a `|`, `Error::MissingPath` if a redirection has no
+    /// path, and `Error::UnexpectedOperator` if anything follows the path.
+    pub fn parse(s: &str, status: ExitStatus) -> Result<Pipeline, Error> {
+        let mut tokens = tokenize(s, Some(status))?.into_iter();
+        let mut commands = Vec::new();
+        let mut args = Vec::new();
+        let mut redirect = None;
+
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Word(word) => args.push(word),
+                Token::Pipe if args.is_empty() => return Err(Error::MissingCommand),
+                Token::Pipe => {
+                    commands.push(Command::new(args)?);
+                    args = Vec::new();
+                }
+                Token::Redirect { append } => {
+                    let path = match tokens.next() {
+                        Some(Token::Word(path)) => path,
+                        Some(_) => return Err(Error::UnexpectedOperator),
+                        None => return Err(Error::MissingPath)
+                    };
+
+                    if tokens.next().is_some() {
+                        return Err(Error::UnexpectedOperator);
+                    }
+
+                    redirect = Some(Redirect { path, append });
+                }
+            }
+        }
+
+        match (args.is_empty(), commands.is_empty()) {
+            (true, true) if redirect.is_none() => return Err(Error::Empty),
+            (true, _) => return Err(Error::MissingCommand),
+            (false, _) => commands.push(Command::new(args)?),
+        }
+
+        Ok(Pipeline { commands, redirect })
+    }
+}
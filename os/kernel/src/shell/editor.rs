@@ -0,0 +1,430 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use console::Console;
+
+const BELL: u8 = 7;
+const BACKSPACE: u8 = 8;
+const DELETE: u8 = 127;
+const ESCAPE: u8 = 0x1B;
+
+/// Control characters for the editing shortcuts.
+const CTRL_A: u8 = 0x01;
+const CTRL_E: u8 = 0x05;
+const CTRL_U: u8 = 0x15;
+const CTRL_W: u8 = 0x17;
+
+/// The byte-level interface that the line editor reads keys from and draws
+/// the line to. Implemented by the console and, in tests, by a fake console.
+pub trait Terminal {
+    /// Reads a byte, blocking until one is available.
+    fn read_byte(&mut self) -> u8;
+
+    /// Writes all of `bytes`.
+    fn write_bytes(&mut self, bytes: &[u8]);
+}
+
+impl Terminal for Console {
+    fn read_byte(&mut self) -> u8 {
+        Console::read_byte(self)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        let _ = self.write_all(bytes);
+    }
+}
+
+/// Provides the candidates for tab completion.
+pub trait Completer {
+    /// Returns every word that `word` can be completed to. `first` is `true`
+    /// if `word` is the first word on the line.
+    fn complete(&self, word: &str, first: bool) -> Vec<String>;
+}
+
+/// A key press, decoded from one or more input bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(u8),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillLine,
+    KillWord,
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After an `ESC`.
+    Escape,
+    /// After `ESC [`, with the numeric parameter read so far.
+    Csi(u8),
+    /// After `ESC O`.
+    Ss3,
+}
+
+/// Decodes input bytes, including ANSI/VT100 escape sequences, into `Key`s.
+#[derive(Debug)]
+pub struct Decoder {
+    state: State,
+    /// Whether the last byte was a `\r`, so that a following `\n` is ignored.
+    after_cr: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder { state: State::Ground, after_cr: false }
+    }
+
+    /// Feeds `byte` to the decoder. Returns the key it completes, if any.
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        let after_cr = self.after_cr;
+        self.after_cr = byte == b'\r';
+
+        let (state, key) = match (self.state, byte) {
+            (State::Ground, ESCAPE) => (State::Escape, None),
+            (State::Ground, b'\n') if after_cr => (State::Ground, None),
+            (State::Ground, b'\r') | (State::Ground, b'\n') => (State::Ground, Some(Key::Enter)),
+            (State::Ground, b'\t') => (State::Ground, Some(Key::Tab)),
+            (State::Ground, BACKSPACE) | (State::Ground, DELETE) => (State::Ground, Some(Key::Backspace)),
+            (State::Ground, CTRL_A) => (State::Ground, Some(Key::Home)),
+            (State::Ground, CTRL_E) => (State::Ground, Some(Key::End)),
+            (State::Ground, CTRL_U) => (State::Ground, Some(Key::KillLine)),
+            (State::Ground, CTRL_W) => (State::Ground, Some(Key::KillWord)),
+            (State::Ground, 32...126) => (State::Ground, Some(Key::Char(byte))),
+            (State::Ground, _) => (State::Ground, Some(Key::Unknown)),
+
+            (State::Escape, b'[') => (State::Csi(0), None),
+            (State::Escape, b'O') => (State::Ss3, None),
+            (State::Escape, _) => (State::Ground, Some(Key::Unknown)),
+
+            (State::Csi(n), b'0'...b'9') => {
+                (State::Csi(n.saturating_mul(10).saturating_add(byte - b'0')), None)
+            }
+            (State::Csi(n), b'~') => (State::Ground, Some(match n {
+                1 | 7 => Key::Home,
+                4 | 8 => Key::End,
+                3 => Key::Delete,
+                _ => Key::Unknown
+            })),
+            (State::Csi(_), 0x40...0x7E) => (State::Ground, Some(Decoder::final_key(byte))),
+            (State::Csi(n), _) => (State::Csi(n), None),
+
+            (State::Ss3, _) => (State::Ground, Some(Decoder::final_key(byte))),
+        };
+
+        self.state = state;
+        key
+    }
+
+    /// Returns the key for the final byte of a `CSI` or `SS3` sequence.
+    fn final_key(byte: u8) -> Key {
+        match byte {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            _ => Key::Unknown
+        }
+    }
+}
+
+/// A bounded ring of previously entered lines, newest last.
+#[derive(Debug)]
+pub struct History {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl History {
+    /// Returns an empty history that remembers up to `capacity` lines.
+    pub fn new(capacity: usize) -> History {
+        History { lines: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Adds `line` as the newest entry, dropping the oldest entry if the
+    /// history is full. Blank lines and repeats of the newest entry are not
+    /// added.
+    pub fn push(&mut self, line: &str) {
+        if line.trim().is_empty() || self.lines.back().map(|l| l.as_str()) == Some(line) {
+            return;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+
+        if self.capacity > 0 {
+            self.lines.push_back(line.to_string());
+        }
+    }
+
+    /// Returns the number of lines in the history.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns the line `age` entries back from the newest, which is `0`.
+    pub fn get(&self, age: usize) -> Option<&str> {
+        if age >= self.lines.len() {
+            return None;
+        }
+
+        self.lines.get(self.lines.len() - 1 - age).map(|l| l.as_str())
+    }
+}
+
+/// An interactive line editor.
+///
+/// The line is kept in a buffer of at most `max_len` printable ASCII bytes.
+/// The cursor can be moved with the arrow keys, Home and End (or Ctrl-A and
+/// Ctrl-E). Backspace and Delete remove the byte before or under the cursor,
+/// Ctrl-U removes everything before the cursor, and Ctrl-W removes the word
+/// before it. Up and down walk through the history, and Tab completes the
+/// word before the cursor. Anything else rings the bell.
+#[derive(Debug)]
+pub struct LineEditor {
+    line: Vec<u8>,
+    cursor: usize,
+    max_len: usize,
+    decoder: Decoder,
+    history: History,
+    /// How far back in the history the line was taken from, and the line
+    /// that was being edited before browsing began.
+    browsing: Option<(usize, Vec<u8>)>,
+}
+
+impl LineEditor {
+    /// Returns an editor for lines of at most `max_len` bytes that remembers
+    /// the last `history` lines.
+    pub fn new(max_len: usize, history: usize) -> LineEditor {
+        LineEditor {
+            line: Vec::with_capacity(max_len),
+            cursor: 0,
+            max_len,
+            decoder: Decoder::new(),
+            history: History::new(history),
+            browsing: None,
+        }
+    }
+
+    /// Returns the history of entered lines.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Writes `prompt` to `term` and reads an edited line, which is returned
+    /// once Enter is pressed. Non-blank lines are added to the history.
+    pub fn read_line<T, C>(&mut self, term: &mut T, prompt: &str, completer: &C) -> String
+        where T: Terminal, C: Completer + ?Sized
+    {
+        self.line.clear();
+        self.cursor = 0;
+        self.browsing = None;
+        term.write_bytes(prompt.as_bytes());
+
+        loop {
+            let key = match self.decoder.feed(term.read_byte()) {
+                Some(key) => key,
+                None => continue
+            };
+
+            if key == Key::Enter {
+                term.write_bytes(b"\r\n");
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.history.push(&line);
+                return line;
+            }
+
+            self.handle(key, term, prompt, completer);
+        }
+    }
+
+    fn handle<T, C>(&mut self, key: Key, term: &mut T, prompt: &str, completer: &C)
+        where T: Terminal, C: Completer + ?Sized
+    {
+        match key {
+            Key::Char(byte) => {
+                if !self.insert(&[byte]) {
+                    return term.write_bytes(&[BELL]);
+                }
+
+                if self.cursor == self.line.len() {
+                    term.write_bytes(&[byte]);
+                } else {
+                    self.refresh(term, prompt);
+                }
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+                if self.cursor == self.line.len() {
+                    term.write_bytes(&[BACKSPACE, b' ', BACKSPACE]);
+                } else {
+                    self.refresh(term, prompt);
+                }
+            }
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+                self.refresh(term, prompt);
+            }
+            Key::Left if self.cursor > 0 => {
+                self.cursor -= 1;
+                term.write_bytes(b"\x1b[D");
+            }
+            Key::Right if self.cursor < self.line.len() => {
+                self.cursor += 1;
+                term.write_bytes(b"\x1b[C");
+            }
+            Key::Home => {
+                self.cursor = 0;
+                self.refresh(term, prompt);
+            }
+            Key::End => {
+                self.cursor = self.line.len();
+                self.refresh(term, prompt);
+            }
+            Key::KillLine => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+                self.refresh(term, prompt);
+            }
+            Key::KillWord if self.cursor > 0 => {
+                let mut start = self.cursor;
+                while start > 0 && self.line[start - 1] == b' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.line[start - 1] != b' ' {
+                    start -= 1;
+                }
+
+                self.line.drain(start..self.cursor);
+                self.cursor = start;
+                self.refresh(term, prompt);
+            }
+            Key::Up => self.browse(1, term, prompt),
+            Key::Down => self.browse(-1, term, prompt),
+            Key::Tab => self.complete(term, prompt, completer),
+            _ => term.write_bytes(&[BELL]),
+        }
+    }
+
+    /// Inserts `bytes` at the cursor and moves the cursor past them. Returns
+    /// `false`, leaving the line unchanged, if they would not fit.
+    fn insert(&mut self, bytes: &[u8]) -> bool {
+        if self.line.len() + bytes.len() > self.max_len {
+            return false;
+        }
+
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.line.insert(self.cursor + i, byte);
+        }
+
+        self.cursor += bytes.len();
+        true
+    }
+
+    /// Redraws the prompt and the line, and places the cursor.
+    fn refresh<T: Terminal>(&self, term: &mut T, prompt: &str) {
+        term.write_bytes(b"\r");
+        term.write_bytes(prompt.as_bytes());
+        term.write_bytes(&self.line);
+        term.write_bytes(b"\x1b[K");
+
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            term.write_bytes(format!("\x1b[{}D", back).as_bytes());
+        }
+    }
+
+    /// Moves `step` entries further back in the history (or forward, if
+    /// negative) and replaces the line with that entry. Moving forward past
+    /// the newest entry restores the line that was being edited.
+    fn browse<T: Terminal>(&mut self, step: isize, term: &mut T, prompt: &str) {
+        let age = match self.browsing {
+            Some((age, _)) => age as isize + step,
+            None => step - 1,
+        };
+
+        if age < -1 || age >= self.history.len() as isize || (age == -1 && self.browsing.is_none()) {
+            return term.write_bytes(&[BELL]);
+        }
+
+        let line = match age {
+            -1 => self.browsing.take().map(|(_, saved)| saved).unwrap_or_default(),
+            _ => {
+                let line = self.history.get(age as usize).unwrap_or("").as_bytes().to_vec();
+                let saved = match self.browsing.take() {
+                    Some((_, saved)) => saved,
+                    None => self.line.clone(),
+                };
+                self.browsing = Some((age as usize, saved));
+                line
+            }
+        };
+
+        self.line = line;
+        self.line.truncate(self.max_len);
+        self.cursor = self.line.len();
+        self.refresh(term, prompt);
+    }
+
+    /// Completes the word before the cursor. A single candidate is inserted
+    /// in full, followed by a space unless it names a directory. With several
+    /// candidates, their longest common prefix is inserted; if that adds
+    /// nothing, the candidates are listed below the line.
+    fn complete<T, C>(&mut self, term: &mut T, prompt: &str, completer: &C)
+        where T: Terminal, C: Completer + ?Sized
+    {
+        let start = self.line[..self.cursor].iter()
+            .rposition(|&b| b == b' ')
+            .map_or(0, |i| i + 1);
+        let first = self.line[..start].iter().all(|&b| b == b' ');
+        let word = String::from_utf8_lossy(&self.line[start..self.cursor]).into_owned();
+
+        let candidates = completer.complete(&word, first);
+        let mut prefix = match candidates.first() {
+            Some(first) => first.as_bytes().to_vec(),
+            None => return term.write_bytes(&[BELL]),
+        };
+
+        for candidate in &candidates[1..] {
+            let common = prefix.iter().zip(candidate.as_bytes())
+                .take_while(|&(a, b)| a == b)
+                .count();
+            prefix.truncate(common);
+        }
+
+        if candidates.len() == 1 && !prefix.ends_with(b"/") {
+            prefix.push(b' ');
+        }
+
+        let added = prefix.len().saturating_sub(word.len());
+        if added > 0 && prefix.starts_with(word.as_bytes()) {
+            if !self.insert(&prefix[word.len()..]) {
+                return term.write_bytes(&[BELL]);
+            }
+        } else if candidates.len() > 1 {
+            term.write_bytes(b"\r\n");
+            for candidate in &candidates {
+                term.write_bytes(candidate.as_bytes());
+                term.write_bytes(b"  ");
+            }
+            term.write_bytes(b"\r\n");
+        } else {
+            return term.write_bytes(&[BELL]);
+        }
+
+        self.refresh(term, prompt);
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+use allocator::{Leaks, Stats};
+use shell::{Command, CommandError, Shell, Stdio};
+use ALLOCATOR;
+
+/// Writes `stats` to `out` as `meminfo` prints them: totals, then the free
+/// blocks of each order, then each slab cache that has been used.
+pub fn write_stats(stats: &Stats, out: &mut Write) -> io::Result<()> {
+    writeln!(out, "heap:    {} of {} bytes in use, peak {}", stats.in_use, stats.total, stats.peak)?;
+    writeln!(out, "         largest free block {} bytes", stats.largest_free)?;
+    writeln!(out, "allocs:  {} ({} freed)", stats.allocs, stats.deallocs)?;
+
+    write!(out, "free:   ")?;
+    for (order, &count) in stats.free_blocks.iter().enumerate() {
+        if count > 0 {
+            write!(out, " {}x{}", count, 1usize << order)?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "caches:  {:>5} {:>6} {:>5} {:>5} {:>5} {:>15} {:>8} {:>8}",
+             "size", "slabs", "full", "part", "empty", "in use/total", "allocs", "frees")?;
+    for cache in stats.caches.iter().filter(|cache| cache.allocs > 0) {
+        let slabs = cache.full_slabs + cache.partial_slabs + cache.empty_slabs;
+        let objects = format!("{}/{}", cache.objects_in_use, cache.objects_total);
+        writeln!(out, "         {:>5} {:>6} {:>5} {:>5} {:>5} {:>15} {:>8} {:>8}",
+                 cache.object_size, slabs, cache.full_slabs, cache.partial_slabs,
+                 cache.empty_slabs, objects, cache.allocs, cache.deallocs)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the allocations recorded by `leaks` and not yet freed to `out`,
+/// oldest first.
+pub fn write_leaks(leaks: &Leaks, out: &mut Write) -> io::Result<()> {
+    let state = if leaks.is_enabled() { "on" } else { "off" };
+    writeln!(out, "{} live allocations recorded, tracking {}",
+             leaks.records().len(), state)?;
+
+    let mut records = leaks.records().to_vec();
+    records.sort_by_key(|record| record.serial);
+    for record in records {
+        write!(out, "  #{:<5} {:#010x} {:>8} bytes, align {:<5} from",
+               record.serial, record.ptr, record.size, record.align)?;
+        for address in record.backtrace.iter().take_while(|&&address| address != 0) {
+            write!(out, " {:#010x}", address)?;
+        }
+
+        writeln!(out)?;
+    }
+
+    if leaks.untracked() > 0 {
+        writeln!(out, "  ... and {} allocations made once the table was full",
+                 leaks.untracked())?;
+    }
+
+    Ok(())
+}
+
+/// Prints the heap's statistics. With `track`, starts recording allocations
+/// afresh; with `untrack`, stops; and with `leaks`, prints those recorded
+/// that are still live.
+pub fn meminfo(cmd: &Command, _shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    let arg = match cmd.args().len() {
+        0 => None,
+        1 => Some(cmd.args()[0].as_str()),
+        _ => return Err(CommandError::Usage)
+    };
+
+    match arg {
+        None => {
+            let stats = ALLOCATOR.stats()
+                .ok_or(io::Error::new(io::ErrorKind::Other, "heap is uninitialized"))?;
+            write_stats(&stats, stdio.stdout)?;
+        }
+        Some("track") => {
+            ALLOCATOR.track_leaks(true);
+            writeln!(stdio.stdout, "meminfo: recording allocations")?;
+        }
+        Some("untrack") => {
+            ALLOCATOR.track_leaks(false);
+            writeln!(stdio.stdout, "meminfo: no longer recording allocations")?;
+        }
+        Some("leaks") => write_leaks(&ALLOCATOR.leaks(), stdio.stdout)?,
+        Some(_) => return Err(CommandError::Usage)
+    }
+
+    Ok(())
+}
@@ -0,0 +1,302 @@
+mod editor;
+mod command;
+mod registry;
+mod builtins;
+mod transfer;
+mod meminfo;
+
+#[cfg(test)]
+mod tests;
+
+use console::{kprintln, CONSOLE};
+
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use fat32::volume;
+use fs::traits::{Dir, Entry, File, FileSystem};
+use FILE_SYSTEM;
+
+pub use self::editor::{Completer, Decoder, History, Key, LineEditor, Terminal};
+pub use self::command::{Command, Error, Pipeline, Redirect};
+pub use self::registry::{CommandError, Descriptor, ExitStatus, Handler, Registry, Stdio};
+pub use self::registry::{FAILURE, NOT_FOUND, USAGE};
+use self::builtins::{is_hidden, BUILTINS};
+
+/// The shell's commands. Kernel modules may register more before the shell
+/// starts.
+pub static COMMANDS: Registry = Registry::new(BUILTINS);
+
+/// The longest line the shell accepts, in bytes.
+const MAX_LINE: usize = 512;
+/// The number of lines kept in the shell's history.
+const HISTORY: usize = 32;
+
+/// The script run when the shell starts, if it exists.
+const INIT_SCRIPT: &str = "/boot/init.sh";
+
+/// The most scripts that may be running at once, each sourced by the last.
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+const WELCOME: &str = r#"ONI OS"#;
+
+/// Writes to the console, locking it only for the duration of each write so
+/// that `kprint!` may be used between writes.
+struct ConsoleWriter;
+
+impl Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        CONSOLE.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        CONSOLE.lock().flush()
+    }
+}
+
+/// Completes command names and paths relative to the working directory.
+struct ShellCompleter<'a> {
+    cwd: &'a Path
+}
+
+impl<'a> Completer for ShellCompleter<'a> {
+    fn complete(&self, word: &str, first: bool) -> Vec<String> {
+        if first {
+            return COMMANDS.all().iter()
+                .filter(|command| command.name.starts_with(word))
+                .map(|command| command.name.to_string())
+                .collect();
+        }
+
+        // Complete the last component of `word` within the directory named by
+        // the rest of it, keeping `word`'s directory part as written.
+        let (dir, name) = match word.rfind('/') {
+            Some(i) => (&word[..(i + 1)], &word[(i + 1)..]),
+            None => ("", word)
+        };
+
+        let entries = match (&FILE_SYSTEM).open_dir(resolve(self.cwd, dir)) {
+            Ok(dir) => match dir.entries() {
+                Ok(entries) => entries,
+                Err(_) => return Vec::new()
+            },
+            Err(_) => return Vec::new()
+        };
+
+        let mut candidates: Vec<String> = entries
+            .filter(|entry| entry.name().starts_with(name))
+            .filter(|entry| name.starts_with('.') || !is_hidden(entry))
+            .map(|entry| {
+                let suffix = if entry.is_dir() { "/" } else { "" };
+                format!("{}{}{}", dir, entry.name(), suffix)
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+/// The state of a shell: its working directory, the exit status of the last
+/// command, and the number of scripts running.
+pub struct Shell {
+    cwd: PathBuf,
+    status: ExitStatus,
+    depth: usize,
+}
+
+impl Shell {
+    /// Returns a shell in the root directory.
+    pub fn new() -> Shell {
+        Shell { cwd: PathBuf::from("/"), status: 0, depth: 0 }
+    }
+
+    /// Returns the working directory.
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Returns the exit status of the last command run.
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    /// Returns the absolute path of `path` relative to the working directory.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        resolve(&self.cwd, path)
+    }
+
+    /// Parses and runs the pipeline in `line`, writing the output of its last
+    /// command to `out` unless it is redirected to a file, and returns the
+    /// exit status of the last command. `$?` in `line` is replaced with the
+    /// current exit status. Errors are reported on `err`. A blank line or
+    /// comment does nothing and leaves the exit status unchanged.
+    pub fn run(&mut self, line: &str, out: &mut io::Write, err: &mut io::Write) -> ExitStatus {
+        let pipeline = match Pipeline::parse(line, self.status) {
+            Ok(pipeline) => pipeline,
+            Err(Error::Empty) => return self.status,
+            Err(e) => {
+                let _ = writeln!(err, "error: {}", e);
+                self.status = USAGE;
+                return self.status;
+            }
+        };
+
+        let mut file = match pipeline.redirect {
+            Some(ref redirect) => match open_output(&self.resolve(&redirect.path), redirect.append) {
+                Ok(file) => Some(file),
+                Err(e) => {
+                    let _ = writeln!(err, "{}: {}", redirect.path, e);
+                    self.status = FAILURE;
+                    return self.status;
+                }
+            },
+            None => None
+        };
+
+        // Each command but the last writes to a buffer that the next reads.
+        let last = pipeline.commands.len() - 1;
+        let mut input = Vec::new();
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            let mut output = Vec::new();
+            {
+                let mut stdin = io::Cursor::new(&input[..]);
+                let stdout: &mut io::Write = match file {
+                    _ if i < last => &mut output,
+                    Some(ref mut file) => file,
+                    None => &mut *out
+                };
+
+                let stdio = &mut Stdio { stdin: &mut stdin, stdout, stderr: &mut *err };
+                self.status = self.execute(command, stdio);
+            }
+
+            input = output;
+        }
+
+        if let Some(mut file) = file {
+            if let Err(e) = file.sync() {
+                let _ = writeln!(err, "{}: {}", pipeline.redirect.unwrap().path, e);
+                self.status = FAILURE;
+            }
+        }
+
+        self.status
+    }
+
+    /// Runs each line of `script` as with `run()` and returns the exit status
+    /// of the last, or `0` if there is none. A failing line does not stop the
+    /// script. Scripts may run scripts, up to `MAX_SCRIPT_DEPTH` deep; past
+    /// that, `FAILURE` is returned without running `script`.
+    pub fn run_script(&mut self, script: &str, out: &mut io::Write,
+                      err: &mut io::Write) -> ExitStatus {
+        if self.depth >= MAX_SCRIPT_DEPTH {
+            let _ = writeln!(err, "error: scripts nested more than {} deep", MAX_SCRIPT_DEPTH);
+            self.status = FAILURE;
+            return self.status;
+        }
+
+        self.depth += 1;
+        let mut status = 0;
+        for line in script.lines() {
+            status = self.run(line, out, err);
+        }
+
+        self.depth -= 1;
+        status
+    }
+
+    /// Runs `command` with the streams `stdio` and returns its exit status.
+    fn execute(&mut self, command: &Command, stdio: &mut Stdio) -> ExitStatus {
+        match COMMANDS.find(command.path()) {
+            None => {
+                let _ = writeln!(stdio.stderr, "{}: command not found", command.path());
+                NOT_FOUND
+            }
+            Some(descriptor) => match (descriptor.handler)(command, self, stdio) {
+                Ok(()) => 0,
+                Err(CommandError::Usage) => {
+                    let _ = writeln!(stdio.stderr, "usage: {}", descriptor.usage);
+                    USAGE
+                }
+                Err(CommandError::Io(e)) => {
+                    let _ = writeln!(stdio.stderr, "{}: {}", descriptor.name, e);
+                    FAILURE
+                }
+                Err(CommandError::Status(status)) => status
+            }
+        }
+    }
+}
+
+/// Opens the file at `path` for writing, creating it if it does not exist.
+/// The file is emptied unless `append` is `true`, in which case writes go to
+/// its end. What is written reaches the SD card when the file is synced,
+/// which returns once the card has taken every sector.
+///
+/// The file system must be writable: if it is read-only, an error of kind
+/// `PermissionDenied` is returned before anything is opened, so that a
+/// redirected command doesn't run only to lose its output.
+fn open_output(path: &Path, append: bool) -> io::Result<volume::File> {
+    if FILE_SYSTEM.is_read_only() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                  "the file system is read-only"));
+    }
+
+    let mut file = match (&FILE_SYSTEM).open_file(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            return (&FILE_SYSTEM).create_file(path);
+        }
+        Err(e) => return Err(e)
+    };
+
+    if append {
+        file.seek(SeekFrom::End(0))?;
+    } else {
+        file.set_len(0)?;
+    }
+
+    Ok(file)
+}
+
+/// Starts a shell using `prefix` as the prefix for each line. This function
+/// never returns: it is perpetually in a shell loop. After a command fails,
+/// the prefix is preceded by its exit status. If `/boot/init.sh` exists, it
+/// is sourced first.
+pub fn shell(prefix: &str) -> ! {
+    kprintln!("{}", WELCOME);
+    let mut shell = Shell::new();
+    if (&FILE_SYSTEM).open_file(INIT_SCRIPT).is_ok() {
+        shell.run(&format!("source {}", INIT_SCRIPT), &mut ConsoleWriter, &mut ConsoleWriter);
+    }
+
+    let mut editor = LineEditor::new(MAX_LINE, HISTORY);
+    loop {
+        let prompt = match shell.status() {
+            0 => prefix.to_string(),
+            status => format!("[{}] {}", status, prefix)
+        };
+
+        let line = {
+            let completer = ShellCompleter { cwd: shell.cwd() };
+            editor.read_line(&mut *CONSOLE.lock(), &prompt, &completer)
+        };
+
+        shell.run(&line, &mut ConsoleWriter, &mut ConsoleWriter);
+    }
+}
+
+/// Returns the absolute path of `path` relative to `cwd`, with `.` and `..`
+/// components resolved. `..` at the root stays at the root.
+fn resolve<P: AsRef<Path>>(cwd: &Path, path: P) -> PathBuf {
+    let mut resolved = PathBuf::from("/");
+    for component in cwd.join(path).components() {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::ParentDir => { resolved.pop(); }
+            _ => {}
+        }
+    }
+
+    resolved
+}
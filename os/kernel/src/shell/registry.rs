@@ -0,0 +1,106 @@
+use std::io;
+
+use mutex::Mutex;
+use shell::{Command, Shell};
+
+/// The status a command exits with. `0` means success.
+pub type ExitStatus = u8;
+
+/// The status of a command that ran but failed.
+pub const FAILURE: ExitStatus = 1;
+/// The status of a command that was called with invalid arguments.
+pub const USAGE: ExitStatus = 2;
+/// The status reported for a command that does not exist.
+pub const NOT_FOUND: ExitStatus = 127;
+
+/// The ways a command can fail.
+#[derive(Debug)]
+pub enum CommandError {
+    /// The arguments were invalid. The shell prints the command's usage.
+    Usage,
+    /// The command failed with an I/O error. The shell prints the error.
+    Io(io::Error),
+    /// The command failed with this status, having reported why itself.
+    Status(ExitStatus),
+}
+
+impl From<io::Error> for CommandError {
+    fn from(error: io::Error) -> CommandError {
+        CommandError::Io(error)
+    }
+}
+
+/// The streams a command reads its input from and writes its output to.
+pub struct Stdio<'a> {
+    /// The output of the previous command in the pipeline, or nothing.
+    pub stdin: &'a mut io::Read,
+    /// The console, the next command in the pipeline, or a file.
+    pub stdout: &'a mut io::Write,
+    /// Where errors are reported: the console, whatever the output is.
+    pub stderr: &'a mut io::Write,
+}
+
+/// A function that runs a command in a shell.
+pub type Handler = fn(&Command, &mut Shell, &mut Stdio) -> Result<(), CommandError>;
+
+/// Describes a shell command.
+#[derive(Copy, Clone)]
+pub struct Descriptor {
+    /// The name the command is invoked by.
+    pub name: &'static str,
+    /// The command's synopsis, such as `ls [-a] [path]`.
+    pub usage: &'static str,
+    /// A one-line description of what the command does.
+    pub help: &'static str,
+    /// The function that runs the command.
+    pub handler: Handler,
+}
+
+/// A set of shell commands: a fixed set of built-in commands and the commands
+/// registered at run time, which take precedence.
+pub struct Registry {
+    builtins: &'static [Descriptor],
+    registered: Mutex<Option<Vec<Descriptor>>>,
+}
+
+impl Registry {
+    /// Returns a registry holding only `builtins`.
+    pub const fn new(builtins: &'static [Descriptor]) -> Registry {
+        Registry { builtins, registered: Mutex::new(None) }
+    }
+
+    /// Adds `command` to the registry, replacing any registered command with
+    /// the same name.
+    pub fn register(&self, command: Descriptor) {
+        let mut registered = self.registered.lock();
+        let commands = registered.get_or_insert_with(Vec::new);
+        commands.retain(|c| c.name != command.name);
+        commands.push(command);
+    }
+
+    /// Returns the command named `name`, if there is one.
+    pub fn find(&self, name: &str) -> Option<Descriptor> {
+        let registered = self.registered.lock();
+        registered.iter().flat_map(|commands| commands.iter())
+            .chain(self.builtins.iter())
+            .find(|c| c.name == name)
+            .cloned()
+    }
+
+    /// Returns every command, sorted by name.
+    pub fn all(&self) -> Vec<Descriptor> {
+        let mut all: Vec<Descriptor> = self.registered.lock().iter()
+            .flat_map(|commands| commands.iter())
+            .cloned()
+            .collect();
+
+        for builtin in self.builtins {
+            if !all.iter().any(|c| c.name == builtin.name) {
+                all.push(*builtin);
+            }
+        }
+
+        all.sort_by_key(|c| c.name);
+        all
+    }
+}
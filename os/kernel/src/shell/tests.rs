@@ -0,0 +1,464 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use shell::{Completer, Decoder, History, Key, LineEditor, Terminal};
+use shell::{Command, CommandError, Descriptor, Error, Registry, Shell, Stdio};
+use shell::{Pipeline, Redirect, COMMANDS, FAILURE, NOT_FOUND, USAGE};
+
+/// A console that replays scripted input and records everything written.
+struct FakeConsole {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl FakeConsole {
+    fn new(input: &[u8]) -> FakeConsole {
+        FakeConsole { input: input.iter().cloned().collect(), output: Vec::new() }
+    }
+
+    fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+impl Terminal for FakeConsole {
+    fn read_byte(&mut self) -> u8 {
+        self.input.pop_front().expect("line editor read past the scripted input")
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+    }
+}
+
+/// Completes from a fixed list of commands and paths.
+struct FakeCompleter;
+
+impl Completer for FakeCompleter {
+    fn complete(&self, word: &str, first: bool) -> Vec<String> {
+        let words: &[&str] = match first {
+            true => &["cat", "cd", "echo"],
+            false => &["boot/", "boot/config.txt", "boot/kernel.bin", "data/"],
+        };
+
+        // Like a directory listing, only offer one more path component.
+        words.iter()
+            .filter(|w| w.starts_with(word))
+            .filter(|w| match w[word.len()..].find('/') {
+                Some(i) => i == w.len() - word.len() - 1,
+                None => true
+            })
+            .map(|w| w.to_string())
+            .collect()
+    }
+}
+
+const LEFT: &[u8] = b"\x1b[D";
+const RIGHT: &[u8] = b"\x1b[C";
+const UP: &[u8] = b"\x1b[A";
+const DOWN: &[u8] = b"\x1b[B";
+
+/// Feeds the concatenation of `keys` to `editor` and returns the line read.
+fn edit(editor: &mut LineEditor, keys: &[&[u8]]) -> (String, FakeConsole) {
+    let mut console = FakeConsole::new(&keys.concat());
+    let line = editor.read_line(&mut console, "> ", &FakeCompleter);
+    assert!(console.input.is_empty(), "unread input: {:?}", console.input);
+    (line, console)
+}
+
+fn read(keys: &[&[u8]]) -> String {
+    edit(&mut LineEditor::new(64, 8), keys).0
+}
+
+#[test]
+fn test_decoder() {
+    let mut decoder = Decoder::new();
+    let mut decode = |bytes: &[u8]| -> Vec<Key> {
+        bytes.iter().filter_map(|&b| decoder.feed(b)).collect()
+    };
+
+    assert_eq!(decode(b"a\x1b[A\x1b[B\x1b[C\x1b[D"),
+               vec![Key::Char(b'a'), Key::Up, Key::Down, Key::Right, Key::Left]);
+    assert_eq!(decode(b"\x1b[H\x1b[F\x1bOH\x1bOF\x1b[1~\x1b[4~\x1b[7~\x1b[8~"),
+               vec![Key::Home, Key::End, Key::Home, Key::End,
+                    Key::Home, Key::End, Key::Home, Key::End]);
+    assert_eq!(decode(b"\x1b[3~\x08\x7f\t\x01\x05\x15\x17"),
+               vec![Key::Delete, Key::Backspace, Key::Backspace, Key::Tab,
+                    Key::Home, Key::End, Key::KillLine, Key::KillWord]);
+    assert_eq!(decode(b"\x1b[1;5C\x1b[15~\x1bx\x02"),
+               vec![Key::Right, Key::Unknown, Key::Unknown, Key::Unknown]);
+    assert_eq!(decode(b"\r\n\n\r"), vec![Key::Enter, Key::Enter, Key::Enter]);
+}
+
+#[test]
+fn test_typing() {
+    let mut editor = LineEditor::new(64, 8);
+    let (line, console) = edit(&mut editor, &[b"ls -a\r"]);
+    assert_eq!(line, "ls -a");
+    assert_eq!(console.output(), "> ls -a\r\n");
+
+    let (line, console) = edit(&mut editor, &[b"cax\x7ft\r"]);
+    assert_eq!(line, "cat");
+    assert_eq!(console.output(), "> cax\x08 \x08t\r\n");
+
+    // Backspace on an empty line rings the bell, as does a full line.
+    let (line, console) = edit(&mut LineEditor::new(3, 0), &[b"\x08abcd\n"]);
+    assert_eq!(line, "abc");
+    assert_eq!(console.output(), "> \x07abc\x07\r\n");
+}
+
+#[test]
+fn test_cursor_movement() {
+    assert_eq!(read(&[b"helo", LEFT, b"l\r"]), "hello");
+    assert_eq!(read(&[b"world", b"\x01", b"hello ", b"\x05", b"!\r"]), "hello world!");
+    assert_eq!(read(&[b"ab", b"\x1b[H", RIGHT, RIGHT, RIGHT, b"c\r"]), "abc");
+    assert_eq!(read(&[b"xabc", b"\x1bOH", b"\x1b[3~", b"\x1b[4~", b"d\r"]), "abcd");
+    assert_eq!(read(&[b"ab", LEFT, LEFT, LEFT, b"\x1b[3~\x1b[3~\x1b[3~\r"]), "");
+
+    // Inserting in the middle redraws the rest of the line.
+    let (_, console) = edit(&mut LineEditor::new(64, 8), &[b"ac", LEFT, b"b\r"]);
+    assert_eq!(console.output(), "> ac\x1b[D\r> abc\x1b[K\x1b[1D\r\n");
+}
+
+#[test]
+fn test_kill() {
+    assert_eq!(read(&[b"cat /a/b", b"\x15", b"ls\r"]), "ls");
+    assert_eq!(read(&[b"cat /a/b", LEFT, LEFT, b"\x15\r"]), "/b");
+    assert_eq!(read(&[b"cat /a/b  ", b"\x17", b"x\r"]), "cat x");
+    assert_eq!(read(&[b"one two", b"\x01", b"\x17", b"\x17\r"]), "one two");
+}
+
+#[test]
+fn test_history() {
+    let mut editor = LineEditor::new(64, 2);
+    edit(&mut editor, &[b"first\r"]);
+    edit(&mut editor, &[b"second\r"]);
+    edit(&mut editor, &[b"\r"]);
+    edit(&mut editor, &[b"second\r"]);
+    assert_eq!(editor.history().len(), 2);
+
+    assert_eq!(edit(&mut editor, &[UP, b"\r"]).0, "second");
+    assert_eq!(edit(&mut editor, &[UP, UP, b"!\r"]).0, "first!");
+    assert_eq!(edit(&mut editor, &[b"new", UP, DOWN, b"er\r"]).0, "newer");
+
+    // Only the two newest lines are kept.
+    let (line, console) = edit(&mut editor, &[UP, UP, UP, b"\r"]);
+    assert_eq!(line, "first!");
+    assert!(console.output().contains("\x07"));
+
+    let (line, console) = edit(&mut editor, &[DOWN, b"\r"]);
+    assert_eq!(line, "");
+    assert_eq!(console.output(), "> \x07\r\n");
+}
+
+#[test]
+fn test_history_ring() {
+    let mut history = History::new(3);
+    for line in &["a", "b", "b", " ", "c", "d"] {
+        history.push(line);
+    }
+
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.get(0), Some("d"));
+    assert_eq!(history.get(2), Some("b"));
+    assert_eq!(history.get(3), None);
+}
+
+#[test]
+fn test_tab_completion() {
+    assert_eq!(read(&[b"ec\t", b"hi\r"]), "echo hi");
+    assert_eq!(read(&[b"cat bo\t", b"k\t\r"]), "cat boot/kernel.bin ");
+    assert_eq!(read(&[b"cat boot/c\t\r"]), "cat boot/config.txt ");
+    assert_eq!(read(&[b"xyz\t\r"]), "xyz");
+
+    // An ambiguous word is completed as far as possible, then listed.
+    let (line, console) = edit(&mut LineEditor::new(64, 8), &[b"c\t", b"\t", b"d\t\r"]);
+    assert_eq!(line, "cd ");
+    assert!(console.output().contains("\r\ncat  cd  \r\n"));
+}
+
+fn args(line: &str) -> Vec<String> {
+    let command = Command::parse(line).expect("command parses");
+    let mut args = vec![command.path().to_string()];
+    args.extend(command.args().iter().cloned());
+    args
+}
+
+#[test]
+fn test_parse_plain() {
+    assert_eq!(args("ls"), vec!["ls"]);
+    assert_eq!(args("  ls \t -a   /boot  "), vec!["ls", "-a", "/boot"]);
+    assert_eq!(Command::parse(""), Err(Error::Empty));
+    assert_eq!(Command::parse("   \t "), Err(Error::Empty));
+
+    let many = vec!["a"; 64].join(" ");
+    assert_eq!(args(&many).len(), 64);
+    assert_eq!(Command::parse(&(many.clone() + " b")), Err(Error::TooManyArgs));
+    assert_eq!(Command::parse(&(many + " b ")), Err(Error::TooManyArgs));
+}
+
+#[test]
+fn test_parse_quotes() {
+    assert_eq!(args(r#"echo "hello world" 'a  b'"#), vec!["echo", "hello world", "a  b"]);
+    assert_eq!(args(r#"echo "" '' x"#), vec!["echo", "", "", "x"]);
+    assert_eq!(args(r#"cat my" "file'.txt'"#), vec!["cat", "my file.txt"]);
+    assert_eq!(args(r#"echo 'say "hi"' "it's""#), vec!["echo", "say \"hi\"", "it's"]);
+    assert_eq!(Command::parse(r#"echo "open"#), Err(Error::UnterminatedQuote));
+    assert_eq!(Command::parse("echo 'open"), Err(Error::UnterminatedQuote));
+}
+
+#[test]
+fn test_parse_escapes() {
+    assert_eq!(args(r"cat my\ file.txt"), vec!["cat", "my file.txt"]);
+    assert_eq!(args(r#"echo a\nb "c\td" 'e\nf' \\ \" \'"#),
+               vec!["echo", "a\nb", "c\td", "e\\nf", "\\", "\"", "'"]);
+    assert_eq!(args(r#"echo "\"quoted\"""#), vec!["echo", "\"quoted\""]);
+    assert_eq!(Command::parse(r"echo \"), Err(Error::TrailingEscape));
+    assert_eq!(Command::parse(r#"echo "\"#), Err(Error::UnterminatedQuote));
+}
+
+fn succeed(_cmd: &Command, _shell: &mut Shell, _stdio: &mut Stdio) -> Result<(), CommandError> {
+    Ok(())
+}
+
+fn fail(_cmd: &Command, _shell: &mut Shell, _stdio: &mut Stdio) -> Result<(), CommandError> {
+    Err(CommandError::Usage)
+}
+
+static TEST_BUILTINS: &[Descriptor] = &[
+    Descriptor { name: "b", usage: "b", help: "builtin b", handler: succeed },
+    Descriptor { name: "a", usage: "a", help: "builtin a", handler: succeed },
+];
+
+#[test]
+fn test_registry() {
+    let registry = Registry::new(TEST_BUILTINS);
+    assert_eq!(registry.find("a").map(|c| c.help), Some("builtin a"));
+    assert!(registry.find("c").is_none());
+
+    registry.register(Descriptor { name: "c", usage: "c", help: "added c", handler: succeed });
+    registry.register(Descriptor { name: "a", usage: "a", help: "added a", handler: fail });
+    registry.register(Descriptor { name: "a", usage: "a", help: "replaced a", handler: fail });
+    assert_eq!(registry.find("c").map(|c| c.help), Some("added c"));
+
+    let names: Vec<_> = registry.all().iter().map(|c| (c.name, c.help)).collect();
+    assert_eq!(names, vec![("a", "replaced a"), ("b", "builtin b"), ("c", "added c")]);
+
+    let command = Command::parse("a").unwrap();
+    let handler = registry.find("a").unwrap().handler;
+    let mut stdio = Stdio {
+        stdin: &mut ::std::io::empty(),
+        stdout: &mut Vec::new(),
+        stderr: &mut Vec::new()
+    };
+    expect_usage(handler(&command, &mut Shell::new(), &mut stdio));
+}
+
+fn expect_usage(result: Result<(), CommandError>) {
+    match result {
+        Err(CommandError::Usage) => {}
+        other => panic!("expected a usage error, found {:?}", other)
+    }
+}
+
+#[test]
+fn test_shell_resolve() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.resolve("boot"), Path::new("/boot"));
+    shell.cwd = PathBuf::from("/boot/old");
+    assert_eq!(shell.resolve("../x/./y"), Path::new("/boot/x/y"));
+    assert_eq!(shell.resolve("../../../.."), Path::new("/"));
+    assert_eq!(shell.resolve("/data/"), Path::new("/data"));
+}
+
+fn pipeline(line: &str) -> Vec<Vec<String>> {
+    Pipeline::parse(line, 0).expect("pipeline parses").commands.iter()
+        .map(|command| {
+            let mut args = vec![command.path().to_string()];
+            args.extend(command.args().iter().cloned());
+            args
+        })
+        .collect()
+}
+
+fn redirect(line: &str) -> Option<Redirect> {
+    Pipeline::parse(line, 0).expect("pipeline parses").redirect
+}
+
+#[test]
+fn test_parse_pipeline() {
+    assert_eq!(pipeline("ls"), vec![vec!["ls"]]);
+    assert_eq!(pipeline("cat a|grep x | wc"), vec![vec!["cat", "a"], vec!["grep", "x"], vec!["wc"]]);
+    assert_eq!(pipeline("echo 'a|b' \\| \"c > d\""), vec![vec!["echo", "a|b", "|", "c > d"]]);
+    assert_eq!(Pipeline::parse("", 0), Err(Error::Empty));
+    assert_eq!(Pipeline::parse("| ls", 0), Err(Error::MissingCommand));
+    assert_eq!(Pipeline::parse("ls |", 0), Err(Error::MissingCommand));
+    assert_eq!(Pipeline::parse("ls || cat", 0), Err(Error::MissingCommand));
+
+    // Operators are only special in pipelines.
+    assert_eq!(Command::parse("ls | cat"), Err(Error::UnexpectedOperator));
+    assert_eq!(Command::parse("ls>out"), Err(Error::UnexpectedOperator));
+}
+
+#[test]
+fn test_parse_redirect() {
+    assert_eq!(redirect("ls"), None);
+    assert_eq!(redirect("ls > /out"), Some(Redirect { path: "/out".to_string(), append: false }));
+    assert_eq!(redirect("ls>>'my log'"), Some(Redirect { path: "my log".to_string(), append: true }));
+    assert_eq!(pipeline("ls -a | cat >> log"), vec![vec!["ls", "-a"], vec!["cat"]]);
+    assert_eq!(Pipeline::parse("ls >", 0), Err(Error::MissingPath));
+    assert_eq!(Pipeline::parse("ls > a b", 0), Err(Error::UnexpectedOperator));
+    assert_eq!(Pipeline::parse("ls > a | cat", 0), Err(Error::UnexpectedOperator));
+    assert_eq!(Pipeline::parse("ls > > a", 0), Err(Error::UnexpectedOperator));
+    assert_eq!(Pipeline::parse("> a", 0), Err(Error::MissingCommand));
+}
+
+#[test]
+fn test_parse_comments_and_status() {
+    assert_eq!(Pipeline::parse("# a comment", 0), Err(Error::Empty));
+    assert_eq!(Command::parse("   # a comment"), Err(Error::Empty));
+    assert_eq!(args("echo a#b '#c' # d"), vec!["echo", "a#b", "#c"]);
+    assert_eq!(args("echo $? $"), vec!["echo", "$?", "$"]);
+
+    let status = |line: &str| Pipeline::parse(line, 127).unwrap().commands[0].args().to_vec();
+    assert_eq!(status("echo $?"), vec!["127"]);
+    assert_eq!(status("echo x$?y \"($?)\""), vec!["x127y", "(127)"]);
+    assert_eq!(status("echo '$?' \\$? $"), vec!["$?", "$?", "$"]);
+}
+
+fn upper(_cmd: &Command, _shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    let mut input = String::new();
+    stdio.stdin.read_to_string(&mut input)?;
+    stdio.stdout.write_all(input.to_uppercase().as_bytes())?;
+    Ok(())
+}
+
+#[test]
+fn test_shell_run() {
+    COMMANDS.register(Descriptor { name: "upper", usage: "upper", help: "", handler: upper });
+
+    let mut errors = Vec::new();
+    let mut shell = Shell::new();
+    let mut run = |line: &str| {
+        let mut out = Vec::new();
+        let status = shell.run(line, &mut out, &mut errors);
+        (status, String::from_utf8(out).unwrap())
+    };
+
+    assert_eq!(run("echo hello  world"), (0, "hello world\n".to_string()));
+    assert_eq!(run("echo hello | upper"), (0, "HELLO\n".to_string()));
+    assert_eq!(run("echo a | cat | upper | cat"), (0, "A\n".to_string()));
+    assert_eq!(run("upper"), (0, String::new()));
+
+    // The status of a pipeline is that of its last command.
+    assert_eq!(run("nonexistent | echo ok"), (0, "ok\n".to_string()));
+    assert_eq!(run("echo ok | nonexistent"), (NOT_FOUND, String::new()));
+    assert_eq!(run("# comments keep the status"), (NOT_FOUND, String::new()));
+    assert_eq!(run("echo $?"), (0, "127\n".to_string()));
+    assert_eq!(run("pwd extra"), (USAGE, String::new()));
+    assert_eq!(run("echo $? | upper"), (0, "2\n".to_string()));
+    assert_eq!(run("echo 'unterminated"), (USAGE, String::new()));
+
+    // Diagnostics go to the error stream, never to the output.
+    assert_eq!(String::from_utf8(errors).unwrap(),
+               "nonexistent: command not found\n\
+                nonexistent: command not found\n\
+                usage: pwd\n\
+                error: unterminated quote\n");
+}
+
+/// Runs a script that runs itself, forever but for the nesting limit.
+fn recurse(_cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    match shell.run_script("echo in\nrecurse", stdio.stdout, stdio.stderr) {
+        0 => Ok(()),
+        status => Err(CommandError::Status(status))
+    }
+}
+
+#[test]
+fn test_shell_run_script() {
+    let mut shell = Shell::new();
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+
+    // The status of a script is that of its last line, comments aside.
+    assert_eq!(shell.run_script("echo a\nnonexistent\n# done\n", &mut out, &mut err), NOT_FOUND);
+    assert_eq!(shell.run_script("nonexistent\necho b", &mut out, &mut err), 0);
+    assert_eq!(shell.run_script("", &mut out, &mut err), 0);
+    assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+
+    // A script that runs itself is stopped once nested too deeply.
+    COMMANDS.register(Descriptor { name: "recurse", usage: "recurse", help: "", handler: recurse });
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    assert_eq!(shell.run("recurse", &mut out, &mut err), FAILURE);
+    assert_eq!(String::from_utf8(out).unwrap(), "in\n".repeat(8));
+    assert_eq!(String::from_utf8(err).unwrap(), "error: scripts nested more than 8 deep\n");
+    assert_eq!(shell.run_script("echo again", &mut Vec::new(), &mut Vec::new()), 0);
+}
+
+#[test]
+fn test_meminfo_output() {
+    use alloc::heap::Layout;
+    use allocator::{Leaks, Stats};
+    use shell::meminfo::{write_leaks, write_stats};
+
+    let mut stats = Stats { total: 4096, in_use: 1024, peak: 2048, allocs: 3, deallocs: 1,
+                            largest_free: 2048, ..Stats::default() };
+    stats.free_blocks[10] = 1;
+    stats.free_blocks[11] = 1;
+    stats.caches[0].object_size = 16;
+    stats.caches[0].allocs = 2;
+    stats.caches[1].object_size = 32;
+
+    let mut out = Vec::new();
+    write_stats(&stats, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "heap:    1024 of 4096 bytes in use, peak 2048");
+    assert_eq!(lines[1], "         largest free block 2048 bytes");
+    assert_eq!(lines[2], "allocs:  3 (1 freed)");
+    assert_eq!(lines[3], "free:    1x1024 1x2048");
+    assert!(lines[4].starts_with("caches:"));
+
+    // Only caches that have been used are listed.
+    assert_eq!(lines.len(), 6);
+    assert!(lines[5].trim_left().starts_with("16 "));
+
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let mut leaks = Leaks::new();
+    leaks.record(0x1000 as *mut u8, &layout, [0x80000, 0, 0, 0]);
+    assert!(leaks.records().is_empty());
+
+    leaks.enable();
+    leaks.record(0x1000 as *mut u8, &layout, [0x80000, 0, 0, 0]);
+    leaks.record(0x2000 as *mut u8, &layout, [0x80004, 0x80100, 0x80200, 0x80300]);
+    leaks.record(0x3000 as *mut u8, &layout, [0x80008, 0x80100, 0, 0]);
+    leaks.forget(0x1000 as *mut u8);
+    leaks.forget(0x4000 as *mut u8);
+
+    let mut out = Vec::new();
+    write_leaks(&leaks, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "2 live allocations recorded, tracking on\n\
+               \x20 #1     0x00002000       24 bytes, align 8     from 0x00080004 0x00080100 \
+               0x00080200 0x00080300\n\
+               \x20 #2     0x00003000       24 bytes, align 8     from 0x00080008 0x00080100\n");
+}
+
+#[test]
+fn test_fsinfo_output() {
+    use fat32::vfat::CacheStats;
+    use shell::builtins::write_cache_stats;
+
+    let stats = CacheStats { hits: 30, misses: 10, evictions: 4, writebacks: 2, read_aheads: 8 };
+    let mut out = Vec::new();
+    write_cache_stats(&stats, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(),
+               "cache:   30 hits, 10 misses (75% hit rate)\n\
+               \x20        4 evictions, 2 write-backs, 8 sectors read ahead\n");
+
+    // An unused cache has no hit rate to speak of.
+    let mut out = Vec::new();
+    write_cache_stats(&CacheStats::default(), &mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with("cache:   0 hits, 0 misses (0% hit rate)"));
+}
@@ -0,0 +1,136 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use console::{Console, CONSOLE};
+use fs::traits::{File, FileSystem};
+use shell::{Command, CommandError, Shell, Stdio};
+use xmodem::{Progress, ReadTimeout, Xmodem, XmodemConfig};
+use FILE_SYSTEM;
+
+use super::open_output;
+
+/// How long to wait for each byte from the other end, in milliseconds.
+const TIMEOUT_MS: u32 = 1000;
+/// How many `CAN` bytes cancel a transfer. Terminal programs tend to ignore a
+/// lone `CAN` as line noise.
+const CANCEL_LEN: usize = 2;
+/// How many times a transfer is restarted while the other end has not yet
+/// responded, waiting `TIMEOUT_MS` each time.
+const ATTEMPTS: usize = 60;
+
+/// The number of packets sent or received by the current transfer.
+static PACKETS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the packets of the current transfer. The console carries the
+/// transfer itself, so progress can only be reported once it has ended.
+fn count(progress: Progress) {
+    if let Progress::Packet(_) = progress {
+        PACKETS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Runs the XMODEM transfer `f` over the console, with the console locked so
+/// that nothing else writes to it in the meantime. The transfer is started
+/// again if it times out before the first packet, giving the user time to
+/// start the other end. Reads from the console block again afterwards.
+///
+/// On success, returns the number of bytes transferred and the number of
+/// packets it took.
+///
+/// # Errors
+///
+/// Returns the error of the transfer, with cancellations and timeouts
+/// described for the user.
+fn transfer<F>(mut f: F) -> io::Result<(usize, usize)>
+    where F: FnMut(&mut Xmodem<&mut Console>) -> io::Result<usize>
+{
+    PACKETS.store(0, Ordering::Relaxed);
+    let mut console = CONSOLE.lock();
+    let config = XmodemConfig::new()
+        .start_timeout(Some(TIMEOUT_MS))
+        .packet_timeout(Some(TIMEOUT_MS))
+        .byte_timeout(Some(TIMEOUT_MS))
+        .cancel_len(CANCEL_LEN);
+
+    let mut result = Err(io::Error::new(io::ErrorKind::TimedOut,
+                                        "the other end never started the transfer"));
+    for _ in 0..ATTEMPTS {
+        let mut xmodem = Xmodem::new_with_progress(&mut *console, count);
+        xmodem.set_config(config);
+        xmodem.enable_timeouts();
+        result = match f(&mut xmodem) {
+            Ok(bytes) => Ok((bytes, PACKETS.load(Ordering::Relaxed))),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut
+                && PACKETS.load(Ordering::Relaxed) == 0 => continue,
+            Err(e) => Err(match e.kind() {
+                io::ErrorKind::ConnectionAborted => {
+                    io::Error::new(e.kind(), "transfer cancelled by the other end")
+                }
+                io::ErrorKind::TimedOut => {
+                    io::Error::new(e.kind(), "transfer timed out")
+                }
+                _ => e
+            })
+        };
+
+        break;
+    }
+
+    console.set_read_timeout(None)?;
+    result
+}
+
+/// Receives a file over XMODEM on the console and writes it to the path at
+/// the single argument, replacing any file there. The data is received into
+/// memory first: the UART cannot hold the next packet while the SD card is
+/// written, and a failed transfer leaves the file system untouched. As with
+/// any XMODEM transfer, the file is padded to a multiple of 128 bytes.
+///
+/// Fails before the transfer starts if the file system is read-only, as an
+/// exFAT volume is.
+pub fn rx(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if cmd.args().len() != 1 {
+        return Err(CommandError::Usage);
+    }
+
+    if FILE_SYSTEM.is_read_only() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                  "the file system is read-only").into());
+    }
+
+    let path = shell.resolve(&cmd.args()[0]);
+    writeln!(stdio.stdout, "rx: waiting for the sender of {}...", path.display())?;
+    stdio.stdout.flush()?;
+
+    let mut data = Vec::new();
+    let (bytes, packets) = transfer(|xmodem| {
+        data.clear();
+        xmodem.receive_into(&mut data)
+    })?;
+
+    let mut file = open_output(&path, false)?;
+    file.write_all(&data)?;
+    file.sync()?;
+
+    writeln!(stdio.stdout, "rx: received {} bytes in {} packets", bytes, packets)?;
+    Ok(())
+}
+
+/// Sends the file at the single argument over XMODEM on the console.
+pub fn tx(cmd: &Command, shell: &mut Shell, stdio: &mut Stdio) -> Result<(), CommandError> {
+    if cmd.args().len() != 1 {
+        return Err(CommandError::Usage);
+    }
+
+    let path = shell.resolve(&cmd.args()[0]);
+    let mut data = Vec::new();
+    (&FILE_SYSTEM).open_file(&path)?.read_to_end(&mut data)?;
+
+    writeln!(stdio.stdout, "tx: waiting for the receiver of {}...", path.display())?;
+    stdio.stdout.flush()?;
+
+    let (bytes, packets) = transfer(|xmodem| xmodem.transmit_from(&data[..]))?;
+
+    writeln!(stdio.stdout, "tx: sent {} bytes in {} packets", bytes, packets)?;
+    Ok(())
+}
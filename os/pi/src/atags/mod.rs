@@ -18,6 +18,20 @@ impl Atags {
             ptr: unsafe { &*(ATAG_BASE as *const raw::Atag) }
         }
     }
+
+    /// Returns the range of addresses `(start, end)` taken by the ATAGS from
+    /// this iterator's position on, up to and including the `NONE` ATAG that
+    /// ends them.
+    pub fn extent(&self) -> (usize, usize) {
+        let mut last = self.ptr;
+        while let Some(next) = last.next() {
+            last = next;
+        }
+
+        // The `NONE` ATAG is its two header words, whatever its size says.
+        let start = self.ptr as *const raw::Atag as usize;
+        (start, last as *const raw::Atag as usize + 8)
+    }
 }
 
 impl Iterator for Atags {
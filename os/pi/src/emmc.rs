@@ -0,0 +1,237 @@
+use core::fmt;
+
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile};
+
+use timer;
+use common::IO_BASE;
+
+/// The base address for the EMMC (SD host controller) registers.
+const EMMC_REG_BASE: usize = IO_BASE + 0x300000;
+
+/// The size of a block, in bytes.
+pub const BLOCK_SIZE: usize = 512;
+
+/// How long a command may take to complete, in microseconds.
+const CMD_TIMEOUT_US: u64 = 100_000;
+
+/// How long the card may take to accept or finish writing data, in
+/// microseconds.
+const DATA_TIMEOUT_US: u64 = 1_000_000;
+
+/// `CMDTM` values: the command index, the response type and whether a data
+/// transfer follows.
+const CMD_DESELECT: u32 = 0x07000000;
+const CMD_SELECT: u32 = 0x07030000;
+const CMD_SEND_REL_ADDR: u32 = 0x03020000;
+const CMD_SEND_CSD: u32 = 0x09010000;
+const CMD_STOP_TRANS: u32 = 0x0C030000;
+const CMD_WRITE_SINGLE: u32 = 0x18220000;
+const CMD_WRITE_MULTI: u32 = 0x19220022;
+
+/// The bits of an R1 response that report an error.
+const R1_ERRORS: u32 = 0xfff9c004;
+/// The bits of an R6 response (to `CMD_SEND_REL_ADDR`) that report an error.
+const R6_ERRORS: u32 = 0xe000;
+
+/// Enum representing bit fields of the `STATUS` register.
+#[repr(u32)]
+enum Status {
+    CmdInhibit = 1,
+    DatInhibit = 1 << 1,
+}
+
+/// Enum representing bit fields of the `INTERRUPT` register.
+#[repr(u32)]
+enum Interrupt {
+    CmdDone = 1,
+    DataDone = 1 << 1,
+    WriteReady = 1 << 4,
+    CmdTimeout = 1 << 16,
+    DataTimeout = 1 << 20,
+    Errors = 0x017E8000,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    ARG2: Volatile<u32>,
+    BLKSIZECNT: Volatile<u32>,
+    ARG1: Volatile<u32>,
+    CMDTM: Volatile<u32>,
+    RESP: [ReadVolatile<u32>; 4],
+    DATA: Volatile<u32>,
+    STATUS: ReadVolatile<u32>,
+    CONTROL0: Volatile<u32>,
+    CONTROL1: Volatile<u32>,
+    INTERRUPT: Volatile<u32>,
+    IRPT_MASK: Volatile<u32>,
+    IRPT_EN: Volatile<u32>,
+    CONTROL2: Volatile<u32>,
+}
+
+/// An error from the EMMC controller or the card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The controller or the card did not respond in time.
+    Timeout,
+    /// The controller or the card reported an error.
+    Command,
+    /// The buffer is not a whole number of blocks, or the blocks are out of
+    /// the card's address range.
+    InvalidArgument,
+}
+
+/// Writes to an SD card through the EMMC controller.
+///
+/// The controller and the card must already be initialized, as `libsd`'s
+/// `sd_init` does, and the card selected; the card stays selected.
+pub struct Emmc {
+    registers: &'static mut Registers,
+    high_capacity: bool,
+}
+
+impl Emmc {
+    /// Takes over the initialized EMMC controller. Asks the card for its CSD
+    /// to learn whether it is addressed by block (SDHC and SDXC) or by byte
+    /// (SDSC), which means deselecting it and selecting it again under a new
+    /// relative address.
+    pub fn new() -> Result<Emmc, Error> {
+        let mut emmc = Emmc {
+            registers: unsafe { &mut *(EMMC_REG_BASE as *mut Registers) },
+            high_capacity: false,
+        };
+
+        // The CSD can only be read while the card is in standby.
+        emmc.command(CMD_DESELECT, 0)?;
+        let rca = emmc.command(CMD_SEND_REL_ADDR, 0)?;
+        if rca & R6_ERRORS != 0 {
+            return Err(Error::Command);
+        }
+
+        let rca = rca & 0xffff0000;
+        emmc.command(CMD_SEND_CSD, rca)?;
+
+        // `CSD_STRUCTURE`, the CSD's top two bits, is 0 only for SDSC cards.
+        let csd_structure = (emmc.registers.RESP[3].read() >> 22) & 0b11;
+        emmc.high_capacity = csd_structure != 0;
+
+        emmc.command_r1(CMD_SELECT, rca)?;
+        Ok(emmc)
+    }
+
+    /// Writes `buf`, a whole number of blocks, to the card starting at block
+    /// `block`. A single block is written with `WRITE_BLOCK` (CMD24), more
+    /// with `WRITE_MULTIPLE_BLOCK` (CMD25) followed by `STOP_TRANSMISSION`.
+    ///
+    /// Returns once the card has taken all of the data.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidArgument` if `buf` is empty, not a multiple of
+    /// `BLOCK_SIZE` bytes long or more than 65535 blocks long, or if the
+    /// blocks can't be addressed. Returns `Timeout` if the controller or the
+    /// card does not respond in time, and `Command` if either reports an
+    /// error.
+    pub fn write_blocks(&mut self, block: u32, buf: &[u8]) -> Result<(), Error> {
+        let count = buf.len() / BLOCK_SIZE;
+        if buf.is_empty() || buf.len() % BLOCK_SIZE != 0 || count > 0xffff {
+            return Err(Error::InvalidArgument);
+        }
+
+        let address = if self.high_capacity {
+            block
+        } else {
+            block.checked_mul(BLOCK_SIZE as u32).ok_or(Error::InvalidArgument)?
+        };
+
+        // The card holds the data line busy while it programs earlier writes.
+        self.wait_status(Status::DatInhibit as u32, DATA_TIMEOUT_US)?;
+        self.registers.BLKSIZECNT.write(((count as u32) << 16) | BLOCK_SIZE as u32);
+        let cmd = if count == 1 { CMD_WRITE_SINGLE } else { CMD_WRITE_MULTI };
+        self.command_r1(cmd, address)?;
+
+        for chunk in buf.chunks(BLOCK_SIZE) {
+            self.wait_interrupt(Interrupt::WriteReady as u32, DATA_TIMEOUT_US)?;
+            for word in chunk.chunks(4) {
+                let word = word[0] as u32 | (word[1] as u32) << 8
+                    | (word[2] as u32) << 16 | (word[3] as u32) << 24;
+                self.registers.DATA.write(word);
+            }
+        }
+
+        self.wait_interrupt(Interrupt::DataDone as u32, DATA_TIMEOUT_US)?;
+        if count > 1 {
+            self.command_r1(CMD_STOP_TRANS, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends `cmd` with argument `arg` and returns the first word of the
+    /// card's response.
+    fn command(&mut self, cmd: u32, arg: u32) -> Result<u32, Error> {
+        self.wait_status(Status::CmdInhibit as u32, CMD_TIMEOUT_US)?;
+
+        let pending = self.registers.INTERRUPT.read();
+        self.registers.INTERRUPT.write(pending);
+        self.registers.ARG1.write(arg);
+        self.registers.CMDTM.write(cmd);
+
+        self.wait_interrupt(Interrupt::CmdDone as u32, CMD_TIMEOUT_US)?;
+        Ok(self.registers.RESP[0].read())
+    }
+
+    /// Sends `cmd`, whose response is R1, with argument `arg`, and fails if
+    /// the card reports an error in the response.
+    fn command_r1(&mut self, cmd: u32, arg: u32) -> Result<(), Error> {
+        match self.command(cmd, arg)? & R1_ERRORS {
+            0 => Ok(()),
+            _ => Err(Error::Command)
+        }
+    }
+
+    /// Spins until none of the bits in `mask` are set in `STATUS`.
+    fn wait_status(&self, mask: u32, timeout_us: u64) -> Result<(), Error> {
+        let deadline = timer::current_time() + timeout_us;
+        while self.registers.STATUS.read() & mask != 0 {
+            if timer::current_time() > deadline {
+                return Err(Error::Timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Spins until one of the bits in `mask` is set in `INTERRUPT`, then
+    /// clears them. Fails early if an error is raised instead.
+    fn wait_interrupt(&mut self, mask: u32, timeout_us: u64) -> Result<(), Error> {
+        let deadline = timer::current_time() + timeout_us;
+        let wanted = mask | Interrupt::Errors as u32;
+        let mut raised = self.registers.INTERRUPT.read();
+        while raised & wanted == 0 {
+            if timer::current_time() > deadline {
+                return Err(Error::Timeout);
+            }
+
+            raised = self.registers.INTERRUPT.read();
+        }
+
+        let timeouts = Interrupt::CmdTimeout as u32 | Interrupt::DataTimeout as u32;
+        if raised & Interrupt::Errors as u32 != 0 {
+            self.registers.INTERRUPT.write(raised);
+            return Err(if raised & timeouts != 0 { Error::Timeout } else { Error::Command });
+        }
+
+        self.registers.INTERRUPT.write(mask);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Emmc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Emmc")
+            .field("high_capacity", &self.high_capacity)
+            .finish()
+    }
+}
@@ -83,6 +83,11 @@ impl MiniUart {
       self.timeout = Some(milliseconds);
     }
 
+    /// Clears the read timeout, so that reads block until there is a byte.
+    pub fn clear_read_timeout(&mut self) {
+        self.timeout = None;
+    }
+
     /// Write the byte `byte`. This method blocks until there is space available
     /// in the output FIFO.
     pub fn write_byte(&mut self, byte: u8) {
@@ -113,20 +118,22 @@ impl MiniUart {
     /// returns `Ok(())`, a subsequent call to `read_byte` is guaranteed to
     /// return immediately.
     pub fn wait_for_byte(&self) -> Result<(), ()> {
-        match self.timeout {
-            Some(time_out) => {
-                let cur_time = timer::current_time() + ((time_out * 1000) as u64);
-                while timer::current_time() <= cur_time {
-                    if self.has_byte() {
-                        return Ok(());
-                    }
-                }
-            },
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
             None => {
-                return Err(());
+                while !self.has_byte() {}
+                return Ok(());
+            }
+        };
+
+        let deadline = timer::current_time() + timeout as u64 * 1000;
+        while timer::current_time() <= deadline {
+            if self.has_byte() {
+                return Ok(());
             }
         }
-        return Err(());
+
+        Err(())
     }
 
     /// Reads a byte. Blocks indefinitely until a byte is ready to be read.